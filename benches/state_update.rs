@@ -0,0 +1,168 @@
+//! Benchmark of the per-sync state update for a 55-pin device
+//!
+//! Compares the previous clone-and-compare change detection with the
+//! incremental update used by `SharedDeviceState`, reporting time and heap
+//! allocations per sync cycle.
+//!
+//! Run with `cargo bench --bench state_update`.
+
+use pokeys_lib::encoders::EncoderData;
+use pokeys_lib::io::PinData;
+use pokeys_lib::pwm::PwmData;
+use pokeys_thread::{SharedDeviceState, StateChangeType};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Allocator that counts allocations
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const PIN_COUNT: usize = 55;
+const ENCODER_COUNT: usize = 25;
+const CYCLES: u32 = 20_000;
+const REFRESH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Simulated device data for one sync cycle
+fn device_io(cycle: u32) -> (Vec<PinData>, Vec<EncoderData>, PwmData) {
+    let mut pins = vec![PinData::new(); PIN_COUNT];
+    for (i, pin) in pins.iter_mut().enumerate() {
+        // A handful of inputs toggle every cycle, the rest are idle
+        pin.digital_value_get = if i < 4 { (cycle % 2) as u8 } else { 0 };
+        pin.analog_value = if i == 40 { cycle % 4096 } else { 0 };
+    }
+
+    let mut encoders = vec![EncoderData::new(); ENCODER_COUNT];
+    encoders[0].encoder_value = cycle as i32;
+
+    (pins, encoders, PwmData::new())
+}
+
+fn new_shared_state() -> SharedDeviceState {
+    let shared_state = SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+    let (pins, encoders, pwm) = device_io(0);
+    shared_state.update(|state| {
+        state.pins = pins;
+        state.encoders = encoders;
+        state.pwm = pwm;
+    });
+    shared_state
+}
+
+/// The change detection used before incremental diffing: clone pins, encoders
+/// and PWM before and after the update, then compare element by element.
+fn clone_and_compare(
+    shared_state: &SharedDeviceState,
+    pins: &[PinData],
+    encoders: &[EncoderData],
+    pwm: &PwmData,
+) -> usize {
+    let old = shared_state.with_state(|state| {
+        (
+            state.pins.clone(),
+            state.encoders.clone(),
+            state.pwm.clone(),
+        )
+    });
+
+    shared_state.update(|state| {
+        state.pins = pins.to_vec();
+        state.encoders = encoders.to_vec();
+        state.pwm = pwm.clone();
+    });
+
+    let new = shared_state.with_state(|state| {
+        (
+            state.pins.clone(),
+            state.encoders.clone(),
+            state.pwm.clone(),
+        )
+    });
+
+    let mut changes = 0;
+    for (old_pin, new_pin) in old.0.iter().zip(new.0.iter()) {
+        changes += (old_pin.digital_value_get != new_pin.digital_value_get) as usize;
+        changes += (old_pin.digital_value_set != new_pin.digital_value_set) as usize;
+        changes += (old_pin.analog_value != new_pin.analog_value) as usize;
+    }
+    for (old_encoder, new_encoder) in old.1.iter().zip(new.1.iter()) {
+        changes += (old_encoder.encoder_value != new_encoder.encoder_value) as usize;
+    }
+    for (old_duty, new_duty) in old.2.pwm_values.iter().zip(new.2.pwm_values.iter()) {
+        changes += (old_duty != new_duty) as usize;
+    }
+    changes
+}
+
+/// The incremental update used by `update_from_device_with_notifications`
+fn incremental(
+    shared_state: &SharedDeviceState,
+    pins: &[PinData],
+    encoders: &[EncoderData],
+    pwm: &PwmData,
+) -> usize {
+    let mut changes = 0;
+    shared_state.update(|state| {
+        state.update_io_with(pins, encoders, pwm, |change: StateChangeType| {
+            black_box(&change);
+            changes += 1;
+        });
+    });
+    changes
+}
+
+fn run(name: &str, update: fn(&SharedDeviceState, &[PinData], &[EncoderData], &PwmData) -> usize) {
+    let shared_state = new_shared_state();
+    let inputs: Vec<_> = (0..CYCLES).map(device_io).collect();
+
+    // Warm up so the state buffers reach their steady-state size
+    for (pins, encoders, pwm) in inputs.iter().take(10) {
+        update(&shared_state, pins, encoders, pwm);
+    }
+
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut changes = 0;
+    for (pins, encoders, pwm) in &inputs {
+        changes += update(&shared_state, pins, encoders, pwm);
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+
+    let per_cycle = elapsed / CYCLES;
+    println!(
+        "{name:<18} {per_cycle:>10.2?}/sync  {:>6.2} allocs/sync  {:>7.4}% of {REFRESH_INTERVAL:?} budget  ({changes} changes)",
+        allocations as f64 / CYCLES as f64,
+        per_cycle.as_secs_f64() / REFRESH_INTERVAL.as_secs_f64() * 100.0,
+    );
+}
+
+fn main() {
+    println!("{PIN_COUNT} pins, {ENCODER_COUNT} encoders, {CYCLES} sync cycles");
+    run("clone-and-compare", clone_and_compare);
+    run("incremental", incremental);
+}
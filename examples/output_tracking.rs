@@ -145,6 +145,24 @@ fn main() {
                                         pin, value, voltage
                                     );
                                 }
+                                StateChangeType::PinCount { count } => {
+                                    info!("📋 Pin count changed to {}", count);
+                                }
+                                StateChangeType::EncoderCount { count } => {
+                                    info!("📋 Encoder count changed to {}", count);
+                                }
+                                StateChangeType::PinFunction { pin, function } => {
+                                    info!("📋 Pin {} function changed to {:#04x}", pin, function);
+                                }
+                                StateChangeType::PwmConfiguration {
+                                    period,
+                                    enabled_channels,
+                                } => {
+                                    info!(
+                                        "⚡ PWM period changed to {} (enabled channels {:#08b})",
+                                        period, enabled_channels
+                                    );
+                                }
                                 StateChangeType::PwmDutyCycle { channel, duty } => {
                                     let percentage = (duty as f32 / 4095.0) * 100.0;
                                     info!(
@@ -155,6 +173,41 @@ fn main() {
                                 StateChangeType::EncoderValue { index, value } => {
                                     info!("🔄 Encoder {} value changed to {}", index, value);
                                 }
+                                StateChangeType::EncoderVelocity { index, velocity } => {
+                                    info!(
+                                        "🔄 Encoder {} velocity changed to {:.3}",
+                                        index, velocity
+                                    );
+                                }
+                                StateChangeType::ServoPosition { pin, angle } => {
+                                    info!("⚙️ Servo on pin {} moved to {}°", pin, angle);
+                                }
+                                StateChangeType::ServoSpeed { pin, speed } => {
+                                    info!("⚙️ Servo on pin {} speed changed to {}", pin, speed);
+                                }
+                                StateChangeType::ServoMotionComplete {
+                                    pin,
+                                    angle,
+                                    cancelled,
+                                } => {
+                                    info!(
+                                        "⚙️ Servo on pin {} move finished at {}° (cancelled: {})",
+                                        pin, angle, cancelled
+                                    );
+                                }
+                                StateChangeType::PwmAnimationComplete {
+                                    channel,
+                                    duty,
+                                    cancelled,
+                                } => {
+                                    info!(
+                                        "⚙️ PWM channel {} animation finished at duty {} (cancelled: {})",
+                                        channel, duty, cancelled
+                                    );
+                                }
+                                StateChangeType::CounterValue { pin, value } => {
+                                    info!("🔢 Counter pin {} value changed to {}", pin, value);
+                                }
                                 StateChangeType::ThreadStatus { status } => {
                                     info!("🔧 Thread status changed to {:?}", status);
                                 }
@@ -171,6 +224,15 @@ fn main() {
                                 StateChangeType::CustomValue { key, value } => {
                                     info!("🏷️  Custom value {} changed to {}", key, value);
                                 }
+                                StateChangeType::TypedCustomValue { key, value } => {
+                                    info!("🏷️  Custom value {} changed to {}", key, value);
+                                }
+                                StateChangeType::CustomValueRemoved { key } => {
+                                    info!("🏷️  Custom value {} removed", key);
+                                }
+                                StateChangeType::ModelChanged { name } => {
+                                    info!("📋 Device model changed to {:?}", name);
+                                }
                             }
                         }
                     }
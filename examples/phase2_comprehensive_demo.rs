@@ -1,3 +1,4 @@
+#![allow(clippy::uninlined_format_args)]
 //! Phase 2 Comprehensive Demo
 //!
 //! This example demonstrates all Phase 2 features:
@@ -6,7 +7,7 @@
 //! - uSPIBridge Integration
 
 use log::info;
-use pokeys_lib::{ServoConfig, ServoType, USPIBridgeConfig};
+use pokeys_lib::{ServoConfig, USPIBridgeConfig};
 use pokeys_thread::{DeviceOperations, ThreadController, ThreadControllerBuilder};
 use std::time::Duration;
 
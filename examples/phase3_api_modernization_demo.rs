@@ -1,3 +1,4 @@
+#![allow(clippy::uninlined_format_args)]
 //! Phase 3 API Modernization Demo
 //!
 //! This example demonstrates all Phase 3 features:
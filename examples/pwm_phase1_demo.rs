@@ -1,3 +1,4 @@
+#![allow(clippy::uninlined_format_args)]
 //! PWM Phase 1 Demo
 //!
 //! This example demonstrates the PWM functionality after Phase 1 core library alignment.
@@ -2,6 +2,8 @@
 
 use crate::error::{Result, ThreadError};
 use crate::logging::ThreadLogger;
+use crate::persistence::PersistenceConfig;
+use crate::sync::AdaptiveRefreshConfig;
 use crate::worker::{DeviceWorker, DeviceWorkerImpl};
 use log::info;
 use pokeys_lib::{
@@ -16,6 +18,10 @@ pub struct ThreadWorkerBuilder {
     thread_id: u32,
     /// Refresh interval in milliseconds
     refresh_interval: u64,
+    /// Adaptive refresh configuration
+    adaptive_refresh: Option<AdaptiveRefreshConfig>,
+    /// State persistence configuration
+    persistence: Option<PersistenceConfig>,
     /// Logger
     logger: Option<Arc<ThreadLogger>>,
 }
@@ -26,6 +32,8 @@ impl ThreadWorkerBuilder {
         Self {
             thread_id,
             refresh_interval: 100, // Default refresh interval: 100ms
+            adaptive_refresh: None,
+            persistence: None,
             logger: None,
         }
     }
@@ -36,6 +44,24 @@ impl ThreadWorkerBuilder {
         self
     }
 
+    /// Enable adaptive refresh
+    ///
+    /// The worker backs off to the slow interval while inputs are idle and
+    /// returns to the fast interval on any input change or command.
+    pub fn adaptive_refresh(mut self, config: AdaptiveRefreshConfig) -> Self {
+        self.adaptive_refresh = Some(config);
+        self
+    }
+
+    /// Enable state persistence
+    ///
+    /// Outputs, PWM duty cycles, custom values and servo positions are saved
+    /// per device serial and optionally restored when the thread starts.
+    pub fn persistence(mut self, config: PersistenceConfig) -> Self {
+        self.persistence = Some(config);
+        self
+    }
+
     /// Set the logger
     pub fn with_logger(mut self, logger: Arc<ThreadLogger>) -> Self {
         self.logger = Some(logger);
@@ -56,6 +82,13 @@ impl ThreadWorkerBuilder {
             );
         }
 
+        if let Some(config) = &self.adaptive_refresh {
+            config.validate()?;
+        }
+        if let Some(config) = &self.persistence {
+            config.validate()?;
+        }
+
         // Connect to the device to get initial state
         let device = connect_to_device(device_index).map_err(ThreadError::DeviceError)?;
 
@@ -72,6 +105,16 @@ impl ThreadWorkerBuilder {
             worker = worker.with_logger(logger);
         }
 
+        // Enable adaptive refresh if configured
+        if let Some(config) = self.adaptive_refresh {
+            worker = worker.with_adaptive_refresh(config);
+        }
+
+        // Enable state persistence if configured
+        if let Some(config) = self.persistence {
+            worker = worker.with_persistence(config);
+        }
+
         // Create a boxed worker
         let mut boxed_worker: Box<dyn DeviceWorker> = Box::new(worker);
 
@@ -98,6 +141,13 @@ impl ThreadWorkerBuilder {
             );
         }
 
+        if let Some(config) = &self.adaptive_refresh {
+            config.validate()?;
+        }
+        if let Some(config) = &self.persistence {
+            config.validate()?;
+        }
+
         // Connect to the device to get initial state
         let device =
             connect_to_network_device(&device_summary).map_err(ThreadError::DeviceError)?;
@@ -115,6 +165,16 @@ impl ThreadWorkerBuilder {
             worker = worker.with_logger(logger);
         }
 
+        // Enable adaptive refresh if configured
+        if let Some(config) = self.adaptive_refresh {
+            worker = worker.with_adaptive_refresh(config);
+        }
+
+        // Enable state persistence if configured
+        if let Some(config) = self.persistence {
+            worker = worker.with_persistence(config);
+        }
+
         // Create a boxed worker
         let mut boxed_worker: Box<dyn DeviceWorker> = Box::new(worker);
 
@@ -143,6 +203,13 @@ impl ThreadWorkerBuilder {
             );
         }
 
+        if let Some(config) = &self.adaptive_refresh {
+            config.validate()?;
+        }
+        if let Some(config) = &self.persistence {
+            config.validate()?;
+        }
+
         // Connect to the device to get initial state
         let device = connect_to_device_with_serial(serial_number, check_network, timeout_ms)
             .map_err(ThreadError::DeviceError)?;
@@ -183,6 +250,16 @@ impl ThreadWorkerBuilder {
             worker = worker.with_logger(logger);
         }
 
+        // Enable adaptive refresh if configured
+        if let Some(config) = self.adaptive_refresh {
+            worker = worker.with_adaptive_refresh(config);
+        }
+
+        // Enable state persistence if configured
+        if let Some(config) = self.persistence {
+            worker = worker.with_persistence(config);
+        }
+
         // Create a boxed worker
         let mut boxed_worker: Box<dyn DeviceWorker> = Box::new(worker);
 
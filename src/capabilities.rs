@@ -0,0 +1,226 @@
+//! Pin capability lookup in device models
+//!
+//! Device models list the capabilities of each pin by name, for example
+//! `PwmOutput`, `Encoder_1A` or `MatrixKeyboard_Row1`. This module maps every
+//! [`PinCapability`] to the model names that provide it, so capability
+//! queries are answered from the model of the connected device instead of
+//! fixed pin ranges.
+//!
+//! The bundled PoKeys56/57 models only list digital I/O, analog inputs, PWM
+//! outputs, encoder inputs, matrix keyboard and SPI pins. The following
+//! capabilities are therefore only found on models that name them
+//! explicitly, and are reported as unsupported on the bundled models:
+//!
+//! * `AnalogOutput`;
+//! * `DigitalCounter` (the controller also asks the device for counter
+//!   support);
+//! * the index inputs `FastEncoder1I`-`FastEncoder3I` and the ultra fast
+//!   encoder inputs;
+//! * the LCD pins `LcdE` to `LcdD7`.
+
+use crate::error::{Result, ThreadError};
+use pokeys_lib::models::DeviceModel;
+use pokeys_lib::PinCapability;
+
+/// All pin capabilities, in declaration order
+pub const ALL_CAPABILITIES: [PinCapability; 28] = [
+    PinCapability::DigitalInput,
+    PinCapability::DigitalOutput,
+    PinCapability::AnalogInput,
+    PinCapability::MfAnalogInput,
+    PinCapability::AnalogOutput,
+    PinCapability::KeyboardMapping,
+    PinCapability::TriggeredInput,
+    PinCapability::DigitalCounter,
+    PinCapability::PwmOutput,
+    PinCapability::FastEncoder1A,
+    PinCapability::FastEncoder1B,
+    PinCapability::FastEncoder1I,
+    PinCapability::FastEncoder2A,
+    PinCapability::FastEncoder2B,
+    PinCapability::FastEncoder2I,
+    PinCapability::FastEncoder3A,
+    PinCapability::FastEncoder3B,
+    PinCapability::FastEncoder3I,
+    PinCapability::UltraFastEncoderA,
+    PinCapability::UltraFastEncoderB,
+    PinCapability::UltraFastEncoderI,
+    PinCapability::LcdE,
+    PinCapability::LcdRw,
+    PinCapability::LcdRs,
+    PinCapability::LcdD4,
+    PinCapability::LcdD5,
+    PinCapability::LcdD6,
+    PinCapability::LcdD7,
+];
+
+/// Pins of the A and B inputs of fast encoders 1-3, fixed by the firmware
+pub const FAST_ENCODER_PINS: [(u8, u8); 3] = [(1, 2), (3, 4), (15, 16)];
+
+/// Get the model capability names that provide a capability
+///
+/// Names ending in `*` match any model capability with that prefix. Besides
+/// its own name, a capability is provided by:
+///
+/// * analog inputs for `MfAnalogInput`;
+/// * digital inputs for `TriggeredInput` and `KeyboardMapping`, and matrix
+///   keyboard rows and columns for `KeyboardMapping`.
+///
+/// [`pin_supports`] also accepts any encoder input on the
+/// [`FAST_ENCODER_PINS`] for the A and B inputs of fast encoders 1-3.
+pub fn model_capability_names(capability: PinCapability) -> &'static [&'static str] {
+    match capability {
+        PinCapability::DigitalInput => &["DigitalInput"],
+        PinCapability::DigitalOutput => &["DigitalOutput"],
+        PinCapability::AnalogInput => &["AnalogInput"],
+        PinCapability::MfAnalogInput => &["MfAnalogInput", "AnalogInput"],
+        PinCapability::AnalogOutput => &["AnalogOutput"],
+        PinCapability::KeyboardMapping => &["KeyboardMapping", "DigitalInput", "MatrixKeyboard_*"],
+        PinCapability::TriggeredInput => &["TriggeredInput", "DigitalInput"],
+        PinCapability::DigitalCounter => &["DigitalCounter"],
+        PinCapability::PwmOutput => &["PwmOutput"],
+        PinCapability::FastEncoder1A => &["FastEncoder1A"],
+        PinCapability::FastEncoder1B => &["FastEncoder1B"],
+        PinCapability::FastEncoder1I => &["FastEncoder1I"],
+        PinCapability::FastEncoder2A => &["FastEncoder2A"],
+        PinCapability::FastEncoder2B => &["FastEncoder2B"],
+        PinCapability::FastEncoder2I => &["FastEncoder2I"],
+        PinCapability::FastEncoder3A => &["FastEncoder3A"],
+        PinCapability::FastEncoder3B => &["FastEncoder3B"],
+        PinCapability::FastEncoder3I => &["FastEncoder3I"],
+        PinCapability::UltraFastEncoderA => &["UltraFastEncoderA"],
+        PinCapability::UltraFastEncoderB => &["UltraFastEncoderB"],
+        PinCapability::UltraFastEncoderI => &["UltraFastEncoderI"],
+        PinCapability::LcdE => &["LcdE"],
+        PinCapability::LcdRw => &["LcdRw"],
+        PinCapability::LcdRs => &["LcdRs"],
+        PinCapability::LcdD4 => &["LcdD4"],
+        PinCapability::LcdD5 => &["LcdD5"],
+        PinCapability::LcdD6 => &["LcdD6"],
+        PinCapability::LcdD7 => &["LcdD7"],
+    }
+}
+
+/// Check if a model capability name matches a name from [`model_capability_names`]
+fn name_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+/// Get the pin of a fast encoder A or B input
+fn fast_encoder_pin(capability: PinCapability) -> Option<u8> {
+    let (encoder, b_input) = match capability {
+        PinCapability::FastEncoder1A => (0, false),
+        PinCapability::FastEncoder1B => (0, true),
+        PinCapability::FastEncoder2A => (1, false),
+        PinCapability::FastEncoder2B => (1, true),
+        PinCapability::FastEncoder3A => (2, false),
+        PinCapability::FastEncoder3B => (2, true),
+        _ => return None,
+    };
+    let (a, b) = FAST_ENCODER_PINS[encoder];
+    Some(if b_input { b } else { a })
+}
+
+/// Check if a pin of a device model supports a capability
+///
+/// Inactive pins and pins missing from the model support nothing.
+pub fn pin_supports(model: &DeviceModel, pin: u8, capability: PinCapability) -> bool {
+    let Some(pin_model) = model.pins.get(&pin).filter(|pin_model| pin_model.active) else {
+        return false;
+    };
+    let has_name = |pattern: &str| {
+        pin_model
+            .capabilities
+            .iter()
+            .any(|name| name_matches(pattern, name))
+    };
+    model_capability_names(capability)
+        .iter()
+        .any(|pattern| has_name(pattern))
+        || (fast_encoder_pin(capability) == Some(pin) && has_name("Encoder_*"))
+}
+
+/// Get the pins of a device model that support a capability, in pin order
+pub fn pins_with_capability(model: &DeviceModel, capability: PinCapability) -> Vec<u8> {
+    let mut pins: Vec<u8> = model
+        .pins
+        .keys()
+        .copied()
+        .filter(|&pin| pin_supports(model, pin, capability))
+        .collect();
+    pins.sort_unstable();
+    pins
+}
+
+/// Get the A and B input pins of an encoder from the `Encoder_<n>A/B` names of a model
+///
+/// Encoder `encoder_index` uses the pins named with `n = encoder_index + 1`.
+pub fn encoder_pins(model: &DeviceModel, encoder_index: u32) -> Option<(u8, u8)> {
+    let input_pin = |input: char| {
+        let name = format!("Encoder_{}{input}", encoder_index + 1);
+        pins_with_names(model, &name).first().copied()
+    };
+    Some((input_pin('A')?, input_pin('B')?))
+}
+
+/// Get the active pins of a model listing a capability name, in pin order
+fn pins_with_names(model: &DeviceModel, name: &str) -> Vec<u8> {
+    let mut pins: Vec<u8> = model
+        .pins
+        .iter()
+        .filter(|(_, pin_model)| {
+            pin_model.active && pin_model.capabilities.iter().any(|c| c == name)
+        })
+        .map(|(&pin, _)| pin)
+        .collect();
+    pins.sort_unstable();
+    pins
+}
+
+/// Get up to `limit` pins supporting a capability, closest to `pin` first
+pub fn suggest_pins(
+    model: &DeviceModel,
+    pin: u8,
+    capability: PinCapability,
+    limit: usize,
+) -> Vec<u8> {
+    let mut pins = pins_with_capability(model, capability);
+    pins.retain(|&other| other != pin);
+    pins.sort_by_key(|&other| (other.abs_diff(pin), other));
+    pins.truncate(limit);
+    pins
+}
+
+/// Parse the operation name of `validate_pin_operation` into a capability
+///
+/// Accepts `digital_output`, `digital_input`, `analog_input`, `analog_output`,
+/// `pwm`, `servo`, `counter`, `triggered_input` and `keyboard`, as well as the
+/// name of any [`PinCapability`] such as `FastEncoder1A` or `LcdE`.
+pub fn parse_operation(operation: &str) -> Result<PinCapability> {
+    let capability = match operation {
+        "digital_output" => Some(PinCapability::DigitalOutput),
+        "digital_input" => Some(PinCapability::DigitalInput),
+        "analog_input" => Some(PinCapability::AnalogInput),
+        "analog_output" => Some(PinCapability::AnalogOutput),
+        "pwm" | "servo" => Some(PinCapability::PwmOutput), // Servos use PWM pins
+        "counter" => Some(PinCapability::DigitalCounter),
+        "triggered_input" => Some(PinCapability::TriggeredInput),
+        "keyboard" => Some(PinCapability::KeyboardMapping),
+        _ => ALL_CAPABILITIES
+            .into_iter()
+            .find(|capability| format!("{capability:?}").eq_ignore_ascii_case(operation)),
+    };
+    capability.ok_or_else(|| {
+        ThreadError::validation_error(
+            "Unknown operation type",
+            operation,
+            Some(
+                "Use: digital_output, digital_input, analog_input, analog_output, pwm, servo, \
+                 counter, triggered_input, keyboard, or a pin capability name",
+            ),
+        )
+    })
+}
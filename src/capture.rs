@@ -0,0 +1,415 @@
+//! Triggered capture
+//!
+//! Oscilloscope-style captures of selected channels around a trigger event.
+//! An armed capture keeps the most recent pre-trigger samples from the sync
+//! loop, waits for its trigger condition and then collects the post-trigger
+//! samples. The completed capture is delivered through a [`CaptureHandle`]
+//! and can be saved to and loaded from a JSON file.
+
+use crate::error::{Result, ThreadError};
+use crate::history::{now_ms, HistoryChannel};
+use crate::state::DeviceState;
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Signal edge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Edge {
+    /// Low to high
+    Rising,
+    /// High to low
+    Falling,
+    /// Either direction
+    Either,
+}
+
+/// Condition that completes the pre-trigger phase of a capture
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriggerCondition {
+    /// Digital input edge on a pin
+    DigitalEdge { pin: u32, edge: Edge },
+    /// Analog input crossing a threshold on a pin
+    AnalogThreshold {
+        pin: u32,
+        threshold: u32,
+        edge: Edge,
+    },
+    /// A new error reported by the device thread
+    Error,
+}
+
+/// Value of the trigger source in one sample
+#[derive(Debug, Clone, PartialEq)]
+enum Observation {
+    Value(Option<i64>),
+    Error(Option<String>),
+}
+
+impl TriggerCondition {
+    fn observe(&self, state: &DeviceState) -> Observation {
+        match *self {
+            Self::DigitalEdge { pin, .. } => {
+                Observation::Value(state.get_digital_input(pin).map(i64::from))
+            }
+            Self::AnalogThreshold { pin, .. } => {
+                Observation::Value(state.get_analog_input(pin).map(i64::from))
+            }
+            Self::Error => Observation::Error(state.error_message.clone()),
+        }
+    }
+
+    fn fires(&self, previous: &Observation, current: &Observation) -> bool {
+        match (self, previous, current) {
+            (
+                Self::DigitalEdge { edge, .. },
+                Observation::Value(Some(previous)),
+                Observation::Value(Some(current)),
+            ) => crossed(*edge, *previous, *current, 1),
+            (
+                Self::AnalogThreshold {
+                    threshold, edge, ..
+                },
+                Observation::Value(Some(previous)),
+                Observation::Value(Some(current)),
+            ) => crossed(*edge, *previous, *current, i64::from(*threshold)),
+            (Self::Error, Observation::Error(previous), Observation::Error(Some(current))) => {
+                previous.as_ref() != Some(current)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Check if a value crossed `level` in the direction of `edge`
+fn crossed(edge: Edge, previous: i64, current: i64, level: i64) -> bool {
+    let rising = previous < level && current >= level;
+    let falling = previous >= level && current < level;
+    match edge {
+        Edge::Rising => rising,
+        Edge::Falling => falling,
+        Edge::Either => rising || falling,
+    }
+}
+
+/// Capture configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureConfig {
+    /// Captured channels, in column order
+    pub channels: Vec<HistoryChannel>,
+    /// Trigger condition
+    pub trigger: TriggerCondition,
+    /// Number of samples kept before the trigger
+    pub pre_samples: usize,
+    /// Number of samples collected after the trigger
+    pub post_samples: usize,
+}
+
+impl CaptureConfig {
+    /// Create a configuration with 100 pre- and post-trigger samples
+    pub fn new(trigger: TriggerCondition) -> Self {
+        Self {
+            channels: Vec::new(),
+            trigger,
+            pre_samples: 100,
+            post_samples: 100,
+        }
+    }
+
+    /// Add a captured channel
+    pub fn channel(mut self, channel: HistoryChannel) -> Self {
+        self.channels.push(channel);
+        self
+    }
+
+    /// Add several captured channels
+    pub fn channels(mut self, channels: impl IntoIterator<Item = HistoryChannel>) -> Self {
+        self.channels.extend(channels);
+        self
+    }
+
+    /// Set the number of pre- and post-trigger samples
+    pub fn samples(mut self, pre_samples: usize, post_samples: usize) -> Self {
+        self.pre_samples = pre_samples;
+        self.post_samples = post_samples;
+        self
+    }
+
+    /// Validate the configuration
+    pub fn validate(&self) -> Result<()> {
+        if self.channels.is_empty() {
+            return Err(ThreadError::ConfigurationError(
+                "At least one channel must be captured".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// One captured sample
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureFrame {
+    /// Timestamp in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Time relative to the trigger sample in milliseconds
+    pub offset_ms: i64,
+    /// Channel values in the order of [`Capture::channels`]
+    pub values: Vec<Option<i64>>,
+}
+
+/// A completed capture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capture {
+    /// Thread ID the capture was taken from
+    pub thread_id: u32,
+    /// Device serial number
+    pub serial: u32,
+    /// Trigger condition
+    pub trigger: TriggerCondition,
+    /// Captured channels
+    pub channels: Vec<HistoryChannel>,
+    /// Timestamp of the trigger sample in milliseconds since the Unix epoch
+    pub trigger_timestamp: u64,
+    /// Index of the trigger sample in `frames`
+    pub trigger_index: usize,
+    /// Captured samples, oldest first
+    pub frames: Vec<CaptureFrame>,
+}
+
+impl Capture {
+    /// Get the values of one channel, oldest first
+    pub fn channel_values(&self, channel: HistoryChannel) -> Option<Vec<Option<i64>>> {
+        let column = self.channels.iter().position(|c| *c == channel)?;
+        Some(
+            self.frames
+                .iter()
+                .map(|frame| frame.values[column])
+                .collect(),
+        )
+    }
+
+    /// Save the capture as JSON
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)
+            .map_err(|e| ThreadError::OperationFailed(e.to_string()))
+    }
+
+    /// Load a capture saved with [`Capture::save`]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        serde_json::from_reader(reader).map_err(|e| ThreadError::OperationFailed(e.to_string()))
+    }
+}
+
+/// Handle to an armed capture
+///
+/// Dropping the handle disarms the capture.
+pub struct CaptureHandle {
+    receiver: Receiver<Capture>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CaptureHandle {
+    /// Wait for the capture to complete
+    ///
+    /// # Errors
+    ///
+    /// Returns `ThreadError::Timeout` if the capture did not complete in time.
+    pub fn wait(&self, timeout: Duration) -> Result<Capture> {
+        self.receiver.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => ThreadError::Timeout,
+            RecvTimeoutError::Disconnected => {
+                ThreadError::ChannelReceiveError("Capture was discarded".to_string())
+            }
+        })
+    }
+
+    /// Get the capture if it has completed
+    pub fn try_get(&self) -> Option<Capture> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Disarm the capture
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for CaptureHandle {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Phase of an armed capture
+enum Phase {
+    /// Waiting for the trigger
+    Armed,
+    /// Collecting post-trigger samples
+    Triggered {
+        trigger_index: usize,
+        timestamp: u64,
+    },
+}
+
+struct ArmedCapture {
+    config: CaptureConfig,
+    frames: VecDeque<CaptureFrame>,
+    previous: Option<Observation>,
+    phase: Phase,
+    sender: Sender<Capture>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ArmedCapture {
+    /// Add a sample and return true once the capture is complete
+    fn process(&mut self, state: &DeviceState, timestamp: u64) -> bool {
+        let values = self
+            .config
+            .channels
+            .iter()
+            .map(|channel| channel.value_in(state))
+            .collect();
+        self.frames.push_back(CaptureFrame {
+            timestamp,
+            offset_ms: 0,
+            values,
+        });
+
+        match self.phase {
+            Phase::Armed => {
+                let observation = self.config.trigger.observe(state);
+                let fired = self
+                    .previous
+                    .as_ref()
+                    .is_some_and(|previous| self.config.trigger.fires(previous, &observation));
+                self.previous = Some(observation);
+
+                if fired {
+                    self.phase = Phase::Triggered {
+                        trigger_index: self.frames.len() - 1,
+                        timestamp,
+                    };
+                } else if self.frames.len() > self.config.pre_samples {
+                    self.frames.pop_front();
+                }
+            }
+            Phase::Triggered { .. } => {}
+        }
+
+        match self.phase {
+            Phase::Triggered { trigger_index, .. } => {
+                self.frames.len() > trigger_index + self.config.post_samples
+            }
+            Phase::Armed => false,
+        }
+    }
+
+    fn finish(self, thread_id: u32, serial: u32) {
+        let Phase::Triggered {
+            trigger_index,
+            timestamp,
+        } = self.phase
+        else {
+            return;
+        };
+
+        let frames = self
+            .frames
+            .into_iter()
+            .map(|mut frame| {
+                frame.offset_ms = frame.timestamp as i64 - timestamp as i64;
+                frame
+            })
+            .collect();
+
+        // The handle may have been dropped in the meantime
+        let _ = self.sender.send(Capture {
+            thread_id,
+            serial,
+            trigger: self.config.trigger,
+            channels: self.config.channels,
+            trigger_timestamp: timestamp,
+            trigger_index,
+            frames,
+        });
+    }
+}
+
+/// Set of armed captures of a device thread
+#[derive(Default)]
+pub struct CaptureSet {
+    captures: Mutex<Vec<ArmedCapture>>,
+}
+
+impl CaptureSet {
+    /// Create an empty capture set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Arm a new capture
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration is invalid.
+    pub fn arm(&self, config: CaptureConfig) -> Result<CaptureHandle> {
+        config.validate()?;
+
+        let (sender, receiver) = bounded(1);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.captures.lock().push(ArmedCapture {
+            frames: VecDeque::with_capacity(config.pre_samples + config.post_samples + 1),
+            config,
+            previous: None,
+            phase: Phase::Armed,
+            sender,
+            cancelled: cancelled.clone(),
+        });
+
+        Ok(CaptureHandle {
+            receiver,
+            cancelled,
+        })
+    }
+
+    /// Check if any capture is armed
+    pub fn is_empty(&self) -> bool {
+        self.captures.lock().is_empty()
+    }
+
+    /// Get the number of armed captures
+    pub fn len(&self) -> usize {
+        self.captures.lock().len()
+    }
+
+    /// Feed a sample from the sync loop to every armed capture
+    ///
+    /// Completed captures are delivered to their handles and removed,
+    /// as are captures whose handle was cancelled or dropped.
+    pub fn process(&self, thread_id: u32, state: &DeviceState) {
+        let timestamp = now_ms();
+        let mut captures = self.captures.lock();
+
+        let mut i = 0;
+        while i < captures.len() {
+            if captures[i].cancelled.load(Ordering::Relaxed) {
+                captures.swap_remove(i);
+            } else if captures[i].process(state, timestamp) {
+                captures
+                    .swap_remove(i)
+                    .finish(thread_id, state.device_data.serial_number);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
@@ -1,9 +1,34 @@
+use crate::error::Result;
+use crate::i2c::{I2cScanConfig, I2cScanReport};
+use crate::pwm::{PwmChannelMap, PwmConfig, PwmWaveform};
+use crate::resources::{pin_resource, ClaimId, Resource, ResourceClaim};
+use crate::servo::ServoProfile;
+use crate::uspibridge::{BridgeCommand, DEFAULT_USPIBRIDGE_ADDRESS};
+use crossbeam_channel::Sender;
 use log::LevelFilter;
 use pokeys_lib::models::DeviceModel;
 use pokeys_lib::{ServoConfig, USPIBridgeConfig};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Channel on which a device thread answers a command
+pub type ResponseSender<T> = Sender<Result<T>>;
+
+/// Response channel of a deserialized command, whose answer is dropped
+fn detached_response<T>() -> ResponseSender<T> {
+    crossbeam_channel::bounded(1).0
+}
+
+/// Bridge address of uSPIBridge commands recorded without one
+fn default_uspibridge_address() -> u8 {
+    DEFAULT_USPIBRIDGE_ADDRESS
+}
 
 /// Commands that can be sent to device threads
-#[derive(Debug, Clone)]
+///
+/// Commands serialize without their response channel; a deserialized
+/// command that has one answers on a channel nobody receives from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DeviceCommand {
     /// Start the device thread
     Start,
@@ -21,6 +46,15 @@ pub enum DeviceCommand {
     SetAnalogOutput { pin: u32, value: u32 },
     /// Set PWM duty cycle
     SetPwmDuty { channel: usize, duty: u32 },
+    /// Configure the PWM period and enabled channels
+    ConfigurePwm { config: PwmConfig },
+    /// Run a PWM fade, ramp, waveform or step sequence on a channel
+    AnimatePwm {
+        channel: usize,
+        waveform: PwmWaveform,
+    },
+    /// Cancel a PWM animation in progress
+    CancelPwmAnimation { channel: usize },
     /// Configure a servo
     ConfigureServo { pin: u8, config: ServoConfig },
     /// Set servo angle
@@ -29,6 +63,14 @@ pub enum DeviceCommand {
     SetServoSpeed { pin: u8, speed: f32 },
     /// Stop servo
     StopServo { pin: u8 },
+    /// Move servos to target angles with speed and acceleration limits,
+    /// arriving at the same time
+    MoveServos {
+        moves: Vec<(u8, f32)>,
+        profile: ServoProfile,
+    },
+    /// Cancel a servo move in progress
+    CancelServoMotion { pin: u8 },
     /// I2C write operation
     I2cWrite { address: u8, data: Vec<u8> },
     /// I2C read operation
@@ -39,12 +81,45 @@ pub enum DeviceCommand {
         write_data: Vec<u8>,
         read_length: u8,
     },
-    /// I2C bus scan
+    /// I2C write then read, answered with the bytes read
+    ///
+    /// The write is skipped if `write_data` is empty and the read if
+    /// `read_length` is 0.
+    I2cTransfer {
+        address: u8,
+        write_data: Vec<u8>,
+        read_length: u8,
+        #[serde(skip, default = "detached_response")]
+        response: ResponseSender<Vec<u8>>,
+    },
+    /// I2C bus scan of the default range, logging the devices found
     I2cScan,
-    /// Configure uSPIBridge
-    ConfigureUSPIBridge { config: USPIBridgeConfig },
-    /// Send uSPIBridge command
-    USPIBridgeCommand { command: Vec<u8> },
+    /// I2C bus scan, answered with the results
+    I2cScanBus {
+        config: I2cScanConfig,
+        #[serde(skip, default = "detached_response")]
+        response: ResponseSender<I2cScanReport>,
+    },
+    /// Configure the uSPIBridge at an address
+    ConfigureUSPIBridge {
+        #[serde(default = "default_uspibridge_address")]
+        address: u8,
+        #[serde(with = "crate::uspibridge::config_serde")]
+        config: USPIBridgeConfig,
+    },
+    /// Send a raw `[command, device_id, payload...]` uSPIBridge command to an address
+    USPIBridgeCommand {
+        #[serde(default = "default_uspibridge_address")]
+        address: u8,
+        command: Vec<u8>,
+    },
+    /// Send uSPIBridge commands in order, answered with the response to the last one
+    USPIBridgeRequest {
+        address: u8,
+        commands: Vec<BridgeCommand>,
+        #[serde(skip, default = "detached_response")]
+        response: ResponseSender<Vec<u8>>,
+    },
     /// Bulk set digital outputs
     SetDigitalOutputsBulk { pin_states: Vec<(u32, bool)> },
     /// Bulk set PWM duty cycles
@@ -82,4 +157,158 @@ pub enum DeviceCommand {
     SetLogLevel(LevelFilter),
     /// Update device model
     UpdateModel(DeviceModel),
+    /// Run a command for a resource claim
+    ///
+    /// Claimed resources may only be configured, and leased outputs only
+    /// written, by commands wrapped with the ID of their claim. The ID is not
+    /// serialized, so a deserialized command acts for no claim.
+    Claimed {
+        #[serde(skip, default = "ClaimId::detached")]
+        claim: ClaimId,
+        command: Box<DeviceCommand>,
+    },
+    /// Release a claim once the commands queued before it have run
+    ///
+    /// Leases send this after the reverts of their handles, so the outputs
+    /// stay leased until they are reverted.
+    ReleaseClaim {
+        #[serde(skip, default = "ResourceClaim::detached")]
+        claim: Arc<ResourceClaim>,
+    },
+}
+
+impl DeviceCommand {
+    /// Get the name of the command, as used in errors
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Pause => "pause",
+            Self::Terminate => "terminate",
+            Self::Restart => "restart",
+            Self::GetStatus => "get_status",
+            Self::SetDigitalOutput { .. } => "set_digital_output",
+            Self::SetAnalogOutput { .. } => "set_analog_output",
+            Self::SetPwmDuty { .. } => "set_pwm_duty",
+            Self::ConfigurePwm { .. } => "configure_pwm",
+            Self::AnimatePwm { .. } => "animate_pwm",
+            Self::CancelPwmAnimation { .. } => "cancel_pwm_animation",
+            Self::ConfigureServo { .. } => "configure_servo",
+            Self::SetServoAngle { .. } => "set_servo_angle",
+            Self::SetServoSpeed { .. } => "set_servo_speed",
+            Self::StopServo { .. } => "stop_servo",
+            Self::MoveServos { .. } => "move_servos",
+            Self::CancelServoMotion { .. } => "cancel_servo_motion",
+            Self::I2cWrite { .. } => "i2c_write",
+            Self::I2cRead { .. } => "i2c_read",
+            Self::I2cWriteRead { .. } => "i2c_write_read",
+            Self::I2cTransfer { .. } => "i2c_transfer",
+            Self::I2cScan => "i2c_scan",
+            Self::I2cScanBus { .. } => "i2c_scan_bus",
+            Self::ConfigureUSPIBridge { .. } => "configure_uspibridge",
+            Self::USPIBridgeCommand { .. } => "uspibridge_command",
+            Self::USPIBridgeRequest { .. } => "uspibridge_request",
+            Self::SetDigitalOutputsBulk { .. } => "set_digital_outputs_bulk",
+            Self::SetPwmDutiesBulk { .. } => "set_pwm_duties_bulk",
+            Self::ReadAnalogInputsBulk { .. } => "read_analog_inputs_bulk",
+            Self::CheckPinCapability { .. } => "check_pin_capability",
+            Self::ValidatePinOperation { .. } => "validate_pin_operation",
+            Self::ConfigureEncoder { .. } => "configure_encoder",
+            Self::ResetDigitalCounter { .. } => "reset_digital_counter",
+            Self::SetPinFunction { .. } => "set_pin_function",
+            Self::Custom { .. } => "custom",
+            Self::SetLogLevel(_) => "set_log_level",
+            Self::UpdateModel(_) => "update_model",
+            Self::Claimed { command, .. } => command.name(),
+            Self::ReleaseClaim { .. } => "release_claim",
+        }
+    }
+
+    /// Get the resources the command configures
+    ///
+    /// # Parameters
+    ///
+    /// * `channels` - The PWM channel map of the device; a PWM configuration
+    ///   configures every channel.
+    pub fn configured_resources(&self, channels: &PwmChannelMap) -> Vec<Resource> {
+        match self {
+            Self::SetPinFunction { pin, .. } => pin_resource(*pin).into_iter().collect(),
+            Self::ConfigureEncoder {
+                encoder_index,
+                pin_a,
+                pin_b,
+                ..
+            } => std::iter::once(Resource::Encoder(*encoder_index))
+                .chain(pin_resource(*pin_a))
+                .chain(pin_resource(*pin_b))
+                .collect(),
+            Self::ConfigureServo { pin, .. } => vec![Resource::Pin(*pin)],
+            Self::ConfigurePwm { .. } => channels
+                .channels()
+                .into_iter()
+                .map(Resource::PwmChannel)
+                .collect(),
+            Self::Claimed { command, .. } => command.configured_resources(channels),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get the outputs and buses the command uses
+    ///
+    /// PWM channels are reported with their pins and servo pins with their
+    /// PWM channels, so a lease on either covers both. I2C transfers, scans
+    /// and uSPIBridge commands use the I2C bus. No command of the device
+    /// thread uses the SPI bus, so claims on it only keep owners apart.
+    ///
+    /// # Parameters
+    ///
+    /// * `channels` - The PWM channel map of the device.
+    pub fn written_resources(&self, channels: &PwmChannelMap) -> Vec<Resource> {
+        let pwm_channel = |channel: usize| {
+            std::iter::once(Resource::PwmChannel(channel))
+                .chain(channels.pin(channel).map(Resource::Pin))
+        };
+        let servo_pin = |pin: u8| {
+            std::iter::once(Resource::Pin(pin))
+                .chain(channels.channel(pin).map(Resource::PwmChannel))
+        };
+        match self {
+            Self::SetDigitalOutput { pin, .. } | Self::SetAnalogOutput { pin, .. } => {
+                pin_resource(*pin).into_iter().collect()
+            }
+            Self::SetDigitalOutputsBulk { pin_states } => pin_states
+                .iter()
+                .filter_map(|&(pin, _)| pin_resource(pin))
+                .collect(),
+            Self::SetPwmDuty { channel, .. }
+            | Self::AnimatePwm { channel, .. }
+            | Self::CancelPwmAnimation { channel } => pwm_channel(*channel).collect(),
+            Self::SetPwmDutiesBulk { channel_duties } => channel_duties
+                .iter()
+                .flat_map(|&(channel, _)| pwm_channel(channel))
+                .collect(),
+            Self::ConfigurePwm { .. } => channels
+                .channels()
+                .into_iter()
+                .flat_map(pwm_channel)
+                .collect(),
+            Self::SetServoAngle { pin, .. }
+            | Self::SetServoSpeed { pin, .. }
+            | Self::StopServo { pin }
+            | Self::CancelServoMotion { pin } => servo_pin(*pin).collect(),
+            Self::MoveServos { moves, .. } => {
+                moves.iter().flat_map(|&(pin, _)| servo_pin(pin)).collect()
+            }
+            Self::I2cWrite { .. }
+            | Self::I2cRead { .. }
+            | Self::I2cWriteRead { .. }
+            | Self::I2cTransfer { .. }
+            | Self::I2cScan
+            | Self::I2cScanBus { .. }
+            | Self::ConfigureUSPIBridge { .. }
+            | Self::USPIBridgeCommand { .. }
+            | Self::USPIBridgeRequest { .. } => vec![Resource::I2cBus],
+            Self::Claimed { command, .. } => command.written_resources(channels),
+            _ => Vec::new(),
+        }
+    }
 }
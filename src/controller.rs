@@ -27,18 +27,42 @@
 //! ```
 
 use crate::builder::ThreadWorkerBuilder;
+use crate::capabilities;
+use crate::capture::{CaptureConfig, CaptureHandle};
 use crate::commands::DeviceCommand;
+use crate::device::DeviceHandle;
+use crate::encoder::{EncoderMotion, EncoderScaling};
 use crate::error::{Result, ThreadError};
+use crate::history::{HistoryChannel, HistorySample};
+use crate::i2c::{I2cDevice, I2cScanConfig, I2cScanReport};
+use crate::lease::{DigitalOutputHandle, LeaseRequest, OutputLease, PwmHandle};
 use crate::logging::{Logger, ThreadLogger};
 use crate::observer::StateObserver;
 use crate::operations::DeviceOperations;
+use crate::persistence::PersistenceConfig;
+use crate::pwm::{PwmChannelMap, PwmConfig, PwmDuty, PwmWaveform};
+use crate::recorder::{DataRecorder, RecorderConfig};
+use crate::resources::{Resource, ResourceClaim};
+use crate::servo::ServoProfile;
+use crate::session::SessionRecorder;
 use crate::state::{DeviceState, SharedDeviceState, ThreadStatus};
+use crate::stats::SyncStatsSnapshot;
+use crate::sync::AdaptiveRefreshConfig;
+use crate::uspibridge::{BridgeCommand, USPIBridge, DEFAULT_USPIBRIDGE_ADDRESS};
 use crate::worker::DeviceWorker;
 use log::{debug, error, info, LevelFilter};
 use pokeys_lib::{enumerate_network_devices, enumerate_usb_devices, NetworkDeviceSummary};
 use pokeys_lib::{PinCapability, ServoConfig, USPIBridgeConfig};
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
+
+/// Pin count assumed for digital I/O before the device reports its pins
+const DEFAULT_PIN_COUNT: usize = 55;
+
+/// Full-scale duty used for percentages when no PWM period is configured
+const LEGACY_PWM_DUTY_SCALE: u32 = 4095;
 
 /// Thread controller for managing device threads.
 ///
@@ -170,6 +194,24 @@ pub trait ThreadController {
     /// Returns an error if the thread is not found.
     fn get_state(&self, thread_id: u32) -> Result<DeviceState>;
 
+    /// Get an immutable snapshot of the state of a device thread.
+    ///
+    /// Unlike `get_state`, this does not copy the state and never blocks
+    /// the device thread. All fields of the snapshot are consistent.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to get the state snapshot of.
+    ///
+    /// # Returns
+    ///
+    /// The current state snapshot of the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found.
+    fn get_state_snapshot(&self, thread_id: u32) -> Result<Arc<DeviceState>>;
+
     /// Get the shared state of a device thread.
     ///
     /// # Parameters
@@ -185,6 +227,130 @@ pub trait ThreadController {
     /// Returns an error if the thread is not found.
     fn get_shared_state(&self, thread_id: u32) -> Result<Arc<SharedDeviceState>>;
 
+    /// Get the sync timing statistics of a device thread.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to get the statistics of.
+    ///
+    /// # Returns
+    ///
+    /// A snapshot of sync duration, period, jitter, group read times,
+    /// command latency and error counts over the rolling window.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found.
+    fn get_sync_stats(&self, thread_id: u32) -> Result<SyncStatsSnapshot>;
+
+    /// Reset the sync timing statistics of a device thread.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to reset the statistics of.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found.
+    fn reset_sync_stats(&self, thread_id: u32) -> Result<()>;
+
+    /// Enable value history recording for a channel of a device thread.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to record history for.
+    /// * `channel` - The pin or encoder value to record.
+    /// * `depth` - The maximum number of samples to keep.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found.
+    fn enable_history(&self, thread_id: u32, channel: HistoryChannel, depth: usize) -> Result<()>;
+
+    /// Disable value history recording for a channel of a device thread.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to stop recording history for.
+    /// * `channel` - The pin or encoder value to stop recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found.
+    fn disable_history(&self, thread_id: u32, channel: HistoryChannel) -> Result<()>;
+
+    /// Get the recorded value history of a channel.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to get the history of.
+    /// * `channel` - The pin or encoder value to get the history of.
+    /// * `since` - How far back to look.
+    ///
+    /// # Returns
+    ///
+    /// The samples recorded within `since` of now, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found.
+    fn get_history(
+        &self,
+        thread_id: u32,
+        channel: HistoryChannel,
+        since: Duration,
+    ) -> Result<Vec<HistorySample>>;
+
+    /// Start recording sampled I/O of one or more device threads to files.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_ids` - The IDs of the threads to record.
+    /// * `config` - The recorder configuration.
+    ///
+    /// # Returns
+    ///
+    /// A handle that stops the recorder when stopped or dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a thread is not found, the configuration is
+    /// invalid or the output directory cannot be created.
+    fn start_recorder(&self, thread_ids: &[u32], config: RecorderConfig) -> Result<DataRecorder>;
+
+    /// Arm a triggered capture on a device thread.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to capture from.
+    /// * `config` - The channels, trigger condition and pre/post sample counts.
+    ///
+    /// # Returns
+    ///
+    /// A handle that delivers the capture once the trigger fired and the
+    /// post-trigger samples were collected. Dropping it disarms the capture.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or the configuration is invalid.
+    fn arm_capture(&self, thread_id: u32, config: CaptureConfig) -> Result<CaptureHandle>;
+
+    /// Record the state changes and commands of a device thread to a file.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to record.
+    /// * `path` - The file to write.
+    ///
+    /// # Returns
+    ///
+    /// A handle that stops the recording when stopped or dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or the file cannot be created.
+    fn record_session(&self, thread_id: u32, path: &Path) -> Result<SessionRecorder>;
+
     /// Create a state observer for a device thread.
     ///
     /// # Parameters
@@ -328,6 +494,10 @@ pub struct ThreadControllerImpl {
     next_thread_id: u32,
     /// Default refresh interval in milliseconds
     default_refresh_interval: u64,
+    /// Default adaptive refresh configuration
+    default_adaptive_refresh: Option<AdaptiveRefreshConfig>,
+    /// Default state persistence configuration
+    default_persistence: Option<PersistenceConfig>,
     /// Logger
     logger: Option<Arc<dyn Logger>>,
     /// Model monitors
@@ -351,6 +521,8 @@ impl ThreadControllerImpl {
             threads: HashMap::new(),
             next_thread_id: 1,
             default_refresh_interval: 100, // Default refresh interval: 100ms
+            default_adaptive_refresh: None,
+            default_persistence: None,
             logger: None,
             model_monitors: HashMap::new(),
         }
@@ -370,6 +542,8 @@ impl ThreadControllerImpl {
             threads: HashMap::new(),
             next_thread_id: 1,
             default_refresh_interval: 100,
+            default_adaptive_refresh: None,
+            default_persistence: None,
             logger: Some(logger),
             model_monitors: HashMap::new(),
         }
@@ -384,6 +558,24 @@ impl ThreadControllerImpl {
         self.default_refresh_interval = interval_ms;
     }
 
+    /// Set the default adaptive refresh configuration.
+    ///
+    /// # Parameters
+    ///
+    /// * `config` - The adaptive refresh configuration, or None for a fixed interval.
+    pub fn set_default_adaptive_refresh(&mut self, config: Option<AdaptiveRefreshConfig>) {
+        self.default_adaptive_refresh = config;
+    }
+
+    /// Set the default state persistence configuration.
+    ///
+    /// # Parameters
+    ///
+    /// * `config` - The persistence configuration, or None to disable persistence.
+    pub fn set_default_persistence(&mut self, config: Option<PersistenceConfig>) {
+        self.default_persistence = config;
+    }
+
     /// Set the logger.
     ///
     /// # Parameters
@@ -508,6 +700,16 @@ impl ThreadController for ThreadControllerImpl {
             builder = builder.with_logger(thread_logger);
         }
 
+        // Enable adaptive refresh if configured
+        if let Some(config) = self.default_adaptive_refresh {
+            builder = builder.adaptive_refresh(config);
+        }
+
+        // Enable state persistence if configured
+        if let Some(config) = &self.default_persistence {
+            builder = builder.persistence(config.clone());
+        }
+
         let worker = builder.build_usb_device(device_index)?;
 
         // Store the worker
@@ -547,6 +749,16 @@ impl ThreadController for ThreadControllerImpl {
             builder = builder.with_logger(thread_logger);
         }
 
+        // Enable adaptive refresh if configured
+        if let Some(config) = self.default_adaptive_refresh {
+            builder = builder.adaptive_refresh(config);
+        }
+
+        // Enable state persistence if configured
+        if let Some(config) = &self.default_persistence {
+            builder = builder.persistence(config.clone());
+        }
+
         let worker = builder.build_network_device(device_summary)?;
 
         // Store the worker
@@ -588,6 +800,16 @@ impl ThreadController for ThreadControllerImpl {
             builder = builder.with_logger(thread_logger);
         }
 
+        // Enable adaptive refresh if configured
+        if let Some(config) = self.default_adaptive_refresh {
+            builder = builder.adaptive_refresh(config);
+        }
+
+        // Enable state persistence if configured
+        if let Some(config) = &self.default_persistence {
+            builder = builder.persistence(config.clone());
+        }
+
         let worker = builder.build_device_by_serial(serial_number, check_network, timeout_ms)?;
 
         // Store the worker
@@ -611,6 +833,10 @@ impl ThreadController for ThreadControllerImpl {
             &format!("Sending command {command:?} to thread {thread_id}"),
         );
 
+        let shared_state = self.get_shared_state(thread_id)?;
+        shared_state
+            .resources()
+            .check_command(&command, &shared_state.pwm_channel_map())?;
         let thread = self.get_thread(thread_id)?;
         thread.send_command(command)
     }
@@ -627,11 +853,101 @@ impl ThreadController for ThreadControllerImpl {
         Ok(shared_state.read(|state| state.clone()))
     }
 
+    fn get_state_snapshot(&self, thread_id: u32) -> Result<Arc<DeviceState>> {
+        let thread = self.get_thread(thread_id)?;
+        Ok(thread.shared_state().snapshot())
+    }
+
     fn get_shared_state(&self, thread_id: u32) -> Result<Arc<SharedDeviceState>> {
         let thread = self.get_thread(thread_id)?;
         Ok(thread.shared_state())
     }
 
+    fn get_sync_stats(&self, thread_id: u32) -> Result<SyncStatsSnapshot> {
+        let thread = self.get_thread(thread_id)?;
+        Ok(thread.shared_state().sync_stats().snapshot())
+    }
+
+    fn reset_sync_stats(&self, thread_id: u32) -> Result<()> {
+        self.log(
+            log::Level::Debug,
+            &format!("Resetting sync statistics for thread {thread_id}"),
+        );
+
+        let thread = self.get_thread(thread_id)?;
+        thread.shared_state().sync_stats().reset();
+        Ok(())
+    }
+
+    fn enable_history(&self, thread_id: u32, channel: HistoryChannel, depth: usize) -> Result<()> {
+        self.log(
+            log::Level::Debug,
+            &format!("Enabling history for {channel:?} on thread {thread_id} with depth {depth}"),
+        );
+
+        let thread = self.get_thread(thread_id)?;
+        thread.shared_state().history().enable(channel, depth);
+        Ok(())
+    }
+
+    fn disable_history(&self, thread_id: u32, channel: HistoryChannel) -> Result<()> {
+        let thread = self.get_thread(thread_id)?;
+        thread.shared_state().history().disable(channel);
+        Ok(())
+    }
+
+    fn get_history(
+        &self,
+        thread_id: u32,
+        channel: HistoryChannel,
+        since: Duration,
+    ) -> Result<Vec<HistorySample>> {
+        let thread = self.get_thread(thread_id)?;
+        Ok(thread.shared_state().history().history(channel, since))
+    }
+
+    fn start_recorder(&self, thread_ids: &[u32], config: RecorderConfig) -> Result<DataRecorder> {
+        self.log(
+            log::Level::Info,
+            &format!(
+                "Starting recorder for threads {thread_ids:?} in {}",
+                config.directory.display()
+            ),
+        );
+
+        let sources = thread_ids
+            .iter()
+            .map(|&thread_id| Ok((thread_id, self.get_thread(thread_id)?.shared_state())))
+            .collect::<Result<Vec<_>>>()?;
+        DataRecorder::start(config, sources)
+    }
+
+    fn arm_capture(&self, thread_id: u32, config: CaptureConfig) -> Result<CaptureHandle> {
+        self.log(
+            log::Level::Debug,
+            &format!(
+                "Arming capture on thread {thread_id} for {:?}",
+                config.trigger
+            ),
+        );
+
+        let thread = self.get_thread(thread_id)?;
+        thread.shared_state().captures().arm(config)
+    }
+
+    fn record_session(&self, thread_id: u32, path: &Path) -> Result<SessionRecorder> {
+        self.log(
+            log::Level::Info,
+            &format!(
+                "Recording session of thread {thread_id} to {}",
+                path.display()
+            ),
+        );
+
+        let thread = self.get_thread(thread_id)?;
+        SessionRecorder::start(thread_id, thread.shared_state(), path)
+    }
+
     fn create_observer(&self, thread_id: u32) -> Result<StateObserver> {
         let thread = self.get_thread(thread_id)?;
         let shared_state = thread.shared_state();
@@ -889,11 +1205,7 @@ impl DeviceOperations for ThreadControllerImpl {
     }
 
     fn set_pwm_duty_cycle(&self, thread_id: u32, channel: usize, duty: u32) -> Result<()> {
-        self.log(
-            log::Level::Debug,
-            &format!("Setting PWM channel {channel} duty to {duty} on thread {thread_id}"),
-        );
-        self.send_command(thread_id, DeviceCommand::SetPwmDuty { channel, duty })
+        self.set_pwm_duty(thread_id, channel, PwmDuty::Ticks(duty))
     }
 
     fn set_pwm_duty_cycle_percent(
@@ -902,14 +1214,57 @@ impl DeviceOperations for ThreadControllerImpl {
         channel: usize,
         duty_percent: f32,
     ) -> Result<()> {
+        // Without a configured period, scale to 12 bits as earlier versions did
+        let period = match self.get_shared_state(thread_id)?.get_pwm_period() {
+            0 => LEGACY_PWM_DUTY_SCALE,
+            period => period,
+        };
+        let duty = PwmDuty::Percent(duty_percent).ticks(period)?;
+        self.set_pwm_duty(thread_id, channel, PwmDuty::Ticks(duty))
+    }
+
+    fn set_pwm_duty(&self, thread_id: u32, channel: usize, duty: PwmDuty) -> Result<()> {
         self.log(
             log::Level::Debug,
-            &format!("Setting PWM channel {channel} duty to {duty_percent}% on thread {thread_id}"),
+            &format!("Setting PWM channel {channel} duty to {duty:?} on thread {thread_id}"),
         );
+        let command = self
+            .get_shared_state(thread_id)?
+            .pwm_duty_command(channel, duty)?;
+        self.send_command(thread_id, command)
+    }
+
+    fn configure_pwm(&self, thread_id: u32, config: PwmConfig) -> Result<()> {
+        self.log(
+            log::Level::Info,
+            &format!("Configuring PWM on thread {thread_id}: {config:?}"),
+        );
+        let shared_state = self.get_shared_state(thread_id)?;
+        config.validate(&shared_state.pwm_channel_map())?;
+        self.send_command(thread_id, DeviceCommand::ConfigurePwm { config })
+    }
 
-        // Convert percentage to raw duty cycle value (0-4095 for 12-bit)
-        let duty = ((duty_percent / 100.0) * 4095.0) as u32;
-        self.set_pwm_duty_cycle(thread_id, channel, duty)
+    fn get_pwm_channel_map(&self, thread_id: u32) -> Result<PwmChannelMap> {
+        Ok(self.get_shared_state(thread_id)?.pwm_channel_map())
+    }
+
+    fn animate_pwm(&self, thread_id: u32, channel: usize, waveform: PwmWaveform) -> Result<()> {
+        self.log(
+            log::Level::Debug,
+            &format!("Animating PWM channel {channel} with {waveform:?} on thread {thread_id}"),
+        );
+        let shared_state = self.get_shared_state(thread_id)?;
+        shared_state.pwm_channel_map().check_channel(channel)?;
+        waveform.validate(shared_state.get_pwm_period())?;
+        self.send_command(thread_id, DeviceCommand::AnimatePwm { channel, waveform })
+    }
+
+    fn cancel_pwm_animation(&self, thread_id: u32, channel: usize) -> Result<()> {
+        self.log(
+            log::Level::Debug,
+            &format!("Cancelling PWM animation on channel {channel} for thread {thread_id}"),
+        );
+        self.send_command(thread_id, DeviceCommand::CancelPwmAnimation { channel })
     }
 
     fn configure_servo(&self, thread_id: u32, pin: u8, servo_config: ServoConfig) -> Result<()> {
@@ -950,6 +1305,58 @@ impl DeviceOperations for ThreadControllerImpl {
         self.send_command(thread_id, DeviceCommand::StopServo { pin })
     }
 
+    fn get_servo_position(&self, thread_id: u32, pin: u8) -> Result<f32> {
+        let shared_state = self.get_shared_state(thread_id)?;
+        shared_state.get_servo_position(pin).ok_or_else(|| {
+            ThreadError::InvalidParameter(format!("No servo position set on pin {pin}"))
+        })
+    }
+
+    fn move_servo(&self, thread_id: u32, pin: u8, angle: f32, profile: ServoProfile) -> Result<()> {
+        self.move_servos(thread_id, &[(pin, angle)], profile)
+    }
+
+    fn move_servos(
+        &self,
+        thread_id: u32,
+        moves: &[(u8, f32)],
+        profile: ServoProfile,
+    ) -> Result<()> {
+        self.log(
+            log::Level::Debug,
+            &format!("Moving servos {moves:?} with {profile:?} on thread {thread_id}"),
+        );
+        profile.validate()?;
+        if moves.is_empty() {
+            return Err(ThreadError::InvalidParameter(
+                "No servo moves given".to_string(),
+            ));
+        }
+        for (i, (pin, _)) in moves.iter().enumerate() {
+            if moves[..i].iter().any(|(other, _)| other == pin) {
+                return Err(ThreadError::InvalidParameter(format!(
+                    "Servo pin {pin} is moved more than once"
+                )));
+            }
+        }
+
+        self.send_command(
+            thread_id,
+            DeviceCommand::MoveServos {
+                moves: moves.to_vec(),
+                profile,
+            },
+        )
+    }
+
+    fn cancel_servo_motion(&self, thread_id: u32, pin: u8) -> Result<()> {
+        self.log(
+            log::Level::Debug,
+            &format!("Cancelling servo motion on pin {pin} for thread {thread_id}"),
+        );
+        self.send_command(thread_id, DeviceCommand::CancelServoMotion { pin })
+    }
+
     fn i2c_write(&self, thread_id: u32, address: u8, data: Vec<u8>) -> Result<()> {
         self.log(
             log::Level::Debug,
@@ -969,9 +1376,7 @@ impl DeviceOperations for ThreadControllerImpl {
                 address, thread_id
             ),
         );
-        // For now, return empty vector - full implementation would need response channel
-        self.send_command(thread_id, DeviceCommand::I2cRead { address, length })?;
-        Ok(Vec::new())
+        self.device(thread_id)?.i2c(address)?.read(length)
     }
 
     fn i2c_write_read(
@@ -988,16 +1393,13 @@ impl DeviceOperations for ThreadControllerImpl {
                 address, thread_id
             ),
         );
-        // For now, return empty vector - full implementation would need response channel
-        self.send_command(
-            thread_id,
-            DeviceCommand::I2cWriteRead {
-                address,
-                write_data,
-                read_length,
-            },
-        )?;
-        Ok(Vec::new())
+        self.device(thread_id)?
+            .i2c(address)?
+            .write_read(&write_data, read_length)
+    }
+
+    fn i2c_device(&self, thread_id: u32, address: u8) -> Result<I2cDevice> {
+        self.device(thread_id)?.i2c(address)
     }
 
     fn i2c_scan(&self, thread_id: u32) -> Result<Vec<u8>> {
@@ -1005,9 +1407,17 @@ impl DeviceOperations for ThreadControllerImpl {
             log::Level::Debug,
             &format!("I2C scan on thread {}", thread_id),
         );
-        // For now, return empty vector - full implementation would need response channel
-        self.send_command(thread_id, DeviceCommand::I2cScan)?;
-        Ok(Vec::new())
+        Ok(self
+            .i2c_scan_with(thread_id, &I2cScanConfig::default())?
+            .found())
+    }
+
+    fn i2c_scan_with(&self, thread_id: u32, config: &I2cScanConfig) -> Result<I2cScanReport> {
+        self.log(
+            log::Level::Debug,
+            &format!("I2C scan with {config:?} on thread {thread_id}"),
+        );
+        self.device(thread_id)?.i2c_bus().scan(config)
     }
 
     fn configure_uspibridge(&self, thread_id: u32, config: USPIBridgeConfig) -> Result<()> {
@@ -1015,7 +1425,8 @@ impl DeviceOperations for ThreadControllerImpl {
             log::Level::Debug,
             &format!("Configuring uSPIBridge on thread {}", thread_id),
         );
-        self.send_command(thread_id, DeviceCommand::ConfigureUSPIBridge { config })
+        self.uspibridge(thread_id, DEFAULT_USPIBRIDGE_ADDRESS)?
+            .configure(&config)
     }
 
     fn uspibridge_command(&self, thread_id: u32, command: Vec<u8>) -> Result<Vec<u8>> {
@@ -1023,9 +1434,13 @@ impl DeviceOperations for ThreadControllerImpl {
             log::Level::Debug,
             &format!("Sending uSPIBridge command on thread {}", thread_id),
         );
-        // For now, return empty vector - full implementation would need response channel
-        self.send_command(thread_id, DeviceCommand::USPIBridgeCommand { command })?;
-        Ok(Vec::new())
+        let command = BridgeCommand::from_raw(&command)?;
+        self.uspibridge(thread_id, DEFAULT_USPIBRIDGE_ADDRESS)?
+            .command(command)
+    }
+
+    fn uspibridge(&self, thread_id: u32, address: u8) -> Result<USPIBridge> {
+        USPIBridge::new(self.device(thread_id)?, address)
     }
 
     fn check_pin_capability(
@@ -1034,17 +1449,32 @@ impl DeviceOperations for ThreadControllerImpl {
         pin: u8,
         capability: PinCapability,
     ) -> Result<bool> {
-        let _shared_state = self.get_shared_state(thread_id)?;
-
-        // For now, return basic capability check based on common PoKeys device capabilities
-        // Full implementation would use device model database
-        match capability {
-            PinCapability::DigitalOutput => Ok(pin <= 55), // Most pins support digital output
-            PinCapability::DigitalInput => Ok(pin <= 55),
-            PinCapability::AnalogInput => Ok(pin <= 7), // Typically pins 0-7 for analog
-            PinCapability::PwmOutput => Ok((17..=22).contains(&pin)), // PWM pins 17-22
-            _ => Ok(false),                             // Conservative default
-        }
+        let shared_state = self.get_shared_state(thread_id)?;
+        shared_state.read(|state| {
+            // Counter support is also reported by the device itself
+            let counter_available = capability == PinCapability::DigitalCounter
+                && pin > 0
+                && state
+                    .pins
+                    .get(usize::from(pin) - 1)
+                    .is_some_and(|pin_data| pin_data.digital_counter_available != 0);
+            let Some(model) = state.model.as_ref() else {
+                // Without a model, answer from what the device reports
+                return match capability {
+                    PinCapability::DigitalInput | PinCapability::DigitalOutput => {
+                        let pin_count = match state.pins.len() {
+                            0 => DEFAULT_PIN_COUNT,
+                            count => count,
+                        };
+                        Ok((1..=pin_count).contains(&usize::from(pin)))
+                    }
+                    PinCapability::PwmOutput => Ok(state.pwm_channel_map().channel(pin).is_some()),
+                    PinCapability::DigitalCounter if counter_available => Ok(true),
+                    _ => Err(no_device_model()),
+                };
+            };
+            Ok(counter_available || capabilities::pin_supports(model, pin, capability))
+        })
     }
 
     fn get_device_model(&self, thread_id: u32) -> Result<Option<String>> {
@@ -1069,29 +1499,26 @@ impl DeviceOperations for ThreadControllerImpl {
     }
 
     fn validate_pin_operation(&self, thread_id: u32, pin: u8, operation: &str) -> Result<()> {
-        let capability = match operation {
-            "digital_output" => PinCapability::DigitalOutput,
-            "digital_input" => PinCapability::DigitalInput,
-            "analog_input" => PinCapability::AnalogInput,
-            "pwm" => PinCapability::PwmOutput,
-            "servo" => PinCapability::PwmOutput, // Servos use PWM pins
-            _ => {
-                return Err(ThreadError::validation_error(
-                    "Unknown operation type",
-                    operation,
-                    Some("Use: digital_output, digital_input, analog_input, pwm, or servo"),
-                ))
-            }
-        };
+        let capability = capabilities::parse_operation(operation)?;
 
         if !self.check_pin_capability(thread_id, pin, capability)? {
+            let alternatives = self.get_shared_state(thread_id)?.read(|state| {
+                state
+                    .model
+                    .as_ref()
+                    .map(|model| capabilities::suggest_pins(model, pin, capability, 5))
+                    .unwrap_or_default()
+            });
+            let suggestion = if alternatives.is_empty() {
+                format!("This device has no pins that support {operation}")
+            } else {
+                let pins: Vec<String> = alternatives.iter().map(u8::to_string).collect();
+                format!("Pins that support {operation}: {}", pins.join(", "))
+            };
             return Err(ThreadError::pin_capability_error(
                 pin,
                 operation,
-                Some(format!(
-                    "Check device model for supported pins for {}",
-                    operation
-                )),
+                Some(suggestion),
             ));
         }
 
@@ -1122,6 +1549,13 @@ impl DeviceOperations for ThreadControllerImpl {
                 thread_id
             ),
         );
+        let shared_state = self.get_shared_state(thread_id)?;
+        let map = shared_state.pwm_channel_map();
+        let period = shared_state.get_pwm_period();
+        for &(channel, duty) in &channel_duties {
+            map.check_channel(channel)?;
+            PwmDuty::Ticks(duty).ticks(period)?;
+        }
         self.send_command(
             thread_id,
             DeviceCommand::SetPwmDutiesBulk { channel_duties },
@@ -1194,6 +1628,66 @@ impl DeviceOperations for ThreadControllerImpl {
         )
     }
 
+    fn configure_encoder_scaling(
+        &self,
+        thread_id: u32,
+        encoder_index: u32,
+        scaling: EncoderScaling,
+    ) -> Result<()> {
+        self.log(
+            log::Level::Debug,
+            &format!(
+                "Configuring encoder {encoder_index} scaling to {} counts per unit on thread {thread_id}",
+                scaling.counts_per_unit
+            ),
+        );
+        let shared_state = self.get_shared_state(thread_id)?;
+        shared_state.set_encoder_scaling(encoder_index, scaling)
+    }
+
+    fn get_encoder_motion(&self, thread_id: u32, encoder_index: u32) -> Result<EncoderMotion> {
+        let shared_state = self.get_shared_state(thread_id)?;
+        shared_state
+            .get_encoder_motion(encoder_index)
+            .ok_or_else(|| unscaled_encoder(encoder_index))
+    }
+
+    fn zero_encoder(&self, thread_id: u32, encoder_index: u32) -> Result<()> {
+        self.log(
+            log::Level::Debug,
+            &format!("Zeroing encoder {encoder_index} on thread {thread_id}"),
+        );
+        let shared_state = self.get_shared_state(thread_id)?;
+        if !shared_state.zero_encoder(encoder_index) {
+            return Err(unscaled_encoder(encoder_index));
+        }
+        Ok(())
+    }
+
+    fn preset_encoder(&self, thread_id: u32, encoder_index: u32, position: f64) -> Result<()> {
+        self.log(
+            log::Level::Debug,
+            &format!("Presetting encoder {encoder_index} to {position} on thread {thread_id}"),
+        );
+        let shared_state = self.get_shared_state(thread_id)?;
+        if !shared_state.preset_encoder(encoder_index, position) {
+            return Err(unscaled_encoder(encoder_index));
+        }
+        Ok(())
+    }
+
+    fn set_encoder_offset(&self, thread_id: u32, encoder_index: u32, offset: i64) -> Result<()> {
+        self.log(
+            log::Level::Debug,
+            &format!("Setting encoder {encoder_index} offset to {offset} on thread {thread_id}"),
+        );
+        let shared_state = self.get_shared_state(thread_id)?;
+        if !shared_state.set_encoder_offset(encoder_index, offset) {
+            return Err(unscaled_encoder(encoder_index));
+        }
+        Ok(())
+    }
+
     fn reset_digital_counter(&self, thread_id: u32, pin: u32) -> Result<()> {
         self.log(
             log::Level::Debug,
@@ -1202,6 +1696,34 @@ impl DeviceOperations for ThreadControllerImpl {
         self.send_command(thread_id, DeviceCommand::ResetDigitalCounter { pin })
     }
 
+    fn get_digital_counter(&self, thread_id: u32, pin: u32) -> Result<u32> {
+        self.log(
+            log::Level::Debug,
+            &format!("Getting digital counter pin {pin} from thread {thread_id}"),
+        );
+        let shared_state = self.get_shared_state(thread_id)?;
+        shared_state.get_digital_counter(pin).ok_or_else(|| {
+            ThreadError::InvalidParameter(format!("Pin {pin} is not a digital counter"))
+        })
+    }
+
+    fn get_counter_frequency(
+        &self,
+        thread_id: u32,
+        pin: u32,
+        window: Duration,
+    ) -> Result<Option<f64>> {
+        let shared_state = self.get_shared_state(thread_id)?;
+        let history = shared_state.history();
+        let channel = HistoryChannel::DigitalCounter(pin);
+        if !history.is_enabled(channel) {
+            return Err(ThreadError::InvalidParameter(format!(
+                "History is not enabled for digital counter pin {pin}"
+            )));
+        }
+        Ok(history.counter_frequency(channel, window))
+    }
+
     fn send_custom_request(
         &self,
         thread_id: u32,
@@ -1248,6 +1770,148 @@ impl DeviceOperations for ThreadControllerImpl {
             DeviceCommand::SetPinFunction { pin, pin_function },
         )
     }
+
+    fn claim_resources(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        resources: &[Resource],
+    ) -> Result<ResourceClaim> {
+        self.log(
+            log::Level::Debug,
+            &format!("Claiming {resources:?} for {owner} on thread {thread_id}"),
+        );
+        self.get_shared_state(thread_id)?
+            .resources()
+            .claim(owner, resources)
+    }
+
+    fn claim_pwm_channel(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        channel: usize,
+    ) -> Result<ResourceClaim> {
+        let pin = self
+            .get_shared_state(thread_id)?
+            .pwm_channel_map()
+            .check_channel(channel)?;
+        self.claim_resources(
+            thread_id,
+            owner,
+            &[Resource::PwmChannel(channel), Resource::Pin(pin)],
+        )
+    }
+
+    fn claim_encoder(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        encoder_index: u32,
+    ) -> Result<ResourceClaim> {
+        let (pin_a, pin_b) = self.get_shared_state(thread_id)?.read(|state| {
+            let model = state.model.as_ref().ok_or_else(no_device_model)?;
+            capabilities::encoder_pins(model, encoder_index).ok_or_else(|| {
+                ThreadError::validation_error(
+                    &format!("Encoder {encoder_index} has no input pins in the device model"),
+                    "encoder claim",
+                    Some("Claim the encoder pins with claim_resources"),
+                )
+            })
+        })?;
+        self.claim_resources(
+            thread_id,
+            owner,
+            &[
+                Resource::Encoder(encoder_index),
+                Resource::Pin(pin_a),
+                Resource::Pin(pin_b),
+            ],
+        )
+    }
+
+    fn send_claimed_command(
+        &self,
+        thread_id: u32,
+        claim: &ResourceClaim,
+        command: DeviceCommand,
+    ) -> Result<()> {
+        if !self.get_shared_state(thread_id)?.resources().holds(claim) {
+            return Err(ThreadError::InvalidParameter(format!(
+                "The claim of {} is not for thread {thread_id}",
+                claim.owner()
+            )));
+        }
+        self.send_command(
+            thread_id,
+            DeviceCommand::Claimed {
+                claim: claim.id(),
+                command: Box::new(command),
+            },
+        )
+    }
+
+    fn lease_outputs(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        request: &LeaseRequest,
+    ) -> Result<OutputLease> {
+        self.log(
+            log::Level::Debug,
+            &format!("Leasing {request:?} for {owner} on thread {thread_id}"),
+        );
+        let thread = self.get_thread(thread_id)?;
+        OutputLease::acquire(
+            owner,
+            request,
+            thread.shared_state(),
+            thread.command_sender().clone(),
+        )
+    }
+
+    fn lease_digital_output(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        pin: u8,
+        default: Option<bool>,
+    ) -> Result<DigitalOutputHandle> {
+        let request = LeaseRequest {
+            digital_outputs: vec![(pin, default)],
+            ..LeaseRequest::default()
+        };
+        let (mut handles, _) = self
+            .lease_outputs(thread_id, owner, &request)?
+            .into_handles();
+        Ok(handles.remove(0))
+    }
+
+    fn lease_pwm_channel(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        channel: usize,
+        default: Option<PwmDuty>,
+    ) -> Result<PwmHandle> {
+        let request = LeaseRequest {
+            pwm_channels: vec![(channel, default)],
+            ..LeaseRequest::default()
+        };
+        let (_, mut handles) = self
+            .lease_outputs(thread_id, owner, &request)?
+            .into_handles();
+        Ok(handles.remove(0))
+    }
+
+    fn device(&self, thread_id: u32) -> Result<DeviceHandle> {
+        let thread = self.get_thread(thread_id)?;
+        Ok(DeviceHandle::new(
+            thread_id,
+            thread.shared_state(),
+            thread.command_sender().clone(),
+        ))
+    }
 }
 
 impl Drop for ThreadControllerImpl {
@@ -1261,3 +1925,17 @@ impl Drop for ThreadControllerImpl {
         }
     }
 }
+
+/// Error for motion operations on an encoder without a scaling.
+fn unscaled_encoder(encoder_index: u32) -> ThreadError {
+    ThreadError::InvalidParameter(format!("Encoder {encoder_index} has no scaling configured"))
+}
+
+/// Error for capability queries on a device without a model.
+fn no_device_model() -> ThreadError {
+    ThreadError::validation_error(
+        "No device model is loaded",
+        "pin capability",
+        Some("Add a model file for the device or set one with update_device_model"),
+    )
+}
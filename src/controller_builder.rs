@@ -2,6 +2,8 @@
 
 use crate::controller::ThreadControllerImpl;
 use crate::logging::Logger;
+use crate::persistence::PersistenceConfig;
+use crate::sync::AdaptiveRefreshConfig;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -9,6 +11,10 @@ use std::sync::Arc;
 pub struct ThreadControllerBuilder {
     /// Default refresh interval in milliseconds
     default_refresh_interval: u64,
+    /// Adaptive refresh configuration for new threads
+    adaptive_refresh: Option<AdaptiveRefreshConfig>,
+    /// State persistence configuration for new threads
+    persistence: Option<PersistenceConfig>,
     /// Logger
     logger: Option<Arc<dyn Logger>>,
     /// Model directory
@@ -20,6 +26,8 @@ impl ThreadControllerBuilder {
     pub fn new() -> Self {
         Self {
             default_refresh_interval: 100, // Default refresh interval: 100ms
+            adaptive_refresh: None,
+            persistence: None,
             logger: None,
             model_dir: None,
         }
@@ -31,6 +39,18 @@ impl ThreadControllerBuilder {
         self
     }
 
+    /// Enable adaptive refresh for all device threads
+    pub fn adaptive_refresh(mut self, config: AdaptiveRefreshConfig) -> Self {
+        self.adaptive_refresh = Some(config);
+        self
+    }
+
+    /// Enable state persistence for all device threads
+    pub fn persistence(mut self, config: PersistenceConfig) -> Self {
+        self.persistence = Some(config);
+        self
+    }
+
     /// Set the logger
     pub fn with_logger(mut self, logger: Arc<dyn Logger>) -> Self {
         self.logger = Some(logger);
@@ -52,6 +72,8 @@ impl ThreadControllerBuilder {
         };
 
         controller.set_default_refresh_interval(self.default_refresh_interval);
+        controller.set_default_adaptive_refresh(self.adaptive_refresh);
+        controller.set_default_persistence(self.persistence);
         controller
     }
 }
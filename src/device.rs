@@ -0,0 +1,506 @@
+//! Typed device and pin handles
+//!
+//! A [`DeviceHandle`] binds the operations of one device thread so they can
+//! be passed around without a controller or thread ID. Its pin handles are
+//! validated against the configured pin functions when created, so a
+//! [`DigitalOutputPin`] can only exist for a pin that is set up as a digital
+//! output.
+//!
+//! ```ignore
+//! let device = controller.device(thread_id)?;
+//! let led = device.digital_output(5)?;
+//! led.toggle()?;
+//! let button = device.digital_input(6)?;
+//! println!("Button: {:?}", button.get());
+//! ```
+
+use crate::commands::{DeviceCommand, ResponseSender};
+use crate::encoder::EncoderMotion;
+use crate::error::{Result, ThreadError};
+use crate::i2c::{I2cBus, I2cDevice};
+use crate::pwm::{PwmDuty, PwmWaveform};
+use crate::resources::{ClaimId, ResourceClaim};
+use crate::state::SharedDeviceState;
+use crossbeam_channel::Sender;
+use parking_lot::Mutex;
+use pokeys_lib::io::PinData;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Operations on the device of one thread
+///
+/// Clones share the thread; the handle stays usable as long as the thread runs.
+#[derive(Clone)]
+pub struct DeviceHandle {
+    thread_id: u32,
+    shared_state: Arc<SharedDeviceState>,
+    command_tx: Sender<DeviceCommand>,
+    /// Claim the commands are sent for
+    claim: Option<ClaimId>,
+}
+
+impl DeviceHandle {
+    /// Create a handle for a device thread
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread of the device.
+    /// * `shared_state` - The state of the device.
+    /// * `command_tx` - The command sender of the device thread.
+    pub fn new(
+        thread_id: u32,
+        shared_state: Arc<SharedDeviceState>,
+        command_tx: Sender<DeviceCommand>,
+    ) -> Self {
+        Self {
+            thread_id,
+            shared_state,
+            command_tx,
+            claim: None,
+        }
+    }
+
+    /// Get a handle that sends its commands for a claim
+    ///
+    /// Pin, I2C and uSPIBridge handles created from it may use the resources
+    /// of the claim, such as an I2C bus claimed exclusively.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the claim is not on this device.
+    pub fn with_claim(&self, claim: &ResourceClaim) -> Result<Self> {
+        if !self.shared_state.resources().holds(claim) {
+            return Err(ThreadError::InvalidParameter(format!(
+                "The claim of {} is not for thread {}",
+                claim.owner(),
+                self.thread_id
+            )));
+        }
+        Ok(Self {
+            claim: Some(claim.id()),
+            ..self.clone()
+        })
+    }
+
+    /// Get the ID of the thread of the device
+    pub fn thread_id(&self) -> u32 {
+        self.thread_id
+    }
+
+    /// Get the state of the device
+    pub fn shared_state(&self) -> &Arc<SharedDeviceState> {
+        &self.shared_state
+    }
+
+    /// Send a command to the device thread
+    ///
+    /// Handles made with [`with_claim`](Self::with_claim) send the command
+    /// wrapped in [`DeviceCommand::Claimed`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command configures resources claimed by
+    /// another owner, writes outputs leased by another owner, or if the
+    /// command send fails.
+    pub fn send_command(&self, command: DeviceCommand) -> Result<()> {
+        let command = match self.claim {
+            Some(claim) if !matches!(command, DeviceCommand::Claimed { .. }) => {
+                DeviceCommand::Claimed {
+                    claim,
+                    command: Box::new(command),
+                }
+            }
+            _ => command,
+        };
+        self.shared_state
+            .resources()
+            .check_command(&command, &self.shared_state.pwm_channel_map())?;
+        self.command_tx
+            .send(command)
+            .map_err(|e| ThreadError::CommandSendFailed(e.to_string()))
+    }
+
+    /// Send a command and wait for the device thread to answer it
+    ///
+    /// # Parameters
+    ///
+    /// * `command` - Builds the command from the response channel.
+    /// * `timeout` - How long to wait for the answer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ThreadError::Timeout` if no answer arrives in time, an error
+    /// if the thread has stopped, or the error the thread answered with.
+    pub fn request<T>(
+        &self,
+        command: impl FnOnce(ResponseSender<T>) -> DeviceCommand,
+        timeout: Duration,
+    ) -> Result<T> {
+        let (response_tx, response_rx) = crossbeam_channel::bounded(1);
+        self.send_command(command(response_tx))?;
+        match response_rx.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => Err(ThreadError::Timeout),
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => Err(
+                ThreadError::ChannelReceiveError("Device thread stopped".to_string()),
+            ),
+        }
+    }
+
+    /// Get a handle to a pin configured as a digital output
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pin does not exist or is not configured as a digital output.
+    pub fn digital_output(&self, pin: u8) -> Result<DigitalOutputPin> {
+        self.check_pin_function(pin, "digital output", "DigitalOutput", |pin_data| {
+            pin_data.is_digital_output()
+        })?;
+        Ok(DigitalOutputPin {
+            pin,
+            device: self.clone(),
+            commanded: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Get a handle to a pin configured as a digital input
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pin does not exist or is not configured as a digital input.
+    pub fn digital_input(&self, pin: u8) -> Result<DigitalInputPin> {
+        self.check_pin_function(pin, "digital input", "DigitalInput", |pin_data| {
+            pin_data.is_digital_input()
+        })?;
+        Ok(DigitalInputPin {
+            pin,
+            device: self.clone(),
+        })
+    }
+
+    /// Get a handle to a pin configured as an analog input
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pin does not exist or is not configured as an analog input.
+    pub fn analog_input(&self, pin: u8) -> Result<AnalogInputPin> {
+        self.check_pin_function(pin, "analog input", "AnalogInput", |pin_data| {
+            pin_data.is_analog_input()
+        })?;
+        Ok(AnalogInputPin {
+            pin,
+            device: self.clone(),
+        })
+    }
+
+    /// Get a handle to a pin configured as an analog output
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pin does not exist or is not configured as an analog output.
+    pub fn analog_output(&self, pin: u8) -> Result<AnalogOutputPin> {
+        self.check_pin_function(pin, "analog output", "AnalogOutput", |pin_data| {
+            pin_data.is_analog_output()
+        })?;
+        Ok(AnalogOutputPin {
+            pin,
+            device: self.clone(),
+        })
+    }
+
+    /// Get a handle to an enabled PWM channel
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device does not have the channel or the channel
+    /// is not enabled.
+    pub fn pwm(&self, channel: usize) -> Result<PwmPin> {
+        let pin = self.shared_state.pwm_channel_map().check_channel(channel)?;
+        let enabled = self
+            .shared_state
+            .read(|state| state.pwm.is_channel_enabled(channel));
+        if !enabled {
+            return Err(ThreadError::validation_error(
+                &format!("PWM channel {channel} is not enabled"),
+                "PWM configuration",
+                Some("Enable the channel with configure_pwm first"),
+            ));
+        }
+        Ok(PwmPin {
+            channel,
+            pin,
+            device: self.clone(),
+        })
+    }
+
+    /// Get a handle to an encoder
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device does not have the encoder.
+    pub fn encoder(&self, index: u32) -> Result<EncoderHandle> {
+        let count = self.shared_state.read(|state| state.encoders.len());
+        if index as usize >= count {
+            return Err(ThreadError::validation_error(
+                &format!("Encoder {index} does not exist"),
+                "encoder index",
+                Some(&format!("Use encoders 0-{}", count.saturating_sub(1))),
+            ));
+        }
+        Ok(EncoderHandle {
+            index,
+            device: self.clone(),
+        })
+    }
+
+    /// Get a handle to the I2C bus
+    pub fn i2c_bus(&self) -> I2cBus {
+        I2cBus::new(self.clone())
+    }
+
+    /// Get a handle to an I2C device on the bus
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address is not a 7-bit address.
+    pub fn i2c(&self, address: u8) -> Result<I2cDevice> {
+        I2cDevice::new(self.i2c_bus(), address)
+    }
+
+    fn check_pin_function(
+        &self,
+        pin: u8,
+        description: &str,
+        function: &str,
+        configured: impl FnOnce(&PinData) -> bool,
+    ) -> Result<()> {
+        let pin_data = self.shared_state.read(|state| {
+            usize::from(pin)
+                .checked_sub(1)
+                .and_then(|index| state.pins.get(index).cloned())
+        });
+        match pin_data {
+            None => Err(ThreadError::validation_error(
+                &format!("Pin {pin} does not exist"),
+                "pin number",
+                None,
+            )),
+            Some(pin_data) if !configured(&pin_data) => Err(ThreadError::validation_error(
+                &format!("Pin {pin} is not configured as a {description}"),
+                "pin function",
+                Some(&format!(
+                    "Set the pin function to PinFunction::{function} with set_pin_function first"
+                )),
+            )),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+/// A pin configured as a digital output
+///
+/// Clones share the last value commanded through the handle.
+#[derive(Clone)]
+pub struct DigitalOutputPin {
+    pin: u8,
+    device: DeviceHandle,
+    /// Value last sent by this handle, ahead of the state until the worker applies it
+    commanded: Arc<Mutex<Option<bool>>>,
+}
+
+impl DigitalOutputPin {
+    /// Get the pin number
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// Set the output
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pin is leased by another owner or the command send fails.
+    pub fn set(&self, value: bool) -> Result<()> {
+        self.device.send_command(DeviceCommand::SetDigitalOutput {
+            pin: u32::from(self.pin),
+            value,
+        })?;
+        *self.commanded.lock() = Some(value);
+        Ok(())
+    }
+
+    /// Invert the last value written to the output
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pin is leased by another owner or the command send fails.
+    pub fn toggle(&self) -> Result<()> {
+        self.set(!self.get().unwrap_or(false))
+    }
+
+    /// Get the last value written to the output
+    ///
+    /// This is the value last set through the handle, or the value in the
+    /// device state if the handle has not set one.
+    pub fn get(&self) -> Option<bool> {
+        self.commanded.lock().or_else(|| {
+            self.device
+                .shared_state
+                .get_digital_output(u32::from(self.pin))
+        })
+    }
+}
+
+/// A pin configured as a digital input
+#[derive(Clone)]
+pub struct DigitalInputPin {
+    pin: u8,
+    device: DeviceHandle,
+}
+
+impl DigitalInputPin {
+    /// Get the pin number
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// Get the input value
+    pub fn get(&self) -> Option<bool> {
+        self.device
+            .shared_state
+            .get_digital_input(u32::from(self.pin))
+    }
+}
+
+/// A pin configured as an analog input
+#[derive(Clone)]
+pub struct AnalogInputPin {
+    pin: u8,
+    device: DeviceHandle,
+}
+
+impl AnalogInputPin {
+    /// Get the pin number
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// Get the raw input value
+    pub fn get(&self) -> Option<u32> {
+        self.device
+            .shared_state
+            .get_analog_input(u32::from(self.pin))
+    }
+}
+
+/// A pin configured as an analog output
+#[derive(Clone)]
+pub struct AnalogOutputPin {
+    pin: u8,
+    device: DeviceHandle,
+}
+
+impl AnalogOutputPin {
+    /// Get the pin number
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// Set the raw output value
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pin is leased by another owner or the command send fails.
+    pub fn set(&self, value: u32) -> Result<()> {
+        self.device.send_command(DeviceCommand::SetAnalogOutput {
+            pin: u32::from(self.pin),
+            value,
+        })
+    }
+}
+
+/// An enabled PWM channel
+#[derive(Clone)]
+pub struct PwmPin {
+    channel: usize,
+    pin: u8,
+    device: DeviceHandle,
+}
+
+impl PwmPin {
+    /// Get the PWM channel
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// Get the pin of the channel
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// Set the duty, cancelling any animation of the channel
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the duty does not fit in the PWM period, the
+    /// channel is leased by another owner or the command send fails.
+    pub fn set_duty(&self, duty: PwmDuty) -> Result<()> {
+        let command = self
+            .device
+            .shared_state
+            .pwm_duty_command(self.channel, duty)?;
+        self.device.send_command(command)
+    }
+
+    /// Run a fade, ramp, waveform or step sequence on the channel
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the waveform is invalid, the channel is leased by
+    /// another owner or the command send fails.
+    pub fn animate(&self, waveform: PwmWaveform) -> Result<()> {
+        waveform.validate(self.device.shared_state.get_pwm_period())?;
+        self.device.send_command(DeviceCommand::AnimatePwm {
+            channel: self.channel,
+            waveform,
+        })
+    }
+
+    /// Get the current duty in PWM clock ticks
+    pub fn duty(&self) -> Option<u32> {
+        self.device.shared_state.get_pwm_duty_cycle(self.channel)
+    }
+
+    /// Get the PWM period in clock ticks
+    pub fn period(&self) -> u32 {
+        self.device.shared_state.get_pwm_period()
+    }
+}
+
+/// An encoder of the device
+#[derive(Clone)]
+pub struct EncoderHandle {
+    index: u32,
+    device: DeviceHandle,
+}
+
+impl EncoderHandle {
+    /// Get the encoder index
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Get the raw encoder count
+    pub fn value(&self) -> Option<i32> {
+        self.device.shared_state.get_encoder_value(self.index)
+    }
+
+    /// Get the position, velocity and direction, if scaling is configured
+    pub fn motion(&self) -> Option<EncoderMotion> {
+        self.device.shared_state.get_encoder_motion(self.index)
+    }
+
+    /// Set the current position as zero, returning false without scaling
+    pub fn zero(&self) -> bool {
+        self.device.shared_state.zero_encoder(self.index)
+    }
+}
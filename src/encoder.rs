@@ -0,0 +1,262 @@
+//! Encoder position and velocity tracking
+//!
+//! Encoders with a scaling configured get a derived position in user units,
+//! a velocity, an optional RPM and a direction, computed by the sync loop from
+//! the raw counts. Counts are unwrapped across i32 overflow, and positions are
+//! relative to a software offset that can be zeroed or preset without touching
+//! the device counter.
+
+use crate::error::{Result, ThreadError};
+use crate::state::{DeviceState, StateChangeType};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Scaling of an encoder's raw counts to user units
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EncoderScaling {
+    /// Counts per unit (e.g. counts per mm or per degree)
+    pub counts_per_unit: f64,
+    /// Counts per revolution, used to compute RPM
+    pub counts_per_revolution: Option<f64>,
+    /// Time window the velocity is averaged over
+    pub velocity_window: Duration,
+    /// Smallest velocity change that is reported, in units per second
+    ///
+    /// None reports changes of at least one count per velocity window.
+    #[serde(default)]
+    pub velocity_threshold: Option<f64>,
+}
+
+impl EncoderScaling {
+    /// Create a scaling with the given counts per unit
+    pub fn new(counts_per_unit: f64) -> Self {
+        Self {
+            counts_per_unit,
+            ..Self::default()
+        }
+    }
+
+    /// Set the counts per revolution, enabling RPM
+    pub fn counts_per_revolution(mut self, counts_per_revolution: f64) -> Self {
+        self.counts_per_revolution = Some(counts_per_revolution);
+        self
+    }
+
+    /// Set the velocity averaging window
+    pub fn velocity_window(mut self, velocity_window: Duration) -> Self {
+        self.velocity_window = velocity_window;
+        self
+    }
+
+    /// Set the smallest velocity change that is reported, in units per second
+    pub fn velocity_threshold(mut self, velocity_threshold: f64) -> Self {
+        self.velocity_threshold = Some(velocity_threshold);
+        self
+    }
+
+    /// Get the smallest velocity change that is reported, in units per second
+    pub fn velocity_change_threshold(&self) -> f64 {
+        self.velocity_threshold.unwrap_or_else(|| {
+            1.0 / (self.counts_per_unit.abs() * self.velocity_window.as_secs_f64())
+        })
+    }
+
+    /// Validate the scaling
+    pub fn validate(&self) -> Result<()> {
+        if !self.counts_per_unit.is_finite() || self.counts_per_unit == 0.0 {
+            return Err(ThreadError::ConfigurationError(
+                "Encoder counts per unit must be finite and non-zero".to_string(),
+            ));
+        }
+        if let Some(counts_per_revolution) = self.counts_per_revolution {
+            if !counts_per_revolution.is_finite() || counts_per_revolution <= 0.0 {
+                return Err(ThreadError::ConfigurationError(
+                    "Encoder counts per revolution must be greater than zero".to_string(),
+                ));
+            }
+        }
+        if self.velocity_window.is_zero() {
+            return Err(ThreadError::ConfigurationError(
+                "Encoder velocity window must be greater than zero".to_string(),
+            ));
+        }
+        if let Some(velocity_threshold) = self.velocity_threshold {
+            if !velocity_threshold.is_finite() || velocity_threshold < 0.0 {
+                return Err(ThreadError::ConfigurationError(
+                    "Encoder velocity threshold must be finite and not negative".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Convert a velocity in units per second to RPM
+    pub fn rpm(&self, velocity: f64) -> Option<f64> {
+        self.counts_per_revolution.map(|counts_per_revolution| {
+            velocity * self.counts_per_unit * 60.0 / counts_per_revolution
+        })
+    }
+}
+
+impl Default for EncoderScaling {
+    fn default() -> Self {
+        Self {
+            counts_per_unit: 1.0,
+            counts_per_revolution: None,
+            velocity_window: Duration::from_millis(100),
+            velocity_threshold: None,
+        }
+    }
+}
+
+/// Direction of encoder motion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EncoderDirection {
+    /// Not moving
+    #[default]
+    Stopped,
+    /// Counting up
+    Forward,
+    /// Counting down
+    Reverse,
+}
+
+impl EncoderDirection {
+    /// Get the direction of a velocity
+    pub fn of(velocity: f64) -> Self {
+        if velocity > 0.0 {
+            Self::Forward
+        } else if velocity < 0.0 {
+            Self::Reverse
+        } else {
+            Self::Stopped
+        }
+    }
+}
+
+/// Derived motion values of a scaled encoder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct EncoderMotion {
+    /// Scaling applied to the raw counts
+    pub scaling: EncoderScaling,
+    /// Unwrapped count that corresponds to position zero
+    pub offset: i64,
+    /// Raw count unwrapped across i32 overflow
+    #[serde(default)]
+    pub count: i64,
+    /// Last raw count seen, used to unwrap the count
+    #[serde(default)]
+    pub last_raw: i32,
+    /// Position in units relative to the offset
+    pub position: f64,
+    /// Velocity in units per second
+    pub velocity: f64,
+    /// Revolutions per minute, if counts per revolution is configured
+    pub rpm: Option<f64>,
+    /// Direction of motion
+    pub direction: EncoderDirection,
+}
+
+impl EncoderMotion {
+    /// Create motion values for a scaling, with zero at raw count zero
+    pub fn new(scaling: EncoderScaling) -> Self {
+        Self {
+            scaling,
+            ..Self::default()
+        }
+    }
+
+    /// Unwrap a raw count and recompute the position from it
+    pub fn update_position(&mut self, raw: i32) {
+        self.count += i64::from(raw.wrapping_sub(self.last_raw));
+        self.last_raw = raw;
+        self.position = (self.count - self.offset) as f64 / self.scaling.counts_per_unit;
+    }
+
+    /// Set the velocity and the values derived from it
+    pub fn set_velocity(&mut self, velocity: f64) {
+        self.velocity = velocity;
+        self.rpm = self.scaling.rpm(velocity);
+        self.direction = EncoderDirection::of(velocity);
+    }
+
+    /// Set the offset so that a raw count reads as `position`
+    pub fn preset(&mut self, raw: i32, position: f64) {
+        self.update_position(raw);
+        self.offset = self.count - (position * self.scaling.counts_per_unit).round() as i64;
+        self.update_position(raw);
+    }
+}
+
+/// Unwrapped counts seen for one encoder in the velocity window
+struct TrackedEncoder {
+    samples: VecDeque<(Instant, i64)>,
+}
+
+/// Computes encoder motion values from successive syncs
+#[derive(Default)]
+pub(crate) struct EncoderTracker {
+    encoders: HashMap<u32, TrackedEncoder>,
+}
+
+impl EncoderTracker {
+    /// Update the motion values of every scaled encoder in a state
+    ///
+    /// Reports an `EncoderVelocity` change for each encoder whose velocity
+    /// changed by at least the threshold of its scaling, or stopped.
+    pub(crate) fn update(
+        &mut self,
+        state: &mut DeviceState,
+        now: Instant,
+        on_change: &mut impl FnMut(StateChangeType),
+    ) {
+        self.encoders
+            .retain(|index, _| state.encoder_motion.contains_key(index));
+
+        for (&index, motion) in state.encoder_motion.iter_mut() {
+            let Some(raw) = state
+                .encoders
+                .get(index as usize)
+                .map(|encoder| encoder.encoder_value)
+            else {
+                continue;
+            };
+
+            motion.update_position(raw);
+            let tracked = self
+                .encoders
+                .entry(index)
+                .or_insert_with(|| TrackedEncoder {
+                    samples: VecDeque::new(),
+                });
+            tracked.samples.push_back((now, motion.count));
+            while tracked.samples.len() > 2
+                && now.duration_since(tracked.samples[1].0) >= motion.scaling.velocity_window
+            {
+                tracked.samples.pop_front();
+            }
+
+            let velocity = match (tracked.samples.front(), tracked.samples.back()) {
+                (Some(&(first_time, first_count)), Some(&(last_time, last_count)))
+                    if last_time > first_time =>
+                {
+                    let seconds = last_time.duration_since(first_time).as_secs_f64();
+                    (last_count - first_count) as f64 / seconds / motion.scaling.counts_per_unit
+                }
+                _ => 0.0,
+            };
+
+            let change = (velocity - motion.velocity).abs();
+            if velocity != motion.velocity
+                && (velocity == 0.0 || change >= motion.scaling.velocity_change_threshold())
+            {
+                motion.set_velocity(velocity);
+                on_change(StateChangeType::EncoderVelocity {
+                    index,
+                    velocity: velocity.into(),
+                });
+            }
+        }
+    }
+}
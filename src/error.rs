@@ -94,6 +94,9 @@ pub enum ThreadError {
         recovery_suggestion: Option<String>,
     },
 
+    #[error("No I2C device acknowledged address 0x{address:02X}")]
+    I2cNack { address: u8 },
+
     #[error("Resource conflict: {message}")]
     ResourceConflict {
         message: String,
@@ -0,0 +1,165 @@
+//! embedded-hal implementations for device handles
+//!
+//! With the `embedded-hal` feature, the pin, PWM and I2C handles of a
+//! [`DeviceHandle`](crate::DeviceHandle) implement the embedded-hal 1.0
+//! traits, so drivers written against them can run on PoKeys devices:
+//!
+//! - `OutputPin` and `StatefulOutputPin` for [`DigitalOutputPin`]
+//! - `InputPin` for [`DigitalInputPin`]
+//! - `SetDutyCycle` for [`PwmPin`]
+//! - `I2c` with 7-bit addresses for [`I2cBus`]
+//!
+//! ```ignore
+//! let device = controller.device(thread_id)?;
+//! let mut sensor = SomeDriver::new(device.i2c_bus());
+//! ```
+//!
+//! I2C transactions are split into transfers of a write followed by a read:
+//! consecutive writes are sent as one write, and each read is combined with
+//! the writes before it. The worker performs the write and the read of a
+//! transfer as separate bus transactions, each ending with a stop, so
+//! devices that need a repeated start between them are not supported.
+
+use crate::device::{DigitalInputPin, DigitalOutputPin, PwmPin};
+use crate::error::ThreadError;
+use crate::i2c::I2cBus;
+use crate::pwm::PwmDuty;
+use embedded_hal::{digital, i2c, pwm};
+
+impl digital::Error for ThreadError {
+    fn kind(&self) -> digital::ErrorKind {
+        digital::ErrorKind::Other
+    }
+}
+
+impl pwm::Error for ThreadError {
+    fn kind(&self) -> pwm::ErrorKind {
+        pwm::ErrorKind::Other
+    }
+}
+
+impl i2c::Error for ThreadError {
+    fn kind(&self) -> i2c::ErrorKind {
+        match self {
+            ThreadError::I2cNack { .. } => {
+                i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Address)
+            }
+            _ => i2c::ErrorKind::Other,
+        }
+    }
+}
+
+/// Error for a pin missing from the device state
+fn pin_not_in_state(pin: u8) -> ThreadError {
+    ThreadError::StateError(format!("Pin {pin} is not in the device state"))
+}
+
+impl digital::ErrorType for DigitalOutputPin {
+    type Error = ThreadError;
+}
+
+impl digital::OutputPin for DigitalOutputPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set(false)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set(true)
+    }
+}
+
+impl digital::StatefulOutputPin for DigitalOutputPin {
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        self.get().ok_or_else(|| pin_not_in_state(self.pin()))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_set_high().map(|high| !high)
+    }
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        DigitalOutputPin::toggle(self)
+    }
+}
+
+impl digital::ErrorType for DigitalInputPin {
+    type Error = ThreadError;
+}
+
+impl digital::InputPin for DigitalInputPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.get().ok_or_else(|| pin_not_in_state(self.pin()))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+impl pwm::ErrorType for PwmPin {
+    type Error = ThreadError;
+}
+
+impl pwm::SetDutyCycle for PwmPin {
+    /// The PWM period in ticks, limited to `u16::MAX`
+    ///
+    /// Without a configured period this is 1, but setting a duty fails.
+    fn max_duty_cycle(&self) -> u16 {
+        u16::try_from(self.period().max(1)).unwrap_or(u16::MAX)
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
+        let period = self.period();
+        if period == 0 {
+            return Err(ThreadError::validation_error(
+                "PWM period is not configured",
+                &format!("Setting the duty cycle of PWM channel {}", self.channel()),
+                Some("Configure the PWM period before setting duty cycles"),
+            ));
+        }
+        let max = u64::from(self.max_duty_cycle());
+        let ticks = (u64::from(duty) * u64::from(period) + max / 2) / max;
+        self.set_duty(PwmDuty::Ticks(ticks as u32))
+    }
+}
+
+impl i2c::ErrorType for I2cBus {
+    type Error = ThreadError;
+}
+
+impl i2c::I2c for I2cBus {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let mut pending = Vec::new();
+        for operation in operations {
+            match operation {
+                i2c::Operation::Write(bytes) => pending.extend_from_slice(bytes),
+                i2c::Operation::Read(buffer) => {
+                    let length = u8::try_from(buffer.len()).map_err(|_| {
+                        ThreadError::InvalidParameter(format!(
+                            "I2C read of {} bytes exceeds 255 bytes",
+                            buffer.len()
+                        ))
+                    })?;
+                    let data = self.transfer(address, &pending, length)?;
+                    if data.len() != buffer.len() {
+                        return Err(ThreadError::OperationFailed(format!(
+                            "I2C read returned {} of {} bytes",
+                            data.len(),
+                            buffer.len()
+                        )));
+                    }
+                    buffer.copy_from_slice(&data);
+                    pending.clear();
+                }
+            }
+        }
+        if !pending.is_empty() {
+            self.transfer(address, &pending, 0)?;
+        }
+        Ok(())
+    }
+}
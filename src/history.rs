@@ -0,0 +1,364 @@
+//! Value history
+//!
+//! Optional ring buffers of recent pin, counter, encoder and PWM values, filled by the sync
+//! loop on every cycle. Each channel has its own configurable depth and can be
+//! queried for recent samples, window statistics and rate of change.
+//!
+//! Samples carry a wall clock timestamp, but windows and rates are computed
+//! from a monotonic clock, so stepping the system clock does not reorder or
+//! drop samples.
+
+use crate::state::DeviceState;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// A value that can be recorded in the history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum HistoryChannel {
+    /// Digital input value of a pin (0 or 1)
+    DigitalInput(u32),
+    /// Digital output value of a pin (0 or 1)
+    DigitalOutput(u32),
+    /// Analog input value of a pin
+    AnalogInput(u32),
+    /// Encoder count
+    Encoder(u32),
+    /// PWM duty cycle of a channel
+    PwmDuty(u32),
+    /// Digital counter value of a pin
+    DigitalCounter(u32),
+}
+
+impl HistoryChannel {
+    /// Read the current value of the channel from a device state
+    pub fn value_in(&self, state: &DeviceState) -> Option<i64> {
+        match *self {
+            Self::DigitalInput(pin) => state.get_digital_input(pin).map(i64::from),
+            Self::DigitalOutput(pin) => pin
+                .checked_sub(1)
+                .and_then(|index| state.pins.get(index as usize))
+                .map(|pin| i64::from(pin.digital_value_set != 0)),
+            Self::AnalogInput(pin) => state.get_analog_input(pin).map(i64::from),
+            Self::Encoder(index) => state.get_encoder_value(index).map(i64::from),
+            Self::PwmDuty(channel) => state.get_pwm_duty_cycle(channel as usize).map(i64::from),
+            Self::DigitalCounter(pin) => state.get_digital_counter(pin).map(i64::from),
+        }
+    }
+}
+
+impl fmt::Display for HistoryChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DigitalInput(pin) => write!(f, "DI{pin}"),
+            Self::DigitalOutput(pin) => write!(f, "DO{pin}"),
+            Self::AnalogInput(pin) => write!(f, "AI{pin}"),
+            Self::Encoder(index) => write!(f, "ENC{index}"),
+            Self::PwmDuty(channel) => write!(f, "PWM{channel}"),
+            Self::DigitalCounter(pin) => write!(f, "CNT{pin}"),
+        }
+    }
+}
+
+/// A recorded value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistorySample {
+    /// Timestamp in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Recorded value
+    pub value: i64,
+}
+
+/// Statistics over a window of samples
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowStats {
+    /// Number of samples in the window
+    pub samples: usize,
+    /// Minimum value
+    pub min: i64,
+    /// Maximum value
+    pub max: i64,
+    /// Mean value
+    pub mean: f64,
+}
+
+/// A sample with the monotonic time it was taken at
+#[derive(Clone, Copy)]
+struct TimedSample {
+    /// Milliseconds since the history was created, may be negative
+    at: i64,
+    sample: HistorySample,
+}
+
+/// Ring buffer of samples for one channel
+struct ChannelHistory {
+    samples: VecDeque<TimedSample>,
+    depth: usize,
+}
+
+impl ChannelHistory {
+    fn new(depth: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(depth),
+            depth,
+        }
+    }
+
+    fn push(&mut self, sample: TimedSample) {
+        if self.samples.len() == self.depth {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn since(&self, cutoff: i64) -> impl Iterator<Item = &TimedSample> {
+        // Samples are in time order, so skip the ones before the cutoff
+        let start = self.samples.partition_point(|sample| sample.at < cutoff);
+        self.samples.range(start..)
+    }
+}
+
+/// Monotonic clock anchored to the wall clock at creation
+struct Clock {
+    created: Instant,
+    created_ms: u64,
+}
+
+impl Clock {
+    fn new() -> Self {
+        Self {
+            created: Instant::now(),
+            created_ms: now_ms(),
+        }
+    }
+
+    /// Milliseconds since creation
+    fn now(&self) -> i64 {
+        self.created.elapsed().as_millis() as i64
+    }
+
+    /// Milliseconds since creation of a wall clock timestamp
+    fn at(&self, timestamp: u64) -> i64 {
+        timestamp as i64 - self.created_ms as i64
+    }
+
+    /// Milliseconds since creation of the time `window` before now
+    fn cutoff(&self, window: Duration) -> i64 {
+        self.now()
+            .saturating_sub(window.as_millis().min(i64::MAX as u128) as i64)
+    }
+}
+
+/// Thread-safe per-channel value history
+pub struct ValueHistory {
+    channels: Mutex<HashMap<HistoryChannel, ChannelHistory>>,
+    clock: Clock,
+}
+
+impl Default for ValueHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ValueHistory {
+    /// Create an empty history with no channels enabled
+    pub fn new() -> Self {
+        Self {
+            channels: Mutex::new(HashMap::new()),
+            clock: Clock::new(),
+        }
+    }
+
+    /// Enable recording for a channel, keeping at most `depth` samples
+    ///
+    /// Re-enabling a channel with a different depth keeps the most recent samples.
+    pub fn enable(&self, channel: HistoryChannel, depth: usize) {
+        let depth = depth.max(1);
+        let mut channels = self.channels.lock();
+        let history = channels
+            .entry(channel)
+            .or_insert_with(|| ChannelHistory::new(depth));
+        history.depth = depth;
+        while history.samples.len() > depth {
+            history.samples.pop_front();
+        }
+    }
+
+    /// Disable recording for a channel and drop its samples
+    pub fn disable(&self, channel: HistoryChannel) {
+        self.channels.lock().remove(&channel);
+    }
+
+    /// Check if a channel is being recorded
+    pub fn is_enabled(&self, channel: HistoryChannel) -> bool {
+        self.channels.lock().contains_key(&channel)
+    }
+
+    /// Get the recorded channels
+    pub fn channels(&self) -> Vec<HistoryChannel> {
+        let mut channels: Vec<HistoryChannel> = self.channels.lock().keys().copied().collect();
+        channels.sort();
+        channels
+    }
+
+    /// Check if any channel is being recorded
+    pub fn is_empty(&self) -> bool {
+        self.channels.lock().is_empty()
+    }
+
+    /// Record a sample for a channel if it is enabled
+    ///
+    /// The timestamp is placed on the monotonic clock of the history relative
+    /// to the wall clock when the history was created.
+    pub fn record(&self, channel: HistoryChannel, timestamp: u64, value: i64) {
+        if let Some(history) = self.channels.lock().get_mut(&channel) {
+            history.push(TimedSample {
+                at: self.clock.at(timestamp),
+                sample: HistorySample { timestamp, value },
+            });
+        }
+    }
+
+    /// Record the current value of every enabled channel from a device state
+    ///
+    /// Samples are timestamped with the current time.
+    pub fn record_state(&self, state: &DeviceState) {
+        let at = self.clock.now();
+        let timestamp = now_ms();
+        let mut channels = self.channels.lock();
+        for (channel, history) in channels.iter_mut() {
+            if let Some(value) = channel.value_in(state) {
+                history.push(TimedSample {
+                    at,
+                    sample: HistorySample { timestamp, value },
+                });
+            }
+        }
+    }
+
+    /// Get the samples recorded within `since` of now, oldest first
+    pub fn history(&self, channel: HistoryChannel, since: Duration) -> Vec<HistorySample> {
+        let cutoff = self.clock.cutoff(since);
+        self.channels
+            .lock()
+            .get(&channel)
+            .map(|history| history.since(cutoff).map(|timed| timed.sample).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the last `count` samples, oldest first
+    pub fn last_samples(&self, channel: HistoryChannel, count: usize) -> Vec<HistorySample> {
+        self.channels
+            .lock()
+            .get(&channel)
+            .map(|history| {
+                let skip = history.samples.len().saturating_sub(count);
+                history
+                    .samples
+                    .iter()
+                    .skip(skip)
+                    .map(|timed| timed.sample)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get the minimum, maximum and mean over the samples within `window` of now
+    ///
+    /// Returns None if the channel is not recorded or has no samples in the window.
+    pub fn window_stats(&self, channel: HistoryChannel, window: Duration) -> Option<WindowStats> {
+        let cutoff = self.clock.cutoff(window);
+        let channels = self.channels.lock();
+        let history = channels.get(&channel)?;
+
+        let mut samples = 0;
+        let mut min = i64::MAX;
+        let mut max = i64::MIN;
+        let mut sum = 0.0;
+        for timed in history.since(cutoff) {
+            let value = timed.sample.value;
+            samples += 1;
+            min = min.min(value);
+            max = max.max(value);
+            sum += value as f64;
+        }
+
+        (samples > 0).then(|| WindowStats {
+            samples,
+            min,
+            max,
+            mean: sum / samples as f64,
+        })
+    }
+
+    /// Get the rate of change in units per second over the samples within `window` of now
+    ///
+    /// Computed from the first and last sample in the window. Returns None if
+    /// fewer than two samples with distinct timestamps are available.
+    pub fn rate_of_change(&self, channel: HistoryChannel, window: Duration) -> Option<f64> {
+        let cutoff = self.clock.cutoff(window);
+        let channels = self.channels.lock();
+        let history = channels.get(&channel)?;
+
+        let mut samples = history.since(cutoff);
+        let first = *samples.next()?;
+        let last = *samples.last()?;
+        if last.at == first.at {
+            return None;
+        }
+
+        let seconds = (last.at - first.at) as f64 / 1000.0;
+        Some((last.sample.value - first.sample.value) as f64 / seconds)
+    }
+
+    /// Get the count frequency in counts per second over the samples within `window` of now
+    ///
+    /// Intended for digital counter channels. Unlike [`Self::rate_of_change`],
+    /// a decreasing value is treated as a counter reset or wrap, so the counts
+    /// after it are still added up. Returns None if fewer than two samples with
+    /// distinct timestamps are available.
+    pub fn counter_frequency(&self, channel: HistoryChannel, window: Duration) -> Option<f64> {
+        let cutoff = self.clock.cutoff(window);
+        let channels = self.channels.lock();
+        let history = channels.get(&channel)?;
+
+        let mut samples = history.since(cutoff);
+        let first = *samples.next()?;
+        let mut previous = first;
+        let mut counts = 0i64;
+        for timed in samples {
+            let (value, previous_value) = (timed.sample.value, previous.sample.value);
+            counts += if value >= previous_value {
+                value - previous_value
+            } else {
+                // Counter was reset, everything since then is new counts
+                value
+            };
+            previous = *timed;
+        }
+        if previous.at == first.at {
+            return None;
+        }
+
+        let seconds = (previous.at - first.at) as f64 / 1000.0;
+        Some(counts as f64 / seconds)
+    }
+
+    /// Drop all recorded samples, keeping the enabled channels
+    pub fn clear(&self) {
+        for history in self.channels.lock().values_mut() {
+            history.samples.clear();
+        }
+    }
+}
+
+/// Current time in milliseconds since the Unix epoch
+pub(crate) fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
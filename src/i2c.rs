@@ -0,0 +1,662 @@
+//! I2C bus and device access
+//!
+//! Transfers run in the device thread through the `pokeys_lib` I2C calls and
+//! wait for their result. An [`I2cDevice`] is bound to one address and adds
+//! register access on top of raw transfers:
+//!
+//! ```ignore
+//! let sensor = controller
+//!     .i2c_device(thread_id, 0x48)?
+//!     .with_retries(3, Duration::from_millis(5));
+//! let id = sensor.read_u8(0x0F)?;
+//! let temperature = sensor.read_i16_be(0x00)?;
+//! sensor.update_bits(0x01, 0x60, 0x40)?;
+//! ```
+//!
+//! [`I2cBus::scan`] probes a range of addresses and reports which devices
+//! answered, how long each probe took and what the pattern of answers says
+//! about the wiring of the bus.
+
+use crate::commands::DeviceCommand;
+use crate::device::DeviceHandle;
+use crate::error::{Result, ThreadError};
+use log::debug;
+use pokeys_lib::I2cStatus;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Default time to wait for an I2C transfer
+pub const DEFAULT_I2C_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The I2C bus of a device
+#[derive(Clone)]
+pub struct I2cBus {
+    device: DeviceHandle,
+    timeout: Duration,
+}
+
+impl I2cBus {
+    /// Create a handle to the I2C bus of a device
+    pub fn new(device: DeviceHandle) -> Self {
+        Self {
+            device,
+            timeout: DEFAULT_I2C_TIMEOUT,
+        }
+    }
+
+    /// Set how long to wait for a transfer
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Get how long to wait for a transfer
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Write bytes, then read bytes from a device
+    ///
+    /// The write is skipped if `write_data` is empty and the read if
+    /// `read_length` is 0.
+    ///
+    /// # Parameters
+    ///
+    /// * `address` - The 7-bit address of the device.
+    /// * `write_data` - The bytes to write.
+    /// * `read_length` - The number of bytes to read.
+    ///
+    /// # Returns
+    ///
+    /// The bytes read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address is invalid, the device does not answer
+    /// or the transfer times out.
+    pub fn transfer(&self, address: u8, write_data: &[u8], read_length: u8) -> Result<Vec<u8>> {
+        check_address(address)?;
+        self.device.request(
+            |response| DeviceCommand::I2cTransfer {
+                address,
+                write_data: write_data.to_vec(),
+                read_length,
+                response,
+            },
+            self.timeout,
+        )
+    }
+
+    /// Probe addresses for devices
+    ///
+    /// Each probe may take up to the bus timeout.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration is invalid or the scan times out.
+    pub fn scan(&self, config: &I2cScanConfig) -> Result<I2cScanReport> {
+        config.validate()?;
+        let probes = config.addresses().count() as u32 * config.attempts;
+        self.device.request(
+            |response| DeviceCommand::I2cScanBus {
+                config: config.clone(),
+                response,
+            },
+            self.timeout.saturating_mul(probes.max(1)),
+        )
+    }
+}
+
+/// How a scan checks for a device at an address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum I2cProbe {
+    /// Read one byte
+    #[default]
+    ReadOne,
+    /// Write a single zero byte, for devices that do not answer reads
+    ///
+    /// This sets the register pointer of most devices to 0.
+    WriteZero,
+}
+
+/// Addresses and probe method of an I2C scan
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cScanConfig {
+    /// First address to probe
+    pub first: u8,
+    /// Last address to probe
+    pub last: u8,
+    /// How to probe each address
+    pub probe: I2cProbe,
+    /// Probes per address; more than one reveals intermittent answers
+    pub attempts: u32,
+}
+
+impl Default for I2cScanConfig {
+    /// Probe the non-reserved addresses 0x08-0x77 once with a read
+    fn default() -> Self {
+        Self {
+            first: 0x08,
+            last: 0x77,
+            probe: I2cProbe::ReadOne,
+            attempts: 1,
+        }
+    }
+}
+
+impl I2cScanConfig {
+    /// Create the default configuration
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the range of addresses to probe
+    pub fn range(mut self, first: u8, last: u8) -> Self {
+        self.first = first;
+        self.last = last;
+        self
+    }
+
+    /// Set how to probe each address
+    pub fn probe(mut self, probe: I2cProbe) -> Self {
+        self.probe = probe;
+        self
+    }
+
+    /// Set the number of probes per address
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    /// Get the addresses to probe
+    pub fn addresses(&self) -> impl Iterator<Item = u8> {
+        self.first..=self.last
+    }
+
+    /// Check the configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range is empty or not 7-bit, or there are no attempts.
+    pub fn validate(&self) -> Result<()> {
+        check_address(self.last)?;
+        if self.first > self.last {
+            return Err(ThreadError::validation_error(
+                &format!(
+                    "I2C scan range 0x{:02X}-0x{:02X} is empty",
+                    self.first, self.last
+                ),
+                "I2C scan",
+                Some("Set the first address at or below the last"),
+            ));
+        }
+        if self.attempts == 0 {
+            return Err(ThreadError::validation_error(
+                "I2C scan needs at least one attempt per address",
+                "I2C scan",
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Probe every address and collect the results
+    ///
+    /// Only [`I2cStatus::DeviceNotFound`] counts as a missing acknowledge;
+    /// other failing statuses and errors are counted as errors.
+    ///
+    /// # Parameters
+    ///
+    /// * `probe` - Probes one address, returning the I2C status of the probe.
+    pub fn run(&self, mut probe: impl FnMut(u8) -> pokeys_lib::Result<I2cStatus>) -> I2cScanReport {
+        let start = Instant::now();
+        let addresses = self
+            .addresses()
+            .map(|address| {
+                let mut result = I2cProbeResult {
+                    address,
+                    ..I2cProbeResult::default()
+                };
+                for _ in 0..self.attempts {
+                    let probe_start = Instant::now();
+                    let outcome = probe(address);
+                    let elapsed = probe_start.elapsed();
+                    result.total_time += elapsed;
+                    result.max_time = result.max_time.max(elapsed);
+                    match outcome {
+                        Ok(I2cStatus::Ok | I2cStatus::Complete) => result.acks += 1,
+                        Ok(I2cStatus::DeviceNotFound) => result.nacks += 1,
+                        Ok(I2cStatus::Timeout) => {
+                            result.timeouts += 1;
+                            result.last_error = Some("I2C timeout".to_string());
+                        }
+                        Ok(status) => {
+                            result.errors += 1;
+                            result.last_error = Some(format!("I2C status {status:?}"));
+                        }
+                        Err(e) => {
+                            result.errors += 1;
+                            result.last_error = Some(e.to_string());
+                        }
+                    }
+                }
+                result
+            })
+            .collect();
+        I2cScanReport {
+            config: self.clone(),
+            addresses,
+            duration: start.elapsed(),
+        }
+    }
+}
+
+/// Answers of one address during a scan
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cProbeResult {
+    /// The probed address
+    pub address: u8,
+    /// Probes the device acknowledged
+    pub acks: u32,
+    /// Probes no device acknowledged
+    pub nacks: u32,
+    /// Probes that timed out
+    pub timeouts: u32,
+    /// Probes that failed to reach the device
+    pub errors: u32,
+    /// Message of the last timeout or error
+    pub last_error: Option<String>,
+    /// Time spent on all probes
+    pub total_time: Duration,
+    /// Time of the slowest probe
+    pub max_time: Duration,
+}
+
+impl I2cProbeResult {
+    /// Get the number of probes
+    pub fn attempts(&self) -> u32 {
+        self.acks + self.nacks + self.timeouts + self.errors
+    }
+
+    /// Check if a device acknowledged any probe
+    pub fn found(&self) -> bool {
+        self.acks > 0
+    }
+
+    /// Check if a device acknowledged some probes but not all
+    pub fn intermittent(&self) -> bool {
+        self.acks > 0 && self.acks < self.attempts()
+    }
+
+    /// Get the average time of a probe
+    pub fn average_time(&self) -> Duration {
+        self.total_time
+            .checked_div(self.attempts())
+            .unwrap_or_default()
+    }
+}
+
+/// Overall condition of an I2C bus judged from a scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum I2cBusHealth {
+    /// Devices answered consistently
+    Ok,
+    /// No device answered
+    NoDevices,
+    /// Some devices answered only some probes
+    Intermittent,
+    /// Every address answered, which happens when SDA is held low
+    SdaStuckLow,
+    /// No probe completed, which happens when SCL is held low or the bus is unpowered
+    Unresponsive,
+}
+
+impl fmt::Display for I2cBusHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => write!(f, "OK"),
+            Self::NoDevices => write!(f, "no devices found; check power and wiring"),
+            Self::Intermittent => write!(
+                f,
+                "intermittent answers; check connections, pull-ups and cable length"
+            ),
+            Self::SdaStuckLow => write!(
+                f,
+                "every address answers; SDA is likely held low or shorted to ground"
+            ),
+            Self::Unresponsive => write!(
+                f,
+                "no probe completed; SCL may be held low or the bus is unpowered"
+            ),
+        }
+    }
+}
+
+/// Results of an I2C scan
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct I2cScanReport {
+    /// The configuration of the scan
+    pub config: I2cScanConfig,
+    /// Results by address, in address order
+    pub addresses: Vec<I2cProbeResult>,
+    /// Time the scan took
+    pub duration: Duration,
+}
+
+impl I2cScanReport {
+    /// Get the addresses where a device acknowledged
+    pub fn found(&self) -> Vec<u8> {
+        self.addresses
+            .iter()
+            .filter(|result| result.found())
+            .map(|result| result.address)
+            .collect()
+    }
+
+    /// Get the addresses where a device acknowledged only some probes
+    pub fn intermittent(&self) -> Vec<u8> {
+        self.addresses
+            .iter()
+            .filter(|result| result.intermittent())
+            .map(|result| result.address)
+            .collect()
+    }
+
+    /// Get the result of an address
+    pub fn result(&self, address: u8) -> Option<&I2cProbeResult> {
+        self.addresses
+            .iter()
+            .find(|result| result.address == address)
+    }
+
+    /// Get the number of probes no device acknowledged
+    pub fn nack_count(&self) -> u32 {
+        self.addresses.iter().map(|result| result.nacks).sum()
+    }
+
+    /// Get the number of probes that timed out
+    pub fn timeout_count(&self) -> u32 {
+        self.addresses.iter().map(|result| result.timeouts).sum()
+    }
+
+    /// Get the number of probes that failed to reach the device
+    pub fn error_count(&self) -> u32 {
+        self.addresses.iter().map(|result| result.errors).sum()
+    }
+
+    /// Judge the condition of the bus
+    pub fn health(&self) -> I2cBusHealth {
+        let all_acked = self
+            .addresses
+            .iter()
+            .all(|result| result.acks == result.attempts());
+        if self.addresses.len() > 1 && all_acked {
+            I2cBusHealth::SdaStuckLow
+        } else if self.found().is_empty() && self.nack_count() == 0 {
+            I2cBusHealth::Unresponsive
+        } else if self.found().is_empty() {
+            I2cBusHealth::NoDevices
+        } else if !self.intermittent().is_empty() {
+            I2cBusHealth::Intermittent
+        } else {
+            I2cBusHealth::Ok
+        }
+    }
+}
+
+/// A device on the I2C bus
+///
+/// Failed transfers are retried if retries are configured; invalid
+/// parameters are reported without retrying. Transfers that write data are
+/// not retried after a timeout, as the worker may still have sent them.
+#[derive(Clone)]
+pub struct I2cDevice {
+    bus: I2cBus,
+    address: u8,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl I2cDevice {
+    /// Create a handle to the device at an address
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address is not a 7-bit address.
+    pub fn new(bus: I2cBus, address: u8) -> Result<Self> {
+        check_address(address)?;
+        Ok(Self {
+            bus,
+            address,
+            retries: 0,
+            retry_delay: Duration::ZERO,
+        })
+    }
+
+    /// Set how long to wait for each transfer attempt
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.bus = self.bus.with_timeout(timeout);
+        self
+    }
+
+    /// Retry failed transfers up to `retries` times, waiting `delay` in between
+    pub fn with_retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Get the 7-bit address of the device
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Get the number of retries of a failed transfer
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Write bytes to the device
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device does not acknowledge the write or the
+    /// transfer times out.
+    pub fn write(&self, data: &[u8]) -> Result<()> {
+        self.transfer(data, 0).map(|_| ())
+    }
+
+    /// Read bytes from the device
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device does not answer or the transfer times out.
+    pub fn read(&self, length: u8) -> Result<Vec<u8>> {
+        self.transfer(&[], length)
+    }
+
+    /// Write bytes to the device, then read its answer
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device does not answer or the transfer times out.
+    pub fn write_read(&self, data: &[u8], read_length: u8) -> Result<Vec<u8>> {
+        self.transfer(data, read_length)
+    }
+
+    /// Read consecutive registers
+    ///
+    /// # Parameters
+    ///
+    /// * `register` - The first register.
+    /// * `length` - The number of bytes to read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device does not answer, answers with fewer
+    /// bytes or the transfer times out.
+    pub fn read_register(&self, register: u8, length: u8) -> Result<Vec<u8>> {
+        let data = self.transfer(&[register], length)?;
+        if data.len() != usize::from(length) {
+            return Err(ThreadError::OperationFailed(format!(
+                "I2C device 0x{:02X} returned {} of {} bytes from register 0x{:02X}",
+                self.address,
+                data.len(),
+                length,
+                register
+            )));
+        }
+        Ok(data)
+    }
+
+    /// Write consecutive registers
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device does not acknowledge the write or the
+    /// transfer times out.
+    pub fn write_register(&self, register: u8, data: &[u8]) -> Result<()> {
+        let mut bytes = Vec::with_capacity(data.len() + 1);
+        bytes.push(register);
+        bytes.extend_from_slice(data);
+        self.write(&bytes)
+    }
+
+    /// Read an 8-bit register
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the register cannot be read.
+    pub fn read_u8(&self, register: u8) -> Result<u8> {
+        Ok(self.read_register(register, 1)?[0])
+    }
+
+    /// Write an 8-bit register
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the register cannot be written.
+    pub fn write_u8(&self, register: u8, value: u8) -> Result<()> {
+        self.write_register(register, &[value])
+    }
+
+    /// Read a 16-bit register, most significant byte first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the register cannot be read.
+    pub fn read_u16_be(&self, register: u8) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.read_array(register)?))
+    }
+
+    /// Read a 16-bit register, least significant byte first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the register cannot be read.
+    pub fn read_u16_le(&self, register: u8) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array(register)?))
+    }
+
+    /// Read a signed 16-bit register, most significant byte first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the register cannot be read.
+    pub fn read_i16_be(&self, register: u8) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.read_array(register)?))
+    }
+
+    /// Write a 16-bit register, most significant byte first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the register cannot be written.
+    pub fn write_u16_be(&self, register: u8, value: u16) -> Result<()> {
+        self.write_register(register, &value.to_be_bytes())
+    }
+
+    /// Write a 16-bit register, least significant byte first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the register cannot be written.
+    pub fn write_u16_le(&self, register: u8, value: u16) -> Result<()> {
+        self.write_register(register, &value.to_le_bytes())
+    }
+
+    /// Change the bits of a register selected by a mask
+    ///
+    /// The register is only written if its value changes.
+    ///
+    /// # Parameters
+    ///
+    /// * `register` - The register to change.
+    /// * `mask` - The bits to change.
+    /// * `value` - The new value of the masked bits.
+    ///
+    /// # Returns
+    ///
+    /// The new value of the register.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the register cannot be read or written.
+    pub fn update_bits(&self, register: u8, mask: u8, value: u8) -> Result<u8> {
+        let current = self.read_u8(register)?;
+        let updated = (current & !mask) | (value & mask);
+        if updated != current {
+            self.write_u8(register, updated)?;
+        }
+        Ok(updated)
+    }
+
+    fn read_array<const N: usize>(&self, register: u8) -> Result<[u8; N]> {
+        let data = self.read_register(register, N as u8)?;
+        Ok(data.try_into().expect("read_register checks the length"))
+    }
+
+    fn transfer(&self, write_data: &[u8], read_length: u8) -> Result<Vec<u8>> {
+        let mut attempt = 0;
+        loop {
+            match self.bus.transfer(self.address, write_data, read_length) {
+                Err(e) if attempt < self.retries && is_transient(&e, write_data) => {
+                    attempt += 1;
+                    debug!(
+                        "Retrying I2C transfer with 0x{:02X} ({}/{}): {}",
+                        self.address, attempt, self.retries, e
+                    );
+                    thread::sleep(self.retry_delay);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Check if a failed transfer may succeed when retried
+///
+/// A device that did not acknowledge its address is not retried. A timed out
+/// transfer may still be performed by the worker, so it is only retried if
+/// repeating it writes nothing.
+fn is_transient(error: &ThreadError, write_data: &[u8]) -> bool {
+    match error {
+        ThreadError::DeviceError(_) => true,
+        ThreadError::Timeout => write_data.is_empty(),
+        _ => false,
+    }
+}
+
+pub(crate) fn check_address(address: u8) -> Result<()> {
+    if address > 0x7F {
+        return Err(ThreadError::validation_error(
+            &format!("I2C address 0x{address:02X} is not a 7-bit address"),
+            "I2C address",
+            Some("Use addresses 0x00-0x7F"),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,370 @@
+//! Exclusive output leases
+//!
+//! A lease claims outputs exclusively for one owner and hands out typed
+//! handles that are the only way to write them while held. The handles send
+//! their commands as [`DeviceCommand::Claimed`] with the ID of the lease's
+//! claim; the device thread rejects any other write to leased pins and PWM
+//! channels with [`ThreadError::ResourceConflict`](crate::ThreadError::ResourceConflict).
+//!
+//! Outputs with a default are set to it when their handle is dropped. Once
+//! every handle of the lease has been dropped, the claim is handed to the
+//! device thread, which releases it after running the reverts.
+//!
+//! ```ignore
+//! let lease = controller.lease_outputs(
+//!     thread_id,
+//!     "heater",
+//!     &LeaseRequest::new()
+//!         .digital_output_with_default(5, false)
+//!         .pwm(0),
+//! )?;
+//! lease.digital_output(5).unwrap().set(true)?;
+//! lease.pwm(0).unwrap().set_duty(PwmDuty::Percent(40.0))?;
+//! ```
+
+use crate::commands::DeviceCommand;
+use crate::error::{Result, ThreadError};
+use crate::pwm::{PwmDuty, PwmWaveform};
+use crate::resources::{Resource, ResourceClaim};
+use crate::state::SharedDeviceState;
+use crossbeam_channel::Sender;
+use log::warn;
+use std::sync::Arc;
+
+/// Outputs to lease
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LeaseRequest {
+    /// Digital output pins and the value to set when released
+    pub digital_outputs: Vec<(u8, Option<bool>)>,
+    /// PWM channels and the duty to set when released
+    pub pwm_channels: Vec<(usize, Option<PwmDuty>)>,
+}
+
+impl LeaseRequest {
+    /// Create an empty request
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lease a digital output that keeps its value when released
+    pub fn digital_output(mut self, pin: u8) -> Self {
+        self.digital_outputs.push((pin, None));
+        self
+    }
+
+    /// Lease a digital output that is set to `default` when released
+    pub fn digital_output_with_default(mut self, pin: u8, default: bool) -> Self {
+        self.digital_outputs.push((pin, Some(default)));
+        self
+    }
+
+    /// Lease a PWM channel that keeps its duty when released
+    pub fn pwm(mut self, channel: usize) -> Self {
+        self.pwm_channels.push((channel, None));
+        self
+    }
+
+    /// Lease a PWM channel that is set to `default` when released
+    pub fn pwm_with_default(mut self, channel: usize, default: PwmDuty) -> Self {
+        self.pwm_channels.push((channel, Some(default)));
+        self
+    }
+}
+
+/// Claim of a lease, shared by its handles
+struct LeaseClaim {
+    command_tx: Sender<DeviceCommand>,
+    claim: Option<ResourceClaim>,
+}
+
+impl LeaseClaim {
+    fn claim(&self) -> &ResourceClaim {
+        self.claim
+            .as_ref()
+            .expect("the claim is only taken on drop")
+    }
+}
+
+impl Drop for LeaseClaim {
+    fn drop(&mut self) {
+        // Release in the device thread, after the reverts queued before
+        if let Some(claim) = self.claim.take() {
+            let _ = self.command_tx.send(DeviceCommand::ReleaseClaim {
+                claim: Arc::new(claim),
+            });
+        }
+    }
+}
+
+/// Connection of a handle to its device thread
+#[derive(Clone)]
+struct LeaseTarget {
+    shared_state: Arc<SharedDeviceState>,
+    lease: Arc<LeaseClaim>,
+}
+
+impl LeaseTarget {
+    fn owner(&self) -> &str {
+        self.lease.claim().owner()
+    }
+
+    fn send(&self, command: DeviceCommand) -> Result<()> {
+        self.lease
+            .command_tx
+            .send(DeviceCommand::Claimed {
+                claim: self.lease.claim().id(),
+                command: Box::new(command),
+            })
+            .map_err(|e| ThreadError::CommandSendFailed(e.to_string()))
+    }
+}
+
+/// Exclusive write access to a digital output
+pub struct DigitalOutputHandle {
+    pin: u8,
+    default: Option<bool>,
+    target: LeaseTarget,
+}
+
+impl DigitalOutputHandle {
+    /// Get the pin number
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// Get the owner of the lease
+    pub fn owner(&self) -> &str {
+        self.target.owner()
+    }
+
+    /// Set the output
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command send fails.
+    pub fn set(&self, value: bool) -> Result<()> {
+        self.target.send(DeviceCommand::SetDigitalOutput {
+            pin: u32::from(self.pin),
+            value,
+        })
+    }
+
+    /// Get the last value written to the output
+    pub fn get(&self) -> Option<bool> {
+        self.target
+            .shared_state
+            .get_digital_output(u32::from(self.pin))
+    }
+
+    /// Set the value written when the handle is dropped, or None to keep the output
+    pub fn set_default(&mut self, default: Option<bool>) {
+        self.default = default;
+    }
+}
+
+impl Drop for DigitalOutputHandle {
+    fn drop(&mut self) {
+        if let Some(value) = self.default {
+            if let Err(e) = self.set(value) {
+                warn!("Failed to revert digital output {}: {}", self.pin, e);
+            }
+        }
+    }
+}
+
+/// Exclusive write access to a PWM channel
+pub struct PwmHandle {
+    channel: usize,
+    pin: u8,
+    default: Option<PwmDuty>,
+    target: LeaseTarget,
+}
+
+impl PwmHandle {
+    /// Get the PWM channel
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// Get the pin of the channel
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// Get the owner of the lease
+    pub fn owner(&self) -> &str {
+        self.target.owner()
+    }
+
+    /// Set the duty, cancelling any animation of the channel
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the duty does not fit in the PWM period or the
+    /// command send fails.
+    pub fn set_duty(&self, duty: PwmDuty) -> Result<()> {
+        let command = self
+            .target
+            .shared_state
+            .pwm_duty_command(self.channel, duty)?;
+        self.target.send(command)
+    }
+
+    /// Run a fade, ramp, waveform or step sequence on the channel
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the waveform is invalid or the command send fails.
+    pub fn animate(&self, waveform: PwmWaveform) -> Result<()> {
+        waveform.validate(self.target.shared_state.get_pwm_period())?;
+        self.target.send(DeviceCommand::AnimatePwm {
+            channel: self.channel,
+            waveform,
+        })
+    }
+
+    /// Cancel an animation of the channel, holding the duty reached
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command send fails.
+    pub fn cancel_animation(&self) -> Result<()> {
+        self.target.send(DeviceCommand::CancelPwmAnimation {
+            channel: self.channel,
+        })
+    }
+
+    /// Get the current duty in PWM clock ticks
+    pub fn duty(&self) -> Option<u32> {
+        self.target.shared_state.get_pwm_duty_cycle(self.channel)
+    }
+
+    /// Set the duty written when the handle is dropped, or None to keep the duty
+    pub fn set_default(&mut self, default: Option<PwmDuty>) {
+        self.default = default;
+    }
+}
+
+impl Drop for PwmHandle {
+    fn drop(&mut self) {
+        if let Some(duty) = self.default {
+            if let Err(e) = self.set_duty(duty) {
+                warn!("Failed to revert PWM channel {}: {}", self.channel, e);
+            }
+        }
+    }
+}
+
+/// Handles of the outputs of a lease
+///
+/// The handles may be taken apart with [`into_handles`](Self::into_handles);
+/// the outputs stay leased until every handle is dropped.
+pub struct OutputLease {
+    digital_outputs: Vec<DigitalOutputHandle>,
+    pwm_channels: Vec<PwmHandle>,
+}
+
+impl OutputLease {
+    /// Claim the outputs of a request and create their handles
+    ///
+    /// # Parameters
+    ///
+    /// * `owner` - Name of the leasing module, reported in conflicts.
+    /// * `request` - The outputs to lease.
+    /// * `shared_state` - The state of the device.
+    /// * `command_tx` - The command sender of the device thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request is empty, a PWM channel does not exist,
+    /// a default duty is invalid or an output is already claimed.
+    pub fn acquire(
+        owner: &str,
+        request: &LeaseRequest,
+        shared_state: Arc<SharedDeviceState>,
+        command_tx: Sender<DeviceCommand>,
+    ) -> Result<Self> {
+        if request.digital_outputs.is_empty() && request.pwm_channels.is_empty() {
+            return Err(ThreadError::InvalidParameter(
+                "Lease request has no outputs".to_string(),
+            ));
+        }
+
+        let map = shared_state.pwm_channel_map();
+        let period = shared_state.get_pwm_period();
+        let mut resources: Vec<Resource> = request
+            .digital_outputs
+            .iter()
+            .map(|&(pin, _)| Resource::Pin(pin))
+            .collect();
+        let mut pwm_pins = Vec::new();
+        for &(channel, default) in &request.pwm_channels {
+            let pin = map.check_channel(channel)?;
+            if let Some(default) = default {
+                default.ticks(period)?;
+            }
+            resources.push(Resource::PwmChannel(channel));
+            resources.push(Resource::Pin(pin));
+            pwm_pins.push(pin);
+        }
+        let mut unique = resources.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        if unique.len() != resources.len() {
+            return Err(ThreadError::InvalidParameter(
+                "Lease request contains an output twice".to_string(),
+            ));
+        }
+
+        let claim = shared_state
+            .resources()
+            .claim_exclusive(owner, &resources)?;
+        let target = LeaseTarget {
+            shared_state,
+            lease: Arc::new(LeaseClaim {
+                command_tx,
+                claim: Some(claim),
+            }),
+        };
+
+        Ok(Self {
+            digital_outputs: request
+                .digital_outputs
+                .iter()
+                .map(|&(pin, default)| DigitalOutputHandle {
+                    pin,
+                    default,
+                    target: target.clone(),
+                })
+                .collect(),
+            pwm_channels: request
+                .pwm_channels
+                .iter()
+                .zip(pwm_pins)
+                .map(|(&(channel, default), pin)| PwmHandle {
+                    channel,
+                    pin,
+                    default,
+                    target: target.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Get the handle of a leased digital output
+    pub fn digital_output(&self, pin: u8) -> Option<&DigitalOutputHandle> {
+        self.digital_outputs.iter().find(|handle| handle.pin == pin)
+    }
+
+    /// Get the handle of a leased PWM channel
+    pub fn pwm(&self, channel: usize) -> Option<&PwmHandle> {
+        self.pwm_channels
+            .iter()
+            .find(|handle| handle.channel == channel)
+    }
+
+    /// Take the handles apart
+    pub fn into_handles(self) -> (Vec<DigitalOutputHandle>, Vec<PwmHandle>) {
+        (self.digital_outputs, self.pwm_channels)
+    }
+}
@@ -22,6 +22,23 @@
 //! - **StateObserver**: Allows monitoring state changes.
 //! - **DeviceOperations**: Provides a high-level interface for device operations.
 //! - **DeviceSync**: Handles data synchronization between device and shared state.
+//! - **SyncStatistics**: Records sync timing, jitter and command latency per thread.
+//! - **ValueHistory**: Keeps recent pin and encoder values for trend queries.
+//! - **EncoderMotion**: Encoder position in units, velocity, RPM and direction.
+//! - **CaptureSet**: Takes oscilloscope-style captures around trigger events.
+//! - **DataRecorder**: Writes sampled I/O of one or more threads to rotating CSV or JSON Lines files.
+//! - **SessionRecorder**: Records state changes and commands for offline replay.
+//! - **ServoRegistry**: Converts servo angles and speeds to duty values from their calibration.
+//! - **ServoMotions**: Interpolates speed- and acceleration-limited servo moves in the worker.
+//! - **PwmChannelMap**: Maps PWM channels to the PWM pins of the device model.
+//! - **PwmAnimations**: Runs PWM fades, ramps, waveforms and step sequences in the worker.
+//! - **ResourceManager**: Tracks which module owns each pin, PWM channel, encoder and bus.
+//! - **DeviceHandle**: Binds a thread and creates typed pin, PWM, encoder and I2C handles.
+//! - **I2cDevice**: Register access to an I2C device with retries, answered by the worker.
+//! - **I2cScanReport**: Devices found by an I2C scan with probe timing and bus diagnostics.
+//! - **SevenSegmentDisplay**: Text, numbers and blinking on chained 7-segment modules of a uSPIBridge.
+//! - **OutputLease**: Exclusive handles to outputs shared between modules, reverted on drop.
+//! - **StatePersister**: Saves outputs and custom values per device and restores them on start.
 //! - **Logger**: Provides configurable logging for threads and controllers.
 //!
 //! ## Usage Example
@@ -63,17 +80,35 @@
 //! - Comprehensive error handling
 //! - Configurable logging system
 //! - Support for USB and network devices
+//! - embedded-hal trait implementations for device handles (`embedded-hal` feature)
 
 pub mod builder;
+pub mod capabilities;
+pub mod capture;
 pub mod commands;
 pub mod controller;
 pub mod controller_builder;
+pub mod device;
+pub mod encoder;
 pub mod error;
+#[cfg(feature = "embedded-hal")]
+pub mod hal;
+pub mod history;
+pub mod i2c;
+pub mod lease;
 pub mod logging;
 pub mod observer;
 pub mod operations;
+pub mod persistence;
+pub mod pwm;
+pub mod recorder;
+pub mod resources;
+pub mod servo;
+pub mod session;
 pub mod state;
+pub mod stats;
 pub mod sync;
+pub mod uspibridge;
 pub mod worker;
 
 #[cfg(test)]
@@ -81,13 +116,38 @@ mod tests;
 
 // Re-export main types
 pub use builder::ThreadWorkerBuilder;
-pub use commands::DeviceCommand;
+pub use capture::{
+    Capture, CaptureConfig, CaptureFrame, CaptureHandle, CaptureSet, Edge, TriggerCondition,
+};
+pub use commands::{DeviceCommand, ResponseSender};
 pub use controller::{ThreadController, ThreadControllerImpl};
 pub use controller_builder::ThreadControllerBuilder;
+pub use device::{
+    AnalogInputPin, AnalogOutputPin, DeviceHandle, DigitalInputPin, DigitalOutputPin,
+    EncoderHandle, PwmPin,
+};
+pub use encoder::{EncoderDirection, EncoderMotion, EncoderScaling};
 pub use error::{Result, ThreadError};
+pub use history::{HistoryChannel, HistorySample, ValueHistory, WindowStats};
+pub use i2c::{
+    I2cBus, I2cBusHealth, I2cDevice, I2cProbe, I2cProbeResult, I2cScanConfig, I2cScanReport,
+};
+pub use lease::{DigitalOutputHandle, LeaseRequest, OutputLease, PwmHandle};
 pub use logging::{Logger, SimpleLogger, ThreadLogger};
 pub use observer::StateObserver;
 pub use operations::DeviceOperations;
-pub use state::{DeviceState, SharedDeviceState, StateChangeType, ThreadStatus};
-pub use sync::DeviceSync;
+pub use persistence::{PersistMode, PersistedState, PersistenceConfig, StatePersister};
+pub use pwm::{
+    PwmAnimations, PwmChannelMap, PwmConfig, PwmDuty, PwmPeriod, PwmStep, PwmWaveform, RampCurve,
+};
+pub use recorder::{DataRecorder, RecordFormat, RecordRow, RecorderConfig, SampleMode};
+pub use resources::{ClaimId, Resource, ResourceClaim, ResourceManager};
+pub use servo::{ServoMotions, ServoProfile, ServoRegistry, ServoStep};
+pub use session::{
+    RecordedEvent, SessionEvent, SessionHeader, SessionRecorder, SessionRecording, SessionReplayer,
+};
+pub use state::{DeviceState, FloatValue, SharedDeviceState, StateChangeType, ThreadStatus};
+pub use stats::{SyncGroup, SyncStatistics, SyncStatsSnapshot, TimingStats};
+pub use sync::{AdaptiveRefreshConfig, DeviceSync};
+pub use uspibridge::{BridgeCommand, SevenSegmentDisplay, USPIBridge, DEFAULT_USPIBRIDGE_ADDRESS};
 pub use worker::{DeviceWorker, DeviceWorkerImpl};
@@ -38,8 +38,18 @@
 //! }
 //! ```
 
+use crate::commands::DeviceCommand;
+use crate::device::DeviceHandle;
+use crate::encoder::{EncoderMotion, EncoderScaling};
 use crate::error::Result;
+use crate::i2c::{I2cDevice, I2cScanConfig, I2cScanReport};
+use crate::lease::{DigitalOutputHandle, LeaseRequest, OutputLease, PwmHandle};
+use crate::pwm::{PwmChannelMap, PwmConfig, PwmDuty, PwmWaveform};
+use crate::resources::{Resource, ResourceClaim};
+use crate::servo::ServoProfile;
+use crate::uspibridge::USPIBridge;
 use pokeys_lib::{PinCapability, ServoConfig, USPIBridgeConfig};
+use std::time::Duration;
 
 /// Device operations trait for performing device-specific operations.
 ///
@@ -112,11 +122,12 @@ pub trait DeviceOperations {
     ///
     /// * `thread_id` - The ID of the thread to send the command to.
     /// * `channel` - The PWM channel to set.
-    /// * `duty` - The duty cycle to set (0-4095 for 12-bit PWM).
+    /// * `duty` - The duty cycle to set in PWM clock ticks (0 to the PWM period).
     ///
     /// # Errors
     ///
-    /// Returns an error if the thread is not found or if the command send fails.
+    /// Returns an error if the thread is not found, if the device does not
+    /// have the channel or if the command send fails.
     fn set_pwm_duty_cycle(&self, thread_id: u32, channel: usize, duty: u32) -> Result<()>;
 
     /// Set a PWM duty cycle as a percentage.
@@ -125,11 +136,14 @@ pub trait DeviceOperations {
     ///
     /// * `thread_id` - The ID of the thread to send the command to.
     /// * `channel` - The PWM channel to set.
-    /// * `duty_percent` - The duty cycle to set as a percentage (0.0-100.0).
+    /// * `duty_percent` - The duty cycle to set as a percentage (0.0-100.0)
+    ///   of the configured PWM period. Without a period it is scaled to
+    ///   0-4095 as in earlier versions.
     ///
     /// # Errors
     ///
-    /// Returns an error if the thread is not found or if the command send fails.
+    /// Returns an error if the thread is not found, if the channel or
+    /// percentage is invalid or if the command send fails.
     fn set_pwm_duty_cycle_percent(
         &self,
         thread_id: u32,
@@ -137,12 +151,86 @@ pub trait DeviceOperations {
         duty_percent: f32,
     ) -> Result<()>;
 
+    /// Set a PWM duty cycle in ticks, percent or microseconds.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to send the command to.
+    /// * `channel` - The PWM channel to set.
+    /// * `duty` - The duty cycle, converted using the configured PWM period.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found, if the device does not
+    /// have the channel, if the duty does not fit in the PWM period or if the
+    /// command send fails.
+    fn set_pwm_duty(&self, thread_id: u32, channel: usize, duty: PwmDuty) -> Result<()>;
+
+    /// Configure the PWM period and enabled channels.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to send the command to.
+    /// * `config` - The PWM period or frequency and the channels to enable.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found, if the period is invalid,
+    /// if the device does not have an enabled channel or if the command send
+    /// fails.
+    fn configure_pwm(&self, thread_id: u32, config: PwmConfig) -> Result<()>;
+
+    /// Get the PWM channel map of a device.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to get the map from.
+    ///
+    /// # Returns
+    ///
+    /// The pins of the PWM channels, derived from the device model.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found.
+    fn get_pwm_channel_map(&self, thread_id: u32) -> Result<PwmChannelMap>;
+
+    /// Run a fade, ramp, sine wave or step sequence on a PWM channel.
+    ///
+    /// The worker sets the duty every cycle on its own clock and reports
+    /// `PwmAnimationComplete` when the waveform ends or is cancelled. Setting
+    /// the duty of the channel directly cancels the animation.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to send the command to.
+    /// * `channel` - The PWM channel to animate.
+    /// * `waveform` - The waveform, with duties relative to the configured PWM period.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found, if the device does not
+    /// have the channel, if the waveform is invalid or if the command send fails.
+    fn animate_pwm(&self, thread_id: u32, channel: usize, waveform: PwmWaveform) -> Result<()>;
+
+    /// Cancel a PWM animation in progress, holding the duty reached.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to send the command to.
+    /// * `channel` - The PWM channel.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if the command send fails.
+    fn cancel_pwm_animation(&self, thread_id: u32, channel: usize) -> Result<()>;
+
     /// Configure a servo on a PWM pin.
     ///
     /// # Parameters
     ///
     /// * `thread_id` - The ID of the thread to send the command to.
-    /// * `pin` - The PWM pin to configure (17-22).
+    /// * `pin` - The PWM pin to configure; see `get_pwm_channel_map`.
     /// * `servo_config` - The servo configuration.
     ///
     /// # Errors
@@ -155,7 +243,7 @@ pub trait DeviceOperations {
     /// # Parameters
     ///
     /// * `thread_id` - The ID of the thread to send the command to.
-    /// * `pin` - The PWM pin (17-22).
+    /// * `pin` - A pin in the PWM channel map of the device.
     /// * `angle` - The angle in degrees (0-180 for 180° servos, 0-360 for 360° position servos).
     ///
     /// # Errors
@@ -168,7 +256,7 @@ pub trait DeviceOperations {
     /// # Parameters
     ///
     /// * `thread_id` - The ID of the thread to send the command to.
-    /// * `pin` - The PWM pin (17-22).
+    /// * `pin` - A pin in the PWM channel map of the device.
     /// * `speed` - The speed (-100.0 to 100.0, where 0 is stop, positive is clockwise).
     ///
     /// # Errors
@@ -176,18 +264,84 @@ pub trait DeviceOperations {
     /// Returns an error if the thread is not found or if the command send fails.
     fn set_servo_speed(&self, thread_id: u32, pin: u8, speed: f32) -> Result<()>;
 
-    /// Stop a servo (set to neutral position).
+    /// Stop a continuous rotation servo (set to its calibrated stop duty).
     ///
     /// # Parameters
     ///
     /// * `thread_id` - The ID of the thread to send the command to.
-    /// * `pin` - The PWM pin (17-22).
+    /// * `pin` - A pin in the PWM channel map of the device.
     ///
     /// # Errors
     ///
     /// Returns an error if the thread is not found or if the command send fails.
     fn stop_servo(&self, thread_id: u32, pin: u8) -> Result<()>;
 
+    /// Get the last commanded angle of a servo.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to get the servo position from.
+    /// * `pin` - A pin in the PWM channel map of the device.
+    ///
+    /// # Returns
+    ///
+    /// The angle in degrees.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if no angle has been
+    /// set on the pin.
+    fn get_servo_position(&self, thread_id: u32, pin: u8) -> Result<f32>;
+
+    /// Move a servo to an angle with speed and acceleration limits.
+    ///
+    /// The worker interpolates the angle every cycle and reports
+    /// `ServoMotionComplete` when the target is reached or the move is cancelled.
+    /// The servo must be configured and its current angle known.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to send the command to.
+    /// * `pin` - A pin in the PWM channel map of the device.
+    /// * `angle` - The target angle in degrees.
+    /// * `profile` - The speed and acceleration limits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found, the profile is invalid or
+    /// the command send fails.
+    fn move_servo(&self, thread_id: u32, pin: u8, angle: f32, profile: ServoProfile) -> Result<()>;
+
+    /// Move several servos so that they arrive at their targets at the same time.
+    ///
+    /// The longest move runs at the profile limits and the others are slowed
+    /// down proportionally.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to send the command to.
+    /// * `moves` - The `(pin, angle)` targets.
+    /// * `profile` - The speed and acceleration limits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found, the moves are empty or
+    /// contain a pin twice, the profile is invalid or the command send fails.
+    fn move_servos(&self, thread_id: u32, moves: &[(u8, f32)], profile: ServoProfile)
+        -> Result<()>;
+
+    /// Cancel a servo move in progress, holding the angle reached.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to send the command to.
+    /// * `pin` - A pin in the PWM channel map of the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if the command send fails.
+    fn cancel_servo_motion(&self, thread_id: u32, pin: u8) -> Result<()>;
+
     /// Write data to an I2C device.
     ///
     /// # Parameters
@@ -215,7 +369,8 @@ pub trait DeviceOperations {
     ///
     /// # Errors
     ///
-    /// Returns an error if the thread is not found or if the command send fails.
+    /// Returns an error if the thread is not found, if the device does not
+    /// answer or if the read times out.
     fn i2c_read(&self, thread_id: u32, address: u8, length: u8) -> Result<Vec<u8>>;
 
     /// Write then read from an I2C device (combined operation).
@@ -233,7 +388,8 @@ pub trait DeviceOperations {
     ///
     /// # Errors
     ///
-    /// Returns an error if the thread is not found or if the command send fails.
+    /// Returns an error if the thread is not found, if the device does not
+    /// answer or if the transfer times out.
     fn i2c_write_read(
         &self,
         thread_id: u32,
@@ -242,8 +398,29 @@ pub trait DeviceOperations {
         read_length: u8,
     ) -> Result<Vec<u8>>;
 
+    /// Get a handle to an I2C device with register access.
+    ///
+    /// Transfers run in the device thread and return the data read. Retries
+    /// and the transfer timeout are set on the returned handle.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread of the device.
+    /// * `address` - The 7-bit I2C device address.
+    ///
+    /// # Returns
+    ///
+    /// The device handle.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if the address is invalid.
+    fn i2c_device(&self, thread_id: u32, address: u8) -> Result<I2cDevice>;
+
     /// Scan for I2C devices on the bus.
     ///
+    /// Probes addresses 0x08-0x77 with a one-byte read.
+    ///
     /// # Parameters
     ///
     /// * `thread_id` - The ID of the thread to send the command to.
@@ -254,10 +431,29 @@ pub trait DeviceOperations {
     ///
     /// # Errors
     ///
-    /// Returns an error if the thread is not found or if the command send fails.
+    /// Returns an error if the thread is not found or if the scan times out.
     fn i2c_scan(&self, thread_id: u32) -> Result<Vec<u8>>;
 
-    /// Configure uSPIBridge with custom pinout.
+    /// Scan for I2C devices with a custom range and probe method.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to send the command to.
+    /// * `config` - The addresses, probe method and probes per address.
+    ///
+    /// # Returns
+    ///
+    /// The answers and probe times of each address and the bus diagnostics.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found, if the configuration is
+    /// invalid or if the scan times out.
+    fn i2c_scan_with(&self, thread_id: u32, config: &I2cScanConfig) -> Result<I2cScanReport>;
+
+    /// Configure the uSPIBridge at the default address.
+    ///
+    /// Sets the segment mapping and default brightness of each module.
     ///
     /// # Parameters
     ///
@@ -266,27 +462,52 @@ pub trait DeviceOperations {
     ///
     /// # Errors
     ///
-    /// Returns an error if the thread is not found or if the command send fails.
+    /// Returns an error if the thread is not found, if the bridge rejects a
+    /// command or if the commands time out.
     fn configure_uspibridge(&self, thread_id: u32, config: USPIBridgeConfig) -> Result<()>;
 
-    /// Send uSPIBridge command.
+    /// Send a command to the uSPIBridge at the default address.
     ///
     /// # Parameters
     ///
     /// * `thread_id` - The ID of the thread to send the command to.
-    /// * `command` - The uSPIBridge command data.
+    /// * `command` - The command code, the device ID and the payload.
     ///
     /// # Returns
     ///
-    /// The response data from the uSPIBridge.
+    /// The response data from the uSPIBridge, empty for commands without one.
     ///
     /// # Errors
     ///
-    /// Returns an error if the thread is not found or if the command send fails.
+    /// Returns an error if the thread is not found, if the command is
+    /// malformed, if the bridge rejects it or if it times out.
     fn uspibridge_command(&self, thread_id: u32, command: Vec<u8>) -> Result<Vec<u8>>;
 
+    /// Get a handle to a uSPIBridge on the I2C bus of a device.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread of the device.
+    /// * `address` - The 7-bit I2C address of the bridge.
+    ///
+    /// # Returns
+    ///
+    /// A handle for commands and 7-segment displays of the bridge.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if the address is invalid.
+    fn uspibridge(&self, thread_id: u32, address: u8) -> Result<USPIBridge>;
+
     /// Check if a pin supports a specific capability.
     ///
+    /// The answer comes from the device model; see
+    /// [`crate::capabilities::model_capability_names`] for the model names
+    /// that provide each capability and [`crate::capabilities`] for the
+    /// capabilities the bundled models do not list. Without a model, digital
+    /// I/O is supported on every pin of the device, PWM on the standard PWM
+    /// pins and counters where the device reports them.
+    ///
     /// # Parameters
     ///
     /// * `thread_id` - The ID of the thread to check.
@@ -299,7 +520,8 @@ pub trait DeviceOperations {
     ///
     /// # Errors
     ///
-    /// Returns an error if the thread is not found.
+    /// Returns an error if the thread is not found, or if no device model is
+    /// loaded and the capability cannot be answered without one.
     fn check_pin_capability(
         &self,
         thread_id: u32,
@@ -328,11 +550,15 @@ pub trait DeviceOperations {
     ///
     /// * `thread_id` - The ID of the thread to validate.
     /// * `pin` - The pin number to validate.
-    /// * `operation` - Description of the operation being attempted.
+    /// * `operation` - The operation being attempted, such as `digital_output`,
+    ///   `pwm` or `counter`, or a pin capability name such as `FastEncoder1A`.
     ///
     /// # Errors
     ///
-    /// Returns an error if the pin cannot perform the operation.
+    /// Returns an error if the operation is unknown, if the capability needs
+    /// a device model and none is loaded, or if the pin cannot perform the
+    /// operation. The error suggests
+    /// the nearest pins that can.
     fn validate_pin_operation(&self, thread_id: u32, pin: u8, operation: &str) -> Result<()>;
 
     /// Set multiple digital outputs in a single operation.
@@ -356,7 +582,8 @@ pub trait DeviceOperations {
     ///
     /// # Errors
     ///
-    /// Returns an error if the thread is not found or if the command send fails.
+    /// Returns an error if the thread is not found, if the device does not
+    /// have one of the channels or if the command send fails.
     fn set_pwm_duties_bulk(&self, thread_id: u32, channel_duties: Vec<(usize, u32)>) -> Result<()>;
 
     /// Read multiple analog inputs in a single operation.
@@ -415,6 +642,83 @@ pub trait DeviceOperations {
         sampling_4x: bool,
     ) -> Result<()>;
 
+    /// Configure the scaling of an encoder, enabling position, velocity and
+    /// direction tracking in the sync loop.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to configure the encoder on.
+    /// * `encoder_index` - The encoder index to configure.
+    /// * `scaling` - Counts per unit, counts per revolution and velocity window.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if the scaling is invalid.
+    fn configure_encoder_scaling(
+        &self,
+        thread_id: u32,
+        encoder_index: u32,
+        scaling: EncoderScaling,
+    ) -> Result<()>;
+
+    /// Get the derived motion values of an encoder.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to get the encoder motion from.
+    /// * `encoder_index` - The encoder index to read.
+    ///
+    /// # Returns
+    ///
+    /// The position in units, velocity, RPM and direction of the encoder.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if no scaling is
+    /// configured for the encoder.
+    fn get_encoder_motion(&self, thread_id: u32, encoder_index: u32) -> Result<EncoderMotion>;
+
+    /// Zero the position of an encoder at its current count.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread the encoder belongs to.
+    /// * `encoder_index` - The encoder index to zero.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if no scaling is
+    /// configured for the encoder.
+    fn zero_encoder(&self, thread_id: u32, encoder_index: u32) -> Result<()>;
+
+    /// Preset the position of an encoder at its current count.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread the encoder belongs to.
+    /// * `encoder_index` - The encoder index to preset.
+    /// * `position` - The position in units the current count should read as.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if no scaling is
+    /// configured for the encoder.
+    fn preset_encoder(&self, thread_id: u32, encoder_index: u32, position: f64) -> Result<()>;
+
+    /// Set the raw count that corresponds to position zero of an encoder.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread the encoder belongs to.
+    /// * `encoder_index` - The encoder index to set the offset of.
+    /// * `offset` - The raw count of position zero.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if no scaling is
+    /// configured for the encoder.
+    fn set_encoder_offset(&self, thread_id: u32, encoder_index: u32, offset: i64) -> Result<()>;
+
     /// Reset a digital counter.
     ///
     /// # Parameters
@@ -427,6 +731,49 @@ pub trait DeviceOperations {
     /// Returns an error if the thread is not found or if the command send fails.
     fn reset_digital_counter(&self, thread_id: u32, pin: u32) -> Result<()>;
 
+    /// Get a digital counter value.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to get the counter value from.
+    /// * `pin` - The pin number to read.
+    ///
+    /// # Returns
+    ///
+    /// The counter value as of the last sync.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if the pin is not
+    /// configured as a digital counter.
+    fn get_digital_counter(&self, thread_id: u32, pin: u32) -> Result<u32>;
+
+    /// Get the count frequency of a digital counter.
+    ///
+    /// Requires history to be enabled for `HistoryChannel::DigitalCounter(pin)`.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread to get the frequency from.
+    /// * `pin` - The counter pin number.
+    /// * `window` - The time window to measure over.
+    ///
+    /// # Returns
+    ///
+    /// The counts per second over the window, or None if fewer than two
+    /// samples were recorded in it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if history is not
+    /// enabled for the counter.
+    fn get_counter_frequency(
+        &self,
+        thread_id: u32,
+        pin: u32,
+        window: Duration,
+    ) -> Result<Option<f64>>;
+
     /// Send a custom request.
     ///
     /// # Parameters
@@ -468,4 +815,179 @@ pub trait DeviceOperations {
         pin: u32,
         pin_function: pokeys_lib::PinFunction,
     ) -> Result<()>;
+
+    /// Claim device resources for an owner.
+    ///
+    /// Claims are shared by all users of the device thread and released when
+    /// the returned claim is dropped.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread of the device.
+    /// * `owner` - Name of the claiming module, reported in conflicts.
+    /// * `resources` - The resources to claim.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or
+    /// `ThreadError::ResourceConflict` if a resource is already claimed.
+    fn claim_resources(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        resources: &[Resource],
+    ) -> Result<ResourceClaim>;
+
+    /// Claim a PWM channel together with its pin.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread of the device.
+    /// * `owner` - Name of the claiming module.
+    /// * `channel` - The PWM channel.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found, if the device does not
+    /// have the channel or if the channel or its pin is already claimed.
+    fn claim_pwm_channel(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        channel: usize,
+    ) -> Result<ResourceClaim>;
+
+    /// Claim an encoder together with its input pins.
+    ///
+    /// The input pins are the `Encoder_<n>A` and `Encoder_<n>B` pins of the
+    /// device model, where `n` is `encoder_index + 1`.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread of the device.
+    /// * `owner` - Name of the claiming module.
+    /// * `encoder_index` - The encoder index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found, if no device model is
+    /// loaded or it has no pins for the encoder, or if the encoder or one of
+    /// its pins is already claimed.
+    fn claim_encoder(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        encoder_index: u32,
+    ) -> Result<ResourceClaim>;
+
+    /// Send a command on behalf of a claim.
+    ///
+    /// Commands that configure claimed resources are rejected unless they are
+    /// sent with the claim holding them; another claim under the same owner
+    /// name does not qualify.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread of the device.
+    /// * `claim` - A claim on the device.
+    /// * `command` - The command to send.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found, if the claim is not for
+    /// this device, if the command configures resources claimed by another
+    /// owner or if the command send fails.
+    fn send_claimed_command(
+        &self,
+        thread_id: u32,
+        claim: &ResourceClaim,
+        command: DeviceCommand,
+    ) -> Result<()>;
+
+    /// Lease outputs for exclusive writing.
+    ///
+    /// While any handle of the lease is held, the other write operations of
+    /// this trait reject the leased pins and PWM channels with
+    /// `ThreadError::ResourceConflict`. Outputs with a default are reverted
+    /// to it when their handle is dropped.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread of the device.
+    /// * `owner` - Name of the leasing module, reported in conflicts.
+    /// * `request` - The outputs to lease and their defaults.
+    ///
+    /// # Returns
+    ///
+    /// The handles of the leased outputs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found, if the request is empty
+    /// or invalid, or if an output is already claimed.
+    fn lease_outputs(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        request: &LeaseRequest,
+    ) -> Result<OutputLease>;
+
+    /// Lease a single digital output for exclusive writing.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread of the device.
+    /// * `owner` - Name of the leasing module.
+    /// * `pin` - The output pin.
+    /// * `default` - The value to set when the handle is dropped, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found or if the pin is already claimed.
+    fn lease_digital_output(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        pin: u8,
+        default: Option<bool>,
+    ) -> Result<DigitalOutputHandle>;
+
+    /// Lease a single PWM channel and its pin for exclusive writing.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread of the device.
+    /// * `owner` - Name of the leasing module.
+    /// * `channel` - The PWM channel.
+    /// * `default` - The duty to set when the handle is dropped, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found, if the device does not
+    /// have the channel or if the channel or its pin is already claimed.
+    fn lease_pwm_channel(
+        &self,
+        thread_id: u32,
+        owner: &str,
+        channel: usize,
+        default: Option<PwmDuty>,
+    ) -> Result<PwmHandle>;
+
+    /// Get a handle bound to the device of a thread.
+    ///
+    /// The handle is cheap to clone and creates typed pin, PWM, encoder and
+    /// I2C handles that are validated against the device configuration.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the thread of the device.
+    ///
+    /// # Returns
+    ///
+    /// The device handle.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread is not found.
+    fn device(&self, thread_id: u32) -> Result<DeviceHandle>;
 }
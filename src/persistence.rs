@@ -0,0 +1,266 @@
+//! Device state persistence
+//!
+//! Saves the outputs, PWM configuration and duty cycles, custom values and
+//! servo positions of a device to a JSON file named after its serial number,
+//! either whenever they change or at a fixed interval. With restore enabled,
+//! the device thread re-applies the saved outputs on start before entering its
+//! main loop; PWM duties are only restored for channels the saved
+//! configuration enables.
+
+use crate::error::{Result, ThreadError};
+use crate::history::now_ms;
+use crate::state::DeviceState;
+use pokeys_lib::PinFunction;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// When the state is saved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PersistMode {
+    /// Save whenever a persisted value changes
+    OnChange,
+    /// Save at most once per interval if a persisted value changed
+    Interval(Duration),
+}
+
+/// State persistence configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistenceConfig {
+    /// Directory the state files are written to
+    pub directory: PathBuf,
+    /// When the state is saved
+    pub mode: PersistMode,
+    /// Re-apply the saved outputs when the device thread starts
+    pub restore_on_start: bool,
+}
+
+impl PersistenceConfig {
+    /// Create a configuration saving on change to `directory`, without restore
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            mode: PersistMode::OnChange,
+            restore_on_start: false,
+        }
+    }
+
+    /// Get the default state directory (`~/.config/pokeys/state`)
+    pub fn default_directory() -> PathBuf {
+        let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        path.push(".config/pokeys/state");
+        path
+    }
+
+    /// Set when the state is saved
+    pub fn mode(mut self, mode: PersistMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Enable or disable restoring the saved outputs on thread start
+    pub fn restore_on_start(mut self, restore: bool) -> Self {
+        self.restore_on_start = restore;
+        self
+    }
+
+    /// Get the state file of a device
+    pub fn path_for(&self, serial: u32) -> PathBuf {
+        self.directory.join(format!("device-{serial}.json"))
+    }
+
+    /// Validate the configuration
+    pub fn validate(&self) -> Result<()> {
+        if let PersistMode::Interval(interval) = self.mode {
+            if interval.is_zero() {
+                return Err(ThreadError::ConfigurationError(
+                    "Persistence interval must be greater than zero".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        Self::new(Self::default_directory())
+    }
+}
+
+/// Persisted values of a device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PersistedState {
+    /// Device serial number
+    pub serial: u32,
+    /// Save time in milliseconds since the Unix epoch
+    pub saved_at: u64,
+    /// Digital output values of pins configured as outputs, by pin number
+    pub digital_outputs: BTreeMap<u32, bool>,
+    /// PWM period in clock ticks, 0 if PWM was not configured
+    #[serde(default)]
+    pub pwm_period: u32,
+    /// Enabled PWM channels bitmask (bit 0 = channel 0)
+    #[serde(default)]
+    pub pwm_enabled_channels: u8,
+    /// PWM duty cycles by channel
+    pub pwm_duties: BTreeMap<usize, u32>,
+    /// Custom values
+    pub custom_values: BTreeMap<String, serde_json::Value>,
+    /// Servo angles by pin
+    pub servo_positions: BTreeMap<u8, f32>,
+}
+
+impl PersistedState {
+    /// Extract the persisted values from a device state
+    pub fn from_state(state: &DeviceState) -> Self {
+        let digital_outputs = state
+            .pins
+            .iter()
+            .enumerate()
+            .filter(|(_, pin)| pin.pin_function & PinFunction::DigitalOutput as u8 != 0)
+            .map(|(i, pin)| ((i + 1) as u32, pin.digital_value_set != 0))
+            .collect();
+
+        Self {
+            serial: state.device_data.serial_number,
+            saved_at: now_ms(),
+            digital_outputs,
+            pwm_period: state.pwm.pwm_period,
+            pwm_enabled_channels: state.pwm.enabled_channels,
+            pwm_duties: state.pwm.pwm_values.iter().copied().enumerate().collect(),
+            custom_values: state
+                .custom_keys()
+                .filter_map(|key| Some((key.clone(), state.get_custom_json(key)?)))
+                .collect(),
+            servo_positions: state
+                .servo_positions
+                .iter()
+                .map(|(pin, angle)| (*pin, *angle))
+                .collect(),
+        }
+    }
+
+    /// Check if the persisted values are equal, ignoring the save time
+    pub fn same_values(&self, other: &Self) -> bool {
+        self.serial == other.serial
+            && self.digital_outputs == other.digital_outputs
+            && self.pwm_period == other.pwm_period
+            && self.pwm_enabled_channels == other.pwm_enabled_channels
+            && self.pwm_duties == other.pwm_duties
+            && self.custom_values == other.custom_values
+            && self.servo_positions == other.servo_positions
+    }
+}
+
+/// Saves and loads the persisted state of one device
+pub struct StatePersister {
+    config: PersistenceConfig,
+    path: PathBuf,
+    last_saved: Option<PersistedState>,
+    last_save: Option<Instant>,
+    last_generation: Option<u64>,
+}
+
+impl StatePersister {
+    /// Create a persister for the device with the given serial number
+    pub fn new(config: PersistenceConfig, serial: u32) -> Self {
+        let path = config.path_for(serial);
+        Self {
+            config,
+            path,
+            last_saved: None,
+            last_save: None,
+            last_generation: None,
+        }
+    }
+
+    /// Get the state file
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Get the configuration
+    pub fn config(&self) -> &PersistenceConfig {
+        &self.config
+    }
+
+    /// Load the saved state
+    ///
+    /// Returns None if nothing has been saved for the device yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read or parsed.
+    pub fn load(&self) -> Result<Option<PersistedState>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_str(&contents).map(Some).map_err(|e| {
+            ThreadError::StateError(format!("Invalid state file {}: {e}", self.path.display()))
+        })
+    }
+
+    /// Save the state if the persisted values changed and the mode allows it
+    ///
+    /// # Parameters
+    ///
+    /// * `state` - The current device state.
+    /// * `generation` - The shared state generation, used to skip unchanged states.
+    ///
+    /// # Returns
+    ///
+    /// True if the state file was written.
+    pub fn maybe_save(&mut self, state: &DeviceState, generation: u64) -> Result<bool> {
+        if self.last_generation == Some(generation) {
+            return Ok(false);
+        }
+        if let (PersistMode::Interval(interval), Some(last_save)) =
+            (self.config.mode, self.last_save)
+        {
+            if last_save.elapsed() < interval {
+                return Ok(false);
+            }
+        }
+
+        self.last_generation = Some(generation);
+        self.save_if_changed(PersistedState::from_state(state))
+    }
+
+    /// Save the state if the persisted values changed, regardless of the mode
+    pub fn save_now(&mut self, state: &DeviceState) -> Result<bool> {
+        self.save_if_changed(PersistedState::from_state(state))
+    }
+
+    fn save_if_changed(&mut self, persisted: PersistedState) -> Result<bool> {
+        if self
+            .last_saved
+            .as_ref()
+            .is_some_and(|last| last.same_values(&persisted))
+        {
+            return Ok(false);
+        }
+
+        fs::create_dir_all(&self.config.directory)?;
+        let contents = serde_json::to_string_pretty(&persisted)
+            .map_err(|e| ThreadError::OperationFailed(e.to_string()))?;
+
+        // Write to a temporary file first so a crash never leaves a truncated state file
+        let temp_path = self.path.with_extension("json.tmp");
+        fs::write(&temp_path, contents)?;
+        fs::rename(&temp_path, &self.path)?;
+
+        self.last_saved = Some(persisted);
+        self.last_save = Some(Instant::now());
+        Ok(true)
+    }
+
+    /// Mark a loaded state as already saved so it is not written back unchanged
+    pub fn mark_saved(&mut self, persisted: PersistedState) {
+        self.last_saved = Some(persisted);
+    }
+}
@@ -0,0 +1,653 @@
+//! PWM channel mapping and configuration
+//!
+//! PWM channels are the hardware channels of the device: channel 0 is pin 22,
+//! channel 1 pin 21 and so on down to channel 5 on pin 17. The device model
+//! decides which of these channels exist; without a model all six are used.
+//! Duties, enabled channels and the PWM state are all indexed by channel.
+//!
+//! Periods and duty values are in ticks of the PWM clock; [`PwmPeriod`] and
+//! [`PwmDuty`] convert from frequencies, percentages and microseconds.
+//!
+//! [`PwmWaveform`] describes fades, ramps, sine waves and step sequences that
+//! [`PwmAnimations`] evaluates on every worker cycle.
+
+use crate::capabilities;
+use crate::error::{Result, ThreadError};
+use pokeys_lib::models::DeviceModel;
+use pokeys_lib::PinCapability;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+/// PWM clock frequency in Hz
+pub const PWM_CLOCK_HZ: u32 = 25_000_000;
+
+/// Pins of the hardware PWM channels 0-5
+const STANDARD_PWM_PINS: [u8; 6] = [22, 21, 20, 19, 18, 17];
+
+/// Map of PWM channels to pins
+///
+/// Channel numbers are fixed by the hardware; a map only records which of
+/// them the device has.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PwmChannelMap {
+    pins: Vec<Option<u8>>,
+}
+
+impl PwmChannelMap {
+    /// Get the standard map of PoKeys56/57 devices: 0->22, 1->21, ... 5->17
+    pub fn standard() -> Self {
+        Self {
+            pins: STANDARD_PWM_PINS.iter().copied().map(Some).collect(),
+        }
+    }
+
+    /// Keep the channels whose pins the device model marks as `PwmOutput`
+    ///
+    /// Model pins outside the hardware channels are ignored, the device can
+    /// only generate PWM on pins 17-22.
+    pub fn from_model(model: &DeviceModel) -> Self {
+        let pwm_pins = capabilities::pins_with_capability(model, PinCapability::PwmOutput);
+        Self {
+            pins: STANDARD_PWM_PINS
+                .iter()
+                .map(|pin| pwm_pins.contains(pin).then_some(*pin))
+                .collect(),
+        }
+    }
+
+    /// Derive the map from a device model if one is loaded, otherwise use the standard map
+    pub fn for_model(model: Option<&DeviceModel>) -> Self {
+        model.map_or_else(Self::standard, Self::from_model)
+    }
+
+    /// Get the pin of a channel
+    pub fn pin(&self, channel: usize) -> Option<u8> {
+        self.pins.get(channel).copied().flatten()
+    }
+
+    /// Get the channel of a pin
+    pub fn channel(&self, pin: u8) -> Option<usize> {
+        self.pins.iter().position(|&p| p == Some(pin))
+    }
+
+    /// Get the channels of the device
+    pub fn channels(&self) -> Vec<usize> {
+        (0..self.pins.len())
+            .filter(|&channel| self.pins[channel].is_some())
+            .collect()
+    }
+
+    /// Get the pins of the device's channels, in channel order
+    pub fn pins(&self) -> Vec<u8> {
+        self.pins.iter().flatten().copied().collect()
+    }
+
+    /// Get the number of channels
+    pub fn len(&self) -> usize {
+        self.pins.iter().flatten().count()
+    }
+
+    /// Check if the device has no PWM channels
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the pin of a channel, or an error if the device does not have it
+    pub fn check_channel(&self, channel: usize) -> Result<u8> {
+        self.pin(channel).ok_or_else(|| {
+            ThreadError::validation_error(
+                &format!("PWM channel {channel} does not exist on this device"),
+                "pwm",
+                Some(&recovery("channels", self.channels())),
+            )
+        })
+    }
+
+    /// Get the channel of a pin, or an error if the pin has no PWM channel
+    pub fn check_pin(&self, pin: u8) -> Result<usize> {
+        self.channel(pin).ok_or_else(|| {
+            ThreadError::pin_capability_error(pin, "PWM", Some(recovery("pins", self.pins())))
+        })
+    }
+}
+
+/// Suggest the PWM channels or pins the device has
+fn recovery<T: ToString>(kind: &str, values: Vec<T>) -> String {
+    if values.is_empty() {
+        return "This device has no PWM channels".to_string();
+    }
+    let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+    format!("Use PWM {kind} {}", values.join(", "))
+}
+
+impl Default for PwmChannelMap {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+/// PWM period, shared by all channels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PwmPeriod {
+    /// Period in PWM clock ticks
+    Ticks(u32),
+    /// Frequency in Hz
+    Frequency(f64),
+}
+
+impl PwmPeriod {
+    /// Get the period in PWM clock ticks
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the period is zero or the frequency is out of range.
+    pub fn ticks(&self) -> Result<u32> {
+        let ticks = match *self {
+            Self::Ticks(ticks) => ticks,
+            Self::Frequency(hz) => {
+                if !hz.is_finite() || hz <= 0.0 {
+                    return Err(ThreadError::InvalidParameter(format!(
+                        "Invalid PWM frequency {hz} Hz"
+                    )));
+                }
+                let ticks = (f64::from(PWM_CLOCK_HZ) / hz).round();
+                if ticks > f64::from(u32::MAX) {
+                    return Err(ThreadError::InvalidParameter(format!(
+                        "PWM frequency {hz} Hz is too low"
+                    )));
+                }
+                ticks as u32
+            }
+        };
+        if ticks == 0 {
+            return Err(ThreadError::InvalidParameter(
+                "PWM period must be greater than zero".to_string(),
+            ));
+        }
+        Ok(ticks)
+    }
+}
+
+/// PWM period and enabled channels
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PwmConfig {
+    /// PWM period
+    pub period: PwmPeriod,
+    /// Channels to enable; all other channels are disabled
+    pub enabled_channels: Vec<usize>,
+}
+
+impl PwmConfig {
+    /// Create a configuration with no channels enabled
+    pub fn new(period: PwmPeriod) -> Self {
+        Self {
+            period,
+            enabled_channels: Vec::new(),
+        }
+    }
+
+    /// Create a configuration from a frequency in Hz
+    pub fn frequency(hz: f64) -> Self {
+        Self::new(PwmPeriod::Frequency(hz))
+    }
+
+    /// Enable a channel
+    pub fn channel(mut self, channel: usize) -> Self {
+        if !self.enabled_channels.contains(&channel) {
+            self.enabled_channels.push(channel);
+        }
+        self
+    }
+
+    /// Enable several channels
+    pub fn channels(mut self, channels: impl IntoIterator<Item = usize>) -> Self {
+        for channel in channels {
+            self = self.channel(channel);
+        }
+        self
+    }
+
+    /// Validate the configuration against a channel map
+    ///
+    /// # Returns
+    ///
+    /// The period in PWM clock ticks.
+    pub fn validate(&self, map: &PwmChannelMap) -> Result<u32> {
+        for &channel in &self.enabled_channels {
+            map.check_channel(channel)?;
+        }
+        self.period.ticks()
+    }
+}
+
+/// PWM duty cycle in one of several units
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PwmDuty {
+    /// Duty in PWM clock ticks
+    Ticks(u32),
+    /// Duty as a percentage of the period (0-100)
+    Percent(f32),
+    /// High time in microseconds
+    Micros(f64),
+}
+
+impl PwmDuty {
+    /// Get the duty in PWM clock ticks for a period
+    ///
+    /// # Parameters
+    ///
+    /// * `period` - The configured PWM period in ticks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value is out of range, exceeds the period, or
+    /// needs the period to convert and no period is configured.
+    pub fn ticks(&self, period: u32) -> Result<u32> {
+        let ticks = match *self {
+            Self::Ticks(ticks) => ticks,
+            Self::Percent(percent) => {
+                if !(0.0..=100.0).contains(&percent) {
+                    return Err(ThreadError::InvalidParameter(format!(
+                        "PWM duty cycle {percent}% is outside 0-100%"
+                    )));
+                }
+                require_period(period)?;
+                (f64::from(percent) / 100.0 * f64::from(period)).round() as u32
+            }
+            Self::Micros(micros) => {
+                if !micros.is_finite() || micros < 0.0 {
+                    return Err(ThreadError::InvalidParameter(format!(
+                        "Invalid PWM high time {micros} us"
+                    )));
+                }
+                (micros * f64::from(PWM_CLOCK_HZ) / 1_000_000.0).round() as u32
+            }
+        };
+        if period > 0 && ticks > period {
+            return Err(ThreadError::validation_error(
+                &format!("PWM duty {ticks} exceeds the period {period}"),
+                "pwm",
+                Some("Use a duty value within the configured PWM period"),
+            ));
+        }
+        Ok(ticks)
+    }
+}
+
+/// Shape of a PWM ramp
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RampCurve {
+    /// Constant rate of change
+    #[default]
+    Linear,
+    /// Slow start and fast finish, which looks even when dimming lamps
+    Exponential,
+}
+
+impl RampCurve {
+    /// Get the fraction of the ramp covered at `t` (0-1) of its duration
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::Exponential => (2f64.powf(10.0 * t) - 1.0) / 1023.0,
+        }
+    }
+}
+
+/// PWM animation executed by the device worker
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PwmWaveform {
+    /// Ramp from one duty to another over a duration, then hold the target
+    Ramp {
+        from: PwmDuty,
+        to: PwmDuty,
+        duration: Duration,
+        curve: RampCurve,
+    },
+    /// Sine wave between two duties, starting at `min`; runs until cancelled
+    /// if `cycles` is None
+    Sine {
+        min: PwmDuty,
+        max: PwmDuty,
+        period: Duration,
+        cycles: Option<u32>,
+    },
+    /// Duties held for a duration each, optionally repeated until cancelled
+    Steps {
+        steps: Vec<(PwmDuty, Duration)>,
+        repeat: bool,
+    },
+}
+
+impl PwmWaveform {
+    /// Create a linear ramp
+    pub fn ramp(from: PwmDuty, to: PwmDuty, duration: Duration) -> Self {
+        Self::Ramp {
+            from,
+            to,
+            duration,
+            curve: RampCurve::Linear,
+        }
+    }
+
+    /// Create an exponential ramp
+    pub fn exponential_ramp(from: PwmDuty, to: PwmDuty, duration: Duration) -> Self {
+        Self::Ramp {
+            from,
+            to,
+            duration,
+            curve: RampCurve::Exponential,
+        }
+    }
+
+    /// Create a breathing effect: a sine wave from off to `max` that runs until cancelled
+    pub fn breathe(max: PwmDuty, period: Duration) -> Self {
+        Self::Sine {
+            min: PwmDuty::Ticks(0),
+            max,
+            period,
+            cycles: None,
+        }
+    }
+
+    /// Create a step sequence that runs once
+    pub fn steps(steps: Vec<(PwmDuty, Duration)>) -> Self {
+        Self::Steps {
+            steps,
+            repeat: false,
+        }
+    }
+
+    /// Validate the waveform against a PWM period
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a duty does not convert for the period, the sine
+    /// period or cycle count is zero, or the step sequence is empty or repeats
+    /// with zero length.
+    pub fn validate(&self, period: u32) -> Result<()> {
+        self.resolve(period).map(|_| ())
+    }
+
+    /// Convert all duties to PWM clock ticks
+    fn resolve(&self, period: u32) -> Result<ResolvedWaveform> {
+        match self {
+            Self::Ramp {
+                from,
+                to,
+                duration,
+                curve,
+            } => Ok(ResolvedWaveform::Ramp {
+                from: f64::from(from.ticks(period)?),
+                to: f64::from(to.ticks(period)?),
+                duration: *duration,
+                curve: *curve,
+            }),
+            Self::Sine {
+                min,
+                max,
+                period: wave_period,
+                cycles,
+            } => {
+                if wave_period.is_zero() {
+                    return Err(ThreadError::InvalidParameter(
+                        "Sine period must be greater than zero".to_string(),
+                    ));
+                }
+                if *cycles == Some(0) {
+                    return Err(ThreadError::InvalidParameter(
+                        "Sine cycle count must be greater than zero".to_string(),
+                    ));
+                }
+                Ok(ResolvedWaveform::Sine {
+                    min: f64::from(min.ticks(period)?),
+                    max: f64::from(max.ticks(period)?),
+                    period: *wave_period,
+                    cycles: *cycles,
+                })
+            }
+            Self::Steps { steps, repeat } => {
+                if steps.is_empty() {
+                    return Err(ThreadError::InvalidParameter(
+                        "Step sequence must not be empty".to_string(),
+                    ));
+                }
+                if *repeat && steps.iter().all(|(_, hold)| hold.is_zero()) {
+                    return Err(ThreadError::InvalidParameter(
+                        "Repeated step sequence must have a non-zero length".to_string(),
+                    ));
+                }
+                let steps = steps
+                    .iter()
+                    .map(|(duty, hold)| Ok((duty.ticks(period)?, *hold)))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(ResolvedWaveform::Steps {
+                    steps,
+                    repeat: *repeat,
+                })
+            }
+        }
+    }
+}
+
+/// Waveform with duties in PWM clock ticks
+#[derive(Debug, Clone)]
+enum ResolvedWaveform {
+    Ramp {
+        from: f64,
+        to: f64,
+        duration: Duration,
+        curve: RampCurve,
+    },
+    Sine {
+        min: f64,
+        max: f64,
+        period: Duration,
+        cycles: Option<u32>,
+    },
+    Steps {
+        steps: Vec<(u32, Duration)>,
+        repeat: bool,
+    },
+}
+
+impl ResolvedWaveform {
+    /// Get the duty after `elapsed` and whether the waveform has finished
+    fn sample(&self, elapsed: Duration) -> (u32, bool) {
+        match self {
+            Self::Ramp {
+                from,
+                to,
+                duration,
+                curve,
+            } => {
+                if elapsed >= *duration {
+                    return (to.round() as u32, true);
+                }
+                let t = elapsed.as_secs_f64() / duration.as_secs_f64();
+                ((from + (to - from) * curve.apply(t)).round() as u32, false)
+            }
+            Self::Sine {
+                min,
+                max,
+                period,
+                cycles,
+            } => {
+                let phase = elapsed.as_secs_f64() / period.as_secs_f64();
+                if let Some(cycles) = cycles {
+                    if phase >= f64::from(*cycles) {
+                        return (min.round() as u32, true);
+                    }
+                }
+                let level = (1.0 - (2.0 * PI * phase).cos()) / 2.0;
+                ((min + (max - min) * level).round() as u32, false)
+            }
+            Self::Steps { steps, repeat } => {
+                let total: Duration = steps.iter().map(|(_, hold)| *hold).sum();
+                let mut t = elapsed;
+                if *repeat {
+                    t = Duration::from_nanos((t.as_nanos() % total.as_nanos()) as u64);
+                } else if t >= total {
+                    return (steps[steps.len() - 1].0, true);
+                }
+                for &(duty, hold) in steps {
+                    if t < hold {
+                        return (duty, false);
+                    }
+                    t -= hold;
+                }
+                (steps[steps.len() - 1].0, false)
+            }
+        }
+    }
+}
+
+/// A PWM animation in progress
+#[derive(Debug, Clone)]
+struct PwmAnimation {
+    waveform: ResolvedWaveform,
+    started: Instant,
+}
+
+impl PwmAnimation {
+    fn sample(&self, now: Instant) -> (u32, bool) {
+        self.waveform
+            .sample(now.saturating_duration_since(self.started))
+    }
+}
+
+/// Progress of a PWM animation after a step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PwmStep {
+    /// PWM channel
+    pub channel: usize,
+    /// Duty to set, in PWM clock ticks
+    pub duty: u32,
+    /// True if the animation finished
+    pub complete: bool,
+}
+
+/// PWM animations in progress, evaluated on every worker cycle
+#[derive(Debug, Default)]
+pub struct PwmAnimations {
+    animations: HashMap<usize, PwmAnimation>,
+}
+
+impl PwmAnimations {
+    /// Create an empty set of animations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start an animation, replacing any animation already running on the channel
+    ///
+    /// # Parameters
+    ///
+    /// * `channel` - The PWM channel to animate.
+    /// * `waveform` - The waveform to run.
+    /// * `period` - The PWM period used to convert duties to ticks.
+    /// * `now` - Start time of the animation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the waveform is invalid for the period.
+    pub fn start(
+        &mut self,
+        channel: usize,
+        waveform: &PwmWaveform,
+        period: u32,
+        now: Instant,
+    ) -> Result<()> {
+        let waveform = waveform.resolve(period)?;
+        self.animations.insert(
+            channel,
+            PwmAnimation {
+                waveform,
+                started: now,
+            },
+        );
+        Ok(())
+    }
+
+    /// Cancel the animation of a channel
+    ///
+    /// # Returns
+    ///
+    /// The duty reached when cancelled, or None if the channel was not animated.
+    pub fn cancel(&mut self, channel: usize, now: Instant) -> Option<u32> {
+        self.animations
+            .remove(&channel)
+            .map(|animation| animation.sample(now).0)
+    }
+
+    /// Get the current duty of an animated channel
+    pub fn duty(&self, channel: usize, now: Instant) -> Option<u32> {
+        self.animations
+            .get(&channel)
+            .map(|animation| animation.sample(now).0)
+    }
+
+    /// Delay all animations by `paused`, so they resume where they stopped
+    pub fn delay(&mut self, paused: Duration) {
+        for animation in self.animations.values_mut() {
+            animation.started += paused;
+        }
+    }
+
+    /// Check if a channel is animated
+    pub fn is_running(&self, channel: usize) -> bool {
+        self.animations.contains_key(&channel)
+    }
+
+    /// Check if no channel is animated
+    pub fn is_empty(&self) -> bool {
+        self.animations.is_empty()
+    }
+
+    /// Get the animated channels
+    pub fn channels(&self) -> Vec<usize> {
+        let mut channels: Vec<usize> = self.animations.keys().copied().collect();
+        channels.sort_unstable();
+        channels
+    }
+
+    /// Advance all animations to `now`, removing the ones that finished
+    ///
+    /// # Returns
+    ///
+    /// The duty to set on each animated channel, in channel order.
+    pub fn step(&mut self, now: Instant) -> Vec<PwmStep> {
+        let steps: Vec<PwmStep> = self
+            .channels()
+            .into_iter()
+            .map(|channel| {
+                let (duty, complete) = self.animations[&channel].sample(now);
+                PwmStep {
+                    channel,
+                    duty,
+                    complete,
+                }
+            })
+            .collect();
+        self.animations.retain(|channel, _| {
+            !steps
+                .iter()
+                .any(|step| step.channel == *channel && step.complete)
+        });
+        steps
+    }
+}
+
+fn require_period(period: u32) -> Result<()> {
+    if period == 0 {
+        return Err(ThreadError::validation_error(
+            "PWM period is not configured",
+            "pwm",
+            Some("Configure the PWM period with configure_pwm first"),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,416 @@
+//! Data recorder
+//!
+//! Continuously samples a chosen set of pins, encoders and PWM channels from
+//! one or more device threads and writes them to rotating CSV or JSON Lines
+//! files. Each row carries a timestamp, the thread ID and the device serial
+//! number. The recorder runs in its own thread and flushes and closes the
+//! current file when stopped or dropped.
+
+use crate::error::{Result, ThreadError};
+use crate::history::{now_ms, HistoryChannel};
+use crate::state::SharedDeviceState;
+use crossbeam_channel::{bounded, RecvTimeoutError, Sender};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Output file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordFormat {
+    /// Comma-separated values with a header row per file
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+impl RecordFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::JsonLines => "jsonl",
+        }
+    }
+}
+
+/// When rows are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SampleMode {
+    /// Write a row for every thread at a fixed interval
+    FixedRate(Duration),
+    /// Poll at the given interval and write a row only when a value changed
+    OnChange(Duration),
+}
+
+impl SampleMode {
+    fn interval(&self) -> Duration {
+        match *self {
+            Self::FixedRate(interval) | Self::OnChange(interval) => interval,
+        }
+    }
+}
+
+/// Data recorder configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecorderConfig {
+    /// Directory the files are written to
+    pub directory: PathBuf,
+    /// File name prefix
+    pub file_prefix: String,
+    /// Output file format
+    pub format: RecordFormat,
+    /// Sampling mode
+    pub mode: SampleMode,
+    /// Recorded channels, in column order
+    pub channels: Vec<HistoryChannel>,
+    /// Size in bytes after which a new file is started
+    pub max_file_size: u64,
+    /// Number of files kept; older files are deleted (0 keeps all)
+    pub max_files: usize,
+}
+
+impl RecorderConfig {
+    /// Create a configuration writing CSV files at 10 Hz to `directory`
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            file_prefix: "pokeys".to_string(),
+            format: RecordFormat::Csv,
+            mode: SampleMode::FixedRate(Duration::from_millis(100)),
+            channels: Vec::new(),
+            max_file_size: 10 * 1024 * 1024,
+            max_files: 10,
+        }
+    }
+
+    /// Set the file name prefix
+    pub fn file_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.file_prefix = prefix.into();
+        self
+    }
+
+    /// Set the output file format
+    pub fn format(mut self, format: RecordFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the sampling mode
+    pub fn mode(mut self, mode: SampleMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Add a recorded channel
+    pub fn channel(mut self, channel: HistoryChannel) -> Self {
+        self.channels.push(channel);
+        self
+    }
+
+    /// Add several recorded channels
+    pub fn channels(mut self, channels: impl IntoIterator<Item = HistoryChannel>) -> Self {
+        self.channels.extend(channels);
+        self
+    }
+
+    /// Set the rotation size and the number of files kept
+    pub fn rotation(mut self, max_file_size: u64, max_files: usize) -> Self {
+        self.max_file_size = max_file_size;
+        self.max_files = max_files;
+        self
+    }
+
+    /// Validate the configuration
+    pub fn validate(&self) -> Result<()> {
+        if self.channels.is_empty() {
+            return Err(ThreadError::ConfigurationError(
+                "At least one channel must be recorded".to_string(),
+            ));
+        }
+        if self.mode.interval().is_zero() {
+            return Err(ThreadError::ConfigurationError(
+                "Sample interval must be greater than zero".to_string(),
+            ));
+        }
+        if self.max_file_size == 0 {
+            return Err(ThreadError::ConfigurationError(
+                "Maximum file size must be greater than zero".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A recorded row
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordRow {
+    /// Timestamp in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Thread ID
+    pub thread_id: u32,
+    /// Device serial number
+    pub serial: u32,
+    /// Channel values keyed by channel name; missing channels are omitted
+    pub values: BTreeMap<String, i64>,
+}
+
+/// Writes rows to rotating files
+struct RotatingWriter {
+    config: RecorderConfig,
+    writer: Option<BufWriter<File>>,
+    written: u64,
+    sequence: u32,
+    files: VecDeque<PathBuf>,
+}
+
+impl RotatingWriter {
+    fn new(config: RecorderConfig) -> Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+        Ok(Self {
+            config,
+            writer: None,
+            written: 0,
+            sequence: 0,
+            files: VecDeque::new(),
+        })
+    }
+
+    fn open_next(&mut self) -> Result<()> {
+        self.close()?;
+
+        let path = self.config.directory.join(format!(
+            "{}-{}-{:04}.{}",
+            self.config.file_prefix,
+            now_ms(),
+            self.sequence,
+            self.config.format.extension()
+        ));
+        self.sequence += 1;
+        debug!("Recorder opening {}", path.display());
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+        self.written = 0;
+        if self.config.format == RecordFormat::Csv {
+            let header = csv_header(&self.config.channels);
+            writer.write_all(header.as_bytes())?;
+            self.written += header.len() as u64;
+        }
+        self.writer = Some(writer);
+
+        self.files.push_back(path);
+        if self.config.max_files > 0 {
+            while self.files.len() > self.config.max_files {
+                if let Some(old) = self.files.pop_front() {
+                    fs::remove_file(old)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &RecordRow) -> Result<()> {
+        let line = match self.config.format {
+            RecordFormat::Csv => csv_row(&self.config.channels, row),
+            RecordFormat::JsonLines => {
+                let mut line = serde_json::to_string(row)
+                    .map_err(|e| ThreadError::OperationFailed(e.to_string()))?;
+                line.push('\n');
+                line
+            }
+        };
+
+        if self.writer.is_none() || self.written >= self.config.max_file_size {
+            self.open_next()?;
+        }
+        if let Some(writer) = self.writer.as_mut() {
+            writer.write_all(line.as_bytes())?;
+            self.written += line.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn csv_header(channels: &[HistoryChannel]) -> String {
+    let mut header = String::from("timestamp,thread_id,serial");
+    for channel in channels {
+        header.push(',');
+        header.push_str(&channel.to_string());
+    }
+    header.push('\n');
+    header
+}
+
+fn csv_row(channels: &[HistoryChannel], row: &RecordRow) -> String {
+    let mut line = format!("{},{},{}", row.timestamp, row.thread_id, row.serial);
+    for channel in channels {
+        line.push(',');
+        if let Some(value) = row.values.get(&channel.to_string()) {
+            line.push_str(&value.to_string());
+        }
+    }
+    line.push('\n');
+    line
+}
+
+/// A recorded device thread
+struct RecordSource {
+    thread_id: u32,
+    shared_state: Arc<SharedDeviceState>,
+    last_values: Option<BTreeMap<String, i64>>,
+}
+
+/// Handle to a running data recorder
+///
+/// The recorder stops when [`DataRecorder::stop`] is called or the handle is dropped.
+pub struct DataRecorder {
+    stop_tx: Option<Sender<()>>,
+    handle: Option<JoinHandle<Result<()>>>,
+    directory: PathBuf,
+}
+
+impl DataRecorder {
+    /// Start recording from the given device threads
+    ///
+    /// # Parameters
+    ///
+    /// * `config` - The recorder configuration.
+    /// * `sources` - The thread IDs and shared states to sample.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration is invalid or the output
+    /// directory cannot be created.
+    pub fn start(
+        config: RecorderConfig,
+        sources: Vec<(u32, Arc<SharedDeviceState>)>,
+    ) -> Result<Self> {
+        config.validate()?;
+        if sources.is_empty() {
+            return Err(ThreadError::ConfigurationError(
+                "At least one thread must be recorded".to_string(),
+            ));
+        }
+
+        let directory = config.directory.clone();
+        let mut writer = RotatingWriter::new(config.clone())?;
+        let mut sources: Vec<RecordSource> = sources
+            .into_iter()
+            .map(|(thread_id, shared_state)| RecordSource {
+                thread_id,
+                shared_state,
+                last_values: None,
+            })
+            .collect();
+
+        let (stop_tx, stop_rx) = bounded::<()>(1);
+        let handle = thread::Builder::new()
+            .name("pokeys-recorder".to_string())
+            .spawn(move || {
+                let interval = config.mode.interval();
+                let on_change = matches!(config.mode, SampleMode::OnChange(_));
+                loop {
+                    for source in sources.iter_mut() {
+                        let row = sample(source, &config.channels);
+                        if on_change && source.last_values.as_ref() == Some(&row.values) {
+                            continue;
+                        }
+                        if let Err(e) = writer.write_row(&row) {
+                            error!("Recorder failed to write row: {e}");
+                            return Err(e);
+                        }
+                        source.last_values = Some(row.values);
+                    }
+                    writer.flush()?;
+
+                    match stop_rx.recv_timeout(interval) {
+                        Err(RecvTimeoutError::Timeout) => {}
+                        _ => break,
+                    }
+                }
+                writer.close()
+            })
+            .map_err(|e| ThreadError::ThreadCreationFailed(e.to_string()))?;
+
+        Ok(Self {
+            stop_tx: Some(stop_tx),
+            handle: Some(handle),
+            directory,
+        })
+    }
+
+    /// Get the output directory
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Check if the recorder is still running
+    pub fn is_running(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Stop recording, flush and close the current file
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that stopped the recorder, if any.
+    pub fn stop(mut self) -> Result<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        // Dropping the sender wakes the recorder thread immediately
+        self.stop_tx.take();
+        match self.handle.take() {
+            Some(handle) => handle.join().map_err(|_| ThreadError::ThreadJoinError)?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for DataRecorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.shutdown() {
+            error!("Recorder stopped with error: {e}");
+        }
+    }
+}
+
+fn sample(source: &RecordSource, channels: &[HistoryChannel]) -> RecordRow {
+    let state = source.shared_state.snapshot();
+    let values = channels
+        .iter()
+        .filter_map(|channel| {
+            channel
+                .value_in(&state)
+                .map(|value| (channel.to_string(), value))
+        })
+        .collect();
+
+    RecordRow {
+        timestamp: now_ms(),
+        thread_id: source.thread_id,
+        serial: state.device_data.serial_number,
+        values,
+    }
+}
@@ -0,0 +1,346 @@
+//! Device resource allocation
+//!
+//! Modules sharing a device claim the pins, PWM channels, encoders and buses
+//! they use before configuring them. A claim that overlaps a resource held by
+//! another owner is rejected with [`ThreadError::ResourceConflict`], and the
+//! resources are released when the [`ResourceClaim`] is dropped.
+//!
+//! Only the holder of a claim may configure its resources: the device thread
+//! rejects pin functions, encoder, servo and PWM configurations on claimed
+//! resources unless the command is wrapped in [`DeviceCommand::Claimed`] with
+//! the [`ClaimId`] of the claim, for example by
+//! `DeviceOperations::send_claimed_command`. Claim IDs cannot be made up, so
+//! another module claiming under the same owner name cannot act for the claim.
+//!
+//! Exclusive claims back output leases: while held, writes to the resources
+//! from anyone but the holder are rejected, whether they come from the
+//! controller, a device handle or a raw command.
+
+use crate::commands::DeviceCommand;
+use crate::error::{Result, ThreadError};
+use crate::pwm::PwmChannelMap;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+
+/// A device resource that can be claimed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Resource {
+    /// Pin by number
+    Pin(u8),
+    /// PWM channel
+    PwmChannel(usize),
+    /// Encoder by index
+    Encoder(u32),
+    /// I2C bus
+    I2cBus,
+    /// SPI bus
+    SpiBus,
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pin(pin) => write!(f, "pin {pin}"),
+            Self::PwmChannel(channel) => write!(f, "PWM channel {channel}"),
+            Self::Encoder(index) => write!(f, "encoder {index}"),
+            Self::I2cBus => write!(f, "I2C bus"),
+            Self::SpiBus => write!(f, "SPI bus"),
+        }
+    }
+}
+
+/// Get the resource of a pin number; pins beyond `u8` cannot be claimed
+pub(crate) fn pin_resource(pin: u32) -> Option<Resource> {
+    u8::try_from(pin).ok().map(Resource::Pin)
+}
+
+/// Identity of one claim
+///
+/// IDs are only handed out with a [`ResourceClaim`]; deserialized commands
+/// carry the detached ID, which matches no claim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClaimId(u64);
+
+impl ClaimId {
+    /// The ID of no claim
+    pub(crate) fn detached() -> Self {
+        Self(0)
+    }
+
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Holder of a claimed resource
+#[derive(Debug, Clone)]
+struct ClaimEntry {
+    id: ClaimId,
+    owner: String,
+    /// Only the holder may write the resource
+    exclusive: bool,
+}
+
+type Claims = Mutex<HashMap<Resource, ClaimEntry>>;
+
+/// Tracks the owners of the resources of one device
+#[derive(Debug, Clone, Default)]
+pub struct ResourceManager {
+    claims: Arc<Claims>,
+}
+
+impl ResourceManager {
+    /// Create a manager with no claims
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Claim resources for an owner
+    ///
+    /// Either all resources are claimed or none are.
+    ///
+    /// # Parameters
+    ///
+    /// * `owner` - Name of the claiming module, reported in conflicts.
+    /// * `resources` - The resources to claim.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ThreadError::ResourceConflict` if a resource is already claimed.
+    pub fn claim(&self, owner: &str, resources: &[Resource]) -> Result<ResourceClaim> {
+        self.claim_with(owner, resources, false)
+    }
+
+    /// Claim resources for exclusive writing by an owner
+    ///
+    /// Like [`claim`](Self::claim), but [`check_writable`](Self::check_writable)
+    /// rejects writes to the resources until the claim is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ThreadError::ResourceConflict` if a resource is already claimed.
+    pub fn claim_exclusive(&self, owner: &str, resources: &[Resource]) -> Result<ResourceClaim> {
+        self.claim_with(owner, resources, true)
+    }
+
+    fn claim_with(
+        &self,
+        owner: &str,
+        resources: &[Resource],
+        exclusive: bool,
+    ) -> Result<ResourceClaim> {
+        let mut resources = resources.to_vec();
+        resources.sort_unstable();
+        resources.dedup();
+
+        let mut claims = self.claims.lock();
+        if let Some((resource, current)) = resources
+            .iter()
+            .find_map(|resource| claims.get(resource).map(|current| (resource, current)))
+        {
+            return Err(ThreadError::ResourceConflict {
+                message: format!("{resource} is already claimed by {}", current.owner),
+                resource: resource.to_string(),
+                conflicting_operation: format!("claim by {owner}"),
+            });
+        }
+        let id = ClaimId::next();
+        for &resource in &resources {
+            claims.insert(
+                resource,
+                ClaimEntry {
+                    id,
+                    owner: owner.to_string(),
+                    exclusive,
+                },
+            );
+        }
+
+        Ok(ResourceClaim {
+            claims: Arc::downgrade(&self.claims),
+            id,
+            owner: owner.to_string(),
+            resources,
+        })
+    }
+
+    /// Get the owner of a resource
+    pub fn owner(&self, resource: Resource) -> Option<String> {
+        self.claims
+            .lock()
+            .get(&resource)
+            .map(|entry| entry.owner.clone())
+    }
+
+    /// Check that a resource may be written outside of a lease
+    ///
+    /// # Errors
+    ///
+    /// Returns `ThreadError::ResourceConflict` if the resource is claimed exclusively.
+    pub fn check_writable(&self, resource: Resource, operation: &str) -> Result<()> {
+        self.check_writable_by(resource, None, operation)
+    }
+
+    /// Check that a resource may be written for a claim
+    ///
+    /// # Parameters
+    ///
+    /// * `resource` - The resource to write.
+    /// * `claim` - The claim writing it, or None outside of any lease.
+    /// * `operation` - Name of the operation, reported in the conflict.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ThreadError::ResourceConflict` if another claim leased the resource.
+    pub fn check_writable_by(
+        &self,
+        resource: Resource,
+        claim: Option<ClaimId>,
+        operation: &str,
+    ) -> Result<()> {
+        match self.claims.lock().get(&resource) {
+            Some(entry) if entry.exclusive && Some(entry.id) != claim => {
+                Err(ThreadError::ResourceConflict {
+                    message: format!("{resource} is leased by {}", entry.owner),
+                    resource: resource.to_string(),
+                    conflicting_operation: operation.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that a resource may be configured for a claim
+    ///
+    /// # Parameters
+    ///
+    /// * `resource` - The resource to configure.
+    /// * `claim` - The claim configuring it, or None outside of any claim.
+    /// * `operation` - Name of the operation, reported in the conflict.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ThreadError::ResourceConflict` if another claim holds the resource.
+    pub fn check_configurable(
+        &self,
+        resource: Resource,
+        claim: Option<ClaimId>,
+        operation: &str,
+    ) -> Result<()> {
+        match self.claims.lock().get(&resource) {
+            Some(entry) if Some(entry.id) != claim => Err(ThreadError::ResourceConflict {
+                message: format!("{resource} is claimed by {}", entry.owner),
+                resource: resource.to_string(),
+                conflicting_operation: operation.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that a command only configures and writes resources its sender may use
+    ///
+    /// A command wrapped in [`DeviceCommand::Claimed`] acts for its claim,
+    /// any other command for no claim.
+    ///
+    /// # Parameters
+    ///
+    /// * `command` - The command to check.
+    /// * `channels` - The PWM channel map of the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ThreadError::ResourceConflict` if the command configures a
+    /// resource held by another claim or writes an output leased by another
+    /// claim.
+    pub fn check_command(&self, command: &DeviceCommand, channels: &PwmChannelMap) -> Result<()> {
+        let (claim, command) = match command {
+            DeviceCommand::Claimed { claim, command } => (Some(*claim), command.as_ref()),
+            command => (None, command),
+        };
+        for resource in command.configured_resources(channels) {
+            self.check_configurable(resource, claim, command.name())?;
+        }
+        for resource in command.written_resources(channels) {
+            self.check_writable_by(resource, claim, command.name())?;
+        }
+        Ok(())
+    }
+
+    /// Check if a claim was made on this manager
+    pub fn holds(&self, claim: &ResourceClaim) -> bool {
+        std::ptr::eq(claim.claims.as_ptr(), Arc::as_ptr(&self.claims))
+    }
+
+    /// Check if a resource is claimed
+    pub fn is_claimed(&self, resource: Resource) -> bool {
+        self.claims.lock().contains_key(&resource)
+    }
+
+    /// Get all claimed resources and their owners, in resource order
+    pub fn claims(&self) -> Vec<(Resource, String)> {
+        let mut claims: Vec<(Resource, String)> = self
+            .claims
+            .lock()
+            .iter()
+            .map(|(resource, entry)| (*resource, entry.owner.clone()))
+            .collect();
+        claims.sort_unstable();
+        claims
+    }
+}
+
+/// Resources held by one owner
+///
+/// Dropping the claim releases the resources.
+#[derive(Debug)]
+pub struct ResourceClaim {
+    claims: Weak<Claims>,
+    id: ClaimId,
+    owner: String,
+    resources: Vec<Resource>,
+}
+
+impl ResourceClaim {
+    /// Get the ID of the claim, for wrapping commands in [`DeviceCommand::Claimed`]
+    pub fn id(&self) -> ClaimId {
+        self.id
+    }
+
+    /// A claim on no resources, for commands deserialized without their claim
+    pub(crate) fn detached() -> Arc<Self> {
+        Arc::new(Self {
+            claims: Weak::new(),
+            id: ClaimId::detached(),
+            owner: String::new(),
+            resources: Vec::new(),
+        })
+    }
+
+    /// Get the owner of the claim
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// Get the claimed resources
+    pub fn resources(&self) -> &[Resource] {
+        &self.resources
+    }
+
+    /// Release the resources
+    pub fn release(self) {}
+}
+
+impl Drop for ResourceClaim {
+    fn drop(&mut self) {
+        if let Some(claims) = self.claims.upgrade() {
+            let mut claims = claims.lock();
+            for resource in &self.resources {
+                claims.remove(resource);
+            }
+        }
+    }
+}
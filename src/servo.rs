@@ -0,0 +1,442 @@
+//! Servo registry
+//!
+//! Keeps the [`ServoConfig`] of every configured servo pin and converts angles
+//! and speeds to PWM duty values using the calibrated pulse widths of the
+//! servo type. Duty values are in PWM clock ticks, like the PWM period.
+//!
+//! [`ServoMotions`] interpolates moves with speed and acceleration limits so
+//! the worker can move servos smoothly instead of jumping to the target.
+
+use crate::error::{Result, ThreadError};
+use crate::pwm::PwmChannelMap;
+use pokeys_lib::{ServoConfig, ServoType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Convert a servo angle to a PWM duty value
+///
+/// # Errors
+///
+/// Returns an error if the angle is outside the range of the servo type or
+/// the servo is a speed servo.
+pub fn angle_to_duty(config: &ServoConfig, angle: f32) -> Result<u32> {
+    let (pos_0, pos_max, max_angle) = match config.servo_type {
+        ServoType::OneEighty { pos_0, pos_180 } => (pos_0, pos_180, 180.0),
+        ServoType::ThreeSixtyPosition { pos_0, pos_360 } => (pos_0, pos_360, 360.0),
+        ServoType::ThreeSixtySpeed { .. } => {
+            return Err(ThreadError::validation_error(
+                &format!("Servo on pin {} is a speed servo", config.pin),
+                "servo angle",
+                Some("Use set_servo_speed for continuous rotation servos"),
+            ));
+        }
+    };
+    if !(0.0..=max_angle).contains(&angle) {
+        return Err(ThreadError::InvalidParameter(format!(
+            "Servo angle {angle} is outside 0-{max_angle} degrees"
+        )));
+    }
+
+    Ok(interpolate(pos_0, pos_max, angle / max_angle))
+}
+
+/// Convert a servo speed (-100 to 100, positive is clockwise) to a PWM duty value
+///
+/// # Errors
+///
+/// Returns an error if the speed is out of range or the servo is a position servo.
+pub fn speed_to_duty(config: &ServoConfig, speed: f32) -> Result<u32> {
+    let ServoType::ThreeSixtySpeed {
+        stop,
+        clockwise,
+        anti_clockwise,
+    } = config.servo_type
+    else {
+        return Err(ThreadError::validation_error(
+            &format!("Servo on pin {} is a position servo", config.pin),
+            "servo speed",
+            Some("Use set_servo_angle for position servos"),
+        ));
+    };
+    if !(-100.0..=100.0).contains(&speed) {
+        return Err(ThreadError::InvalidParameter(format!(
+            "Servo speed {speed} is outside -100 to 100"
+        )));
+    }
+
+    let end = if speed >= 0.0 {
+        clockwise
+    } else {
+        anti_clockwise
+    };
+    Ok(interpolate(stop, end, speed.abs() / 100.0))
+}
+
+/// Get the PWM duty value that stops a speed servo
+///
+/// # Errors
+///
+/// Returns an error if the servo is a position servo.
+pub fn stop_duty(config: &ServoConfig) -> Result<u32> {
+    match config.servo_type {
+        ServoType::ThreeSixtySpeed { stop, .. } => Ok(stop),
+        _ => Err(ThreadError::validation_error(
+            &format!("Servo on pin {} is a position servo", config.pin),
+            "servo stop",
+            Some("Stop only applies to continuous rotation servos"),
+        )),
+    }
+}
+
+/// Linear interpolation between two duty values
+fn interpolate(from: u32, to: u32, fraction: f32) -> u32 {
+    let duty = f64::from(from) + (f64::from(to) - f64::from(from)) * f64::from(fraction);
+    duty.round() as u32
+}
+
+/// Servo configurations by pin
+#[derive(Debug, Default)]
+pub struct ServoRegistry {
+    servos: HashMap<u8, ServoConfig>,
+}
+
+impl ServoRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Validate and store the configuration of a servo pin
+    ///
+    /// # Parameters
+    ///
+    /// * `pin` - The PWM pin the servo is connected to; overrides `config.pin`.
+    /// * `config` - The servo type and calibrated duty values.
+    /// * `channels` - The PWM channel map of the device.
+    /// * `pwm_period` - The current PWM period in clock ticks, or 0 if unknown.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pin is not a PWM pin, the calibration range is
+    /// empty or a duty value exceeds the PWM period.
+    pub fn configure(
+        &mut self,
+        pin: u8,
+        mut config: ServoConfig,
+        channels: &PwmChannelMap,
+        pwm_period: u32,
+    ) -> Result<()> {
+        config.pin = pin;
+        channels.check_pin(pin)?;
+        validate(&config, pwm_period)?;
+        self.servos.insert(pin, config);
+        Ok(())
+    }
+
+    /// Remove a servo configuration
+    pub fn remove(&mut self, pin: u8) -> Option<ServoConfig> {
+        self.servos.remove(&pin)
+    }
+
+    /// Get the configuration of a servo pin
+    pub fn get(&self, pin: u8) -> Option<&ServoConfig> {
+        self.servos.get(&pin)
+    }
+
+    /// Get the configured servo pins
+    pub fn pins(&self) -> Vec<u8> {
+        let mut pins: Vec<u8> = self.servos.keys().copied().collect();
+        pins.sort_unstable();
+        pins
+    }
+
+    /// Convert an angle to a duty value for a configured servo
+    pub fn angle_duty(&self, pin: u8, angle: f32) -> Result<u32> {
+        angle_to_duty(self.config(pin)?, angle)
+    }
+
+    /// Convert a speed to a duty value for a configured servo
+    pub fn speed_duty(&self, pin: u8, speed: f32) -> Result<u32> {
+        speed_to_duty(self.config(pin)?, speed)
+    }
+
+    /// Get the stop duty value of a configured servo
+    pub fn stop_duty(&self, pin: u8) -> Result<u32> {
+        stop_duty(self.config(pin)?)
+    }
+
+    fn config(&self, pin: u8) -> Result<&ServoConfig> {
+        self.servos.get(&pin).ok_or_else(|| {
+            ThreadError::validation_error(
+                &format!("No servo configured on pin {pin}"),
+                "servo control",
+                Some("Configure the servo with configure_servo first"),
+            )
+        })
+    }
+}
+
+/// Check a servo configuration against the PWM period
+fn validate(config: &ServoConfig, pwm_period: u32) -> Result<()> {
+    let pin = config.pin;
+
+    let (values, distinct) = match config.servo_type {
+        ServoType::OneEighty { pos_0, pos_180 } => (vec![pos_0, pos_180], pos_0 != pos_180),
+        ServoType::ThreeSixtyPosition { pos_0, pos_360 } => {
+            (vec![pos_0, pos_360], pos_0 != pos_360)
+        }
+        ServoType::ThreeSixtySpeed {
+            stop,
+            clockwise,
+            anti_clockwise,
+        } => (
+            vec![stop, clockwise, anti_clockwise],
+            stop != clockwise && stop != anti_clockwise,
+        ),
+    };
+    let context = format!("servo on pin {pin}");
+
+    if !distinct {
+        return Err(ThreadError::validation_error(
+            "Servo calibration range is empty",
+            &context,
+            Some("Use different duty values for the end positions"),
+        ));
+    }
+    if pwm_period > 0 {
+        if let Some(duty) = values.iter().find(|&&duty| duty > pwm_period) {
+            return Err(ThreadError::validation_error(
+                &format!("Servo duty value {duty} exceeds the PWM period {pwm_period}"),
+                &context,
+                Some("Duty values are in PWM clock ticks; check the PWM period"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Speed and acceleration limits of a servo move
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ServoProfile {
+    /// Maximum angular speed in degrees per second
+    pub max_speed: f32,
+    /// Maximum angular acceleration in degrees per second squared, or None
+    /// to move at constant speed
+    pub max_acceleration: Option<f32>,
+}
+
+impl ServoProfile {
+    /// Create a constant speed profile
+    pub fn new(max_speed: f32) -> Self {
+        Self {
+            max_speed,
+            max_acceleration: None,
+        }
+    }
+
+    /// Limit the acceleration, giving a trapezoidal speed profile
+    pub fn max_acceleration(mut self, max_acceleration: f32) -> Self {
+        self.max_acceleration = Some(max_acceleration);
+        self
+    }
+
+    /// Validate the profile
+    pub fn validate(&self) -> Result<()> {
+        if !self.max_speed.is_finite() || self.max_speed <= 0.0 {
+            return Err(ThreadError::InvalidParameter(
+                "Servo speed limit must be greater than zero".to_string(),
+            ));
+        }
+        if let Some(acceleration) = self.max_acceleration {
+            if !acceleration.is_finite() || acceleration <= 0.0 {
+                return Err(ThreadError::InvalidParameter(
+                    "Servo acceleration limit must be greater than zero".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the time needed to move `distance` degrees
+    pub fn duration(&self, distance: f32) -> Duration {
+        let distance = f64::from(distance.abs());
+        let speed = f64::from(self.max_speed);
+        let seconds = match self.max_acceleration.map(f64::from) {
+            None => distance / speed,
+            // Too short to reach full speed: accelerate then decelerate
+            Some(acceleration) if distance <= speed * speed / acceleration => {
+                2.0 * (distance / acceleration).sqrt()
+            }
+            Some(acceleration) => distance / speed + speed / acceleration,
+        };
+        Duration::from_secs_f64(seconds)
+    }
+
+    /// Get the distance covered after `elapsed` of a move of `distance` degrees
+    pub fn position(&self, distance: f32, elapsed: Duration) -> f32 {
+        let distance = f64::from(distance.abs());
+        let total = self.duration(distance as f32).as_secs_f64();
+        let t = elapsed.as_secs_f64();
+        if t >= total {
+            return distance as f32;
+        }
+
+        let speed = f64::from(self.max_speed);
+        let covered = match self.max_acceleration.map(f64::from) {
+            None => speed * t,
+            Some(acceleration) => {
+                let ramp = (speed / acceleration).min(total / 2.0);
+                if t < ramp {
+                    0.5 * acceleration * t * t
+                } else if t < total - ramp {
+                    0.5 * acceleration * ramp * ramp + speed * (t - ramp)
+                } else {
+                    distance - 0.5 * acceleration * (total - t) * (total - t)
+                }
+            }
+        };
+        covered.min(distance) as f32
+    }
+}
+
+/// A servo move in progress
+#[derive(Debug, Clone)]
+struct ServoMotion {
+    from: f32,
+    to: f32,
+    profile: ServoProfile,
+    /// Distance that sets the timing; the longest distance of a synchronized move
+    timing_distance: f32,
+    started: Instant,
+}
+
+impl ServoMotion {
+    fn angle(&self, now: Instant) -> (f32, bool) {
+        if self.timing_distance == 0.0 {
+            return (self.to, true);
+        }
+        let elapsed = now.saturating_duration_since(self.started);
+        let covered = self.profile.position(self.timing_distance, elapsed);
+        if covered >= self.timing_distance {
+            return (self.to, true);
+        }
+        let fraction = covered / self.timing_distance;
+        (self.from + (self.to - self.from) * fraction, false)
+    }
+}
+
+/// Progress of a servo move after a step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServoStep {
+    /// Servo pin
+    pub pin: u8,
+    /// Angle to command
+    pub angle: f32,
+    /// True if the move reached its target
+    pub complete: bool,
+}
+
+/// Servo moves in progress, interpolated on every worker cycle
+#[derive(Debug, Default)]
+pub struct ServoMotions {
+    motions: HashMap<u8, ServoMotion>,
+}
+
+impl ServoMotions {
+    /// Create an empty set of moves
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start moving servos from their current angles to their targets
+    ///
+    /// All moves arrive at the same time: the longest move uses the profile
+    /// limits and the others are slowed down proportionally. A move replaces
+    /// any move already in progress on the same pin.
+    ///
+    /// # Parameters
+    ///
+    /// * `moves` - `(pin, from, to)` angles of each servo.
+    /// * `profile` - Speed and acceleration limits.
+    /// * `now` - Start time of the moves.
+    pub fn start(&mut self, moves: &[(u8, f32, f32)], profile: ServoProfile, now: Instant) {
+        let timing_distance = moves
+            .iter()
+            .map(|(_, from, to)| (to - from).abs())
+            .fold(0.0, f32::max);
+        for &(pin, from, to) in moves {
+            self.motions.insert(
+                pin,
+                ServoMotion {
+                    from,
+                    to,
+                    profile,
+                    timing_distance,
+                    started: now,
+                },
+            );
+        }
+    }
+
+    /// Cancel the move of a servo
+    ///
+    /// # Returns
+    ///
+    /// The angle reached when cancelled, or None if the servo was not moving.
+    pub fn cancel(&mut self, pin: u8, now: Instant) -> Option<f32> {
+        self.motions.remove(&pin).map(|motion| motion.angle(now).0)
+    }
+
+    /// Get the interpolated angle of a moving servo
+    pub fn angle(&self, pin: u8, now: Instant) -> Option<f32> {
+        self.motions.get(&pin).map(|motion| motion.angle(now).0)
+    }
+
+    /// Delay all moves by `paused`, so they resume where they stopped
+    pub fn delay(&mut self, paused: Duration) {
+        for motion in self.motions.values_mut() {
+            motion.started += paused;
+        }
+    }
+
+    /// Check if a servo is moving
+    pub fn is_moving(&self, pin: u8) -> bool {
+        self.motions.contains_key(&pin)
+    }
+
+    /// Check if no servo is moving
+    pub fn is_empty(&self) -> bool {
+        self.motions.is_empty()
+    }
+
+    /// Get the pins of the moving servos
+    pub fn pins(&self) -> Vec<u8> {
+        let mut pins: Vec<u8> = self.motions.keys().copied().collect();
+        pins.sort_unstable();
+        pins
+    }
+
+    /// Advance all moves to `now`, removing the ones that completed
+    ///
+    /// # Returns
+    ///
+    /// The angle to command for each moving servo, in pin order.
+    pub fn step(&mut self, now: Instant) -> Vec<ServoStep> {
+        let steps: Vec<ServoStep> = self
+            .pins()
+            .into_iter()
+            .map(|pin| {
+                let (angle, complete) = self.motions[&pin].angle(now);
+                ServoStep {
+                    pin,
+                    angle,
+                    complete,
+                }
+            })
+            .collect();
+        self.motions
+            .retain(|pin, _| !steps.iter().any(|step| step.pin == *pin && step.complete));
+        steps
+    }
+}
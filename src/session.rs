@@ -0,0 +1,351 @@
+//! Session recording and replay
+//!
+//! A [`SessionRecorder`] writes the state-change stream and the commands of a
+//! live device thread to a JSON Lines file, together with the state at the
+//! start of the recording. A [`SessionReplayer`] feeds a recording back into a
+//! `SharedDeviceState` with the original timing, optionally scaled, so that
+//! code built on `StateObserver` can be tested without hardware.
+//!
+//! The first line of a recording is a [`SessionHeader`], every following line
+//! is a [`RecordedEvent`].
+//!
+//! Commands are recorded as [`DeviceCommand`]s, so a replay can send them to
+//! a device thread again. Commands answered on a response channel are
+//! recorded without the channel.
+
+use crate::commands::DeviceCommand;
+use crate::error::{Result, ThreadError};
+use crate::history::now_ms;
+use crate::state::{DeviceState, SharedDeviceState, StateChangeType};
+use crossbeam_channel::unbounded;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Event captured during a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SessionEvent {
+    /// State change notified by the shared state
+    StateChange(StateChangeType),
+    /// Command received by the device thread
+    Command(DeviceCommand),
+}
+
+/// First line of a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionHeader {
+    /// Thread ID of the recorded thread
+    pub thread_id: u32,
+    /// Device serial number
+    pub serial: u32,
+    /// Recording start in milliseconds since the Unix epoch
+    pub started: u64,
+    /// Device state at the start of the recording
+    pub initial_state: DeviceState,
+}
+
+/// Event with its time offset from the start of the recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Offset from the start of the recording in microseconds
+    pub offset_us: u64,
+    /// Recorded event
+    pub event: SessionEvent,
+}
+
+impl RecordedEvent {
+    /// Get the offset from the start of the recording
+    pub fn offset(&self) -> Duration {
+        Duration::from_micros(self.offset_us)
+    }
+}
+
+/// A recorded session loaded from a file
+#[derive(Debug, Clone)]
+pub struct SessionRecording {
+    /// Recording header
+    pub header: SessionHeader,
+    /// Recorded events in order
+    pub events: Vec<RecordedEvent>,
+}
+
+impl SessionRecording {
+    /// Load a recording written by a [`SessionRecorder`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid recording.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+
+        let header = match lines.next() {
+            Some(line) => parse_line(&line?)?,
+            None => {
+                return Err(ThreadError::InvalidParameter(
+                    "Session recording is empty".to_string(),
+                ))
+            }
+        };
+
+        let mut events = Vec::new();
+        for line in lines {
+            let line = line?;
+            if !line.is_empty() {
+                events.push(parse_line(&line)?);
+            }
+        }
+
+        Ok(Self { header, events })
+    }
+
+    /// Get the offset of the last event
+    pub fn duration(&self) -> Duration {
+        self.events
+            .last()
+            .map(RecordedEvent::offset)
+            .unwrap_or_default()
+    }
+}
+
+fn parse_line<T: for<'de> Deserialize<'de>>(line: &str) -> Result<T> {
+    serde_json::from_str(line)
+        .map_err(|e| ThreadError::InvalidParameter(format!("Invalid session recording: {e}")))
+}
+
+fn write_line<T: Serialize>(writer: &mut impl Write, value: &T) -> Result<()> {
+    serde_json::to_writer(&mut *writer, value)
+        .map_err(|e| ThreadError::OperationFailed(e.to_string()))?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Handle to a running session recording
+///
+/// The recording stops when [`SessionRecorder::stop`] is called or the handle is dropped.
+pub struct SessionRecorder {
+    shared_state: Arc<SharedDeviceState>,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl SessionRecorder {
+    /// Start recording a device thread to a file
+    ///
+    /// Replaces any recording already attached to the shared state.
+    ///
+    /// # Parameters
+    ///
+    /// * `thread_id` - The ID of the recorded thread.
+    /// * `shared_state` - The shared state of the recorded thread.
+    /// * `path` - The file to write.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn start(
+        thread_id: u32,
+        shared_state: Arc<SharedDeviceState>,
+        path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let (tx, rx) = unbounded();
+        let started = Instant::now();
+        // Install the tap before taking the initial state so no change is missed
+        shared_state.set_session_tap(Some(tx));
+        let initial_state = (*shared_state.snapshot()).clone();
+
+        let header = SessionHeader {
+            thread_id,
+            serial: initial_state.device_data.serial_number,
+            started: now_ms(),
+            initial_state,
+        };
+        if let Err(e) = write_line(&mut writer, &header) {
+            shared_state.set_session_tap(None);
+            return Err(e);
+        }
+
+        let handle = thread::Builder::new()
+            .name(format!("pokeys-session-{thread_id}"))
+            .spawn(move || {
+                // Runs until the tap is removed and all pending events are written
+                for (at, event) in rx {
+                    let recorded = RecordedEvent {
+                        offset_us: at.saturating_duration_since(started).as_micros() as u64,
+                        event,
+                    };
+                    write_line(&mut writer, &recorded)?;
+                }
+                writer.flush()?;
+                Ok(())
+            })
+            .map_err(|e| {
+                shared_state.set_session_tap(None);
+                ThreadError::ThreadCreationFailed(e.to_string())
+            })?;
+
+        Ok(Self {
+            shared_state,
+            handle: Some(handle),
+        })
+    }
+
+    /// Stop recording and close the file
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that stopped the recording, if any.
+    pub fn stop(mut self) -> Result<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        match self.handle.take() {
+            Some(handle) => {
+                self.shared_state.set_session_tap(None);
+                handle.join().map_err(|_| ThreadError::ThreadJoinError)?
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SessionRecorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.shutdown() {
+            error!("Session recording stopped with error: {e}");
+        }
+    }
+}
+
+/// Replays a recorded session into a shared state
+pub struct SessionReplayer {
+    recording: SessionRecording,
+    target: Arc<SharedDeviceState>,
+    speed: f64,
+    position: usize,
+}
+
+impl SessionReplayer {
+    /// Create a replayer and reset the target to the initial recorded state
+    ///
+    /// # Parameters
+    ///
+    /// * `recording` - The recording to replay.
+    /// * `target` - The shared state receiving the replayed changes.
+    pub fn new(recording: SessionRecording, target: Arc<SharedDeviceState>) -> Self {
+        let replayer = Self {
+            recording,
+            target,
+            speed: 1.0,
+            position: 0,
+        };
+        replayer.restore_initial_state();
+        replayer
+    }
+
+    /// Set the playback speed
+    ///
+    /// `2.0` plays twice as fast as recorded; `f64::INFINITY` plays without delays.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the speed is not positive.
+    pub fn set_speed(&mut self, speed: f64) -> Result<()> {
+        if speed.is_nan() || speed <= 0.0 {
+            return Err(ThreadError::InvalidParameter(format!(
+                "Replay speed must be positive, got {speed}"
+            )));
+        }
+        self.speed = speed;
+        Ok(())
+    }
+
+    /// Get the recording
+    pub fn recording(&self) -> &SessionRecording {
+        &self.recording
+    }
+
+    /// Get the offset of the next event, or the duration if finished
+    pub fn position(&self) -> Duration {
+        self.recording
+            .events
+            .get(self.position)
+            .map(RecordedEvent::offset)
+            .unwrap_or_else(|| self.recording.duration())
+    }
+
+    /// Check if all events have been replayed
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.recording.events.len()
+    }
+
+    /// Replay the next event immediately
+    ///
+    /// State changes are applied to the target and notified; commands are
+    /// returned without being executed and can be sent to a device thread.
+    pub fn step(&mut self) -> Option<&RecordedEvent> {
+        let event = self.recording.events.get(self.position)?;
+        self.position += 1;
+        if let SessionEvent::StateChange(change) = &event.event {
+            self.target.apply_change(change.clone());
+        }
+        Some(event)
+    }
+
+    /// Replay the remaining events with the recorded timing
+    pub fn run(&mut self) {
+        self.run_until(self.recording.duration());
+    }
+
+    /// Replay events up to and including `offset` with the recorded timing
+    pub fn run_until(&mut self, offset: Duration) {
+        let started = Instant::now();
+        let base = self.position();
+
+        while let Some(next) = self.recording.events.get(self.position) {
+            if next.offset() > offset {
+                break;
+            }
+            if self.speed.is_finite() {
+                let due = next.offset().saturating_sub(base).div_f64(self.speed);
+                if let Some(wait) = due.checked_sub(started.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+            self.step();
+        }
+    }
+
+    /// Move to `offset`
+    ///
+    /// The target is reset to the initial recorded state and all events
+    /// before `offset` are applied in a single update, notified as the
+    /// changes from the previous position followed by a `FullUpdate`, so
+    /// playback continues from the same state as the original session.
+    pub fn seek(&mut self, offset: Duration) {
+        let events = &self.recording.events;
+        let position = events.partition_point(|event| event.offset() < offset);
+        let initial_state = &self.recording.header.initial_state;
+
+        self.target.update(|state| {
+            state.clone_from(initial_state);
+            for event in &events[..position] {
+                if let SessionEvent::StateChange(change) = &event.event {
+                    state.apply_change(change);
+                }
+            }
+        });
+        self.position = position;
+    }
+
+    fn restore_initial_state(&self) {
+        let initial_state = &self.recording.header.initial_state;
+        self.target.update(|state| state.clone_from(initial_state));
+    }
+}
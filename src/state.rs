@@ -42,15 +42,30 @@
 //! }
 //! ```
 
+use crate::capture::CaptureSet;
+use crate::commands::DeviceCommand;
+use crate::encoder::{EncoderMotion, EncoderScaling, EncoderTracker};
+use crate::error::{Result, ThreadError};
+use crate::history::ValueHistory;
+use crate::pwm::{PwmChannelMap, PwmDuty};
+use crate::resources::ResourceManager;
+use crate::session::SessionEvent;
+use crate::stats::SyncStatistics;
+use arc_swap::ArcSwap;
 use crossbeam_channel::{Receiver, Sender};
-use parking_lot::{Mutex, RwLock};
+use parking_lot::Mutex;
 use pokeys_lib::encoders::EncoderData;
 use pokeys_lib::io::PinData;
 use pokeys_lib::pwm::PwmData;
 use pokeys_lib::{DeviceData, DeviceInfo};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 
 /// Thread status enumeration.
 ///
@@ -92,7 +107,22 @@ pub struct DeviceState {
     /// Error message if any
     pub error_message: Option<String>,
     /// Custom state values
+    ///
+    /// Holds the string values; other values are in `typed_custom_values`.
+    /// A key is in at most one of the two maps.
     pub custom_values: HashMap<String, String>,
+    /// Custom state values that are not strings, as JSON values
+    #[serde(default)]
+    pub typed_custom_values: HashMap<String, serde_json::Value>,
+    /// Last commanded servo angles by pin
+    #[serde(default)]
+    pub servo_positions: HashMap<u8, f32>,
+    /// Last commanded speeds of continuous rotation servos by pin
+    #[serde(default)]
+    pub servo_speeds: HashMap<u8, f32>,
+    /// Derived motion values of encoders with a scaling configured, by index
+    #[serde(default)]
+    pub encoder_motion: HashMap<u32, EncoderMotion>,
 }
 
 impl DeviceState {
@@ -118,6 +148,10 @@ impl DeviceState {
             status: ThreadStatus::Stopped,
             error_message: None,
             custom_values: HashMap::new(),
+            typed_custom_values: HashMap::new(),
+            servo_positions: HashMap::new(),
+            servo_speeds: HashMap::new(),
+            encoder_motion: HashMap::new(),
         }
     }
 
@@ -127,18 +161,209 @@ impl DeviceState {
     ///
     /// * `device` - The PoKeys device to update from.
     pub fn update_from_device(&mut self, device: &pokeys_lib::PoKeysDevice) {
-        self.device_info = device.info.clone();
-        self.device_data = device.device_data.clone();
-        self.model = device.model.clone();
-        self.pins = device.pins.clone();
-        self.encoders = device.encoders.clone();
-        self.pwm = device.pwm.clone();
+        self.update_from_device_with(device, |_| {});
+    }
+
+    /// Update the state from a PoKeys device, reporting changes as they are found.
+    ///
+    /// Fields are copied in place, so no allocations are made unless the
+    /// number of pins or encoders or the device model changes.
+    ///
+    /// # Parameters
+    ///
+    /// * `device` - The PoKeys device to update from.
+    /// * `on_change` - Called for every pin, encoder and PWM change.
+    pub fn update_from_device_with(
+        &mut self,
+        device: &pokeys_lib::PoKeysDevice,
+        mut on_change: impl FnMut(StateChangeType),
+    ) {
+        self.device_info.clone_from(&device.info);
+        self.device_data.clone_from(&device.device_data);
+        self.update_io_with(&device.pins, &device.encoders, &device.pwm, &mut on_change);
+        if diff_model(&self.model, &device.model, &mut on_change) {
+            self.model.clone_from(&device.model);
+        }
+    }
+
+    /// Update pin, encoder and PWM data, reporting changes as they are found.
+    ///
+    /// Changes are reported for the pins and encoders present in both the
+    /// current and the new data.
+    ///
+    /// # Parameters
+    ///
+    /// * `pins` - The new pin data.
+    /// * `encoders` - The new encoder data.
+    /// * `pwm` - The new PWM data.
+    /// * `on_change` - Called for every pin, encoder and PWM change.
+    pub fn update_io_with(
+        &mut self,
+        pins: &[PinData],
+        encoders: &[EncoderData],
+        pwm: &PwmData,
+        mut on_change: impl FnMut(StateChangeType),
+    ) {
+        diff_pins(&self.pins, pins, &mut on_change);
+        self.pins.truncate(pins.len());
+        for (pin, new_pin) in self.pins.iter_mut().zip(pins) {
+            pin.clone_from(new_pin);
+        }
+        self.pins.extend_from_slice(&pins[self.pins.len()..]);
+
+        diff_encoders(&self.encoders, encoders, &mut on_change);
+        self.encoders.truncate(encoders.len());
+        for (encoder, new_encoder) in self.encoders.iter_mut().zip(encoders) {
+            encoder.clone_from(new_encoder);
+        }
+        self.encoders
+            .extend_from_slice(&encoders[self.encoders.len()..]);
+
+        diff_pwm(&self.pwm, pwm, &mut on_change);
+        self.pwm.clone_from(pwm);
+
         self.last_update = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap_or_default()
             .as_millis() as u64;
     }
 
+    /// Compute the changes between this state and another state.
+    ///
+    /// This is the same comparison used to notify observers during a sync,
+    /// extended to pins and encoders that were added or removed and to the
+    /// fields that change outside of a sync: servos, model, thread status,
+    /// error message and custom values.
+    ///
+    /// Applying the changes with [`DeviceState::apply_change`] turns this
+    /// state into `other`, except for:
+    ///
+    /// * `device_info`, `device_data` and `last_update`, which are not compared
+    /// * pin fields other than the digital, analog, counter and function values
+    /// * encoder fields other than the value
+    /// * the model, which is only reported by name
+    /// * servos and scaled encoders that were removed
+    ///
+    /// # Parameters
+    ///
+    /// * `other` - The newer state to compare against.
+    ///
+    /// # Returns
+    ///
+    /// The changes that turn this state into `other`, in a stable order:
+    /// pins, encoders, PWM, servos, model, status, error and custom values by key.
+    pub fn diff(&self, other: &DeviceState) -> Vec<StateChangeType> {
+        let mut changes = Vec::new();
+        let mut on_change = |change| changes.push(change);
+
+        // Pins and encoders added in `other` are compared against defaults
+        if self.pins.len() != other.pins.len() {
+            on_change(StateChangeType::PinCount {
+                count: other.pins.len(),
+            });
+        }
+        diff_pins(
+            &padded(&self.pins, other.pins.len(), PinData::new),
+            &other.pins,
+            &mut on_change,
+        );
+        if self.encoders.len() != other.encoders.len() {
+            on_change(StateChangeType::EncoderCount {
+                count: other.encoders.len(),
+            });
+        }
+        diff_encoders(
+            &padded(&self.encoders, other.encoders.len(), EncoderData::new),
+            &other.encoders,
+            &mut on_change,
+        );
+        diff_encoder_motion(&self.encoder_motion, &other.encoder_motion, &mut on_change);
+        diff_pwm(&self.pwm, &other.pwm, &mut on_change);
+        diff_servos(
+            &self.servo_positions,
+            &other.servo_positions,
+            |pin, angle| StateChangeType::ServoPosition {
+                pin,
+                angle: angle.into(),
+            },
+            &mut on_change,
+        );
+        diff_servos(
+            &self.servo_speeds,
+            &other.servo_speeds,
+            |pin, speed| StateChangeType::ServoSpeed {
+                pin,
+                speed: speed.into(),
+            },
+            &mut on_change,
+        );
+        diff_model(&self.model, &other.model, &mut on_change);
+
+        if self.status != other.status {
+            on_change(StateChangeType::ThreadStatus {
+                status: other.status,
+            });
+        }
+
+        if self.error_message != other.error_message {
+            on_change(StateChangeType::Error {
+                message: other.error_message.clone(),
+            });
+        }
+
+        let mut keys: Vec<&String> = self.custom_keys().chain(other.custom_keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            match (self.get_custom_json(key), other.get_custom_json(key)) {
+                (old, Some(new)) if old.as_ref() != Some(&new) => {
+                    on_change(custom_value_change(key, &new));
+                }
+                (Some(_), None) => {
+                    on_change(StateChangeType::CustomValueRemoved { key: key.clone() });
+                }
+                _ => {}
+            }
+        }
+
+        changes
+    }
+
+    /// Copy another state into this one, reusing existing allocations.
+    ///
+    /// # Parameters
+    ///
+    /// * `other` - The state to copy from.
+    fn copy_from(&mut self, other: &DeviceState) {
+        self.device_info.clone_from(&other.device_info);
+        self.device_data.clone_from(&other.device_data);
+        if self.model != other.model {
+            self.model.clone_from(&other.model);
+        }
+        self.pins.clone_from(&other.pins);
+        self.encoders.clone_from(&other.encoders);
+        self.pwm.clone_from(&other.pwm);
+        self.last_update = other.last_update;
+        self.status = other.status;
+        self.error_message.clone_from(&other.error_message);
+        if self.custom_values != other.custom_values {
+            self.custom_values.clone_from(&other.custom_values);
+        }
+        if self.typed_custom_values != other.typed_custom_values {
+            self.typed_custom_values
+                .clone_from(&other.typed_custom_values);
+        }
+        if self.servo_positions != other.servo_positions {
+            self.servo_positions.clone_from(&other.servo_positions);
+        }
+        if self.servo_speeds != other.servo_speeds {
+            self.servo_speeds.clone_from(&other.servo_speeds);
+        }
+        if self.encoder_motion != other.encoder_motion {
+            self.encoder_motion.clone_from(&other.encoder_motion);
+        }
+    }
+
     /// Get a digital input value.
     ///
     /// # Parameters
@@ -158,6 +383,24 @@ impl DeviceState {
         Some(self.pins[pin_index].digital_value_get != 0)
     }
 
+    /// Get the value last written to a digital output.
+    ///
+    /// # Parameters
+    ///
+    /// * `pin` - The pin number to read.
+    ///
+    /// # Returns
+    ///
+    /// The output value (true for high, false for low), or None if the pin is invalid.
+    pub fn get_digital_output(&self, pin: u32) -> Option<bool> {
+        if pin == 0 || pin as usize > self.pins.len() {
+            return None;
+        }
+
+        let pin_index = (pin - 1) as usize;
+        Some(self.pins[pin_index].digital_value_set != 0)
+    }
+
     /// Get an analog input value.
     ///
     /// # Parameters
@@ -194,6 +437,39 @@ impl DeviceState {
         Some(self.encoders[encoder_index as usize].encoder_value)
     }
 
+    /// Get the derived motion values of an encoder.
+    ///
+    /// # Parameters
+    ///
+    /// * `encoder_index` - The encoder index to read.
+    ///
+    /// # Returns
+    ///
+    /// The position, velocity and direction of the encoder, or None if no
+    /// scaling is configured for it.
+    pub fn get_encoder_motion(&self, encoder_index: u32) -> Option<EncoderMotion> {
+        self.encoder_motion.get(&encoder_index).copied()
+    }
+
+    /// Get a digital counter value.
+    ///
+    /// # Parameters
+    ///
+    /// * `pin` - The pin number to read.
+    ///
+    /// # Returns
+    ///
+    /// The counter value of the pin, or None if the pin is invalid or
+    /// not configured as a digital counter.
+    pub fn get_digital_counter(&self, pin: u32) -> Option<u32> {
+        let pin_data = self.pins.get((pin as usize).checked_sub(1)?)?;
+        if !pin_data.is_digital_counter() {
+            return None;
+        }
+
+        Some(pin_data.digital_counter_value)
+    }
+
     /// Get a PWM duty cycle.
     ///
     /// # Parameters
@@ -211,13 +487,444 @@ impl DeviceState {
 
         Some(self.pwm.pwm_values[channel])
     }
+
+    /// Get the PWM period in clock ticks, or 0 if it is not configured.
+    pub fn get_pwm_period(&self) -> u32 {
+        self.pwm.pwm_period
+    }
+
+    /// Get the PWM channel map of the device model, or the standard map without a model.
+    pub fn pwm_channel_map(&self) -> PwmChannelMap {
+        PwmChannelMap::for_model(self.model.as_ref())
+    }
+
+    /// Get a custom value as a string.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key of the custom value.
+    ///
+    /// # Returns
+    ///
+    /// String values as stored and other values as JSON text,
+    /// or None if the key is not found.
+    pub fn get_custom_value(&self, key: &str) -> Option<String> {
+        self.custom_values.get(key).cloned().or_else(|| {
+            self.typed_custom_values
+                .get(key)
+                .map(|value| value.to_string())
+        })
+    }
+
+    /// Get a custom value as a JSON value.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key of the custom value.
+    ///
+    /// # Returns
+    ///
+    /// String values as JSON strings and other values as stored,
+    /// or None if the key is not found.
+    pub fn get_custom_json(&self, key: &str) -> Option<serde_json::Value> {
+        match self.custom_values.get(key) {
+            Some(value) => Some(serde_json::Value::String(value.clone())),
+            None => self.typed_custom_values.get(key).cloned(),
+        }
+    }
+
+    /// Get the keys of all custom values, strings and typed.
+    pub fn custom_keys(&self) -> impl Iterator<Item = &String> {
+        self.custom_values
+            .keys()
+            .chain(self.typed_custom_values.keys())
+    }
+
+    /// Set a custom value from a JSON value, returning true if it changed.
+    ///
+    /// String values are stored in `custom_values`, others in `typed_custom_values`.
+    fn insert_custom(&mut self, key: &str, value: serde_json::Value) -> bool {
+        if self.get_custom_json(key).as_ref() == Some(&value) {
+            return false;
+        }
+        match value {
+            serde_json::Value::String(value) => {
+                self.typed_custom_values.remove(key);
+                self.custom_values.insert(key.to_string(), value);
+            }
+            value => {
+                self.custom_values.remove(key);
+                self.typed_custom_values.insert(key.to_string(), value);
+            }
+        }
+        true
+    }
+
+    /// Remove a custom value, returning true if it was present.
+    fn remove_custom(&mut self, key: &str) -> bool {
+        let removed_string = self.custom_values.remove(key).is_some();
+        let removed_typed = self.typed_custom_values.remove(key).is_some();
+        removed_string || removed_typed
+    }
+
+    /// Get a typed custom value.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key of the custom value.
+    ///
+    /// # Returns
+    ///
+    /// The deserialized value, or None if the key is not found.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stored value cannot be deserialized as `T`.
+    pub fn get_custom<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.get_custom_json(key)
+            .map(|value| {
+                serde_json::from_value(value).map_err(|e| {
+                    ThreadError::StateError(format!("Custom value {key} has a different type: {e}"))
+                })
+            })
+            .transpose()
+    }
+
+    /// Apply a state change to this state.
+    ///
+    /// Pins and encoders are added as needed, and added or removed to match
+    /// `PinCount` and `EncoderCount`. `ModelChanged`, `AnalogOutput`
+    /// and `FullUpdate` carry no state of their own and are ignored.
+    ///
+    /// # Parameters
+    ///
+    /// * `change` - The state change to apply.
+    pub fn apply_change(&mut self, change: &StateChangeType) {
+        match change {
+            StateChangeType::PinCount { count } => self.pins.resize(*count, PinData::new()),
+            StateChangeType::EncoderCount { count } => {
+                self.encoders.resize(*count, EncoderData::new());
+            }
+            StateChangeType::PinFunction { pin, function } => {
+                if let Some(pin) = self.pin_mut(*pin) {
+                    pin.pin_function = *function;
+                }
+            }
+            StateChangeType::PwmConfiguration {
+                period,
+                enabled_channels,
+            } => {
+                self.pwm.pwm_period = *period;
+                self.pwm.enabled_channels = *enabled_channels;
+            }
+            StateChangeType::DigitalInput { pin, value } => {
+                if let Some(pin) = self.pin_mut(*pin) {
+                    pin.digital_value_get = u8::from(*value);
+                }
+            }
+            StateChangeType::DigitalOutput { pin, value } => {
+                if let Some(pin) = self.pin_mut(*pin) {
+                    pin.digital_value_set = u8::from(*value);
+                }
+            }
+            StateChangeType::AnalogInput { pin, value } => {
+                if let Some(pin) = self.pin_mut(*pin) {
+                    pin.analog_value = *value;
+                }
+            }
+            StateChangeType::CounterValue { pin, value } => {
+                if let Some(pin) = self.pin_mut(*pin) {
+                    pin.digital_counter_value = *value;
+                }
+            }
+            StateChangeType::EncoderValue { index, value } => {
+                let index = *index as usize;
+                if self.encoders.len() <= index {
+                    self.encoders.resize(index + 1, EncoderData::new());
+                }
+                self.encoders[index].encoder_value = *value;
+                if let Some(motion) = self.encoder_motion.get_mut(&(index as u32)) {
+                    motion.update_position(*value);
+                }
+            }
+            StateChangeType::EncoderVelocity { index, velocity } => {
+                if let Some(motion) = self.encoder_motion.get_mut(index) {
+                    motion.set_velocity(velocity.get());
+                }
+            }
+            StateChangeType::PwmDutyCycle { channel, duty } => {
+                if let Some(value) = self.pwm.pwm_values.get_mut(*channel) {
+                    *value = *duty;
+                }
+            }
+            StateChangeType::ServoPosition { pin, angle } => {
+                self.servo_positions.insert(*pin, angle.get());
+            }
+            StateChangeType::ServoSpeed { pin, speed } => {
+                self.servo_speeds.insert(*pin, speed.get());
+            }
+            StateChangeType::ServoMotionComplete { pin, angle, .. } => {
+                self.servo_positions.insert(*pin, angle.get());
+            }
+            StateChangeType::PwmAnimationComplete { channel, duty, .. } => {
+                if let Some(value) = self.pwm.pwm_values.get_mut(*channel) {
+                    *value = *duty;
+                }
+            }
+            StateChangeType::ThreadStatus { status } => self.status = *status,
+            StateChangeType::Error { message } => self.error_message = message.clone(),
+            StateChangeType::CustomValue { key, value } => {
+                self.insert_custom(key, serde_json::Value::String(value.clone()));
+            }
+            StateChangeType::TypedCustomValue { key, value } => {
+                self.insert_custom(key, value.clone());
+            }
+            StateChangeType::CustomValueRemoved { key } => {
+                self.remove_custom(key);
+            }
+            StateChangeType::AnalogOutput { .. }
+            | StateChangeType::ModelChanged { .. }
+            | StateChangeType::FullUpdate => {}
+        }
+    }
+
+    /// Get a pin by its 1-based number, adding pins up to it if needed.
+    fn pin_mut(&mut self, pin: u32) -> Option<&mut PinData> {
+        let index = (pin as usize).checked_sub(1)?;
+        if self.pins.len() <= index {
+            self.pins.resize(index + 1, PinData::new());
+        }
+        self.pins.get_mut(index)
+    }
+}
+
+/// Build the notification for a custom value.
+///
+/// String values are reported as `CustomValue` so listeners written for
+/// string-only custom values keep working; other values as `TypedCustomValue`.
+fn custom_value_change(key: &str, value: &serde_json::Value) -> StateChangeType {
+    match value {
+        serde_json::Value::String(value) => StateChangeType::CustomValue {
+            key: key.to_string(),
+            value: value.clone(),
+        },
+        value => StateChangeType::TypedCustomValue {
+            key: key.to_string(),
+            value: value.clone(),
+        },
+    }
+}
+
+/// Extend `old` with default entries up to `len`.
+fn padded<T: Clone>(old: &[T], len: usize, default: impl Fn() -> T) -> Cow<'_, [T]> {
+    if old.len() >= len {
+        return Cow::Borrowed(old);
+    }
+    let mut padded = old.to_vec();
+    padded.resize_with(len, default);
+    Cow::Owned(padded)
+}
+
+/// Report function, digital, analog and counter changes of the pins present in both slices.
+fn diff_pins(old: &[PinData], new: &[PinData], on_change: &mut impl FnMut(StateChangeType)) {
+    for (i, (old_pin, new_pin)) in old.iter().zip(new).enumerate() {
+        let pin_number = (i + 1) as u32;
+
+        // Pin function changes
+        if old_pin.pin_function != new_pin.pin_function {
+            on_change(StateChangeType::PinFunction {
+                pin: pin_number,
+                function: new_pin.pin_function,
+            });
+        }
+
+        // Digital input changes
+        if old_pin.digital_value_get != new_pin.digital_value_get {
+            on_change(StateChangeType::DigitalInput {
+                pin: pin_number,
+                value: new_pin.digital_value_get != 0,
+            });
+        }
+
+        // Digital output changes
+        if old_pin.digital_value_set != new_pin.digital_value_set {
+            on_change(StateChangeType::DigitalOutput {
+                pin: pin_number,
+                value: new_pin.digital_value_set != 0,
+            });
+        }
+
+        // Analog input changes
+        if old_pin.analog_value != new_pin.analog_value {
+            on_change(StateChangeType::AnalogInput {
+                pin: pin_number,
+                value: new_pin.analog_value,
+            });
+        }
+
+        // Digital counter changes
+        if old_pin.digital_counter_value != new_pin.digital_counter_value {
+            on_change(StateChangeType::CounterValue {
+                pin: pin_number,
+                value: new_pin.digital_counter_value,
+            });
+        }
+    }
+}
+
+/// Report value changes of the encoders present in both slices.
+fn diff_encoders(
+    old: &[EncoderData],
+    new: &[EncoderData],
+    on_change: &mut impl FnMut(StateChangeType),
+) {
+    for (i, (old_encoder, new_encoder)) in old.iter().zip(new).enumerate() {
+        if old_encoder.encoder_value != new_encoder.encoder_value {
+            on_change(StateChangeType::EncoderValue {
+                index: i as u32,
+                value: new_encoder.encoder_value,
+            });
+        }
+    }
+}
+
+/// Report servo values that were added or changed, in pin order.
+fn diff_servos(
+    old: &HashMap<u8, f32>,
+    new: &HashMap<u8, f32>,
+    change: impl Fn(u8, f32) -> StateChangeType,
+    on_change: &mut impl FnMut(StateChangeType),
+) {
+    let mut pins: Vec<u8> = new.keys().copied().collect();
+    pins.sort_unstable();
+    for pin in pins {
+        let value = new[&pin];
+        if old.get(&pin) != Some(&value) {
+            on_change(change(pin, value));
+        }
+    }
+}
+
+/// Report velocity changes of the scaled encoders present in both maps.
+fn diff_encoder_motion(
+    old: &HashMap<u32, EncoderMotion>,
+    new: &HashMap<u32, EncoderMotion>,
+    on_change: &mut impl FnMut(StateChangeType),
+) {
+    let mut indices: Vec<u32> = new.keys().copied().collect();
+    indices.sort_unstable();
+    for index in indices {
+        if let (Some(old_motion), Some(new_motion)) = (old.get(&index), new.get(&index)) {
+            if old_motion.velocity != new_motion.velocity {
+                on_change(StateChangeType::EncoderVelocity {
+                    index,
+                    velocity: new_motion.velocity.into(),
+                });
+            }
+        }
+    }
+}
+
+/// Report PWM period, enable mask and duty cycle changes.
+fn diff_pwm(old: &PwmData, new: &PwmData, on_change: &mut impl FnMut(StateChangeType)) {
+    if old.pwm_period != new.pwm_period || old.enabled_channels != new.enabled_channels {
+        on_change(StateChangeType::PwmConfiguration {
+            period: new.pwm_period,
+            enabled_channels: new.enabled_channels,
+        });
+    }
+    for (channel, (old_duty, new_duty)) in
+        old.pwm_values.iter().zip(new.pwm_values.iter()).enumerate()
+    {
+        if old_duty != new_duty {
+            on_change(StateChangeType::PwmDutyCycle {
+                channel,
+                duty: *new_duty,
+            });
+        }
+    }
+}
+
+/// Report a device model change, returning true if the model changed.
+fn diff_model(
+    old: &Option<pokeys_lib::models::DeviceModel>,
+    new: &Option<pokeys_lib::models::DeviceModel>,
+    on_change: &mut impl FnMut(StateChangeType),
+) -> bool {
+    if old == new {
+        return false;
+    }
+
+    on_change(StateChangeType::ModelChanged {
+        name: new.as_ref().map(|model| model.name.clone()),
+    });
+    true
+}
+
+/// Floating point value carried by a state change.
+///
+/// Values compare equal when their bit patterns are equal, so state changes
+/// can be compared with `Eq` even though they carry floats.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FloatValue<T>(pub T);
+
+impl<T: Copy> FloatValue<T> {
+    /// Get the value.
+    pub fn get(self) -> T {
+        self.0
+    }
+}
+
+macro_rules! impl_float_value {
+    ($($float:ty),*) => {$(
+        impl PartialEq for FloatValue<$float> {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.to_bits() == other.0.to_bits()
+            }
+        }
+
+        impl Eq for FloatValue<$float> {}
+
+        impl PartialEq<$float> for FloatValue<$float> {
+            fn eq(&self, other: &$float) -> bool {
+                *self == FloatValue(*other)
+            }
+        }
+
+        impl From<$float> for FloatValue<$float> {
+            fn from(value: $float) -> Self {
+                Self(value)
+            }
+        }
+    )*};
+}
+
+impl_float_value!(f32, f64);
+
+impl<T: fmt::Debug> fmt::Debug for FloatValue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for FloatValue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
 }
 
 /// State change notification type.
 ///
 /// Represents the type of state change that occurred.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StateChangeType {
+    /// Number of pins changed
+    PinCount { count: usize },
+    /// Number of encoders changed
+    EncoderCount { count: usize },
+    /// Pin function changed
+    PinFunction { pin: u32, function: u8 },
     /// Digital input changed
     DigitalInput { pin: u32, value: bool },
     /// Digital output changed
@@ -226,16 +933,50 @@ pub enum StateChangeType {
     AnalogInput { pin: u32, value: u32 },
     /// Analog output changed
     AnalogOutput { pin: u32, value: u32 },
+    /// Digital counter value changed
+    CounterValue { pin: u32, value: u32 },
     /// Encoder value changed
     EncoderValue { index: u32, value: i32 },
+    /// Velocity of a scaled encoder changed, in units per second
+    EncoderVelocity {
+        index: u32,
+        velocity: FloatValue<f64>,
+    },
+    /// PWM period or enabled channel mask changed
+    PwmConfiguration { period: u32, enabled_channels: u8 },
     /// PWM duty cycle changed
     PwmDutyCycle { channel: usize, duty: u32 },
+    /// Servo angle changed
+    ServoPosition { pin: u8, angle: FloatValue<f32> },
+    /// Continuous rotation servo speed changed
+    ServoSpeed { pin: u8, speed: FloatValue<f32> },
+    /// Servo move finished at `angle`, either at its target or cancelled
+    ServoMotionComplete {
+        pin: u8,
+        angle: FloatValue<f32>,
+        cancelled: bool,
+    },
+    /// PWM animation finished at `duty`, either at its end or cancelled
+    PwmAnimationComplete {
+        channel: usize,
+        duty: u32,
+        cancelled: bool,
+    },
     /// Thread status changed
     ThreadStatus { status: ThreadStatus },
     /// Error occurred
     Error { message: Option<String> },
-    /// Custom value changed
+    /// Custom value changed to a string
     CustomValue { key: String, value: String },
+    /// Custom value changed to a non-string value
+    TypedCustomValue {
+        key: String,
+        value: serde_json::Value,
+    },
+    /// Custom value removed
+    CustomValueRemoved { key: String },
+    /// Device model changed
+    ModelChanged { name: Option<String> },
     /// Full state update
     FullUpdate,
 }
@@ -244,17 +985,36 @@ pub enum StateChangeType {
 ///
 /// This struct provides thread-safe access to device state
 /// and allows for state change notifications.
+///
+/// The state is published as immutable `Arc<DeviceState>` snapshots that are
+/// swapped atomically on every update, so readers never block the device
+/// thread and always see a consistent view of all fields.
 pub struct SharedDeviceState {
-    /// Device state
-    state: RwLock<DeviceState>,
+    /// Current device state snapshot
+    state: ArcSwap<DeviceState>,
+    /// Previous snapshot, reused for the next update once no reader holds it.
+    /// The lock also serializes writers.
+    spare: Mutex<Option<Arc<DeviceState>>>,
     /// Is the thread running
     running: AtomicBool,
     /// Is the thread paused
     paused: AtomicBool,
     /// Last update timestamp
     last_update: AtomicU64,
+    /// Number of published updates
+    generation: AtomicU64,
     /// State change notification sender
     notification_tx: Mutex<Option<Sender<StateChangeType>>>,
+    /// Sync timing statistics
+    sync_stats: SyncStatistics,
+    /// Recorded pin and encoder value history
+    history: ValueHistory,
+    /// Armed triggered captures
+    captures: CaptureSet,
+    /// Claimed pins, channels, encoders and buses
+    resources: ResourceManager,
+    /// Session recorder tap for state changes and commands
+    session_tx: Mutex<Option<Sender<(Instant, SessionEvent)>>>,
 }
 
 impl SharedDeviceState {
@@ -270,11 +1030,18 @@ impl SharedDeviceState {
     /// A new shared device state.
     pub fn new(device_info: DeviceInfo, device_data: DeviceData) -> Self {
         Self {
-            state: RwLock::new(DeviceState::new(device_info, device_data)),
+            state: ArcSwap::from_pointee(DeviceState::new(device_info, device_data)),
+            spare: Mutex::new(None),
             running: AtomicBool::new(false),
             paused: AtomicBool::new(false),
             last_update: AtomicU64::new(0),
+            generation: AtomicU64::new(0),
             notification_tx: Mutex::new(None),
+            sync_stats: SyncStatistics::new(),
+            history: ValueHistory::new(),
+            captures: CaptureSet::new(),
+            resources: ResourceManager::new(),
+            session_tx: Mutex::new(None),
         }
     }
 
@@ -295,11 +1062,37 @@ impl SharedDeviceState {
     ///
     /// * `change_type` - The type of state change.
     fn notify(&self, change_type: StateChangeType) {
+        if let Some(tx) = &*self.session_tx.lock() {
+            let _ = tx.send((
+                Instant::now(),
+                SessionEvent::StateChange(change_type.clone()),
+            ));
+        }
         if let Some(tx) = &*self.notification_tx.lock() {
             let _ = tx.send(change_type);
         }
     }
 
+    /// Install or remove the session recorder tap.
+    ///
+    /// # Parameters
+    ///
+    /// * `tx` - The sender receiving every state change and command, or None to stop.
+    pub(crate) fn set_session_tap(&self, tx: Option<Sender<(Instant, SessionEvent)>>) {
+        *self.session_tx.lock() = tx;
+    }
+
+    /// Pass a command received by the device thread to the session recorder.
+    ///
+    /// # Parameters
+    ///
+    /// * `command` - The command being executed.
+    pub(crate) fn record_command(&self, command: &DeviceCommand) {
+        if let Some(tx) = &*self.session_tx.lock() {
+            let _ = tx.send((Instant::now(), SessionEvent::Command(command.clone())));
+        }
+    }
+
     /// Get the current thread status.
     ///
     /// # Returns
@@ -348,97 +1141,118 @@ impl SharedDeviceState {
     /// # Parameters
     ///
     /// * `device` - The PoKeys device to update from.
-    pub fn update_from_device_with_notifications(&self, device: &pokeys_lib::PoKeysDevice) {
-        // First, collect the old state for comparison
-        let (old_pins, old_encoders, old_pwm) = self.with_state(|state| {
-            (
-                state.pins.clone(),
-                state.encoders.clone(),
-                state.pwm.clone(),
-            )
-        });
-
-        // Update the state
-        self.update(|state| {
-            state.update_from_device(device);
-        });
+    ///
+    /// # Returns
+    ///
+    /// True if any digital input, analog input, counter or encoder value changed.
+    pub fn update_from_device_with_notifications(&self, device: &pokeys_lib::PoKeysDevice) -> bool {
+        let mut inputs_changed = false;
 
-        // Now detect changes and send notifications
-        let new_state = self.with_state(|state| {
-            (
-                state.pins.clone(),
-                state.encoders.clone(),
-                state.pwm.clone(),
-            )
+        self.update_with_changes(|state, changes| {
+            state.update_from_device_with(device, |change| {
+                if matches!(
+                    change,
+                    StateChangeType::DigitalInput { .. }
+                        | StateChangeType::AnalogInput { .. }
+                        | StateChangeType::CounterValue { .. }
+                        | StateChangeType::EncoderValue { .. }
+                ) {
+                    inputs_changed = true;
+                }
+                changes.push(change);
+            });
         });
 
-        let (new_pins, new_encoders, new_pwm) = new_state;
-
-        // Check for digital input changes
-        for (i, (old_pin, new_pin)) in old_pins.iter().zip(new_pins.iter()).enumerate() {
-            let pin_number = (i + 1) as u32;
-
-            // Digital input changes
-            if old_pin.digital_value_get != new_pin.digital_value_get {
-                let value = new_pin.digital_value_get != 0;
-                self.notify(StateChangeType::DigitalInput {
-                    pin: pin_number,
-                    value,
-                });
-            }
-
-            // Digital output changes
-            if old_pin.digital_value_set != new_pin.digital_value_set {
-                let value = new_pin.digital_value_set != 0;
-                self.notify(StateChangeType::DigitalOutput {
-                    pin: pin_number,
-                    value,
-                });
-            }
-
-            // Analog input changes
-            if old_pin.analog_value != new_pin.analog_value {
-                self.notify(StateChangeType::AnalogInput {
-                    pin: pin_number,
-                    value: new_pin.analog_value,
-                });
-            }
-        }
+        inputs_changed
+    }
 
-        // Check for encoder changes
-        for (i, (old_encoder, new_encoder)) in
-            old_encoders.iter().zip(new_encoders.iter()).enumerate()
-        {
-            if old_encoder.encoder_value != new_encoder.encoder_value {
-                self.notify(StateChangeType::EncoderValue {
-                    index: i as u32,
-                    value: new_encoder.encoder_value,
-                });
-            }
+    /// Update the device state.
+    ///
+    /// The update is applied to a private copy of the current state which is
+    /// then published atomically as the new snapshot. The changes found by
+    /// [`DeviceState::diff`] are notified, followed by a `FullUpdate`.
+    ///
+    /// # Parameters
+    ///
+    /// * `update_fn` - A function that updates the device state.
+    pub fn update(&self, update_fn: impl FnOnce(&mut DeviceState)) {
+        // Only compare the snapshots if someone receives the changes
+        let listening = self.session_tx.lock().is_some() || self.notification_tx.lock().is_some();
+        for change in self.publish_with(update_fn, listening) {
+            self.notify(change);
         }
+        self.notify(StateChangeType::FullUpdate);
+    }
 
-        // Check for PWM changes
-        for (i, (old_duty, new_duty)) in old_pwm
-            .pwm_values
-            .iter()
-            .zip(new_pwm.pwm_values.iter())
-            .enumerate()
-        {
-            if old_duty != new_duty {
-                self.notify(StateChangeType::PwmDutyCycle {
-                    channel: i,
-                    duty: *new_duty,
-                });
-            }
+    /// Update the device state and notify the changes the update reports.
+    ///
+    /// The changes are sent after the new snapshot is published, so observers
+    /// reading the state when a notification arrives see the new values.
+    fn update_with_changes(
+        &self,
+        update_fn: impl FnOnce(&mut DeviceState, &mut Vec<StateChangeType>),
+    ) {
+        let mut changes = Vec::new();
+        self.publish(|state| update_fn(state, &mut changes));
+        for change in changes {
+            self.notify(change);
         }
+        self.notify(StateChangeType::FullUpdate);
     }
+
+    /// Apply a single state change and notify it.
+    ///
+    /// Unlike [`SharedDeviceState::update`], only the change itself is
+    /// notified, so replaying a recorded stream reproduces it exactly.
     ///
     /// # Parameters
     ///
-    /// * `update_fn` - A function that updates the device state.
-    pub fn update(&self, update_fn: impl FnOnce(&mut DeviceState)) {
-        let mut state = self.state.write();
-        update_fn(&mut state);
+    /// * `change` - The state change to apply.
+    pub fn apply_change(&self, change: StateChangeType) {
+        if change != StateChangeType::FullUpdate {
+            self.publish(|state| state.apply_change(&change));
+        }
+        self.notify(change);
+    }
+
+    /// Publish an updated copy of the current state without notifying.
+    fn publish(&self, update_fn: impl FnOnce(&mut DeviceState)) {
+        self.publish_with(update_fn, false);
+    }
+
+    /// Publish an updated copy of the current state, returning the changes if `diff` is set.
+    fn publish_with(
+        &self,
+        update_fn: impl FnOnce(&mut DeviceState),
+        diff: bool,
+    ) -> Vec<StateChangeType> {
+        let mut spare = self.spare.lock();
+        let current = self.state.load_full();
+
+        // Reuse the previous snapshot if no reader still holds it
+        let mut next = match spare.take() {
+            Some(mut recycled) => match Arc::get_mut(&mut recycled) {
+                Some(state) => {
+                    state.copy_from(&current);
+                    recycled
+                }
+                None => Arc::new((*current).clone()),
+            },
+            None => Arc::new((*current).clone()),
+        };
+
+        update_fn(Arc::get_mut(&mut next).expect("unpublished snapshot is uniquely owned"));
+        let changes = if diff {
+            current.diff(&next)
+        } else {
+            Vec::new()
+        };
+
+        self.state.store(next);
+        *spare = Some(current);
+        self.generation.fetch_add(1, Ordering::Release);
+        drop(spare);
+
         self.last_update.store(
             std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
@@ -446,7 +1260,19 @@ impl SharedDeviceState {
                 .as_millis() as u64,
             Ordering::Relaxed,
         );
-        self.notify(StateChangeType::FullUpdate);
+        changes
+    }
+
+    /// Get the current state snapshot.
+    ///
+    /// The snapshot is immutable and never blocks the device thread;
+    /// all fields are consistent with each other.
+    ///
+    /// # Returns
+    ///
+    /// The current device state snapshot.
+    pub fn snapshot(&self) -> Arc<DeviceState> {
+        self.state.load_full()
     }
 
     /// Read the device state.
@@ -459,10 +1285,23 @@ impl SharedDeviceState {
     ///
     /// The result of the read function.
     pub fn read<T>(&self, read_fn: impl FnOnce(&DeviceState) -> T) -> T {
-        let state = self.state.read();
+        let state = self.state.load();
         read_fn(&state)
     }
 
+    /// Compute the changes between an earlier snapshot and the current state.
+    ///
+    /// # Parameters
+    ///
+    /// * `earlier` - A snapshot previously returned by `snapshot`.
+    ///
+    /// # Returns
+    ///
+    /// The changes since `earlier`, as produced by `DeviceState::diff`.
+    pub fn diff_since(&self, earlier: &DeviceState) -> Vec<StateChangeType> {
+        earlier.diff(&self.state.load())
+    }
+
     /// Get the last update timestamp.
     ///
     /// # Returns
@@ -472,6 +1311,52 @@ impl SharedDeviceState {
         self.last_update.load(Ordering::Relaxed)
     }
 
+    /// Get the update generation.
+    ///
+    /// # Returns
+    ///
+    /// A counter that increases with every published update, so callers can
+    /// cheaply detect whether the state changed since they last looked.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Get the sync timing statistics.
+    ///
+    /// # Returns
+    ///
+    /// The rolling sync statistics recorded by the device thread.
+    pub fn sync_stats(&self) -> &SyncStatistics {
+        &self.sync_stats
+    }
+
+    /// Get the value history.
+    ///
+    /// # Returns
+    ///
+    /// The per-channel history buffers filled by the sync loop.
+    pub fn history(&self) -> &ValueHistory {
+        &self.history
+    }
+
+    /// Get the armed captures.
+    ///
+    /// # Returns
+    ///
+    /// The triggered captures fed by the sync loop.
+    pub fn captures(&self) -> &CaptureSet {
+        &self.captures
+    }
+
+    /// Get the resource manager.
+    ///
+    /// # Returns
+    ///
+    /// The claims on the pins, PWM channels, encoders and buses of the device.
+    pub fn resources(&self) -> &ResourceManager {
+        &self.resources
+    }
+
     /// Get a digital input value.
     ///
     /// # Parameters
@@ -486,6 +1371,19 @@ impl SharedDeviceState {
         self.read(|state| state.get_digital_input(pin))
     }
 
+    /// Get the value last written to a digital output.
+    ///
+    /// # Parameters
+    ///
+    /// * `pin` - The pin number to read.
+    ///
+    /// # Returns
+    ///
+    /// The output value (true for high, false for low), or None if the pin is invalid.
+    pub fn get_digital_output(&self, pin: u32) -> Option<bool> {
+        self.read(|state| state.get_digital_output(pin))
+    }
+
     /// Get an analog input value.
     ///
     /// # Parameters
@@ -513,6 +1411,130 @@ impl SharedDeviceState {
         self.read(|state| state.get_encoder_value(encoder_index))
     }
 
+    /// Get the derived motion values of an encoder.
+    ///
+    /// # Parameters
+    ///
+    /// * `encoder_index` - The encoder index to read.
+    ///
+    /// # Returns
+    ///
+    /// The position, velocity and direction of the encoder, or None if no
+    /// scaling is configured for it.
+    pub fn get_encoder_motion(&self, encoder_index: u32) -> Option<EncoderMotion> {
+        self.read(|state| state.get_encoder_motion(encoder_index))
+    }
+
+    /// Configure the scaling of an encoder, enabling motion tracking for it.
+    ///
+    /// An existing offset is kept; velocity is recomputed from the next sync.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the scaling is invalid.
+    pub fn set_encoder_scaling(&self, encoder_index: u32, scaling: EncoderScaling) -> Result<()> {
+        scaling.validate()?;
+        self.update(|state| {
+            let raw = state.get_encoder_value(encoder_index).unwrap_or(0);
+            let motion = state
+                .encoder_motion
+                .entry(encoder_index)
+                .or_insert_with(|| EncoderMotion::new(scaling));
+            motion.scaling = scaling;
+            motion.update_position(raw);
+        });
+        Ok(())
+    }
+
+    /// Stop motion tracking for an encoder.
+    ///
+    /// # Returns
+    ///
+    /// True if a scaling was configured for the encoder.
+    pub fn clear_encoder_scaling(&self, encoder_index: u32) -> bool {
+        self.modify_encoder_motion(encoder_index, |state| {
+            state.encoder_motion.remove(&encoder_index);
+        })
+    }
+
+    /// Set the encoder offset so that the current count reads as position zero.
+    ///
+    /// # Returns
+    ///
+    /// True if a scaling is configured for the encoder.
+    pub fn zero_encoder(&self, encoder_index: u32) -> bool {
+        self.preset_encoder(encoder_index, 0.0)
+    }
+
+    /// Set the encoder offset so that the current count reads as `position` units.
+    ///
+    /// # Returns
+    ///
+    /// True if a scaling is configured for the encoder.
+    pub fn preset_encoder(&self, encoder_index: u32, position: f64) -> bool {
+        self.modify_encoder_motion(encoder_index, |state| {
+            let raw = state.get_encoder_value(encoder_index).unwrap_or(0);
+            if let Some(motion) = state.encoder_motion.get_mut(&encoder_index) {
+                motion.preset(raw, position);
+            }
+        })
+    }
+
+    /// Set the raw count that corresponds to position zero.
+    ///
+    /// # Returns
+    ///
+    /// True if a scaling is configured for the encoder.
+    pub fn set_encoder_offset(&self, encoder_index: u32, offset: i64) -> bool {
+        self.modify_encoder_motion(encoder_index, |state| {
+            let raw = state.get_encoder_value(encoder_index).unwrap_or(0);
+            if let Some(motion) = state.encoder_motion.get_mut(&encoder_index) {
+                motion.offset = offset;
+                motion.update_position(raw);
+            }
+        })
+    }
+
+    /// Update the state if the encoder has a scaling configured.
+    fn modify_encoder_motion(
+        &self,
+        encoder_index: u32,
+        update_fn: impl FnOnce(&mut DeviceState),
+    ) -> bool {
+        if !self.read(|state| state.encoder_motion.contains_key(&encoder_index)) {
+            return false;
+        }
+        self.update(update_fn);
+        true
+    }
+
+    /// Recompute the motion values of scaled encoders after a sync.
+    pub(crate) fn track_encoders(&self, tracker: &mut EncoderTracker, now: Instant) {
+        if self.read(|state| state.encoder_motion.is_empty()) {
+            return;
+        }
+
+        let mut changes = Vec::new();
+        self.publish(|state| tracker.update(state, now, &mut |change| changes.push(change)));
+        for change in changes {
+            self.notify(change);
+        }
+    }
+
+    /// Get a digital counter value.
+    ///
+    /// # Parameters
+    ///
+    /// * `pin` - The pin number to read.
+    ///
+    /// # Returns
+    ///
+    /// The counter value of the pin, or None if the pin is invalid or
+    /// not configured as a digital counter.
+    pub fn get_digital_counter(&self, pin: u32) -> Option<u32> {
+        self.read(|state| state.get_digital_counter(pin))
+    }
+
     /// Get a PWM duty cycle.
     ///
     /// # Parameters
@@ -527,6 +1549,30 @@ impl SharedDeviceState {
         self.read(|state| state.get_pwm_duty_cycle(channel))
     }
 
+    /// Get the PWM period in clock ticks, or 0 if it is not configured.
+    pub fn get_pwm_period(&self) -> u32 {
+        self.read(|state| state.get_pwm_period())
+    }
+
+    /// Get the PWM channel map of the device model.
+    pub fn pwm_channel_map(&self) -> PwmChannelMap {
+        self.read(|state| state.pwm_channel_map())
+    }
+
+    /// Build the command that sets a PWM channel to a duty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the channel is not in the PWM channel map or the
+    /// duty does not fit the PWM period.
+    pub fn pwm_duty_command(&self, channel: usize, duty: PwmDuty) -> Result<DeviceCommand> {
+        let (channels, period) =
+            self.read(|state| (state.pwm_channel_map(), state.get_pwm_period()));
+        channels.check_channel(channel)?;
+        let duty = duty.ticks(period)?;
+        Ok(DeviceCommand::SetPwmDuty { channel, duty })
+    }
+
     /// Set a digital output value.
     ///
     /// # Parameters
@@ -534,11 +1580,11 @@ impl SharedDeviceState {
     /// * `pin` - The pin number to set.
     /// * `value` - The value to set (true for high, false for low).
     pub fn set_digital_output(&self, pin: u32, value: bool) {
-        self.update(|state| {
+        self.update_with_changes(|state, changes| {
             if pin > 0 && pin as usize <= state.pins.len() {
                 let pin_index = (pin - 1) as usize;
                 state.pins[pin_index].digital_value_set = if value { 1 } else { 0 };
-                self.notify(StateChangeType::DigitalOutput { pin, value });
+                changes.push(StateChangeType::DigitalOutput { pin, value });
             }
         });
     }
@@ -550,11 +1596,11 @@ impl SharedDeviceState {
     /// * `pin` - The pin number to set.
     /// * `value` - The value to set (0-4095 for 12-bit DAC).
     pub fn set_analog_output(&self, pin: u32, value: u32) {
-        self.update(|state| {
+        self.update_with_changes(|state, changes| {
             if pin > 0 && pin as usize <= state.pins.len() {
                 let pin_index = (pin - 1) as usize;
                 state.pins[pin_index].analog_value = value;
-                self.notify(StateChangeType::AnalogOutput { pin, value });
+                changes.push(StateChangeType::AnalogOutput { pin, value });
             }
         });
     }
@@ -566,14 +1612,56 @@ impl SharedDeviceState {
     /// * `channel` - The PWM channel to set.
     /// * `duty` - The duty cycle to set (0-4095 for 12-bit PWM).
     pub fn set_pwm_duty_cycle(&self, channel: usize, duty: u32) {
-        self.update(|state| {
+        self.update_with_changes(|state, changes| {
             if channel < state.pwm.pwm_values.len() {
                 state.pwm.pwm_values[channel] = duty;
-                self.notify(StateChangeType::PwmDutyCycle { channel, duty });
+                changes.push(StateChangeType::PwmDutyCycle { channel, duty });
             }
         });
     }
 
+    /// Set the angle of a servo.
+    ///
+    /// # Parameters
+    ///
+    /// * `pin` - The servo pin.
+    /// * `angle` - The commanded angle in degrees.
+    pub fn set_servo_position(&self, pin: u8, angle: f32) {
+        self.update_with_changes(|state, changes| {
+            state.servo_positions.insert(pin, angle);
+            changes.push(StateChangeType::ServoPosition {
+                pin,
+                angle: angle.into(),
+            });
+        });
+    }
+
+    /// Set the speed of a continuous rotation servo.
+    ///
+    /// # Parameters
+    ///
+    /// * `pin` - The servo pin.
+    /// * `speed` - The commanded speed (-100 to 100, 0 is stopped).
+    pub fn set_servo_speed(&self, pin: u8, speed: f32) {
+        self.update_with_changes(|state, changes| {
+            state.servo_speeds.insert(pin, speed);
+            changes.push(StateChangeType::ServoSpeed {
+                pin,
+                speed: speed.into(),
+            });
+        });
+    }
+
+    /// Get the last commanded angle of a servo.
+    pub fn get_servo_position(&self, pin: u8) -> Option<f32> {
+        self.read(|state| state.servo_positions.get(&pin).copied())
+    }
+
+    /// Get the last commanded speed of a continuous rotation servo.
+    pub fn get_servo_speed(&self, pin: u8) -> Option<f32> {
+        self.read(|state| state.servo_speeds.get(&pin).copied())
+    }
+
     /// Set a custom value.
     ///
     /// # Parameters
@@ -581,15 +1669,67 @@ impl SharedDeviceState {
     /// * `key` - The key of the custom value.
     /// * `value` - The value to set.
     pub fn set_custom_value(&self, key: &str, value: &str) {
-        self.update(|state| {
-            state
-                .custom_values
-                .insert(key.to_string(), value.to_string());
-            self.notify(StateChangeType::CustomValue {
-                key: key.to_string(),
-                value: value.to_string(),
-            });
+        self.set_custom_json(key, serde_json::Value::String(value.to_string()));
+    }
+
+    /// Set a typed custom value.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key of the custom value.
+    /// * `value` - The value to set; any serde-serializable value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value cannot be serialized.
+    pub fn set_custom<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        let value = serde_json::to_value(value).map_err(|e| {
+            ThreadError::InvalidParameter(format!("Custom value {key} cannot be serialized: {e}"))
+        })?;
+        self.set_custom_json(key, value);
+        Ok(())
+    }
+
+    /// Set a custom value from a JSON value.
+    ///
+    /// Nothing is published or notified if the key already has this value.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key of the custom value.
+    /// * `value` - The value to set.
+    pub fn set_custom_json(&self, key: &str, value: serde_json::Value) {
+        if self.read(|state| state.get_custom_json(key)).as_ref() == Some(&value) {
+            return;
+        }
+        self.update_with_changes(|state, changes| {
+            let change = custom_value_change(key, &value);
+            if state.insert_custom(key, value) {
+                changes.push(change);
+            }
+        });
+    }
+
+    /// Remove a custom value.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key of the custom value.
+    ///
+    /// # Returns
+    ///
+    /// True if the key was present.
+    pub fn remove_custom_value(&self, key: &str) -> bool {
+        let mut removed = false;
+        self.update_with_changes(|state, changes| {
+            if state.remove_custom(key) {
+                removed = true;
+                changes.push(StateChangeType::CustomValueRemoved {
+                    key: key.to_string(),
+                });
+            }
         });
+        removed
     }
 
     /// Get a custom value.
@@ -602,7 +1742,50 @@ impl SharedDeviceState {
     ///
     /// The custom value, or None if the key is not found.
     pub fn get_custom_value(&self, key: &str) -> Option<String> {
-        self.read(|state| state.custom_values.get(key).cloned())
+        self.read(|state| state.get_custom_value(key))
+    }
+
+    /// Get a typed custom value.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key of the custom value.
+    ///
+    /// # Returns
+    ///
+    /// The deserialized value, or None if the key is not found.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stored value cannot be deserialized as `T`.
+    pub fn get_custom<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.read(|state| state.get_custom(key))
+    }
+
+    /// Get a custom value as a JSON value.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The key of the custom value.
+    ///
+    /// # Returns
+    ///
+    /// The custom value, or None if the key is not found.
+    pub fn get_custom_json(&self, key: &str) -> Option<serde_json::Value> {
+        self.read(|state| state.get_custom_json(key))
+    }
+
+    /// Set the device model.
+    ///
+    /// # Parameters
+    ///
+    /// * `model` - The device model, or None to clear it.
+    pub fn set_model(&self, model: Option<pokeys_lib::models::DeviceModel>) {
+        self.update_with_changes(|state, changes| {
+            if diff_model(&state.model, &model, &mut |change| changes.push(change)) {
+                state.model = model;
+            }
+        });
     }
 
     /// Set an error message.
@@ -611,9 +1794,9 @@ impl SharedDeviceState {
     ///
     /// * `error` - The error message, or None to clear the error.
     pub fn set_error(&self, error: Option<String>) {
-        self.update(|state| {
+        self.update_with_changes(|state, changes| {
             state.error_message = error.clone();
-            self.notify(StateChangeType::Error { message: error });
+            changes.push(StateChangeType::Error { message: error });
         });
     }
 
@@ -639,7 +1822,7 @@ impl SharedDeviceState {
     where
         F: FnOnce(&DeviceState) -> T,
     {
-        let state = self.state.read();
+        let state = self.state.load();
         f(&state)
     }
 }
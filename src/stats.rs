@@ -0,0 +1,262 @@
+//! Sync timing statistics
+//!
+//! Each device thread records how long its sync cycles take, how far the
+//! achieved period drifts from the configured interval and how long commands
+//! take to execute. Statistics are kept over a rolling window of recent
+//! samples and can be queried or reset from the controller.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Default number of samples kept per metric
+pub const DEFAULT_STATS_WINDOW: usize = 1000;
+
+/// Sync groups that are timed individually
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SyncGroup {
+    /// Digital input refresh
+    DigitalInputs,
+    /// Analog input refresh
+    AnalogInputs,
+    /// Encoder refresh
+    Encoders,
+    /// Digital counter refresh
+    DigitalCounters,
+}
+
+/// Summary of a timing metric over the rolling window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimingStats {
+    /// Number of samples in the window
+    pub samples: usize,
+    /// Minimum value
+    pub min: Duration,
+    /// Average value
+    pub avg: Duration,
+    /// Maximum value
+    pub max: Duration,
+    /// 99th percentile value
+    pub p99: Duration,
+}
+
+/// Snapshot of the sync statistics of a device thread
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncStatsSnapshot {
+    /// Duration of complete sync cycles
+    pub sync_duration: TimingStats,
+    /// Achieved period between consecutive sync cycles
+    pub period: TimingStats,
+    /// Deviation of the achieved period from the configured interval
+    pub jitter: TimingStats,
+    /// Time spent reading digital inputs
+    pub digital_input_read: TimingStats,
+    /// Time spent reading analog inputs
+    pub analog_input_read: TimingStats,
+    /// Time spent reading encoders
+    pub encoder_read: TimingStats,
+    /// Time spent reading digital counters
+    pub digital_counter_read: TimingStats,
+    /// Time from command receipt to completion in the worker
+    pub command_latency: TimingStats,
+    /// Total number of sync cycles
+    pub sync_count: u64,
+    /// Number of failed sync cycles or group reads
+    pub sync_errors: u64,
+    /// Total number of commands executed
+    pub command_count: u64,
+    /// Number of commands that reported an error
+    pub command_errors: u64,
+    /// Time since the statistics were created or last reset
+    pub elapsed: Duration,
+}
+
+/// Fixed-capacity window of duration samples
+struct RollingWindow {
+    samples: VecDeque<Duration>,
+    capacity: usize,
+}
+
+impl RollingWindow {
+    fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, sample: Duration) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn summary(&self) -> TimingStats {
+        if self.samples.is_empty() {
+            return TimingStats::default();
+        }
+
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+
+        let total: Duration = sorted.iter().sum();
+        let p99_index = ((sorted.len() as f64 * 0.99).ceil() as usize).saturating_sub(1);
+
+        TimingStats {
+            samples: sorted.len(),
+            min: sorted[0],
+            avg: total / sorted.len() as u32,
+            max: sorted[sorted.len() - 1],
+            p99: sorted[p99_index.min(sorted.len() - 1)],
+        }
+    }
+}
+
+struct StatsInner {
+    sync_duration: RollingWindow,
+    period: RollingWindow,
+    jitter: RollingWindow,
+    digital_input_read: RollingWindow,
+    analog_input_read: RollingWindow,
+    encoder_read: RollingWindow,
+    digital_counter_read: RollingWindow,
+    command_latency: RollingWindow,
+    sync_count: u64,
+    sync_errors: u64,
+    command_count: u64,
+    command_errors: u64,
+    last_sync_start: Option<Instant>,
+    started: Instant,
+}
+
+impl StatsInner {
+    fn new(window: usize) -> Self {
+        Self {
+            sync_duration: RollingWindow::new(window),
+            period: RollingWindow::new(window),
+            jitter: RollingWindow::new(window),
+            digital_input_read: RollingWindow::new(window),
+            analog_input_read: RollingWindow::new(window),
+            encoder_read: RollingWindow::new(window),
+            digital_counter_read: RollingWindow::new(window),
+            command_latency: RollingWindow::new(window),
+            sync_count: 0,
+            sync_errors: 0,
+            command_count: 0,
+            command_errors: 0,
+            last_sync_start: None,
+            started: Instant::now(),
+        }
+    }
+}
+
+/// Thread-safe rolling sync statistics
+pub struct SyncStatistics {
+    inner: Mutex<StatsInner>,
+    window: usize,
+}
+
+impl SyncStatistics {
+    /// Create new statistics with the default window size
+    pub fn new() -> Self {
+        Self::with_window(DEFAULT_STATS_WINDOW)
+    }
+
+    /// Create new statistics keeping `window` samples per metric
+    pub fn with_window(window: usize) -> Self {
+        let window = window.max(1);
+        Self {
+            inner: Mutex::new(StatsInner::new(window)),
+            window,
+        }
+    }
+
+    /// Record the start of a sync cycle
+    ///
+    /// The achieved period and its jitter against `configured_interval` are
+    /// derived from the time since the previous sync start.
+    pub fn record_sync_start(&self, started: Instant, configured_interval: Duration) {
+        let mut inner = self.inner.lock();
+        if let Some(previous) = inner.last_sync_start {
+            let period = started.saturating_duration_since(previous);
+            let jitter = period.abs_diff(configured_interval);
+            inner.period.push(period);
+            inner.jitter.push(jitter);
+        }
+        inner.last_sync_start = Some(started);
+    }
+
+    /// Record a completed sync cycle
+    pub fn record_sync(&self, duration: Duration, success: bool) {
+        let mut inner = self.inner.lock();
+        inner.sync_duration.push(duration);
+        inner.sync_count += 1;
+        if !success {
+            inner.sync_errors += 1;
+        }
+    }
+
+    /// Record the read time of a sync group
+    pub fn record_group_read(&self, group: SyncGroup, duration: Duration) {
+        let mut inner = self.inner.lock();
+        match group {
+            SyncGroup::DigitalInputs => inner.digital_input_read.push(duration),
+            SyncGroup::AnalogInputs => inner.analog_input_read.push(duration),
+            SyncGroup::Encoders => inner.encoder_read.push(duration),
+            SyncGroup::DigitalCounters => inner.digital_counter_read.push(duration),
+        }
+    }
+
+    /// Record a sync error that did not abort the cycle
+    pub fn record_sync_error(&self) {
+        self.inner.lock().sync_errors += 1;
+    }
+
+    /// Record an executed command
+    pub fn record_command(&self, latency: Duration, success: bool) {
+        let mut inner = self.inner.lock();
+        inner.command_latency.push(latency);
+        inner.command_count += 1;
+        if !success {
+            inner.command_errors += 1;
+        }
+    }
+
+    /// Get a snapshot of the current statistics
+    pub fn snapshot(&self) -> SyncStatsSnapshot {
+        let inner = self.inner.lock();
+        SyncStatsSnapshot {
+            sync_duration: inner.sync_duration.summary(),
+            period: inner.period.summary(),
+            jitter: inner.jitter.summary(),
+            digital_input_read: inner.digital_input_read.summary(),
+            analog_input_read: inner.analog_input_read.summary(),
+            encoder_read: inner.encoder_read.summary(),
+            digital_counter_read: inner.digital_counter_read.summary(),
+            command_latency: inner.command_latency.summary(),
+            sync_count: inner.sync_count,
+            sync_errors: inner.sync_errors,
+            command_count: inner.command_count,
+            command_errors: inner.command_errors,
+            elapsed: inner.started.elapsed(),
+        }
+    }
+
+    /// Reset all statistics
+    pub fn reset(&self) {
+        *self.inner.lock() = StatsInner::new(self.window);
+    }
+
+    /// Get the window size
+    pub fn window(&self) -> usize {
+        self.window
+    }
+}
+
+impl Default for SyncStatistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
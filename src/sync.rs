@@ -1,11 +1,98 @@
 //! Data synchronization
 
+use crate::encoder::EncoderTracker;
 use crate::error::{Result, ThreadError};
 use crate::state::SharedDeviceState;
+use crate::stats::SyncGroup;
 use log::error;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+/// Adaptive refresh configuration.
+///
+/// When enabled, the sync interval backs off towards `slow_interval_ms` while
+/// inputs are idle and snaps back to `fast_interval_ms` as soon as an input
+/// change is detected or a command is processed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveRefreshConfig {
+    /// Interval used while the device is active
+    pub fast_interval_ms: u64,
+    /// Upper bound of the interval while the device is idle
+    pub slow_interval_ms: u64,
+    /// Time without activity before the interval starts to back off
+    pub idle_threshold_ms: u64,
+    /// Multiplier applied to the interval on every idle sync once backing off
+    pub backoff_factor: f64,
+}
+
+impl AdaptiveRefreshConfig {
+    /// Create a new adaptive refresh configuration with the given bounds
+    pub fn new(fast_interval_ms: u64, slow_interval_ms: u64) -> Self {
+        Self {
+            fast_interval_ms,
+            slow_interval_ms,
+            ..Self::default()
+        }
+    }
+
+    /// Set the idle time before backing off
+    pub fn idle_threshold(mut self, idle_threshold_ms: u64) -> Self {
+        self.idle_threshold_ms = idle_threshold_ms;
+        self
+    }
+
+    /// Set the backoff multiplier
+    pub fn backoff_factor(mut self, backoff_factor: f64) -> Self {
+        self.backoff_factor = backoff_factor;
+        self
+    }
+
+    /// Validate the configuration
+    pub fn validate(&self) -> Result<()> {
+        if self.fast_interval_ms == 0 {
+            return Err(ThreadError::ConfigurationError(
+                "Adaptive refresh fast interval must be greater than zero".to_string(),
+            ));
+        }
+        if self.slow_interval_ms < self.fast_interval_ms {
+            return Err(ThreadError::ConfigurationError(format!(
+                "Adaptive refresh slow interval ({} ms) is shorter than fast interval ({} ms)",
+                self.slow_interval_ms, self.fast_interval_ms
+            )));
+        }
+        if !self.backoff_factor.is_finite() || self.backoff_factor < 1.0 {
+            return Err(ThreadError::ConfigurationError(format!(
+                "Adaptive refresh backoff factor must be at least 1.0, got {}",
+                self.backoff_factor
+            )));
+        }
+        Ok(())
+    }
+
+    /// Compute the next interval given the current one and the time since the last activity
+    pub fn next_interval(&self, current: Duration, idle_for: Duration) -> Duration {
+        let fast = Duration::from_millis(self.fast_interval_ms);
+        let slow = Duration::from_millis(self.slow_interval_ms);
+
+        if idle_for < Duration::from_millis(self.idle_threshold_ms) {
+            return fast;
+        }
+
+        current.max(fast).mul_f64(self.backoff_factor).min(slow)
+    }
+}
+
+impl Default for AdaptiveRefreshConfig {
+    fn default() -> Self {
+        Self {
+            fast_interval_ms: 10,
+            slow_interval_ms: 1000,
+            idle_threshold_ms: 2000,
+            backoff_factor: 1.5,
+        }
+    }
+}
+
 /// Data synchronization
 pub struct DeviceSync {
     /// Shared device state
@@ -16,6 +103,12 @@ pub struct DeviceSync {
     last_sync: Instant,
     /// Sync interval
     sync_interval: Duration,
+    /// Adaptive refresh configuration
+    adaptive: Option<AdaptiveRefreshConfig>,
+    /// Last time an input change or command was seen
+    last_activity: Instant,
+    /// Encoder velocity tracking
+    encoder_tracker: EncoderTracker,
 }
 
 impl DeviceSync {
@@ -30,6 +123,37 @@ impl DeviceSync {
             thread_id,
             last_sync: Instant::now(),
             sync_interval: Duration::from_millis(sync_interval_ms),
+            adaptive: None,
+            last_activity: Instant::now(),
+            encoder_tracker: EncoderTracker::default(),
+        }
+    }
+
+    /// Enable adaptive refresh
+    pub fn with_adaptive_refresh(mut self, config: AdaptiveRefreshConfig) -> Self {
+        self.set_adaptive_refresh(Some(config));
+        self
+    }
+
+    /// Set or clear the adaptive refresh configuration
+    pub fn set_adaptive_refresh(&mut self, config: Option<AdaptiveRefreshConfig>) {
+        if let Some(config) = &config {
+            self.sync_interval = Duration::from_millis(config.fast_interval_ms);
+        }
+        self.adaptive = config;
+        self.last_activity = Instant::now();
+    }
+
+    /// Get the adaptive refresh configuration
+    pub fn adaptive_refresh(&self) -> Option<AdaptiveRefreshConfig> {
+        self.adaptive
+    }
+
+    /// Record activity, snapping an adaptive interval back to its fast rate
+    pub fn record_activity(&mut self) {
+        self.last_activity = Instant::now();
+        if let Some(config) = &self.adaptive {
+            self.sync_interval = Duration::from_millis(config.fast_interval_ms);
         }
     }
 
@@ -40,10 +164,30 @@ impl DeviceSync {
 
     /// Sync the device state
     pub fn sync(&mut self, device: &mut pokeys_lib::PoKeysDevice) -> Result<()> {
+        let started = Instant::now();
+        self.shared_state
+            .sync_stats()
+            .record_sync_start(started, self.sync_interval);
+
+        let result = self.sync_groups(device);
+
+        self.shared_state
+            .sync_stats()
+            .record_sync(started.elapsed(), result.is_ok());
+        result
+    }
+
+    /// Refresh each sync group and publish the result
+    fn sync_groups(&mut self, device: &mut pokeys_lib::PoKeysDevice) -> Result<()> {
         // debug!("Syncing device state for thread {}", self.thread_id);
+        let shared_state = self.shared_state.clone();
+        let stats = shared_state.sync_stats();
 
         // Refresh digital inputs
-        if let Err(e) = device.get_digital_inputs() {
+        let group_start = Instant::now();
+        let digital_result = device.get_digital_inputs();
+        stats.record_group_read(SyncGroup::DigitalInputs, group_start.elapsed());
+        if let Err(e) = digital_result {
             error!("Failed to refresh digital inputs: {e}");
             self.shared_state
                 .set_error(Some(format!("Failed to refresh digital inputs: {e}")));
@@ -51,7 +195,10 @@ impl DeviceSync {
         }
 
         // Refresh analog inputs
-        if let Err(e) = device.read_analog_inputs() {
+        let group_start = Instant::now();
+        let analog_result = device.read_analog_inputs();
+        stats.record_group_read(SyncGroup::AnalogInputs, group_start.elapsed());
+        if let Err(e) = analog_result {
             error!("Failed to refresh analog inputs: {e}");
             self.shared_state
                 .set_error(Some(format!("Failed to refresh analog inputs: {e}")));
@@ -59,18 +206,53 @@ impl DeviceSync {
         }
 
         // Refresh encoder values
+        let group_start = Instant::now();
         for i in 0..device.encoders.len() {
             if let Err(e) = device.get_encoder_value(i as u8) {
                 error!("Failed to refresh encoder {i}: {e}");
+                stats.record_sync_error();
                 self.shared_state
                     .set_error(Some(format!("Failed to refresh encoder {i}: {e}")));
                 // Continue with other encoders even if one fails
             }
         }
+        stats.record_group_read(SyncGroup::Encoders, group_start.elapsed());
+
+        // Refresh digital counters, only if any pin is configured as a counter
+        if device.pins.iter().any(|pin| pin.is_digital_counter()) {
+            let group_start = Instant::now();
+            let counter_result = device.read_digital_counters();
+            stats.record_group_read(SyncGroup::DigitalCounters, group_start.elapsed());
+            if let Err(e) = counter_result {
+                error!("Failed to refresh digital counters: {e}");
+                stats.record_sync_error();
+                self.shared_state
+                    .set_error(Some(format!("Failed to refresh digital counters: {e}")));
+                // Counters are not critical, keep the rest of the sync
+            }
+        }
 
         // Update the shared state with the refreshed device state and detect changes
-        self.shared_state
+        let inputs_changed = self
+            .shared_state
             .update_from_device_with_notifications(device);
+        self.shared_state
+            .track_encoders(&mut self.encoder_tracker, Instant::now());
+
+        let history = self.shared_state.history();
+        let captures = self.shared_state.captures();
+        if !history.is_empty() || !captures.is_empty() {
+            let snapshot = self.shared_state.snapshot();
+            history.record_state(&snapshot);
+            captures.process(self.thread_id, &snapshot);
+        }
+
+        if inputs_changed {
+            self.record_activity();
+        } else if let Some(config) = &self.adaptive {
+            self.sync_interval =
+                config.next_interval(self.sync_interval, self.last_activity.elapsed());
+        }
 
         self.last_sync = Instant::now();
         Ok(())
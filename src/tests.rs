@@ -476,3 +476,41 @@ fn test_logger() {
     mock_logger.set_level(LevelFilter::Debug);
     assert_eq!(mock_logger.level(), LevelFilter::Debug);
 }
+
+#[test]
+fn test_encoder_velocity_threshold() {
+    use crate::encoder::{EncoderScaling, EncoderTracker};
+    use std::time::Instant;
+
+    let state = SharedDeviceState::new(DeviceInfo::default(), DeviceData::default());
+    state.update(|state| state.encoders = vec![pokeys_lib::encoders::EncoderData::new()]);
+    state
+        .set_encoder_scaling(0, EncoderScaling::new(1.0).velocity_threshold(50.0))
+        .unwrap();
+    let rx = state.setup_notifications();
+
+    let mut tracker = EncoderTracker::default();
+    let start = Instant::now();
+    let mut sync = |millis: u64, raw: i32| {
+        state.update(|state| state.encoders[0].encoder_value = raw);
+        state.track_encoders(&mut tracker, start + Duration::from_millis(millis));
+        state.get_encoder_motion(0).unwrap().velocity
+    };
+
+    sync(0, 0);
+    assert_eq!(sync(100, 100), 1000.0);
+    // Jitter in the sync period changes the velocity by less than the threshold
+    assert_eq!(sync(199, 200), 1000.0);
+    assert_eq!(sync(300, 300), 1000.0);
+    // Stopping is always reported
+    assert_eq!(sync(400, 300), 0.0);
+
+    let velocities: Vec<f64> = rx
+        .try_iter()
+        .filter_map(|change| match change {
+            StateChangeType::EncoderVelocity { velocity, .. } => Some(velocity.get()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(velocities, vec![1000.0, 0.0]);
+}
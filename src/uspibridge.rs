@@ -0,0 +1,748 @@
+//! uSPIBridge displays
+//!
+//! A uSPIBridge is an I2C slave that drives a chain of MAX7219 7-segment
+//! modules. The device thread sends it framed [`BridgeCommand`]s through the
+//! `pokeys_lib` uSPIBridge calls and reads back the answers of commands that
+//! have one.
+//!
+//! A [`SevenSegmentDisplay`] joins modules of the chain into one virtual
+//! display of the bridge firmware, which spreads text over the modules and
+//! runs blinking without further commands:
+//!
+//! ```ignore
+//! let bridge = controller.uspibridge(thread_id, DEFAULT_USPIBRIDGE_ADDRESS)?;
+//! bridge.configure(&USPIBridgeConfig::new().with_device_count(2))?;
+//! let display = bridge.display(0, &[0, 1])?;
+//! display.write_number(-12.5, 2)?;
+//! display.set_brightness(4)?;
+//! display.blink("ALArM", Duration::from_millis(500))?;
+//! ```
+
+use crate::commands::DeviceCommand;
+use crate::device::DeviceHandle;
+use crate::error::{Result, ThreadError};
+use crate::i2c;
+use pokeys_lib::{SegmentMapping, SegmentMappingType, USPIBridgeCommand, USPIBridgeConfig};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Default I2C address of a uSPIBridge
+pub const DEFAULT_USPIBRIDGE_ADDRESS: u8 = 0x42;
+
+/// Time the bridge needs to prepare an answer
+pub const USPIBRIDGE_RESPONSE_DELAY: Duration = Duration::from_millis(10);
+
+/// Digits of one MAX7219 module
+pub const DIGITS_PER_MODULE: usize = 8;
+
+/// Largest payload that fits in one I2C packet with the command framing
+pub const MAX_BRIDGE_PAYLOAD: usize = 28;
+
+/// Highest brightness level
+pub const MAX_BRIGHTNESS: u8 = 15;
+
+/// Commands the bridge firmware accepts, by code
+const BRIDGE_COMMANDS: [USPIBridgeCommand; 24] = [
+    USPIBridgeCommand::SetBrightness,
+    USPIBridgeCommand::DisplayText,
+    USPIBridgeCommand::DisplayNumber,
+    USPIBridgeCommand::SetCharacter,
+    USPIBridgeCommand::SetPattern,
+    USPIBridgeCommand::SetDecimal,
+    USPIBridgeCommand::ClearDevice,
+    USPIBridgeCommand::SetSegmentMapping,
+    USPIBridgeCommand::SetSegmentMappingType,
+    USPIBridgeCommand::GetSegmentMapping,
+    USPIBridgeCommand::TestSegmentMapping,
+    USPIBridgeCommand::CreateVirtualDevice,
+    USPIBridgeCommand::DeleteVirtualDevice,
+    USPIBridgeCommand::ListVirtualDevices,
+    USPIBridgeCommand::VirtualText,
+    USPIBridgeCommand::VirtualBrightness,
+    USPIBridgeCommand::VirtualClear,
+    USPIBridgeCommand::VirtualScrollLeft,
+    USPIBridgeCommand::VirtualScrollRight,
+    USPIBridgeCommand::VirtualFlash,
+    USPIBridgeCommand::VirtualStop,
+    USPIBridgeCommand::SystemReset,
+    USPIBridgeCommand::SystemStatus,
+    USPIBridgeCommand::SystemConfig,
+];
+
+/// Segment mapping types, by code
+const SEGMENT_MAPPING_TYPES: [SegmentMappingType; 6] = [
+    SegmentMappingType::Standard,
+    SegmentMappingType::Reversed,
+    SegmentMappingType::CommonCathode,
+    SegmentMappingType::SparkfunSerial,
+    SegmentMappingType::AdafruitBackpack,
+    SegmentMappingType::Custom,
+];
+
+/// A command for the bridge firmware
+///
+/// Serialized in its raw `[command, device_id, payload...]` form, so the
+/// response length is derived from the command when deserialized.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "Vec<u8>", try_from = "Vec<u8>")]
+pub struct BridgeCommand {
+    /// The command
+    pub command: USPIBridgeCommand,
+    /// Module or virtual display the command is for
+    pub device_id: u8,
+    /// The payload
+    pub data: Vec<u8>,
+    /// Bytes to read back after the command, 0 for none
+    pub response_length: u8,
+}
+
+impl BridgeCommand {
+    /// Create a command, reading back the answer of commands that have one
+    pub fn new(command: USPIBridgeCommand, device_id: u8, data: Vec<u8>) -> Self {
+        Self {
+            command,
+            device_id,
+            data,
+            response_length: response_length(command),
+        }
+    }
+
+    /// Parse a raw command of the form `[command, device_id, payload...]`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command is shorter than two bytes or the
+    /// command code is unknown.
+    pub fn from_raw(raw: &[u8]) -> Result<Self> {
+        let [code, device_id, data @ ..] = raw else {
+            return Err(ThreadError::InvalidParameter(
+                "uSPIBridge command needs a command code and a device ID".to_string(),
+            ));
+        };
+        let command = BRIDGE_COMMANDS
+            .iter()
+            .copied()
+            .find(|&command| command as u8 == *code)
+            .ok_or_else(|| {
+                ThreadError::InvalidParameter(format!("Unknown uSPIBridge command 0x{code:02X}"))
+            })?;
+        Ok(Self::new(command, *device_id, data.to_vec()))
+    }
+
+    /// Check that the command fits in one I2C packet
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload is longer than [`MAX_BRIDGE_PAYLOAD`].
+    pub fn validate(&self) -> Result<()> {
+        if self.data.len() > MAX_BRIDGE_PAYLOAD {
+            return Err(ThreadError::validation_error(
+                &format!(
+                    "uSPIBridge {:?} payload of {} bytes exceeds {} bytes",
+                    self.command,
+                    self.data.len(),
+                    MAX_BRIDGE_PAYLOAD
+                ),
+                "uSPIBridge command",
+                Some("Split the text or data over several commands"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl From<BridgeCommand> for Vec<u8> {
+    fn from(command: BridgeCommand) -> Self {
+        let mut raw = Vec::with_capacity(command.data.len() + 2);
+        raw.push(command.command as u8);
+        raw.push(command.device_id);
+        raw.extend_from_slice(&command.data);
+        raw
+    }
+}
+
+impl TryFrom<Vec<u8>> for BridgeCommand {
+    type Error = ThreadError;
+
+    fn try_from(raw: Vec<u8>) -> Result<Self> {
+        Self::from_raw(&raw)
+    }
+}
+
+/// Serde support for [`USPIBridgeConfig`], which has none of its own
+pub(crate) mod config_serde {
+    use super::SEGMENT_MAPPING_TYPES;
+    use pokeys_lib::{SegmentMapping, USPIBridgeConfig};
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct ConfigRecord {
+        device_count: u8,
+        /// Mapping type code and custom mapping of each module
+        segment_mappings: Vec<(u8, Option<[u8; 8]>)>,
+        default_brightness: u8,
+        max_virtual_devices: u8,
+    }
+
+    pub fn serialize<S: Serializer>(
+        config: &USPIBridgeConfig,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        ConfigRecord {
+            device_count: config.device_count,
+            segment_mappings: config
+                .segment_mappings
+                .iter()
+                .map(|mapping| (mapping.mapping_type as u8, mapping.custom_mapping))
+                .collect(),
+            default_brightness: config.default_brightness,
+            max_virtual_devices: config.max_virtual_devices,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<USPIBridgeConfig, D::Error> {
+        let record = ConfigRecord::deserialize(deserializer)?;
+        let segment_mappings = record
+            .segment_mappings
+            .into_iter()
+            .map(|(code, custom_mapping)| {
+                let mapping_type = SEGMENT_MAPPING_TYPES
+                    .get(usize::from(code))
+                    .copied()
+                    .ok_or_else(|| D::Error::custom(format!("Unknown segment mapping {code}")))?;
+                Ok(SegmentMapping {
+                    mapping_type,
+                    custom_mapping,
+                })
+            })
+            .collect::<Result<_, D::Error>>()?;
+        Ok(USPIBridgeConfig {
+            device_count: record.device_count,
+            segment_mappings,
+            default_brightness: record.default_brightness,
+            max_virtual_devices: record.max_virtual_devices,
+        })
+    }
+}
+
+/// Get the length of the answer to a command, 0 for commands without one
+pub fn response_length(command: USPIBridgeCommand) -> u8 {
+    match command {
+        USPIBridgeCommand::GetSegmentMapping => 10,
+        USPIBridgeCommand::SystemStatus => 16,
+        USPIBridgeCommand::ListVirtualDevices => 32,
+        _ => 0,
+    }
+}
+
+/// Get the command that selects the segment mapping of a module
+pub fn segment_mapping_command(module: u8, mapping: &SegmentMapping) -> BridgeCommand {
+    match mapping.get_custom_mapping() {
+        Some(custom) => BridgeCommand::new(
+            USPIBridgeCommand::SetSegmentMapping,
+            module,
+            custom.to_vec(),
+        ),
+        None => BridgeCommand::new(
+            USPIBridgeCommand::SetSegmentMappingType,
+            module,
+            vec![mapping.mapping_type as u8],
+        ),
+    }
+}
+
+/// Get the commands that apply a configuration to the modules of the chain
+///
+/// Each module gets its segment mapping and the default brightness.
+pub fn configuration_commands(config: &USPIBridgeConfig) -> Vec<BridgeCommand> {
+    let brightness = config.default_brightness.min(MAX_BRIGHTNESS);
+    (0..config.device_count)
+        .flat_map(|module| {
+            let mapping = config
+                .segment_mappings
+                .get(usize::from(module))
+                .cloned()
+                .unwrap_or_default();
+            [
+                segment_mapping_command(module, &mapping),
+                BridgeCommand::new(USPIBridgeCommand::SetBrightness, module, vec![brightness]),
+            ]
+        })
+        .collect()
+}
+
+fn check_brightness(brightness: u8) -> Result<()> {
+    if brightness > MAX_BRIGHTNESS {
+        return Err(ThreadError::validation_error(
+            &format!("Brightness {brightness} is out of range"),
+            "uSPIBridge brightness",
+            Some("Use brightness levels 0-15"),
+        ));
+    }
+    Ok(())
+}
+
+/// A uSPIBridge on the I2C bus of a device
+#[derive(Clone)]
+pub struct USPIBridge {
+    device: DeviceHandle,
+    address: u8,
+    timeout: Duration,
+}
+
+impl USPIBridge {
+    /// Create a handle to the bridge at an address
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address is not a 7-bit address.
+    pub fn new(device: DeviceHandle, address: u8) -> Result<Self> {
+        i2c::check_address(address)?;
+        Ok(Self {
+            device,
+            address,
+            timeout: i2c::DEFAULT_I2C_TIMEOUT,
+        })
+    }
+
+    /// Set how long to wait for each command
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Get the I2C address of the bridge
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Send commands in order, stopping at the first failure
+    ///
+    /// # Returns
+    ///
+    /// The answer to the last command, empty if it has none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a command is too long, the bridge rejects a
+    /// command or the commands time out.
+    pub fn execute(&self, commands: Vec<BridgeCommand>) -> Result<Vec<u8>> {
+        for command in &commands {
+            command.validate()?;
+        }
+        let timeout = self.timeout.saturating_mul(commands.len().max(1) as u32);
+        self.device.request(
+            |response| DeviceCommand::USPIBridgeRequest {
+                address: self.address,
+                commands,
+                response,
+            },
+            timeout,
+        )
+    }
+
+    /// Send one command
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command is too long, the bridge rejects it or
+    /// it times out.
+    pub fn command(&self, command: BridgeCommand) -> Result<Vec<u8>> {
+        self.execute(vec![command])
+    }
+
+    /// Apply the segment mappings and default brightness of a configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge rejects a command or the commands time out.
+    pub fn configure(&self, config: &USPIBridgeConfig) -> Result<()> {
+        self.execute(configuration_commands(config)).map(|_| ())
+    }
+
+    /// Set the segment mapping of a module
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge rejects the command or it times out.
+    pub fn set_segment_mapping(&self, module: u8, mapping: &SegmentMapping) -> Result<()> {
+        self.command(segment_mapping_command(module, mapping))
+            .map(|_| ())
+    }
+
+    /// Set the brightness of a module, 0-15
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the brightness is out of range, the bridge rejects
+    /// the command or it times out.
+    pub fn set_brightness(&self, module: u8, brightness: u8) -> Result<()> {
+        check_brightness(brightness)?;
+        self.command(BridgeCommand::new(
+            USPIBridgeCommand::SetBrightness,
+            module,
+            vec![brightness],
+        ))
+        .map(|_| ())
+    }
+
+    /// Show text on a module
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge rejects the command or it times out.
+    pub fn display_text(&self, module: u8, text: &str) -> Result<()> {
+        self.command(BridgeCommand::new(
+            USPIBridgeCommand::DisplayText,
+            module,
+            text.as_bytes().to_vec(),
+        ))
+        .map(|_| ())
+    }
+
+    /// Show a number on a module
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge rejects the command or it times out.
+    pub fn display_number(&self, module: u8, number: u32) -> Result<()> {
+        self.command(BridgeCommand::new(
+            USPIBridgeCommand::DisplayNumber,
+            module,
+            number.to_le_bytes().to_vec(),
+        ))
+        .map(|_| ())
+    }
+
+    /// Set the raw segments of one digit of a module
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge rejects the command or it times out.
+    pub fn set_pattern(&self, module: u8, position: u8, pattern: u8) -> Result<()> {
+        self.command(BridgeCommand::new(
+            USPIBridgeCommand::SetPattern,
+            module,
+            vec![position, pattern],
+        ))
+        .map(|_| ())
+    }
+
+    /// Clear a module
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge rejects the command or it times out.
+    pub fn clear(&self, module: u8) -> Result<()> {
+        self.command(BridgeCommand::new(
+            USPIBridgeCommand::ClearDevice,
+            module,
+            Vec::new(),
+        ))
+        .map(|_| ())
+    }
+
+    /// Read the segment mapping of a module
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge does not answer or answers with fewer than 8 bytes.
+    pub fn segment_mapping(&self, module: u8) -> Result<[u8; 8]> {
+        let response = self.command(BridgeCommand::new(
+            USPIBridgeCommand::GetSegmentMapping,
+            module,
+            Vec::new(),
+        ))?;
+        response
+            .get(..8)
+            .and_then(|mapping| mapping.try_into().ok())
+            .ok_or_else(|| {
+                ThreadError::OperationFailed(format!(
+                    "uSPIBridge returned {} bytes for a segment mapping",
+                    response.len()
+                ))
+            })
+    }
+
+    /// Read the status of the bridge
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge does not answer or the command times out.
+    pub fn status(&self) -> Result<Vec<u8>> {
+        self.command(BridgeCommand::new(
+            USPIBridgeCommand::SystemStatus,
+            0,
+            Vec::new(),
+        ))
+    }
+
+    /// Reset the bridge and its modules
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge rejects the command or it times out.
+    pub fn reset(&self) -> Result<()> {
+        self.command(BridgeCommand::new(
+            USPIBridgeCommand::SystemReset,
+            0,
+            Vec::new(),
+        ))
+        .map(|_| ())
+    }
+
+    /// Join modules of the chain into a display
+    ///
+    /// # Parameters
+    ///
+    /// * `virtual_id` - ID of the virtual display in the bridge firmware.
+    /// * `modules` - The modules of the display, leftmost first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no modules, the bridge rejects the
+    /// command or it times out.
+    pub fn display(&self, virtual_id: u8, modules: &[u8]) -> Result<SevenSegmentDisplay> {
+        if modules.is_empty() {
+            return Err(ThreadError::InvalidParameter(
+                "A display needs at least one module".to_string(),
+            ));
+        }
+        self.command(BridgeCommand::new(
+            USPIBridgeCommand::CreateVirtualDevice,
+            virtual_id,
+            modules.to_vec(),
+        ))?;
+        Ok(SevenSegmentDisplay {
+            bridge: self.clone(),
+            virtual_id,
+            modules: modules.to_vec(),
+        })
+    }
+}
+
+/// Chained 7-segment modules shown as one display
+///
+/// A `.` after a character lights the decimal point of its digit and does
+/// not take a digit of its own.
+#[derive(Clone)]
+pub struct SevenSegmentDisplay {
+    bridge: USPIBridge,
+    virtual_id: u8,
+    modules: Vec<u8>,
+}
+
+impl SevenSegmentDisplay {
+    /// Get the ID of the virtual display
+    pub fn virtual_id(&self) -> u8 {
+        self.virtual_id
+    }
+
+    /// Get the modules of the display, leftmost first
+    pub fn modules(&self) -> &[u8] {
+        &self.modules
+    }
+
+    /// Get the number of digits
+    pub fn width(&self) -> usize {
+        self.modules.len() * DIGITS_PER_MODULE
+    }
+
+    /// Show text, left aligned
+    ///
+    /// Text too long for one bridge command is split at the module
+    /// boundaries and written to each module of the display.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text does not fit, the bridge rejects a
+    /// command or the commands time out.
+    pub fn write_text(&self, text: &str) -> Result<()> {
+        self.check_fits(text)?;
+        if text.len() <= MAX_BRIDGE_PAYLOAD {
+            return self.virtual_command(USPIBridgeCommand::VirtualText, text.as_bytes().to_vec());
+        }
+        let digits = split_digits(text);
+        let commands = self
+            .modules
+            .iter()
+            .enumerate()
+            .map(|(index, &module)| {
+                // Blank the digits past the end of the text
+                let text: String = (0..DIGITS_PER_MODULE)
+                    .map(|digit| {
+                        digits
+                            .get(index * DIGITS_PER_MODULE + digit)
+                            .copied()
+                            .unwrap_or(" ")
+                    })
+                    .collect();
+                BridgeCommand::new(USPIBridgeCommand::DisplayText, module, text.into_bytes())
+            })
+            .collect();
+        self.bridge.execute(commands).map(|_| ())
+    }
+
+    /// Show a number right aligned with a fixed number of decimals
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number does not fit, the bridge rejects the
+    /// command or it times out.
+    pub fn write_number(&self, value: f64, decimals: usize) -> Result<()> {
+        let text = format!("{value:.decimals$}");
+        let digits = split_digits(&text).len();
+        let padding = " ".repeat(self.width().saturating_sub(digits));
+        self.write_text(&format!("{padding}{text}"))
+    }
+
+    /// Show an integer right aligned
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number does not fit, the bridge rejects the
+    /// command or it times out.
+    pub fn write_integer(&self, value: i64) -> Result<()> {
+        self.write_text(&format!("{value:>width$}", width = self.width()))
+    }
+
+    /// Set the brightness of all modules, 0-15
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the brightness is out of range, the bridge rejects
+    /// the command or it times out.
+    pub fn set_brightness(&self, brightness: u8) -> Result<()> {
+        check_brightness(brightness)?;
+        self.virtual_command(USPIBridgeCommand::VirtualBrightness, vec![brightness])
+    }
+
+    /// Set the segment mapping of all modules
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge rejects a command or the commands time out.
+    pub fn set_segment_mapping(&self, mapping: &SegmentMapping) -> Result<()> {
+        self.bridge
+            .execute(
+                self.modules
+                    .iter()
+                    .map(|&module| segment_mapping_command(module, mapping))
+                    .collect(),
+            )
+            .map(|_| ())
+    }
+
+    /// Blink text until [`stop`](Self::stop) or new text is written
+    ///
+    /// # Parameters
+    ///
+    /// * `text` - The text to blink.
+    /// * `interval` - Time the text is shown and hidden, up to 65535 ms.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text does not fit, is longer than
+    /// `MAX_BRIDGE_PAYLOAD - 2` bytes, the interval is out of range, the
+    /// bridge rejects the command or it times out.
+    pub fn blink(&self, text: &str, interval: Duration) -> Result<()> {
+        self.check_fits(text)?;
+        // The whole display blinks in one command, sharing it with the interval
+        let max_text = MAX_BRIDGE_PAYLOAD - 2;
+        if text.len() > max_text {
+            return Err(ThreadError::validation_error(
+                &format!(
+                    "Blink text is {} bytes but at most {max_text} fit in one command",
+                    text.len()
+                ),
+                "uSPIBridge blink",
+                Some("Blink shorter text"),
+            ));
+        }
+        let interval_ms = u16::try_from(interval.as_millis())
+            .ok()
+            .filter(|&ms| ms > 0)
+            .ok_or_else(|| {
+                ThreadError::validation_error(
+                    &format!("Blink interval of {interval:?} is out of range"),
+                    "uSPIBridge blink",
+                    Some("Use intervals of 1-65535 ms"),
+                )
+            })?;
+        let mut data = text.as_bytes().to_vec();
+        data.extend_from_slice(&interval_ms.to_le_bytes());
+        self.virtual_command(USPIBridgeCommand::VirtualFlash, data)
+    }
+
+    /// Stop blinking, keeping the text shown
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge rejects the command or it times out.
+    pub fn stop(&self) -> Result<()> {
+        self.virtual_command(USPIBridgeCommand::VirtualStop, Vec::new())
+    }
+
+    /// Clear the display
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge rejects the command or it times out.
+    pub fn clear(&self) -> Result<()> {
+        self.virtual_command(USPIBridgeCommand::VirtualClear, Vec::new())
+    }
+
+    /// Remove the virtual display from the bridge, leaving the modules as they are
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bridge rejects the command or it times out.
+    pub fn remove(self) -> Result<()> {
+        self.virtual_command(USPIBridgeCommand::DeleteVirtualDevice, Vec::new())
+    }
+
+    fn virtual_command(&self, command: USPIBridgeCommand, data: Vec<u8>) -> Result<()> {
+        self.bridge
+            .command(BridgeCommand::new(command, self.virtual_id, data))
+            .map(|_| ())
+    }
+
+    fn check_fits(&self, text: &str) -> Result<()> {
+        let digits = split_digits(text).len();
+        if digits > self.width() {
+            return Err(ThreadError::validation_error(
+                &format!(
+                    "\"{text}\" needs {digits} digits but the display has {}",
+                    self.width()
+                ),
+                "7-segment display",
+                Some("Shorten the text or use fewer decimals"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Split text into the digits it takes, with a `.` after a character sharing its digit
+fn split_digits(text: &str) -> Vec<&str> {
+    let mut digits = Vec::new();
+    let mut start = 0;
+    let mut previous = None;
+    for (index, c) in text.char_indices() {
+        if c != '.' || matches!(previous, None | Some('.')) {
+            if index > start {
+                digits.push(&text[start..index]);
+            }
+            start = index;
+        }
+        previous = Some(c);
+    }
+    if start < text.len() {
+        digits.push(&text[start..]);
+    }
+    digits
+}
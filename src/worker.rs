@@ -1,17 +1,22 @@
 use crate::commands::DeviceCommand;
 use crate::error::{Result, ThreadError};
+use crate::i2c::{I2cBusHealth, I2cProbe, I2cScanConfig, I2cScanReport};
 use crate::logging::ThreadLogger;
-use crate::state::{SharedDeviceState, ThreadStatus};
-use crate::sync::DeviceSync;
+use crate::persistence::{PersistedState, PersistenceConfig, StatePersister};
+use crate::pwm::{PwmAnimations, PwmChannelMap, PwmConfig, PwmPeriod};
+use crate::servo::{ServoMotions, ServoRegistry};
+use crate::state::{SharedDeviceState, StateChangeType, ThreadStatus};
+use crate::sync::{AdaptiveRefreshConfig, DeviceSync};
+use crate::uspibridge::{self, BridgeCommand, USPIBRIDGE_RESPONSE_DELAY};
 use crossbeam_channel::{Receiver, Sender, TryRecvError};
 use log::{debug, error, info, warn, LevelFilter};
 use pokeys_lib::{
-    connect_to_device, connect_to_network_device, NetworkDeviceSummary, PoKeysDevice,
+    connect_to_device, connect_to_network_device, I2cStatus, NetworkDeviceSummary, PoKeysDevice,
+    PoKeysError,
 };
-use std::convert::TryInto;
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// Device connection type
 #[derive(Debug, Clone)]
@@ -66,6 +71,10 @@ pub struct DeviceWorkerImpl {
     shared_state: Arc<SharedDeviceState>,
     /// Refresh interval in milliseconds
     refresh_interval: u64,
+    /// Adaptive refresh configuration
+    adaptive_refresh: Option<AdaptiveRefreshConfig>,
+    /// State persistence configuration
+    persistence: Option<PersistenceConfig>,
     /// Device type for reconnection
     device_type: DeviceType,
     /// Logger
@@ -135,6 +144,8 @@ impl DeviceWorkerImpl {
                 command_rx: Some(worker_rx),
                 shared_state,
                 refresh_interval,
+                adaptive_refresh: None,
+                persistence: None,
                 device_type,
                 logger: None,
             },
@@ -148,13 +159,28 @@ impl DeviceWorkerImpl {
         self
     }
 
+    /// Enable adaptive refresh
+    pub fn with_adaptive_refresh(mut self, config: AdaptiveRefreshConfig) -> Self {
+        self.adaptive_refresh = Some(config);
+        self
+    }
+
+    /// Enable state persistence
+    pub fn with_persistence(mut self, config: PersistenceConfig) -> Self {
+        self.persistence = Some(config);
+        self
+    }
+
     /// Run the worker thread
+    #[allow(clippy::too_many_arguments)]
     fn run_thread(
         thread_id: u32,
         device_type: DeviceType,
         command_rx: Receiver<DeviceCommand>,
         shared_state: Arc<SharedDeviceState>,
         refresh_interval: u64,
+        adaptive_refresh: Option<AdaptiveRefreshConfig>,
+        persistence: Option<PersistenceConfig>,
         logger: Option<Arc<ThreadLogger>>,
     ) {
         // Use logger if available, otherwise use standard log macros
@@ -225,6 +251,7 @@ impl DeviceWorkerImpl {
 
         // Create a device sync
         let mut device_sync = DeviceSync::new(thread_id, shared_state.clone(), refresh_interval);
+        device_sync.set_adaptive_refresh(adaptive_refresh);
 
         // Initial sync
         if let Err(e) = device_sync.sync(&mut device) {
@@ -239,6 +266,37 @@ impl DeviceWorkerImpl {
             });
         }
 
+        // Restore saved outputs before normal operation
+        let mut persister = persistence.map(|config| {
+            let mut persister = StatePersister::new(config, device.device_data.serial_number);
+            if persister.config().restore_on_start {
+                match persister.load() {
+                    Ok(Some(saved)) => {
+                        Self::restore_outputs(&mut device, &shared_state, &saved, &logger);
+                        persister.mark_saved(saved);
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        if let Some(logger) = &logger {
+                            logger.error(&format!("Failed to load saved state: {}", e));
+                        } else {
+                            error!("Failed to load saved state: {}", e);
+                        }
+                    }
+                }
+            }
+            persister
+        });
+
+        // Servo configurations received through ConfigureServo
+        let mut servos = ServoRegistry::new();
+        // Servo moves in progress
+        let mut servo_motions = ServoMotions::new();
+        // PWM animations in progress
+        let mut pwm_animations = PwmAnimations::new();
+        // Start of the current pause; moves and animations are frozen while paused
+        let mut paused_since: Option<Instant> = None;
+
         // Main loop
         loop {
             // Check for commands
@@ -256,7 +314,36 @@ impl DeviceWorkerImpl {
                         );
                     }
 
-                    match command {
+                    // Any command counts as activity for adaptive refresh
+                    device_sync.record_activity();
+                    shared_state.record_command(&command);
+
+                    // Track command latency and failures for the sync statistics
+                    let command_start = Instant::now();
+
+                    // Claims are enforced here, whichever path sent the command
+                    if let Err(e) = shared_state
+                        .resources()
+                        .check_command(&command, &pwm_channel_map(&device))
+                    {
+                        let message = format!("Rejected {}: {}", command.name(), e);
+                        if let Some(logger) = &logger {
+                            logger.error(&message);
+                        } else {
+                            error!("{}", message);
+                        }
+                        shared_state.set_error(Some(message));
+                        shared_state
+                            .sync_stats()
+                            .record_command(command_start.elapsed(), false);
+                        continue;
+                    }
+                    let command = match command {
+                        DeviceCommand::Claimed { command, .. } => *command,
+                        command => command,
+                    };
+
+                    let result: Result<()> = match command {
                         DeviceCommand::Terminate => {
                             if let Some(logger) = &logger {
                                 logger.info(&format!("Device thread {} terminating", thread_id));
@@ -275,6 +362,7 @@ impl DeviceWorkerImpl {
                             }
 
                             shared_state.set_paused(true);
+                            Ok(())
                         }
                         DeviceCommand::Start | DeviceCommand::Restart => {
                             if let Some(logger) = &logger {
@@ -288,6 +376,7 @@ impl DeviceWorkerImpl {
 
                             shared_state.set_running(true);
                             shared_state.set_paused(false);
+                            Ok(())
                         }
                         DeviceCommand::GetStatus => {
                             // Just update the status in the shared state
@@ -304,6 +393,7 @@ impl DeviceWorkerImpl {
                                     shared_state.status()
                                 );
                             }
+                            Ok(())
                         }
                         DeviceCommand::SetDigitalOutput { pin, value } => {
                             if let Some(logger) = &logger {
@@ -315,20 +405,26 @@ impl DeviceWorkerImpl {
                                 debug!("Setting digital output pin {} to {}", pin, value);
                             }
 
-                            if let Err(e) = device.set_digital_output(pin, value) {
-                                if let Some(logger) = &logger {
-                                    logger.error(&format!("Failed to set digital output: {}", e));
-                                } else {
-                                    error!("Failed to set digital output: {}", e);
+                            match device.set_digital_output(pin, value) {
+                                Ok(_) => {
+                                    // Update the pin state in the shared state
+                                    shared_state.set_digital_output(pin, value);
+                                    Ok(())
                                 }
+                                Err(e) => {
+                                    if let Some(logger) = &logger {
+                                        logger
+                                            .error(&format!("Failed to set digital output: {}", e));
+                                    } else {
+                                        error!("Failed to set digital output: {}", e);
+                                    }
 
-                                shared_state.update(|state| {
-                                    state.error_message =
-                                        Some(format!("Failed to set digital output: {}", e));
-                                });
-                            } else {
-                                // Update the pin state in the shared state
-                                shared_state.set_digital_output(pin, value);
+                                    shared_state.update(|state| {
+                                        state.error_message =
+                                            Some(format!("Failed to set digital output: {}", e));
+                                    });
+                                    Err(e.into())
+                                }
                             }
                         }
                         DeviceCommand::SetAnalogOutput { pin, value } => {
@@ -341,20 +437,26 @@ impl DeviceWorkerImpl {
                                 debug!("Setting analog output pin {} to {}", pin, value);
                             }
 
-                            if let Err(e) = device.set_analog_output(pin, value) {
-                                if let Some(logger) = &logger {
-                                    logger.error(&format!("Failed to set analog output: {}", e));
-                                } else {
-                                    error!("Failed to set analog output: {}", e);
+                            match device.set_analog_output(pin, value) {
+                                Ok(_) => {
+                                    // Update the pin state in the shared state
+                                    shared_state.set_analog_output(pin, value);
+                                    Ok(())
                                 }
+                                Err(e) => {
+                                    if let Some(logger) = &logger {
+                                        logger
+                                            .error(&format!("Failed to set analog output: {}", e));
+                                    } else {
+                                        error!("Failed to set analog output: {}", e);
+                                    }
 
-                                shared_state.update(|state| {
-                                    state.error_message =
-                                        Some(format!("Failed to set analog output: {}", e));
-                                });
-                            } else {
-                                // Update the pin state in the shared state
-                                shared_state.set_analog_output(pin, value);
+                                    shared_state.update(|state| {
+                                        state.error_message =
+                                            Some(format!("Failed to set analog output: {}", e));
+                                    });
+                                    Err(e.into())
+                                }
                             }
                         }
                         DeviceCommand::SetPwmDuty { channel, duty } => {
@@ -367,40 +469,114 @@ impl DeviceWorkerImpl {
                                 debug!("Setting PWM channel {} duty to {}", channel, duty);
                             }
 
-                            // Convert channel (0-5) to pin number (17-22)
-                            // PWM channels map: 0->22, 1->21, 2->20, 3->19, 4->18, 5->17
-                            let pin = match channel {
-                                0 => 22,
-                                1 => 21,
-                                2 => 20,
-                                3 => 19,
-                                4 => 18,
-                                5 => 17,
-                                _ => {
+                            Self::cancel_pwm_animation(&mut pwm_animations, channel, &shared_state);
+                            let result = set_pwm_duty(&mut device, channel, duty);
+
+                            match &result {
+                                Ok(()) => {
+                                    // Update the PWM state in the shared state
+                                    shared_state.set_pwm_duty_cycle(channel, duty);
+                                }
+                                Err(e) => {
                                     if let Some(logger) = &logger {
-                                        logger.error(&format!("Invalid PWM channel: {}", channel));
+                                        logger
+                                            .error(&format!("Failed to set PWM duty cycle: {}", e));
                                     } else {
-                                        error!("Invalid PWM channel: {}", channel);
+                                        error!("Failed to set PWM duty cycle: {}", e);
                                     }
-                                    continue;
+
+                                    shared_state.update(|state| {
+                                        state.error_message =
+                                            Some(format!("Failed to set PWM duty cycle: {}", e));
+                                    });
                                 }
-                            };
+                            }
+                            result
+                        }
+                        DeviceCommand::ConfigurePwm { config } => {
+                            if let Some(logger) = &logger {
+                                logger.debug(&format!("Configuring PWM: {:?}", config));
+                            } else {
+                                debug!("Configuring PWM: {:?}", config);
+                            }
+
+                            let result =
+                                config
+                                    .validate(&pwm_channel_map(&device))
+                                    .and_then(|period| {
+                                        device.pwm.pwm_period = period;
+                                        for channel in 0..device.pwm.pwm_values.len() {
+                                            device.pwm.set_channel_enabled(
+                                                channel,
+                                                config.enabled_channels.contains(&channel),
+                                            )?;
+                                        }
+                                        Ok(device.set_pwm_configuration()?)
+                                    });
+
+                            match &result {
+                                Ok(()) => {
+                                    shared_state.update(|state| state.pwm.clone_from(&device.pwm));
+                                }
+                                Err(e) => {
+                                    let message = format!("Failed to configure PWM: {}", e);
+                                    if let Some(logger) = &logger {
+                                        logger.error(&message);
+                                    } else {
+                                        error!("{}", message);
+                                    }
+                                    shared_state.set_error(Some(message));
+                                }
+                            }
+                            result
+                        }
+                        DeviceCommand::AnimatePwm { channel, waveform } => {
+                            if let Some(logger) = &logger {
+                                logger.debug(&format!(
+                                    "Animating PWM channel {}: {:?}",
+                                    channel, waveform
+                                ));
+                            } else {
+                                debug!("Animating PWM channel {}: {:?}", channel, waveform);
+                            }
+
+                            Self::cancel_pwm_animation(&mut pwm_animations, channel, &shared_state);
+                            let result =
+                                pwm_channel_map(&device)
+                                    .check_channel(channel)
+                                    .and_then(|_| {
+                                        pwm_animations.start(
+                                            channel,
+                                            &waveform,
+                                            device.pwm.pwm_period,
+                                            Instant::now(),
+                                        )
+                                    });
 
-                            if let Err(e) = device.set_pwm_duty_cycle_for_pin(pin, duty) {
+                            if let Err(e) = &result {
+                                let message =
+                                    format!("Failed to animate PWM channel {}: {}", channel, e);
                                 if let Some(logger) = &logger {
-                                    logger.error(&format!("Failed to set PWM duty cycle: {}", e));
+                                    logger.error(&message);
                                 } else {
-                                    error!("Failed to set PWM duty cycle: {}", e);
+                                    error!("{}", message);
                                 }
-
-                                shared_state.update(|state| {
-                                    state.error_message =
-                                        Some(format!("Failed to set PWM duty cycle: {}", e));
-                                });
+                                shared_state.set_error(Some(message));
+                            }
+                            result
+                        }
+                        DeviceCommand::CancelPwmAnimation { channel } => {
+                            if let Some(logger) = &logger {
+                                logger.debug(&format!(
+                                    "Cancelling PWM animation on channel {}",
+                                    channel
+                                ));
                             } else {
-                                // Update the PWM state in the shared state
-                                shared_state.set_pwm_duty_cycle(channel, duty);
+                                debug!("Cancelling PWM animation on channel {}", channel);
                             }
+
+                            Self::cancel_pwm_animation(&mut pwm_animations, channel, &shared_state);
+                            Ok(())
                         }
                         DeviceCommand::ConfigureServo { pin, config } => {
                             if let Some(logger) = &logger {
@@ -409,17 +585,34 @@ impl DeviceWorkerImpl {
                                 debug!("Configuring servo on pin {}", pin);
                             }
 
-                            // Store servo configuration in device state
-                            // Note: This would typically be stored in a servo configuration map
-                            // For now, we'll just log the configuration
-                            if let Some(logger) = &logger {
+                            let result = servos.configure(
+                                pin,
+                                config,
+                                &pwm_channel_map(&device),
+                                device.pwm.pwm_period,
+                            );
+                            if let Err(e) = &result {
+                                if let Some(logger) = &logger {
+                                    logger.error(&format!("Failed to configure servo: {}", e));
+                                } else {
+                                    error!("Failed to configure servo: {}", e);
+                                }
+                                shared_state
+                                    .set_error(Some(format!("Failed to configure servo: {}", e)));
+                            } else if let Some(logger) = &logger {
                                 logger.info(&format!(
                                     "Servo configured on pin {} with config: {:?}",
-                                    pin, config
+                                    pin,
+                                    servos.get(pin)
                                 ));
                             } else {
-                                info!("Servo configured on pin {} with config: {:?}", pin, config);
+                                info!(
+                                    "Servo configured on pin {} with config: {:?}",
+                                    pin,
+                                    servos.get(pin)
+                                );
                             }
+                            result
                         }
                         DeviceCommand::SetServoAngle { pin, angle } => {
                             if let Some(logger) = &logger {
@@ -431,17 +624,28 @@ impl DeviceWorkerImpl {
                                 debug!("Setting servo angle on pin {} to {}", pin, angle);
                             }
 
-                            // For now, convert angle to PWM duty cycle (simplified implementation)
-                            // In a full implementation, this would use stored servo configuration
-                            let duty = ((angle / 180.0) * 4095.0) as u32;
+                            // A direct command takes over from a move in progress
+                            Self::cancel_servo_motion(&mut servo_motions, pin, &shared_state);
 
-                            if let Err(e) = device.set_pwm_duty_cycle_for_pin(pin, duty) {
-                                if let Some(logger) = &logger {
-                                    logger.error(&format!("Failed to set servo angle: {}", e));
-                                } else {
-                                    error!("Failed to set servo angle: {}", e);
+                            let result = servos
+                                .angle_duty(pin, angle)
+                                .and_then(|duty| set_servo_duty(&mut device, pin, duty));
+
+                            match &result {
+                                Ok(()) => shared_state.set_servo_position(pin, angle),
+                                Err(e) => {
+                                    if let Some(logger) = &logger {
+                                        logger.error(&format!("Failed to set servo angle: {}", e));
+                                    } else {
+                                        error!("Failed to set servo angle: {}", e);
+                                    }
+                                    shared_state.set_error(Some(format!(
+                                        "Failed to set servo angle: {}",
+                                        e
+                                    )));
                                 }
                             }
+                            result
                         }
                         DeviceCommand::SetServoSpeed { pin, speed } => {
                             if let Some(logger) = &logger {
@@ -453,16 +657,28 @@ impl DeviceWorkerImpl {
                                 debug!("Setting servo speed on pin {} to {}", pin, speed);
                             }
 
-                            // Convert speed (-100 to 100) to PWM duty cycle
-                            let duty = (((speed + 100.0) / 200.0) * 4095.0) as u32;
+                            // A direct command takes over from a move in progress
+                            Self::cancel_servo_motion(&mut servo_motions, pin, &shared_state);
 
-                            if let Err(e) = device.set_pwm_duty_cycle_for_pin(pin, duty) {
-                                if let Some(logger) = &logger {
-                                    logger.error(&format!("Failed to set servo speed: {}", e));
-                                } else {
-                                    error!("Failed to set servo speed: {}", e);
+                            let result = servos
+                                .speed_duty(pin, speed)
+                                .and_then(|duty| set_servo_duty(&mut device, pin, duty));
+
+                            match &result {
+                                Ok(()) => shared_state.set_servo_speed(pin, speed),
+                                Err(e) => {
+                                    if let Some(logger) = &logger {
+                                        logger.error(&format!("Failed to set servo speed: {}", e));
+                                    } else {
+                                        error!("Failed to set servo speed: {}", e);
+                                    }
+                                    shared_state.set_error(Some(format!(
+                                        "Failed to set servo speed: {}",
+                                        e
+                                    )));
                                 }
                             }
+                            result
                         }
                         DeviceCommand::StopServo { pin } => {
                             if let Some(logger) = &logger {
@@ -471,17 +687,87 @@ impl DeviceWorkerImpl {
                                 debug!("Stopping servo on pin {}", pin);
                             }
 
-                            // Set to neutral position (1.5ms pulse = ~1500 duty cycle)
-                            let duty = 1500;
+                            // A direct command takes over from a move in progress
+                            Self::cancel_servo_motion(&mut servo_motions, pin, &shared_state);
 
-                            if let Err(e) = device.set_pwm_duty_cycle_for_pin(pin, duty) {
-                                if let Some(logger) = &logger {
-                                    logger.error(&format!("Failed to stop servo: {}", e));
-                                } else {
-                                    error!("Failed to stop servo: {}", e);
+                            let result = servos
+                                .stop_duty(pin)
+                                .and_then(|duty| set_servo_duty(&mut device, pin, duty));
+
+                            match &result {
+                                Ok(()) => shared_state.set_servo_speed(pin, 0.0),
+                                Err(e) => {
+                                    if let Some(logger) = &logger {
+                                        logger.error(&format!("Failed to stop servo: {}", e));
+                                    } else {
+                                        error!("Failed to stop servo: {}", e);
+                                    }
+                                    shared_state
+                                        .set_error(Some(format!("Failed to stop servo: {}", e)));
+                                }
+                            }
+                            result
+                        }
+                        DeviceCommand::MoveServos { moves, profile } => {
+                            if let Some(logger) = &logger {
+                                logger.debug(&format!("Moving servos {:?}", moves));
+                            } else {
+                                debug!("Moving servos {:?}", moves);
+                            }
+
+                            let now = Instant::now();
+                            let planned = profile.validate().and_then(|()| {
+                                moves
+                                    .iter()
+                                    .map(|&(pin, target)| {
+                                        servos.angle_duty(pin, target)?;
+                                        let from = servo_motions
+                                            .angle(pin, now)
+                                            .or_else(|| shared_state.get_servo_position(pin))
+                                            .ok_or_else(|| {
+                                                ThreadError::InvalidParameter(format!(
+                                                    "Servo position on pin {pin} is unknown; set an angle first"
+                                                ))
+                                            })?;
+                                        Ok((pin, from, target))
+                                    })
+                                    .collect::<Result<Vec<_>>>()
+                            });
+
+                            match planned {
+                                Ok(planned) => {
+                                    for &(pin, _, _) in &planned {
+                                        Self::cancel_servo_motion(
+                                            &mut servo_motions,
+                                            pin,
+                                            &shared_state,
+                                        );
+                                    }
+                                    servo_motions.start(&planned, profile, now);
+                                    Ok(())
+                                }
+                                Err(e) => {
+                                    if let Some(logger) = &logger {
+                                        logger.error(&format!("Failed to move servos: {}", e));
+                                    } else {
+                                        error!("Failed to move servos: {}", e);
+                                    }
+                                    shared_state
+                                        .set_error(Some(format!("Failed to move servos: {}", e)));
+                                    Err(e)
                                 }
                             }
                         }
+                        DeviceCommand::CancelServoMotion { pin } => {
+                            if let Some(logger) = &logger {
+                                logger.debug(&format!("Cancelling servo motion on pin {}", pin));
+                            } else {
+                                debug!("Cancelling servo motion on pin {}", pin);
+                            }
+
+                            Self::cancel_servo_motion(&mut servo_motions, pin, &shared_state);
+                            Ok(())
+                        }
                         DeviceCommand::I2cWrite { address, data } => {
                             if let Some(logger) = &logger {
                                 logger.debug(&format!("I2C write to address 0x{:02X}", address));
@@ -489,15 +775,15 @@ impl DeviceWorkerImpl {
                                 debug!("I2C write to address 0x{:02X}", address);
                             }
 
-                            if let Err(e) =
-                                pokeys_lib::i2c_write_simple(&mut device, address, &data)
-                            {
+                            let result = Self::i2c_write(&mut device, address, &data);
+                            if let Err(e) = &result {
                                 if let Some(logger) = &logger {
                                     logger.error(&format!("Failed to write I2C: {}", e));
                                 } else {
                                     error!("Failed to write I2C: {}", e);
                                 }
                             }
+                            result
                         }
                         DeviceCommand::I2cRead { address, length } => {
                             if let Some(logger) = &logger {
@@ -506,13 +792,14 @@ impl DeviceWorkerImpl {
                                 debug!("I2C read from address 0x{:02X}", address);
                             }
 
-                            match pokeys_lib::i2c_read_simple(&mut device, address, length) {
+                            match Self::i2c_read(&mut device, address, length) {
                                 Ok(data) => {
                                     if let Some(logger) = &logger {
                                         logger.debug(&format!("I2C read {} bytes", data.len()));
                                     } else {
                                         debug!("I2C read {} bytes", data.len());
                                     }
+                                    Ok(())
                                 }
                                 Err(e) => {
                                     if let Some(logger) = &logger {
@@ -520,6 +807,7 @@ impl DeviceWorkerImpl {
                                     } else {
                                         error!("Failed to read I2C: {}", e);
                                     }
+                                    Err(e)
                                 }
                             }
                         }
@@ -536,23 +824,22 @@ impl DeviceWorkerImpl {
                             }
 
                             // Perform write then read operation
-                            if let Err(e) =
-                                pokeys_lib::i2c_write_simple(&mut device, address, &write_data)
-                            {
+                            if let Err(e) = Self::i2c_write(&mut device, address, &write_data) {
                                 if let Some(logger) = &logger {
                                     logger.error(&format!("Failed to write I2C: {}", e));
                                 } else {
                                     error!("Failed to write I2C: {}", e);
                                 }
+                                Err(e)
                             } else {
-                                match pokeys_lib::i2c_read_simple(&mut device, address, read_length)
-                                {
+                                match Self::i2c_read(&mut device, address, read_length) {
                                     Ok(data) => {
                                         if let Some(logger) = &logger {
                                             logger.debug(&format!("I2C read {} bytes", data.len()));
                                         } else {
                                             debug!("I2C read {} bytes", data.len());
                                         }
+                                        Ok(())
                                     }
                                     Err(e) => {
                                         if let Some(logger) = &logger {
@@ -560,64 +847,141 @@ impl DeviceWorkerImpl {
                                         } else {
                                             error!("Failed to read I2C: {}", e);
                                         }
+                                        Err(e)
                                     }
                                 }
                             }
                         }
+                        DeviceCommand::I2cTransfer {
+                            address,
+                            write_data,
+                            read_length,
+                            response,
+                        } => {
+                            if let Some(logger) = &logger {
+                                logger
+                                    .debug(&format!("I2C transfer with address 0x{:02X}", address));
+                            } else {
+                                debug!("I2C transfer with address 0x{:02X}", address);
+                            }
+
+                            let result =
+                                Self::i2c_transfer(&mut device, address, &write_data, read_length);
+                            if let Err(e) = &result {
+                                if let Some(logger) = &logger {
+                                    logger.debug(&format!("I2C transfer failed: {}", e));
+                                } else {
+                                    debug!("I2C transfer failed: {}", e);
+                                }
+                            }
+                            let outcome = command_outcome(&result);
+                            // The requester may have timed out and gone away
+                            let _ = response.send(result);
+                            outcome
+                        }
                         DeviceCommand::I2cScan => {
+                            Self::i2c_scan(&mut device, &I2cScanConfig::default(), &logger);
+                            Ok(())
+                        }
+                        DeviceCommand::I2cScanBus { config, response } => {
+                            let report = Self::i2c_scan(&mut device, &config, &logger);
+                            // The requester may have timed out and gone away
+                            let _ = response.send(Ok(report));
+                            Ok(())
+                        }
+                        DeviceCommand::ConfigureUSPIBridge { address, config } => {
                             if let Some(logger) = &logger {
-                                logger.debug("Scanning I2C bus");
+                                logger.debug(&format!(
+                                    "Configuring uSPIBridge at address 0x{:02X}",
+                                    address
+                                ));
                             } else {
-                                debug!("Scanning I2C bus");
+                                debug!("Configuring uSPIBridge at address 0x{:02X}", address);
                             }
 
-                            // Scan I2C addresses 0x08 to 0x77
-                            for addr in 0x08..=0x77 {
-                                if pokeys_lib::i2c_read_simple(&mut device, addr, 1).is_ok() {
+                            let commands = uspibridge::configuration_commands(&config);
+                            match Self::uspibridge_execute(&mut device, address, &commands) {
+                                Ok(_) => {
                                     if let Some(logger) = &logger {
                                         logger.info(&format!(
-                                            "Found I2C device at address 0x{:02X}",
-                                            addr
+                                            "uSPIBridge configured with {} devices",
+                                            config.device_count
                                         ));
                                     } else {
-                                        info!("Found I2C device at address 0x{:02X}", addr);
+                                        info!(
+                                            "uSPIBridge configured with {} devices",
+                                            config.device_count
+                                        );
+                                    }
+                                    Ok(())
+                                }
+                                Err(e) => {
+                                    let message = format!("Failed to configure uSPIBridge: {}", e);
+                                    if let Some(logger) = &logger {
+                                        logger.error(&message);
+                                    } else {
+                                        error!("{}", message);
                                     }
+                                    shared_state.set_error(Some(message));
+                                    Err(e)
                                 }
                             }
                         }
-                        DeviceCommand::ConfigureUSPIBridge { config } => {
+                        DeviceCommand::USPIBridgeCommand { address, command } => {
                             if let Some(logger) = &logger {
-                                logger.debug("Configuring uSPIBridge");
+                                logger.debug(&format!(
+                                    "Sending uSPIBridge command to address 0x{:02X}",
+                                    address
+                                ));
                             } else {
-                                debug!("Configuring uSPIBridge");
+                                debug!("Sending uSPIBridge command to address 0x{:02X}", address);
                             }
 
-                            // For now, just log the configuration
-                            // Full implementation would configure the uSPIBridge hardware
-                            if let Some(logger) = &logger {
-                                logger.info(&format!(
-                                    "uSPIBridge configured with {} devices",
-                                    config.device_count
-                                ));
-                            } else {
-                                info!("uSPIBridge configured with {} devices", config.device_count);
+                            let result = BridgeCommand::from_raw(&command).and_then(|command| {
+                                Self::uspibridge_execute(&mut device, address, &[command])
+                            });
+                            if let Err(e) = &result {
+                                let message = format!("Failed to send uSPIBridge command: {}", e);
+                                if let Some(logger) = &logger {
+                                    logger.error(&message);
+                                } else {
+                                    error!("{}", message);
+                                }
+                                shared_state.set_error(Some(message));
                             }
+                            result.map(|_| ())
                         }
-                        DeviceCommand::USPIBridgeCommand { command } => {
+                        DeviceCommand::USPIBridgeRequest {
+                            address,
+                            commands,
+                            response,
+                        } => {
                             if let Some(logger) = &logger {
-                                logger.debug("Sending uSPIBridge command");
+                                logger.debug(&format!(
+                                    "Sending {} uSPIBridge commands to address 0x{:02X}",
+                                    commands.len(),
+                                    address
+                                ));
                             } else {
-                                debug!("Sending uSPIBridge command");
+                                debug!(
+                                    "Sending {} uSPIBridge commands to address 0x{:02X}",
+                                    commands.len(),
+                                    address
+                                );
                             }
 
-                            // For now, just log the command
-                            // Full implementation would send the command via uSPIBridge
-                            if let Some(logger) = &logger {
-                                logger
-                                    .debug(&format!("uSPIBridge command: {} bytes", command.len()));
-                            } else {
-                                debug!("uSPIBridge command: {} bytes", command.len());
+                            let result = Self::uspibridge_execute(&mut device, address, &commands);
+                            if let Err(e) = &result {
+                                if let Some(logger) = &logger {
+                                    logger.debug(&format!("uSPIBridge command failed: {}", e));
+                                } else {
+                                    debug!("uSPIBridge command failed: {}", e);
+                                }
                             }
+                            let outcome = command_outcome(&result);
+                            // The requester may have timed out and gone away
+                            let _ = response.send(result);
+                            outcome
                         }
                         DeviceCommand::SetDigitalOutputsBulk { pin_states } => {
                             if let Some(logger) = &logger {
@@ -629,6 +993,8 @@ impl DeviceWorkerImpl {
                                 debug!("Bulk setting {} digital outputs", pin_states.len());
                             }
 
+                            // Every output is attempted, the first failure is reported
+                            let mut result = Ok(());
                             for (pin, state) in pin_states {
                                 if let Err(e) = device.set_digital_output(pin, state) {
                                     if let Some(logger) = &logger {
@@ -639,10 +1005,14 @@ impl DeviceWorkerImpl {
                                     } else {
                                         error!("Failed to set digital output pin {}: {}", pin, e);
                                     }
+                                    if result.is_ok() {
+                                        result = Err(e.into());
+                                    }
                                 } else {
                                     shared_state.set_digital_output(pin, state);
                                 }
                             }
+                            result
                         }
                         DeviceCommand::SetPwmDutiesBulk { channel_duties } => {
                             if let Some(logger) = &logger {
@@ -654,30 +1024,33 @@ impl DeviceWorkerImpl {
                                 debug!("Bulk setting {} PWM duties", channel_duties.len());
                             }
 
+                            // Every channel is attempted, the first failure is reported
+                            let mut result = Ok(());
                             for (channel, duty) in channel_duties {
-                                let pin = match channel {
-                                    0 => 22,
-                                    1 => 21,
-                                    2 => 20,
-                                    3 => 19,
-                                    4 => 18,
-                                    5 => 17,
-                                    _ => continue,
-                                };
-
-                                if let Err(e) = device.set_pwm_duty_cycle_for_pin(pin, duty) {
+                                Self::cancel_pwm_animation(
+                                    &mut pwm_animations,
+                                    channel,
+                                    &shared_state,
+                                );
+                                let channel_result = set_pwm_duty(&mut device, channel, duty);
+
+                                if let Err(e) = channel_result {
+                                    let message =
+                                        format!("Failed to set PWM channel {}: {}", channel, e);
                                     if let Some(logger) = &logger {
-                                        logger.error(&format!(
-                                            "Failed to set PWM channel {}: {}",
-                                            channel, e
-                                        ));
+                                        logger.error(&message);
                                     } else {
-                                        error!("Failed to set PWM channel {}: {}", channel, e);
+                                        error!("{}", message);
+                                    }
+                                    shared_state.set_error(Some(message));
+                                    if result.is_ok() {
+                                        result = Err(e);
                                     }
                                 } else {
                                     shared_state.set_pwm_duty_cycle(channel, duty);
                                 }
                             }
+                            result
                         }
                         DeviceCommand::ReadAnalogInputsBulk { pins } => {
                             if let Some(logger) = &logger {
@@ -688,6 +1061,7 @@ impl DeviceWorkerImpl {
 
                             // Analog inputs are read during regular refresh cycle
                             // This command just logs the request
+                            Ok(())
                         }
                         DeviceCommand::CheckPinCapability { pin, capability } => {
                             if let Some(logger) = &logger {
@@ -698,6 +1072,7 @@ impl DeviceWorkerImpl {
                             } else {
                                 debug!("Checking pin {} capability: {}", pin, capability);
                             }
+                            Ok(())
                         }
                         DeviceCommand::ValidatePinOperation { pin, operation } => {
                             if let Some(logger) = &logger {
@@ -708,6 +1083,7 @@ impl DeviceWorkerImpl {
                             } else {
                                 debug!("Validating pin {} for operation: {}", pin, operation);
                             }
+                            Ok(())
                         }
                         DeviceCommand::ConfigureEncoder {
                             encoder_index,
@@ -733,69 +1109,46 @@ impl DeviceWorkerImpl {
                             options.sampling_4x = sampling_4x;
 
                             // Convert u32 to u8 for pin_a and pin_b
-                            let pin_a_u8: u8 = match pin_a.try_into() {
-                                Ok(val) => val,
-                                Err(_) => {
-                                    if let Some(logger) = &logger {
-                                        logger.error(&format!(
-                                            "Pin A value {} is out of range for u8",
-                                            pin_a
-                                        ));
-                                    } else {
-                                        error!("Pin A value {} is out of range for u8", pin_a);
-                                    }
-
-                                    shared_state.update(|state| {
-                                        state.error_message = Some(format!(
-                                            "Pin A value {} is out of range for u8",
-                                            pin_a
-                                        ));
-                                    });
-                                    continue;
+                            let pins = match (u8::try_from(pin_a), u8::try_from(pin_b)) {
+                                (Ok(pin_a_u8), Ok(pin_b_u8)) => Ok((pin_a_u8, pin_b_u8)),
+                                (Err(_), _) => {
+                                    Err(format!("Pin A value {} is out of range for u8", pin_a))
+                                }
+                                (_, Err(_)) => {
+                                    Err(format!("Pin B value {} is out of range for u8", pin_b))
                                 }
                             };
 
-                            let pin_b_u8: u8 = match pin_b.try_into() {
-                                Ok(val) => val,
-                                Err(_) => {
-                                    if let Some(logger) = &logger {
-                                        logger.error(&format!(
-                                            "Pin B value {} is out of range for u8",
-                                            pin_b
-                                        ));
-                                    } else {
-                                        error!("Pin B value {} is out of range for u8", pin_b);
-                                    }
-
-                                    shared_state.update(|state| {
-                                        state.error_message = Some(format!(
-                                            "Pin B value {} is out of range for u8",
-                                            pin_b
-                                        ));
-                                    });
-                                    continue;
+                            let result = match pins {
+                                Ok((pin_a_u8, pin_b_u8)) => device
+                                    .configure_encoder(
+                                        encoder_index as u8,
+                                        pin_a_u8,
+                                        pin_b_u8,
+                                        options,
+                                    )
+                                    .map(|_| ())
+                                    .map_err(|e| {
+                                        (format!("Failed to configure encoder: {}", e), e.into())
+                                    }),
+                                Err(message) => {
+                                    Err((message.clone(), ThreadError::InvalidParameter(message)))
                                 }
                             };
 
-                            if let Err(e) = device.configure_encoder(
-                                encoder_index as u8,
-                                pin_a_u8,
-                                pin_b_u8,
-                                options,
-                            ) {
+                            // The encoder state will be updated in the next sync
+                            result.map_err(|(message, e)| {
                                 if let Some(logger) = &logger {
-                                    logger.error(&format!("Failed to configure encoder: {}", e));
+                                    logger.error(&message);
                                 } else {
-                                    error!("Failed to configure encoder: {}", e);
+                                    error!("{}", message);
                                 }
 
                                 shared_state.update(|state| {
-                                    state.error_message =
-                                        Some(format!("Failed to configure encoder: {}", e));
+                                    state.error_message = Some(message.clone());
                                 });
-                            } else {
-                                // The encoder state will be updated in the next sync
-                            }
+                                e
+                            })
                         }
                         DeviceCommand::ResetDigitalCounter { pin } => {
                             if let Some(logger) = &logger {
@@ -804,7 +1157,7 @@ impl DeviceWorkerImpl {
                                 debug!("Resetting digital counter for pin {}", pin);
                             }
 
-                            if let Err(e) = device.reset_digital_counter(pin) {
+                            device.reset_digital_counter(pin).map_err(|e| {
                                 if let Some(logger) = &logger {
                                     logger
                                         .error(&format!("Failed to reset digital counter: {}", e));
@@ -816,7 +1169,8 @@ impl DeviceWorkerImpl {
                                     state.error_message =
                                         Some(format!("Failed to reset digital counter: {}", e));
                                 });
-                            }
+                                e.into()
+                            })
                         }
                         DeviceCommand::Custom {
                             request_type,
@@ -837,19 +1191,30 @@ impl DeviceWorkerImpl {
                                 );
                             }
 
-                            if let Err(e) =
-                                device.custom_request(request_type, param1, param2, param3, param4)
-                            {
-                                if let Some(logger) = &logger {
-                                    logger.error(&format!("Failed to send custom request: {}", e));
-                                } else {
-                                    error!("Failed to send custom request: {}", e);
-                                }
+                            match device.custom_request(
+                                request_type,
+                                param1,
+                                param2,
+                                param3,
+                                param4,
+                            ) {
+                                Ok(_) => Ok(()),
+                                Err(e) => {
+                                    if let Some(logger) = &logger {
+                                        logger.error(&format!(
+                                            "Failed to send custom request: {}",
+                                            e
+                                        ));
+                                    } else {
+                                        error!("Failed to send custom request: {}", e);
+                                    }
 
-                                shared_state.update(|state| {
-                                    state.error_message =
-                                        Some(format!("Failed to send custom request: {}", e));
-                                });
+                                    shared_state.update(|state| {
+                                        state.error_message =
+                                            Some(format!("Failed to send custom request: {}", e));
+                                    });
+                                    Err(e.into())
+                                }
                             }
                         }
                         DeviceCommand::SetLogLevel(level) => {
@@ -859,6 +1224,7 @@ impl DeviceWorkerImpl {
                                 info!("Setting log level to {:?}", level);
                             }
                             // The actual log level change is handled by the controller
+                            Ok(())
                         }
                         DeviceCommand::SetPinFunction { pin, pin_function } => {
                             if let Some(logger) = &logger {
@@ -870,24 +1236,34 @@ impl DeviceWorkerImpl {
                                 debug!("Setting pin {} function to {:?}", pin, pin_function);
                             }
 
-                            if let Err(e) = device.set_pin_function(pin, pin_function) {
-                                if let Some(logger) = &logger {
-                                    logger.error(&format!("Failed to set pin function: {}", e));
-                                } else {
-                                    error!("Failed to set pin function: {}", e);
+                            match device.set_pin_function(pin, pin_function) {
+                                Ok(_) => {
+                                    if let Some(logger) = &logger {
+                                        logger.info(&format!(
+                                            "Successfully configured pin {} as {:?}",
+                                            pin, pin_function
+                                        ));
+                                    } else {
+                                        info!(
+                                            "Successfully configured pin {} as {:?}",
+                                            pin, pin_function
+                                        );
+                                    }
+                                    Ok(())
                                 }
+                                Err(e) => {
+                                    if let Some(logger) = &logger {
+                                        logger.error(&format!("Failed to set pin function: {}", e));
+                                    } else {
+                                        error!("Failed to set pin function: {}", e);
+                                    }
 
-                                shared_state.update(|state| {
-                                    state.error_message =
-                                        Some(format!("Failed to set pin function: {}", e));
-                                });
-                            } else if let Some(logger) = &logger {
-                                logger.info(&format!(
-                                    "Successfully configured pin {} as {:?}",
-                                    pin, pin_function
-                                ));
-                            } else {
-                                info!("Successfully configured pin {} as {:?}", pin, pin_function);
+                                    shared_state.update(|state| {
+                                        state.error_message =
+                                            Some(format!("Failed to set pin function: {}", e));
+                                    });
+                                    Err(e.into())
+                                }
                             }
                         }
                         DeviceCommand::UpdateModel(model) => {
@@ -901,9 +1277,7 @@ impl DeviceWorkerImpl {
                             device.model = Some(model.clone());
 
                             // Update the model in the shared state
-                            shared_state.update(|state| {
-                                state.model = Some(model);
-                            });
+                            shared_state.set_model(Some(model));
 
                             // Restart the device to apply the new model
                             if let Some(logger) = &logger {
@@ -916,7 +1290,7 @@ impl DeviceWorkerImpl {
                             shared_state.set_paused(true);
 
                             // Reconnect to the device
-                            match device_type {
+                            let result = match device_type {
                                 DeviceType::Usb(index) => {
                                     match connect_to_device(index) {
                                         Ok(new_device) => {
@@ -930,6 +1304,7 @@ impl DeviceWorkerImpl {
                                             } else {
                                                 info!("Device reconnected successfully");
                                             }
+                                            Ok(())
                                         }
                                         Err(e) => {
                                             if let Some(logger) = &logger {
@@ -947,6 +1322,7 @@ impl DeviceWorkerImpl {
                                                     e
                                                 ));
                                             });
+                                            Err(e.into())
                                         }
                                     }
                                 }
@@ -963,6 +1339,7 @@ impl DeviceWorkerImpl {
                                             } else {
                                                 info!("Device reconnected successfully");
                                             }
+                                            Ok(())
                                         }
                                         Err(e) => {
                                             if let Some(logger) = &logger {
@@ -980,15 +1357,32 @@ impl DeviceWorkerImpl {
                                                     e
                                                 ));
                                             });
+                                            Err(e.into())
                                         }
                                     }
                                 }
-                            }
+                            };
 
                             // Resume device operations
                             shared_state.set_paused(false);
+                            result
                         }
-                    }
+                        DeviceCommand::ReleaseClaim { claim } => {
+                            // Dropping the last reference releases the resources
+                            drop(claim);
+                            Ok(())
+                        }
+                        DeviceCommand::Claimed { command, .. } => {
+                            Err(ThreadError::InvalidParameter(format!(
+                                "Nested claimed command {}",
+                                command.name()
+                            )))
+                        }
+                    };
+
+                    shared_state
+                        .sync_stats()
+                        .record_command(command_start.elapsed(), result.is_ok());
                 }
                 Err(TryRecvError::Empty) => {
                     // No command available, continue
@@ -1014,9 +1408,36 @@ impl DeviceWorkerImpl {
 
             // If paused, skip the sync
             if shared_state.status() == ThreadStatus::Paused {
+                paused_since.get_or_insert_with(Instant::now);
                 thread::sleep(Duration::from_millis(100));
                 continue;
             }
+            if let Some(since) = paused_since.take() {
+                let paused = since.elapsed();
+                servo_motions.delay(paused);
+                pwm_animations.delay(paused);
+            }
+
+            // Advance servo moves on the worker's clock
+            if !servo_motions.is_empty() {
+                Self::advance_servo_motions(
+                    &mut device,
+                    &shared_state,
+                    &servos,
+                    &mut servo_motions,
+                    &logger,
+                );
+            }
+
+            // Advance PWM animations on the worker's clock
+            if !pwm_animations.is_empty() {
+                Self::advance_pwm_animations(
+                    &mut device,
+                    &shared_state,
+                    &mut pwm_animations,
+                    &logger,
+                );
+            }
 
             // Check if it's time to sync the device state
             if device_sync.should_sync() {
@@ -1030,16 +1451,363 @@ impl DeviceWorkerImpl {
                 }
             }
 
+            // Save persisted values if they changed
+            if let Some(persister) = &mut persister {
+                let generation = shared_state.generation();
+                if let Err(e) = persister.maybe_save(&shared_state.snapshot(), generation) {
+                    if let Some(logger) = &logger {
+                        logger.error(&format!("Failed to save device state: {}", e));
+                    } else {
+                        error!("Failed to save device state: {}", e);
+                    }
+                }
+            }
+
             // Sleep a bit to avoid busy-waiting
             thread::sleep(Duration::from_millis(10));
         }
 
+        // Save the final state, even if the interval has not elapsed
+        if let Some(persister) = &mut persister {
+            if let Err(e) = persister.save_now(&shared_state.snapshot()) {
+                if let Some(logger) = &logger {
+                    logger.error(&format!("Failed to save device state: {}", e));
+                } else {
+                    error!("Failed to save device state: {}", e);
+                }
+            }
+        }
+
         if let Some(logger) = &logger {
             logger.info(&format!("Device thread {} terminated", thread_id));
         } else {
             info!("Device thread {} terminated", thread_id);
         }
     }
+
+    /// Re-apply saved outputs, PWM duty cycles, servo positions and custom values
+    fn restore_outputs(
+        device: &mut PoKeysDevice,
+        shared_state: &SharedDeviceState,
+        saved: &PersistedState,
+        logger: &Option<Arc<ThreadLogger>>,
+    ) {
+        if let Some(logger) = logger {
+            logger.info(&format!("Restoring saved state of device {}", saved.serial));
+        } else {
+            info!("Restoring saved state of device {}", saved.serial);
+        }
+
+        let mut failures = Vec::new();
+        // Leased outputs are left to their owners
+        let resources = shared_state.resources();
+        let channels = PwmChannelMap::for_model(device.model.as_ref());
+        let leased = |command: &DeviceCommand| resources.check_command(command, &channels).is_err();
+
+        for (&pin, &value) in &saved.digital_outputs {
+            if leased(&DeviceCommand::SetDigitalOutput { pin, value }) {
+                continue;
+            }
+            match device.set_digital_output(pin, value) {
+                Ok(_) => shared_state.set_digital_output(pin, value),
+                Err(e) => failures.push(format!("digital output {pin}: {e}")),
+            }
+        }
+
+        // Duties are sent with the period and enable mask they were saved
+        // with; channels that were disabled keep their current duty. The
+        // configuration covers every channel, so none may be leased.
+        let pwm_config = PwmConfig::new(PwmPeriod::Ticks(saved.pwm_period));
+        if saved.pwm_period > 0 && !leased(&DeviceCommand::ConfigurePwm { config: pwm_config }) {
+            device.pwm.pwm_period = saved.pwm_period;
+            device.pwm.enabled_channels = saved.pwm_enabled_channels;
+            for (&channel, &duty) in &saved.pwm_duties {
+                if device.pwm.is_channel_enabled(channel) {
+                    device.pwm.pwm_values[channel] = duty;
+                }
+            }
+            match device.set_pwm_configuration() {
+                Ok(()) => shared_state.update(|state| state.pwm.clone_from(&device.pwm)),
+                Err(e) => failures.push(format!("PWM configuration: {e}")),
+            }
+        }
+
+        // Servo duty values were restored with the PWM channels
+        for (&pin, &angle) in &saved.servo_positions {
+            shared_state.set_servo_position(pin, angle);
+        }
+
+        for (key, value) in &saved.custom_values {
+            shared_state.set_custom_json(key, value.clone());
+        }
+
+        if !failures.is_empty() {
+            let message = format!("Failed to restore {}", failures.join(", "));
+            if let Some(logger) = logger {
+                logger.error(&message);
+            } else {
+                error!("{}", message);
+            }
+            shared_state.set_error(Some(message));
+        }
+    }
+}
+
+impl DeviceWorkerImpl {
+    /// Cancel the move of a servo, reporting where it stopped
+    fn cancel_servo_motion(
+        servo_motions: &mut ServoMotions,
+        pin: u8,
+        shared_state: &SharedDeviceState,
+    ) {
+        if let Some(angle) = servo_motions.cancel(pin, Instant::now()) {
+            shared_state.apply_change(StateChangeType::ServoMotionComplete {
+                pin,
+                angle: angle.into(),
+                cancelled: true,
+            });
+        }
+    }
+
+    /// Command the interpolated angle of every moving servo
+    fn advance_servo_motions(
+        device: &mut PoKeysDevice,
+        shared_state: &SharedDeviceState,
+        servos: &ServoRegistry,
+        servo_motions: &mut ServoMotions,
+        logger: &Option<Arc<ThreadLogger>>,
+    ) {
+        for step in servo_motions.step(Instant::now()) {
+            let result = servos
+                .angle_duty(step.pin, step.angle)
+                .and_then(|duty| set_servo_duty(device, step.pin, duty));
+
+            match result {
+                Ok(()) => {
+                    if shared_state.get_servo_position(step.pin) != Some(step.angle) {
+                        shared_state.set_servo_position(step.pin, step.angle);
+                    }
+                    if step.complete {
+                        shared_state.apply_change(StateChangeType::ServoMotionComplete {
+                            pin: step.pin,
+                            angle: step.angle.into(),
+                            cancelled: false,
+                        });
+                    }
+                }
+                Err(e) => {
+                    let message = format!("Failed to move servo on pin {}: {}", step.pin, e);
+                    if let Some(logger) = logger {
+                        logger.error(&message);
+                    } else {
+                        error!("{}", message);
+                    }
+                    shared_state.set_error(Some(message));
+                    Self::cancel_servo_motion(servo_motions, step.pin, shared_state);
+                }
+            }
+        }
+    }
+}
+
+impl DeviceWorkerImpl {
+    /// Cancel the animation of a PWM channel, reporting the duty it stopped at
+    fn cancel_pwm_animation(
+        pwm_animations: &mut PwmAnimations,
+        channel: usize,
+        shared_state: &SharedDeviceState,
+    ) {
+        if let Some(duty) = pwm_animations.cancel(channel, Instant::now()) {
+            shared_state.apply_change(StateChangeType::PwmAnimationComplete {
+                channel,
+                duty,
+                cancelled: true,
+            });
+        }
+    }
+
+    /// Set the current duty of every animated PWM channel
+    fn advance_pwm_animations(
+        device: &mut PoKeysDevice,
+        shared_state: &SharedDeviceState,
+        pwm_animations: &mut PwmAnimations,
+        logger: &Option<Arc<ThreadLogger>>,
+    ) {
+        for step in pwm_animations.step(Instant::now()) {
+            let unchanged = shared_state.get_pwm_duty_cycle(step.channel) == Some(step.duty);
+            let result = if unchanged {
+                Ok(())
+            } else {
+                set_pwm_duty(device, step.channel, step.duty)
+            };
+
+            match result {
+                Ok(()) => {
+                    if !unchanged {
+                        shared_state.set_pwm_duty_cycle(step.channel, step.duty);
+                    }
+                    if step.complete {
+                        shared_state.apply_change(StateChangeType::PwmAnimationComplete {
+                            channel: step.channel,
+                            duty: step.duty,
+                            cancelled: false,
+                        });
+                    }
+                }
+                Err(e) => {
+                    let message = format!("Failed to animate PWM channel {}: {}", step.channel, e);
+                    if let Some(logger) = logger {
+                        logger.error(&message);
+                    } else {
+                        error!("{}", message);
+                    }
+                    shared_state.set_error(Some(message));
+                    Self::cancel_pwm_animation(pwm_animations, step.channel, shared_state);
+                }
+            }
+        }
+    }
+}
+
+impl DeviceWorkerImpl {
+    /// Write then read on the I2C bus, skipping empty parts
+    fn i2c_transfer(
+        device: &mut PoKeysDevice,
+        address: u8,
+        write_data: &[u8],
+        read_length: u8,
+    ) -> Result<Vec<u8>> {
+        if !write_data.is_empty() {
+            Self::i2c_write(device, address, write_data)?;
+        }
+        if read_length == 0 {
+            return Ok(Vec::new());
+        }
+        Self::i2c_read(device, address, read_length)
+    }
+
+    /// Write to an I2C device, reporting a missing acknowledge as `I2cNack`
+    fn i2c_write(device: &mut PoKeysDevice, address: u8, data: &[u8]) -> Result<()> {
+        let status = device.i2c_write(address, data)?;
+        Self::i2c_status(address, status, "write")
+    }
+
+    /// Read from an I2C device, reporting a missing acknowledge as `I2cNack`
+    fn i2c_read(device: &mut PoKeysDevice, address: u8, length: u8) -> Result<Vec<u8>> {
+        let (status, data) = device.i2c_read(address, length)?;
+        Self::i2c_status(address, status, "read")?;
+        Ok(data)
+    }
+
+    /// Turn the status of an I2C operation into a result
+    fn i2c_status(address: u8, status: I2cStatus, operation: &str) -> Result<()> {
+        match status {
+            I2cStatus::Ok | I2cStatus::Complete => Ok(()),
+            I2cStatus::DeviceNotFound => Err(ThreadError::I2cNack { address }),
+            status => Err(ThreadError::DeviceError(PoKeysError::Protocol(format!(
+                "I2C {} at address 0x{:02X} returned {:?}",
+                operation, address, status
+            )))),
+        }
+    }
+
+    /// Send uSPIBridge commands in order, stopping at the first failure
+    ///
+    /// Commands with an answer are read back after the bridge had time to
+    /// prepare it. Returns the answer to the last command.
+    fn uspibridge_execute(
+        device: &mut PoKeysDevice,
+        address: u8,
+        commands: &[BridgeCommand],
+    ) -> Result<Vec<u8>> {
+        let mut response = Vec::new();
+        for command in commands {
+            command.validate()?;
+            let status = device.uspibridge_write_command(
+                address,
+                command.command,
+                command.device_id,
+                &command.data,
+            )?;
+            if !matches!(status, I2cStatus::Ok | I2cStatus::Complete) {
+                return Err(ThreadError::DeviceError(
+                    PoKeysError::USPIBridgeCommandFailed(format!(
+                        "{:?} for device {} returned {:?}",
+                        command.command, command.device_id, status
+                    )),
+                ));
+            }
+            response.clear();
+            if command.response_length > 0 {
+                thread::sleep(USPIBRIDGE_RESPONSE_DELAY);
+                response = Self::i2c_read(device, address, command.response_length)?;
+            }
+        }
+        Ok(response)
+    }
+
+    /// Probe the addresses of a scan, logging the devices found and bus problems
+    fn i2c_scan(
+        device: &mut PoKeysDevice,
+        config: &I2cScanConfig,
+        logger: &Option<Arc<ThreadLogger>>,
+    ) -> I2cScanReport {
+        if let Some(logger) = logger {
+            logger.debug("Scanning I2C bus");
+        } else {
+            debug!("Scanning I2C bus");
+        }
+
+        let report = config.run(|address| match config.probe {
+            I2cProbe::ReadOne => device.i2c_read(address, 1).map(|(status, _)| status),
+            I2cProbe::WriteZero => device.i2c_write(address, &[0]),
+        });
+        for address in report.found() {
+            if let Some(logger) = logger {
+                logger.info(&format!("Found I2C device at address 0x{:02X}", address));
+            } else {
+                info!("Found I2C device at address 0x{:02X}", address);
+            }
+        }
+        let health = report.health();
+        if matches!(
+            health,
+            I2cBusHealth::Intermittent | I2cBusHealth::SdaStuckLow | I2cBusHealth::Unresponsive
+        ) {
+            if let Some(logger) = logger {
+                logger.warn(&format!("I2C bus: {}", health));
+            } else {
+                warn!("I2C bus: {}", health);
+            }
+        }
+        report
+    }
+}
+
+/// Get the outcome of a command answered on a response channel, for the statistics
+fn command_outcome<T>(result: &Result<T>) -> Result<()> {
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(ThreadError::OperationFailed(e.to_string())),
+    }
+}
+
+/// Get the PWM channel map of the device model
+fn pwm_channel_map(device: &PoKeysDevice) -> PwmChannelMap {
+    PwmChannelMap::for_model(device.model.as_ref())
+}
+
+/// Set the duty of a hardware PWM channel
+fn set_pwm_duty(device: &mut PoKeysDevice, channel: usize, duty: u32) -> Result<()> {
+    pwm_channel_map(device).check_channel(channel)?;
+    device.pwm.set_duty_cycle(channel, duty)?;
+    Ok(device.update_pwm_duty_values()?)
+}
+
+/// Set the duty of the PWM channel driving a servo pin
+fn set_servo_duty(device: &mut PoKeysDevice, pin: u8, duty: u32) -> Result<()> {
+    let channel = pwm_channel_map(device).check_pin(pin)?;
+    set_pwm_duty(device, channel, duty)
 }
 
 impl DeviceWorker for DeviceWorkerImpl {
@@ -1062,6 +1830,8 @@ impl DeviceWorker for DeviceWorkerImpl {
         };
         let shared_state = self.shared_state.clone();
         let refresh_interval = self.refresh_interval;
+        let adaptive_refresh = self.adaptive_refresh;
+        let persistence = self.persistence.clone();
         let logger = self.logger.clone();
 
         // Start the thread
@@ -1072,6 +1842,8 @@ impl DeviceWorker for DeviceWorkerImpl {
                 command_rx,
                 shared_state,
                 refresh_interval,
+                adaptive_refresh,
+                persistence,
                 logger,
             );
         });
@@ -0,0 +1,146 @@
+//! Tests for triggered captures
+
+use pokeys_lib::io::PinData;
+use pokeys_thread::{
+    Capture, CaptureConfig, CaptureSet, DeviceState, Edge, HistoryChannel, TriggerCondition,
+};
+use std::time::Duration;
+
+fn device_state() -> DeviceState {
+    let mut state = DeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+    state.pins = vec![PinData::new(); 55];
+    state
+}
+
+#[test]
+fn test_analog_threshold_capture() {
+    let captures = CaptureSet::new();
+    let handle = captures
+        .arm(
+            CaptureConfig::new(TriggerCondition::AnalogThreshold {
+                pin: 1,
+                threshold: 50,
+                edge: Edge::Rising,
+            })
+            .channel(HistoryChannel::AnalogInput(1))
+            .channel(HistoryChannel::DigitalInput(2))
+            .samples(3, 2),
+        )
+        .unwrap();
+
+    let mut state = device_state();
+    for value in [0, 10, 20, 30, 40, 60, 70, 80, 90] {
+        state.pins[0].analog_value = value;
+        captures.process(3, &state);
+    }
+
+    let capture = handle.try_get().expect("capture completed");
+    assert!(captures.is_empty());
+    assert_eq!(capture.thread_id, 3);
+    assert_eq!(capture.trigger_index, 3);
+    assert_eq!(
+        capture.channel_values(HistoryChannel::AnalogInput(1)),
+        Some(vec![
+            Some(20),
+            Some(30),
+            Some(40),
+            Some(60),
+            Some(70),
+            Some(80)
+        ])
+    );
+    assert_eq!(capture.frames[capture.trigger_index].offset_ms, 0);
+    assert!(capture
+        .frames
+        .iter()
+        .all(|frame| frame.values[1] == Some(0)));
+}
+
+#[test]
+fn test_digital_edge_and_error_triggers() {
+    let captures = CaptureSet::new();
+    let falling = captures
+        .arm(
+            CaptureConfig::new(TriggerCondition::DigitalEdge {
+                pin: 5,
+                edge: Edge::Falling,
+            })
+            .channel(HistoryChannel::DigitalInput(5))
+            .samples(10, 0),
+        )
+        .unwrap();
+    let error = captures
+        .arm(
+            CaptureConfig::new(TriggerCondition::Error)
+                .channel(HistoryChannel::DigitalInput(5))
+                .samples(0, 1),
+        )
+        .unwrap();
+
+    let mut state = device_state();
+    captures.process(1, &state);
+    state.pins[4].digital_value_get = 1;
+    captures.process(1, &state);
+    // A rising edge does not fire the falling trigger
+    assert!(falling.try_get().is_none());
+
+    state.pins[4].digital_value_get = 0;
+    captures.process(1, &state);
+    let capture = falling.try_get().unwrap();
+    assert_eq!(capture.trigger_index, 2);
+    assert_eq!(
+        capture.channel_values(HistoryChannel::DigitalInput(5)),
+        Some(vec![Some(0), Some(1), Some(0)])
+    );
+
+    state.error_message = Some("read failed".to_string());
+    captures.process(1, &state);
+    captures.process(1, &state);
+    let capture = error.wait(Duration::from_millis(100)).unwrap();
+    assert_eq!(capture.trigger_index, 0);
+    assert_eq!(capture.frames.len(), 2);
+}
+
+#[test]
+fn test_dropped_handle_disarms_capture() {
+    let captures = CaptureSet::new();
+    let handle = captures
+        .arm(CaptureConfig::new(TriggerCondition::Error).channel(HistoryChannel::Encoder(0)))
+        .unwrap();
+    assert_eq!(captures.len(), 1);
+
+    drop(handle);
+    captures.process(1, &device_state());
+    assert!(captures.is_empty());
+
+    assert!(captures
+        .arm(CaptureConfig::new(TriggerCondition::Error))
+        .is_err());
+}
+
+#[test]
+fn test_capture_save_and_load() {
+    let captures = CaptureSet::new();
+    let handle = captures
+        .arm(
+            CaptureConfig::new(TriggerCondition::Error)
+                .channel(HistoryChannel::PwmDuty(0))
+                .samples(1, 1),
+        )
+        .unwrap();
+
+    let mut state = device_state();
+    captures.process(1, &state);
+    state.error_message = Some("boom".to_string());
+    captures.process(1, &state);
+    captures.process(1, &state);
+    let capture = handle.try_get().unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("capture.json");
+    capture.save(&path).unwrap();
+    assert_eq!(Capture::load(&path).unwrap(), capture);
+}
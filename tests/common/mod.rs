@@ -0,0 +1,25 @@
+//! Helpers shared by the integration tests
+
+#![allow(dead_code)]
+
+use pokeys_lib::io::PinData;
+use pokeys_thread::SharedDeviceState;
+
+/// Number of pins of the simulated device
+pub const PIN_COUNT: usize = 55;
+
+/// Create the shared state of a simulated device with all pins unconfigured
+pub fn shared_state() -> SharedDeviceState {
+    shared_state_for(0, PIN_COUNT)
+}
+
+/// Create the shared state of a simulated device with a serial number and pin count
+pub fn shared_state_for(serial: u32, pins: usize) -> SharedDeviceState {
+    let device_data = pokeys_lib::DeviceData {
+        serial_number: serial,
+        ..Default::default()
+    };
+    let shared_state = SharedDeviceState::new(pokeys_lib::DeviceInfo::default(), device_data);
+    shared_state.update(|state| state.pins = vec![PinData::new(); pins]);
+    shared_state
+}
@@ -0,0 +1,93 @@
+//! Tests for digital counter state and frequency measurement
+
+use pokeys_lib::io::{PinData, PinFunction};
+use pokeys_thread::{
+    DeviceState, HistoryChannel, SharedDeviceState, StateChangeType, ValueHistory,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod common;
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+fn counter_state() -> SharedDeviceState {
+    let shared_state = common::shared_state();
+    shared_state.update(|state| state.pins[4].pin_function = PinFunction::DigitalCounter as u8);
+    shared_state
+}
+
+#[test]
+fn test_counter_value_only_for_counter_pins() {
+    let shared_state = counter_state();
+    shared_state.update(|state| state.pins[4].digital_counter_value = 1234);
+
+    assert_eq!(shared_state.get_digital_counter(5), Some(1234));
+    // Pins not configured as counters and invalid pins have no counter value
+    assert_eq!(shared_state.get_digital_counter(6), None);
+    assert_eq!(shared_state.get_digital_counter(0), None);
+    assert_eq!(shared_state.get_digital_counter(56), None);
+
+    let snapshot = shared_state.snapshot();
+    assert_eq!(
+        HistoryChannel::DigitalCounter(5).value_in(&snapshot),
+        Some(1234)
+    );
+    assert_eq!(HistoryChannel::DigitalCounter(5).to_string(), "CNT5");
+}
+
+#[test]
+fn test_counter_change_events() {
+    let mut old = DeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+    old.pins = vec![PinData::new(); 55];
+    let mut new = old.clone();
+    new.pins[4].digital_counter_value = 17;
+
+    let changes = old.diff(&new);
+    assert_eq!(
+        changes,
+        vec![StateChangeType::CounterValue { pin: 5, value: 17 }]
+    );
+
+    // Applying the change reproduces the new value
+    old.apply_change(&changes[0]);
+    assert_eq!(old.pins[4].digital_counter_value, 17);
+}
+
+#[test]
+fn test_counter_frequency_handles_resets() {
+    let history = ValueHistory::new();
+    let channel = HistoryChannel::DigitalCounter(5);
+    history.enable(channel, 100);
+    let start = now_ms() - 2000;
+
+    // 100 counts per second, reset to zero halfway through
+    history.record(channel, start, 1000);
+    history.record(channel, start + 500, 1050);
+    history.record(channel, start + 1000, 1100);
+    history.record(channel, start + 1500, 50);
+    history.record(channel, start + 2000, 100);
+
+    let frequency = history
+        .counter_frequency(channel, Duration::from_secs(10))
+        .unwrap();
+    assert!((frequency - 100.0).abs() < 1e-9);
+
+    // A plain rate of change sees the reset as a large negative rate
+    assert!(
+        history
+            .rate_of_change(channel, Duration::from_secs(10))
+            .unwrap()
+            < 0.0
+    );
+
+    // Not enough samples in a short window
+    assert_eq!(history.counter_frequency(channel, Duration::ZERO), None);
+}
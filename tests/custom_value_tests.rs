@@ -0,0 +1,135 @@
+//! Tests for typed custom values
+
+use pokeys_thread::{DeviceState, StateChangeType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+mod common;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Recipe {
+    name: String,
+    setpoints: Vec<f64>,
+    enabled: bool,
+}
+
+#[test]
+fn test_typed_round_trip() {
+    let shared_state = common::shared_state();
+    let recipe = Recipe {
+        name: "warm-up".to_string(),
+        setpoints: vec![20.5, 35.0],
+        enabled: true,
+    };
+
+    shared_state.set_custom("recipe", &recipe).unwrap();
+    shared_state.set_custom("count", &42u32).unwrap();
+    shared_state.set_custom("ratio", &0.25f64).unwrap();
+    let mut limits = HashMap::new();
+    limits.insert("max".to_string(), 100i32);
+    shared_state.set_custom("limits", &limits).unwrap();
+
+    assert_eq!(
+        shared_state.get_custom::<Recipe>("recipe").unwrap(),
+        Some(recipe)
+    );
+    assert_eq!(shared_state.get_custom::<u32>("count").unwrap(), Some(42));
+    assert_eq!(shared_state.get_custom::<f64>("ratio").unwrap(), Some(0.25));
+    assert_eq!(
+        shared_state
+            .get_custom::<HashMap<String, i32>>("limits")
+            .unwrap(),
+        Some(limits)
+    );
+    assert_eq!(shared_state.get_custom::<u32>("missing").unwrap(), None);
+
+    // Reading with the wrong type is an error, not a silent None
+    assert!(shared_state.get_custom::<bool>("count").is_err());
+}
+
+#[test]
+fn test_string_values_stay_compatible() {
+    let shared_state = common::shared_state();
+
+    shared_state.set_custom_value("mode", "auto");
+    assert_eq!(
+        shared_state.get_custom_value("mode"),
+        Some("auto".to_string())
+    );
+    assert_eq!(
+        shared_state.get_custom::<String>("mode").unwrap(),
+        Some("auto".to_string())
+    );
+
+    // Typed values read as strings come back as JSON text
+    shared_state.set_custom("count", &42).unwrap();
+    shared_state.set_custom("tags", &["a", "b"]).unwrap();
+    assert_eq!(
+        shared_state.get_custom_value("count"),
+        Some("42".to_string())
+    );
+    assert_eq!(
+        shared_state.get_custom_value("tags"),
+        Some(r#"["a","b"]"#.to_string())
+    );
+
+    // States serialized with string-only custom values still load
+    let mut state = DeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+    state
+        .custom_values
+        .insert("mode".to_string(), "manual".into());
+    let json = serde_json::to_string(&state).unwrap();
+    assert!(json.contains(r#""custom_values":{"mode":"manual"}"#));
+    let loaded: DeviceState = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.get_custom_value("mode"), Some("manual".to_string()));
+
+    // Strings stay in the string map, other values move to the typed map
+    let state = shared_state.snapshot();
+    assert_eq!(state.custom_values.get("mode"), Some(&"auto".to_string()));
+    assert_eq!(
+        state.typed_custom_values.get("count"),
+        Some(&serde_json::json!(42))
+    );
+    shared_state.set_custom_value("count", "many");
+    let state = shared_state.snapshot();
+    assert_eq!(state.custom_values.get("count"), Some(&"many".to_string()));
+    assert!(!state.typed_custom_values.contains_key("count"));
+}
+
+#[test]
+fn test_typed_notifications() {
+    let shared_state = common::shared_state();
+    let rx = shared_state.setup_notifications();
+
+    shared_state.set_custom_value("mode", "auto");
+    shared_state.set_custom("count", &7).unwrap();
+    // Setting the same value again is not a change
+    shared_state.set_custom_value("mode", "auto");
+    shared_state.set_custom("count", &7).unwrap();
+    assert!(shared_state.remove_custom_value("count"));
+    assert!(!shared_state.remove_custom_value("count"));
+
+    let changes: Vec<StateChangeType> = rx
+        .try_iter()
+        .filter(|change| *change != StateChangeType::FullUpdate)
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            StateChangeType::CustomValue {
+                key: "mode".to_string(),
+                value: "auto".to_string()
+            },
+            StateChangeType::TypedCustomValue {
+                key: "count".to_string(),
+                value: serde_json::json!(7)
+            },
+            StateChangeType::CustomValueRemoved {
+                key: "count".to_string()
+            },
+        ]
+    );
+}
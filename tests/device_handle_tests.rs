@@ -0,0 +1,194 @@
+//! Tests for typed device and pin handles
+
+use pokeys_lib::encoders::EncoderData;
+use pokeys_lib::PinFunction;
+use pokeys_thread::{
+    DeviceCommand, DeviceHandle, LeaseRequest, OutputLease, PwmDuty, SharedDeviceState, ThreadError,
+};
+use std::sync::Arc;
+
+mod common;
+
+fn device_state() -> Arc<SharedDeviceState> {
+    let shared_state = common::shared_state();
+    shared_state.update(|state| {
+        state.pins[4].pin_function = PinFunction::DigitalOutput as u8;
+        state.pins[5].pin_function = PinFunction::DigitalInput as u8;
+        state.pins[5].digital_value_get = 1;
+        state.pins[40].pin_function = PinFunction::AnalogInput as u8;
+        state.pins[40].analog_value = 2048;
+        state.encoders = vec![EncoderData::default(); 2];
+        state.encoders[1].encoder_value = -42;
+        state.pwm.pwm_period = 1000;
+        state.pwm.enabled_channels = 0b01;
+    });
+    Arc::new(shared_state)
+}
+
+#[test]
+fn test_pin_handles_check_pin_function() {
+    let (tx, _rx) = crossbeam_channel::unbounded();
+    let device = DeviceHandle::new(1, device_state(), tx);
+
+    assert_eq!(device.digital_output(5).unwrap().pin(), 5);
+    assert_eq!(device.digital_input(6).unwrap().get(), Some(true));
+    assert_eq!(device.analog_input(41).unwrap().get(), Some(2048));
+    assert_eq!(device.encoder(1).unwrap().value(), Some(-42));
+    assert_eq!(device.pwm(0).unwrap().pin(), 22);
+
+    match device.digital_output(6) {
+        Err(ThreadError::ValidationError {
+            message,
+            recovery_suggestion,
+            ..
+        }) => {
+            assert_eq!(message, "Pin 6 is not configured as a digital output");
+            assert!(recovery_suggestion.unwrap().contains("DigitalOutput"));
+        }
+        Err(other) => panic!("expected a validation error, got {other:?}"),
+        Ok(_) => panic!("pin 6 is an input"),
+    }
+    assert!(device.digital_input(5).is_err());
+    assert!(device.analog_input(6).is_err());
+    assert!(device.analog_output(41).is_err());
+    assert!(device.digital_output(0).is_err());
+    assert!(device.digital_output(56).is_err());
+    assert!(device.encoder(2).is_err());
+    // Channel 1 is not enabled and channel 6 does not exist
+    assert!(device.pwm(1).is_err());
+    assert!(device.pwm(6).is_err());
+    assert!(device.i2c(0x80).is_err());
+}
+
+#[test]
+fn test_handles_send_commands() {
+    let shared_state = device_state();
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let device = DeviceHandle::new(1, shared_state.clone(), tx);
+
+    let output = device.digital_output(5).unwrap();
+    output.set(true).unwrap();
+    shared_state.set_digital_output(5, true);
+    assert_eq!(output.get(), Some(true));
+    output.toggle().unwrap();
+    device
+        .pwm(0)
+        .unwrap()
+        .set_duty(PwmDuty::Percent(25.0))
+        .unwrap();
+
+    let commands: Vec<String> = rx.try_iter().map(|c| format!("{c:?}")).collect();
+    let expected: Vec<String> = [
+        DeviceCommand::SetDigitalOutput {
+            pin: 5,
+            value: true,
+        },
+        DeviceCommand::SetDigitalOutput {
+            pin: 5,
+            value: false,
+        },
+        DeviceCommand::SetPwmDuty {
+            channel: 0,
+            duty: 250,
+        },
+    ]
+    .iter()
+    .map(|c| format!("{c:?}"))
+    .collect();
+    assert_eq!(commands, expected);
+}
+
+#[test]
+fn test_handles_respect_leases() {
+    let shared_state = device_state();
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let device = DeviceHandle::new(1, shared_state.clone(), tx.clone());
+    let output = device.digital_output(5).unwrap();
+    let pwm = device.pwm(0).unwrap();
+
+    let lease = OutputLease::acquire(
+        "heater",
+        &LeaseRequest::new().digital_output(5).pwm(0),
+        shared_state,
+        tx,
+    )
+    .unwrap();
+    assert!(matches!(
+        output.set(true),
+        Err(ThreadError::ResourceConflict { .. })
+    ));
+    assert!(pwm.set_duty(PwmDuty::Ticks(10)).is_err());
+    assert!(rx.try_recv().is_err());
+
+    // The lease ends once the device thread has run its release
+    drop(lease);
+    assert!(output.set(true).is_err());
+    assert!(matches!(
+        rx.try_recv(),
+        Ok(DeviceCommand::ReleaseClaim { .. })
+    ));
+    output.set(true).unwrap();
+    assert!(rx.try_recv().is_ok());
+}
+
+#[test]
+fn test_i2c_transfers_return_results() {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let device = DeviceHandle::new(1, device_state(), tx);
+
+    // Answer transfers like a device with a register file at 0x48
+    let worker = std::thread::spawn(move || {
+        let mut transfers = Vec::new();
+        for command in rx {
+            if let DeviceCommand::I2cTransfer {
+                address,
+                write_data,
+                read_length,
+                response,
+            } = command
+            {
+                let result = if address == 0x48 {
+                    let start = write_data.first().copied().unwrap_or(0);
+                    Ok((start..start + read_length).collect())
+                } else {
+                    Err(ThreadError::I2cNack { address })
+                };
+                transfers.push((address, write_data, read_length));
+                response.send(result).unwrap();
+            }
+        }
+        transfers
+    });
+
+    let sensor = device.i2c(0x48).unwrap();
+    assert_eq!(
+        sensor.write_read(&[0x10], 3).unwrap(),
+        vec![0x10, 0x11, 0x12]
+    );
+    assert_eq!(sensor.read(2).unwrap(), vec![0x00, 0x01]);
+    sensor.write(&[0x01, 0x60]).unwrap();
+    assert!(device.i2c(0x49).unwrap().read(1).is_err());
+    drop(sensor);
+    drop(device);
+
+    assert_eq!(
+        worker.join().unwrap(),
+        vec![
+            (0x48, vec![0x10], 3),
+            (0x48, vec![], 2),
+            (0x48, vec![0x01, 0x60], 0),
+            (0x49, vec![], 1),
+        ]
+    );
+}
+
+#[test]
+fn test_i2c_transfer_timeout() {
+    let (tx, _rx) = crossbeam_channel::unbounded();
+    let device = DeviceHandle::new(1, device_state(), tx);
+    let sensor = device
+        .i2c(0x48)
+        .unwrap()
+        .with_timeout(std::time::Duration::from_millis(10));
+    assert!(matches!(sensor.read(1), Err(ThreadError::Timeout)));
+}
@@ -0,0 +1,154 @@
+//! Tests for encoder position, velocity and direction tracking
+
+use pokeys_lib::encoders::EncoderData;
+use pokeys_thread::{
+    EncoderDirection, EncoderMotion, EncoderScaling, FloatValue, SharedDeviceState, StateChangeType,
+};
+use std::time::Duration;
+
+mod common;
+
+fn encoder_state() -> SharedDeviceState {
+    let shared_state = common::shared_state();
+    shared_state.update(|state| state.encoders = vec![EncoderData::new(); 4]);
+    shared_state
+}
+
+#[test]
+fn test_position_zero_and_preset() {
+    let shared_state = encoder_state();
+    shared_state.update(|state| state.encoders[1].encoder_value = 1000);
+
+    // Operations on encoders without a scaling are rejected
+    assert!(!shared_state.zero_encoder(1));
+    assert_eq!(shared_state.get_encoder_motion(1), None);
+
+    shared_state
+        .set_encoder_scaling(1, EncoderScaling::new(100.0))
+        .unwrap();
+    assert_eq!(shared_state.get_encoder_motion(1).unwrap().position, 10.0);
+
+    assert!(shared_state.zero_encoder(1));
+    let motion = shared_state.get_encoder_motion(1).unwrap();
+    assert_eq!(motion.offset, 1000);
+    assert_eq!(motion.position, 0.0);
+
+    assert!(shared_state.preset_encoder(1, 2.5));
+    assert_eq!(shared_state.get_encoder_motion(1).unwrap().offset, 750);
+
+    assert!(shared_state.set_encoder_offset(1, -500));
+    assert_eq!(shared_state.get_encoder_motion(1).unwrap().position, 15.0);
+
+    assert!(shared_state.clear_encoder_scaling(1));
+    assert_eq!(shared_state.get_encoder_motion(1), None);
+}
+
+#[test]
+fn test_position_unwraps_across_overflow() {
+    let shared_state = encoder_state();
+    shared_state.update(|state| state.encoders[2].encoder_value = i32::MAX - 10);
+    shared_state
+        .set_encoder_scaling(2, EncoderScaling::new(1.0))
+        .unwrap();
+    assert!(shared_state.zero_encoder(2));
+
+    // The raw count wraps from i32::MAX to i32::MIN, the position keeps counting
+    shared_state.apply_change(StateChangeType::EncoderValue {
+        index: 2,
+        value: i32::MIN + 10,
+    });
+    let motion = shared_state.get_encoder_motion(2).unwrap();
+    assert_eq!(motion.position, 21.0);
+    assert_eq!(motion.count, i64::from(i32::MAX) + 11);
+
+    shared_state.apply_change(StateChangeType::EncoderValue {
+        index: 2,
+        value: i32::MAX - 20,
+    });
+    assert_eq!(shared_state.get_encoder_motion(2).unwrap().position, -10.0);
+}
+
+#[test]
+fn test_velocity_change_threshold() {
+    // One count per 100 ms window at 2 counts per unit is 5 units per second
+    let scaling = EncoderScaling::new(2.0);
+    assert_eq!(scaling.velocity_change_threshold(), 5.0);
+    assert_eq!(
+        scaling.velocity_threshold(0.5).velocity_change_threshold(),
+        0.5
+    );
+    assert!(scaling.velocity_threshold(-1.0).validate().is_err());
+    assert!(scaling.velocity_threshold(f64::NAN).validate().is_err());
+}
+
+#[test]
+fn test_scaling_validation_and_rpm() {
+    assert!(EncoderScaling::new(0.0).validate().is_err());
+    assert!(EncoderScaling::new(f64::NAN).validate().is_err());
+    assert!(EncoderScaling::new(1.0)
+        .counts_per_revolution(0.0)
+        .validate()
+        .is_err());
+    assert!(EncoderScaling::new(1.0)
+        .velocity_window(Duration::ZERO)
+        .validate()
+        .is_err());
+
+    // 4 counts per degree, 1440 counts per revolution
+    let scaling = EncoderScaling::new(4.0).counts_per_revolution(1440.0);
+    assert!(scaling.validate().is_ok());
+    // 360 degrees per second is one revolution per second
+    assert_eq!(scaling.rpm(360.0), Some(60.0));
+
+    let mut motion = EncoderMotion::new(scaling);
+    motion.set_velocity(-720.0);
+    assert_eq!(motion.rpm, Some(-120.0));
+    assert_eq!(motion.direction, EncoderDirection::Reverse);
+    motion.set_velocity(0.0);
+    assert_eq!(motion.direction, EncoderDirection::Stopped);
+}
+
+#[test]
+fn test_velocity_changes_are_reported_and_replayed() {
+    let shared_state = encoder_state();
+    shared_state
+        .set_encoder_scaling(0, EncoderScaling::new(2.0))
+        .unwrap();
+    let before = shared_state.snapshot();
+
+    let rx = shared_state.setup_notifications();
+    shared_state.apply_change(StateChangeType::EncoderValue {
+        index: 0,
+        value: 200,
+    });
+    shared_state.apply_change(StateChangeType::EncoderVelocity {
+        index: 0,
+        velocity: FloatValue(50.0),
+    });
+
+    let motion = shared_state.get_encoder_motion(0).unwrap();
+    assert_eq!(motion.position, 100.0);
+    assert_eq!(motion.velocity, 50.0);
+    assert_eq!(motion.direction, EncoderDirection::Forward);
+
+    let changes: Vec<StateChangeType> = rx.try_iter().collect();
+    assert!(changes.contains(&StateChangeType::EncoderVelocity {
+        index: 0,
+        velocity: FloatValue(50.0)
+    }));
+
+    // Velocity changes are part of the state diff
+    assert_eq!(
+        before.diff(&shared_state.snapshot()),
+        vec![
+            StateChangeType::EncoderValue {
+                index: 0,
+                value: 200
+            },
+            StateChangeType::EncoderVelocity {
+                index: 0,
+                velocity: FloatValue(50.0)
+            },
+        ]
+    );
+}
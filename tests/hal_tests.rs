@@ -0,0 +1,152 @@
+//! Tests for the embedded-hal implementations of device handles
+#![cfg(feature = "embedded-hal")]
+
+use embedded_hal::digital::{InputPin, OutputPin, StatefulOutputPin};
+use embedded_hal::i2c::{Error as _, ErrorKind, I2c, NoAcknowledgeSource};
+use embedded_hal::pwm::SetDutyCycle;
+use pokeys_lib::PinFunction;
+use pokeys_thread::{DeviceCommand, DeviceHandle, SharedDeviceState, ThreadError};
+use std::sync::Arc;
+
+mod common;
+
+fn hal_state(pwm_period: u32) -> Arc<SharedDeviceState> {
+    let shared_state = common::shared_state();
+    shared_state.update(|state| {
+        state.pins[4].pin_function = PinFunction::DigitalOutput as u8;
+        state.pins[4].digital_value_set = 1;
+        state.pins[5].pin_function = PinFunction::DigitalInput as u8;
+        state.pwm.pwm_period = pwm_period;
+        state.pwm.enabled_channels = 0b01;
+    });
+    Arc::new(shared_state)
+}
+
+#[test]
+fn test_digital_pins() {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let device = DeviceHandle::new(1, hal_state(1000), tx);
+
+    let mut output = device.digital_output(5).unwrap();
+    assert!(output.is_set_high().unwrap());
+    output.set_low().unwrap();
+    StatefulOutputPin::toggle(&mut output).unwrap();
+    let mut input = device.digital_input(6).unwrap();
+    assert!(input.is_low().unwrap());
+
+    let commands: Vec<String> = rx.try_iter().map(|c| format!("{c:?}")).collect();
+    assert_eq!(
+        commands,
+        vec![
+            format!(
+                "{:?}",
+                DeviceCommand::SetDigitalOutput {
+                    pin: 5,
+                    value: false
+                }
+            ),
+            format!(
+                "{:?}",
+                DeviceCommand::SetDigitalOutput {
+                    pin: 5,
+                    value: true
+                }
+            ),
+        ]
+    );
+    // The handle reports what it commanded before the worker applies it
+    assert!(output.is_set_high().unwrap());
+}
+
+#[test]
+fn test_pwm_duty_cycle_scaling() {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let mut pwm = DeviceHandle::new(1, hal_state(1000), tx.clone())
+        .pwm(0)
+        .unwrap();
+    assert_eq!(pwm.max_duty_cycle(), 1000);
+    pwm.set_duty_cycle(250).unwrap();
+
+    // Without a period, fully on must not turn the output off
+    let mut pwm = DeviceHandle::new(1, hal_state(0), tx.clone())
+        .pwm(0)
+        .unwrap();
+    assert!(matches!(
+        pwm.set_duty_cycle_fully_on(),
+        Err(ThreadError::ValidationError { .. })
+    ));
+
+    // Periods beyond u16 are scaled; half of u16::MAX is just below half
+    let mut pwm = DeviceHandle::new(1, hal_state(100_000), tx).pwm(0).unwrap();
+    assert_eq!(pwm.max_duty_cycle(), u16::MAX);
+    pwm.set_duty_cycle_percent(50).unwrap();
+    pwm.set_duty_cycle_fully_on().unwrap();
+
+    let duties: Vec<u32> = rx
+        .try_iter()
+        .map(|command| match command {
+            DeviceCommand::SetPwmDuty { duty, .. } => duty,
+            other => panic!("unexpected command {other:?}"),
+        })
+        .collect();
+    assert_eq!(duties, vec![250, 49_999, 100_000]);
+}
+
+#[test]
+fn test_i2c_transactions() {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let device = DeviceHandle::new(1, hal_state(1000), tx);
+
+    let worker = std::thread::spawn(move || {
+        let mut transfers = Vec::new();
+        for command in rx {
+            if let DeviceCommand::I2cTransfer {
+                address,
+                write_data,
+                read_length,
+                response,
+            } = command
+            {
+                let result = if address == 0x76 {
+                    Ok(vec![0xA5; usize::from(read_length)])
+                } else {
+                    Err(ThreadError::I2cNack { address })
+                };
+                transfers.push((address, write_data, read_length));
+                response.send(result).unwrap();
+            }
+        }
+        transfers
+    });
+
+    let mut bus = device.i2c_bus();
+    let mut buffer = [0u8; 2];
+    bus.write_read(0x76, &[0xD0], &mut buffer).unwrap();
+    assert_eq!(buffer, [0xA5, 0xA5]);
+    bus.write(0x76, &[0xF4, 0x27]).unwrap();
+    bus.transaction(
+        0x76,
+        &mut [
+            embedded_hal::i2c::Operation::Write(&[0xF7]),
+            embedded_hal::i2c::Operation::Write(&[0xF8]),
+        ],
+    )
+    .unwrap();
+    let error = bus.read(0x10, &mut buffer).unwrap_err();
+    assert_eq!(
+        error.kind(),
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)
+    );
+    drop(bus);
+    drop(device);
+
+    assert_eq!(
+        worker.join().unwrap(),
+        vec![
+            (0x76, vec![0xD0], 2),
+            (0x76, vec![0xF4, 0x27], 0),
+            (0x76, vec![0xF7, 0xF8], 0),
+            (0x10, vec![], 2),
+        ]
+    );
+}
@@ -0,0 +1,103 @@
+//! Tests for the pin and encoder value history
+
+use pokeys_lib::io::PinData;
+use pokeys_thread::{HistoryChannel, SharedDeviceState, ValueHistory};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+#[test]
+fn test_ring_buffer_keeps_configured_depth() {
+    let history = ValueHistory::new();
+    let channel = HistoryChannel::AnalogInput(1);
+    let now = now_ms();
+
+    // Samples for channels that are not enabled are ignored
+    history.record(channel, now, 1);
+    assert!(history.last_samples(channel, 10).is_empty());
+
+    history.enable(channel, 3);
+    for value in 0..5 {
+        history.record(channel, now + value as u64, value);
+    }
+
+    let values: Vec<i64> = history
+        .last_samples(channel, 10)
+        .iter()
+        .map(|sample| sample.value)
+        .collect();
+    assert_eq!(values, vec![2, 3, 4]);
+
+    // Shrinking the depth keeps the most recent samples
+    history.enable(channel, 2);
+    assert_eq!(history.last_samples(channel, 10).len(), 2);
+    assert_eq!(history.last_samples(channel, 1)[0].value, 4);
+
+    history.disable(channel);
+    assert!(!history.is_enabled(channel));
+}
+
+#[test]
+fn test_window_queries() {
+    let history = ValueHistory::new();
+    let channel = HistoryChannel::Encoder(0);
+    history.enable(channel, 100);
+
+    let now = now_ms();
+    // An old sample outside the query windows
+    history.record(channel, now - 60_000, 1000);
+    history.record(channel, now - 2000, 10);
+    history.record(channel, now - 1000, 30);
+    history.record(channel, now, 50);
+
+    assert_eq!(history.history(channel, Duration::from_secs(5)).len(), 3);
+
+    let stats = history
+        .window_stats(channel, Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(stats.samples, 3);
+    assert_eq!(stats.min, 10);
+    assert_eq!(stats.max, 50);
+    assert_eq!(stats.mean, 30.0);
+
+    let rate = history
+        .rate_of_change(channel, Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(rate, 20.0);
+
+    assert!(history
+        .window_stats(HistoryChannel::Encoder(1), Duration::from_secs(5))
+        .is_none());
+}
+
+#[test]
+fn test_record_state_samples_enabled_channels() {
+    let shared_state = SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+    shared_state.update(|state| {
+        state.pins = vec![PinData::new(); 55];
+        state.pins[2].digital_value_get = 1;
+        state.pins[40].analog_value = 2048;
+    });
+
+    let history = shared_state.history();
+    history.enable(HistoryChannel::DigitalInput(3), 10);
+    history.enable(HistoryChannel::AnalogInput(41), 10);
+    let before = now_ms();
+    history.record_state(&shared_state.snapshot());
+
+    let digital = history.last_samples(HistoryChannel::DigitalInput(3), 10);
+    assert_eq!(digital.len(), 1);
+    assert_eq!(digital[0].value, 1);
+    assert!(digital[0].timestamp >= before);
+
+    let analog = history.last_samples(HistoryChannel::AnalogInput(41), 10);
+    assert_eq!(analog[0].value, 2048);
+}
@@ -0,0 +1,164 @@
+//! Tests for I2C register access and retries
+
+use pokeys_thread::{DeviceCommand, DeviceHandle, I2cDevice, SharedDeviceState, ThreadError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+type Transfer = (Vec<u8>, u8);
+
+/// Answer transfers like a device with 256 registers at 0x40, failing the
+/// first `failures` transfers with a bus error
+fn register_device(
+    registers: [u8; 256],
+    failures: usize,
+) -> (DeviceHandle, JoinHandle<Vec<Transfer>>) {
+    let shared_state = Arc::new(SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    ));
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let worker = std::thread::spawn(move || {
+        let mut registers = registers;
+        let mut transfers = Vec::new();
+        for command in rx {
+            let DeviceCommand::I2cTransfer {
+                address,
+                write_data,
+                read_length,
+                response,
+            } = command
+            else {
+                continue;
+            };
+            transfers.push((write_data.clone(), read_length));
+            if address != 0x40 {
+                let _ = response.send(Err(ThreadError::I2cNack { address }));
+                continue;
+            }
+            if transfers.len() <= failures {
+                let _ = response.send(Err(ThreadError::DeviceError(
+                    pokeys_lib::PoKeysError::Protocol("I2C read failed".to_string()),
+                )));
+                continue;
+            }
+            let register = usize::from(write_data.first().copied().unwrap_or(0));
+            for (offset, &value) in write_data.iter().skip(1).enumerate() {
+                registers[register + offset] = value;
+            }
+            let data = registers[register..register + usize::from(read_length)].to_vec();
+            let _ = response.send(Ok(data));
+        }
+        transfers
+    });
+    (DeviceHandle::new(1, shared_state, tx), worker)
+}
+
+#[test]
+fn test_register_access() {
+    let mut registers = [0u8; 256];
+    registers[0x0F] = 0x33;
+    registers[0x20] = 0x12;
+    registers[0x21] = 0x34;
+    registers[0x22] = 0xFF;
+    registers[0x23] = 0x38;
+    let (device, worker) = register_device(registers, 0);
+    let sensor = device.i2c(0x40).unwrap();
+
+    assert_eq!(sensor.read_u8(0x0F).unwrap(), 0x33);
+    assert_eq!(sensor.read_register(0x20, 2).unwrap(), vec![0x12, 0x34]);
+    assert_eq!(sensor.read_u16_be(0x20).unwrap(), 0x1234);
+    assert_eq!(sensor.read_u16_le(0x20).unwrap(), 0x3412);
+    assert_eq!(sensor.read_i16_be(0x22).unwrap(), -200);
+
+    sensor.write_u16_be(0x30, 0xABCD).unwrap();
+    sensor.write_u16_le(0x32, 0xABCD).unwrap();
+    sensor.write_register(0x34, &[1, 2, 3]).unwrap();
+    assert_eq!(
+        sensor.read_register(0x30, 7).unwrap(),
+        vec![0xAB, 0xCD, 0xCD, 0xAB, 1, 2, 3]
+    );
+    assert!(device.i2c(0x80).is_err());
+
+    drop(sensor);
+    drop(device);
+    let transfers = worker.join().unwrap();
+    assert_eq!(transfers[5], (vec![0x30, 0xAB, 0xCD], 0));
+}
+
+#[test]
+fn test_update_bits() {
+    let mut registers = [0u8; 256];
+    registers[0x01] = 0b1010_0001;
+    let (device, worker) = register_device(registers, 0);
+    let sensor = device.i2c(0x40).unwrap();
+
+    assert_eq!(
+        sensor.update_bits(0x01, 0b0110_0000, 0b0100_0000).unwrap(),
+        0b1100_0001
+    );
+    assert_eq!(sensor.read_u8(0x01).unwrap(), 0b1100_0001);
+    // Unchanged values are not written
+    assert_eq!(
+        sensor.update_bits(0x01, 0b0000_0001, 0xFF).unwrap(),
+        0b1100_0001
+    );
+
+    drop(sensor);
+    drop(device);
+    assert_eq!(
+        worker.join().unwrap(),
+        vec![
+            (vec![0x01], 1),
+            (vec![0x01, 0b1100_0001], 0),
+            (vec![0x01], 1),
+            (vec![0x01], 1),
+        ]
+    );
+}
+
+#[test]
+fn test_retries() {
+    let (device, worker) = register_device([7; 256], 2);
+    let sensor: I2cDevice = device
+        .i2c(0x40)
+        .unwrap()
+        .with_retries(1, Duration::from_millis(1));
+    assert!(matches!(
+        sensor.read_u8(0x00),
+        Err(ThreadError::DeviceError(_))
+    ));
+    let sensor = sensor.with_retries(2, Duration::ZERO);
+    assert_eq!(sensor.retries(), 2);
+    assert_eq!(sensor.read_u8(0x00).unwrap(), 7);
+
+    // A missing device does not acknowledge, which retrying does not change
+    let absent = device.i2c(0x41).unwrap().with_retries(2, Duration::ZERO);
+    assert!(matches!(
+        absent.read_u8(0x00),
+        Err(ThreadError::I2cNack { address: 0x41 })
+    ));
+
+    // Each attempt waits for its own timeout
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let silent = DeviceHandle::new(1, device.shared_state().clone(), tx)
+        .i2c(0x40)
+        .unwrap()
+        .with_timeout(Duration::from_millis(20))
+        .with_retries(2, Duration::ZERO);
+    let start = std::time::Instant::now();
+    assert!(matches!(silent.read(1), Err(ThreadError::Timeout)));
+    assert!(start.elapsed() >= Duration::from_millis(60));
+    assert_eq!(rx.try_iter().count(), 3);
+    // A timed out write may have reached the device, so it is not repeated
+    assert!(matches!(
+        silent.write_u8(0x10, 1),
+        Err(ThreadError::Timeout)
+    ));
+    assert_eq!(rx.try_iter().count(), 1);
+
+    drop(sensor);
+    drop(absent);
+    drop(device);
+    assert_eq!(worker.join().unwrap().len(), 4);
+}
@@ -0,0 +1,134 @@
+//! Tests for I2C bus scans and diagnostics
+
+use pokeys_lib::{I2cStatus, PoKeysError};
+use pokeys_thread::{
+    DeviceCommand, DeviceHandle, I2cBusHealth, I2cProbe, I2cScanConfig, SharedDeviceState,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn ack() -> pokeys_lib::Result<I2cStatus> {
+    Ok(I2cStatus::Ok)
+}
+
+fn nack() -> pokeys_lib::Result<I2cStatus> {
+    Ok(I2cStatus::DeviceNotFound)
+}
+
+fn timeout() -> pokeys_lib::Result<I2cStatus> {
+    Ok(I2cStatus::Timeout)
+}
+
+#[test]
+fn test_scan_results_and_timing() {
+    let config = I2cScanConfig::new().range(0x20, 0x50).attempts(4);
+    let mut probes_of_0x50 = 0;
+    let report = config.run(|address| match address {
+        0x20 => {
+            std::thread::sleep(Duration::from_millis(2));
+            ack()
+        }
+        0x48 => ack(),
+        0x50 => {
+            probes_of_0x50 += 1;
+            if probes_of_0x50 % 2 == 0 {
+                ack()
+            } else {
+                nack()
+            }
+        }
+        0x30 => timeout(),
+        _ => nack(),
+    });
+
+    assert_eq!(report.addresses.len(), 0x31);
+    assert_eq!(report.found(), vec![0x20, 0x48, 0x50]);
+    assert_eq!(report.intermittent(), vec![0x50]);
+    assert_eq!(report.timeout_count(), 4);
+    assert_eq!(report.nack_count(), (0x31 - 4) * 4 + 2);
+    assert_eq!(report.error_count(), 0);
+    assert_eq!(report.health(), I2cBusHealth::Intermittent);
+
+    let slow = report.result(0x20).unwrap();
+    assert_eq!(slow.acks, 4);
+    assert!(slow.max_time >= Duration::from_millis(2));
+    assert!(slow.average_time() >= Duration::from_millis(2));
+    assert!(report.duration >= slow.total_time);
+    let timed_out = report.result(0x30).unwrap();
+    assert_eq!(timed_out.last_error.as_deref(), Some("I2C timeout"));
+    assert!(report.result(0x10).is_none());
+}
+
+#[test]
+fn test_bus_health() {
+    let config = I2cScanConfig::new();
+    assert_eq!(config.run(|_| ack()).health(), I2cBusHealth::SdaStuckLow);
+    assert_eq!(config.run(|_| nack()).health(), I2cBusHealth::NoDevices);
+    assert_eq!(
+        config.run(|_| timeout()).health(),
+        I2cBusHealth::Unresponsive
+    );
+    let report = config.run(|_| Err(PoKeysError::Transfer("USB disconnected".to_string())));
+    assert_eq!(report.error_count(), 0x70);
+    assert_eq!(report.health(), I2cBusHealth::Unresponsive);
+    // Failures other than a missing device are errors, whatever their message
+    let report = config.run(|_| Err(PoKeysError::Protocol("I2C device not found".to_string())));
+    assert_eq!(report.nack_count(), 0);
+    assert_eq!(report.error_count(), 0x70);
+    let report = config.run(|_| Ok(I2cStatus::Error));
+    assert_eq!(report.error_count(), 0x70);
+    assert_eq!(
+        report.result(0x08).unwrap().last_error.as_deref(),
+        Some("I2C status Error")
+    );
+    assert_eq!(
+        config
+            .run(|address| if address == 0x3C { ack() } else { nack() })
+            .health(),
+        I2cBusHealth::Ok
+    );
+    // A single device answering is not a stuck bus
+    let single = I2cScanConfig::new().range(0x3C, 0x3C);
+    assert_eq!(single.run(|_| ack()).health(), I2cBusHealth::Ok);
+    assert!(I2cBusHealth::SdaStuckLow.to_string().contains("SDA"));
+}
+
+#[test]
+fn test_scan_through_device_thread() {
+    let shared_state = Arc::new(SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    ));
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let device = DeviceHandle::new(1, shared_state, tx);
+
+    let worker = std::thread::spawn(move || {
+        for command in rx {
+            if let DeviceCommand::I2cScanBus { config, response } = command {
+                assert_eq!(config.probe, I2cProbe::WriteZero);
+                let report = config.run(|address| if address == 0x68 { ack() } else { nack() });
+                response.send(Ok(report)).unwrap();
+            }
+        }
+    });
+
+    let bus = device.i2c_bus();
+    let report = bus
+        .scan(
+            &I2cScanConfig::new()
+                .range(0x60, 0x6F)
+                .probe(I2cProbe::WriteZero),
+        )
+        .unwrap();
+    assert_eq!(report.found(), vec![0x68]);
+    assert_eq!(report.config.first, 0x60);
+
+    // Invalid configurations are rejected before reaching the thread
+    assert!(bus.scan(&I2cScanConfig::new().range(0x50, 0x40)).is_err());
+    assert!(bus.scan(&I2cScanConfig::new().range(0x70, 0x80)).is_err());
+    assert!(bus.scan(&I2cScanConfig::new().attempts(0)).is_err());
+
+    drop(bus);
+    drop(device);
+    worker.join().unwrap();
+}
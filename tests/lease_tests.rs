@@ -0,0 +1,259 @@
+//! Tests for exclusive output leases
+
+use pokeys_thread::{
+    DeviceCommand, DeviceHandle, LeaseRequest, OutputLease, PwmChannelMap, PwmConfig, PwmDuty,
+    PwmPeriod, Resource, SharedDeviceState, ThreadError,
+};
+use std::sync::Arc;
+
+mod common;
+
+fn lease_state() -> Arc<SharedDeviceState> {
+    let shared_state = common::shared_state();
+    shared_state.update(|state| state.pwm.pwm_period = 1000);
+    Arc::new(shared_state)
+}
+
+#[test]
+fn test_handles_write_and_revert_on_drop() {
+    let shared_state = lease_state();
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let lease = OutputLease::acquire(
+        "heater",
+        &LeaseRequest::new()
+            .digital_output_with_default(5, false)
+            .pwm_with_default(0, PwmDuty::Ticks(0)),
+        shared_state.clone(),
+        tx,
+    )
+    .unwrap();
+
+    let output = lease.digital_output(5).unwrap();
+    assert_eq!(output.owner(), "heater");
+    output.set(true).unwrap();
+    let pwm = lease.pwm(0).unwrap();
+    assert_eq!(pwm.pin(), 22);
+    pwm.set_duty(PwmDuty::Percent(40.0)).unwrap();
+    assert!(pwm.set_duty(PwmDuty::Percent(140.0)).is_err());
+    assert!(lease.digital_output(6).is_none());
+
+    drop(lease);
+    let mut commands: Vec<DeviceCommand> = rx.try_iter().collect();
+    // The claim is released by the device thread, after the reverts
+    let release = commands.pop();
+    assert!(matches!(release, Some(DeviceCommand::ReleaseClaim { .. })));
+    assert_eq!(shared_state.resources().claims().len(), 3);
+    // Lease handles send their commands with the claim of the lease
+    let mut claims = Vec::new();
+    let commands: Vec<String> = commands
+        .into_iter()
+        .map(|command| match command {
+            DeviceCommand::Claimed { claim, command } => {
+                claims.push(claim);
+                format!("{command:?}")
+            }
+            other => panic!("unclaimed command {other:?}"),
+        })
+        .collect();
+    assert!(claims.windows(2).all(|pair| pair[0] == pair[1]));
+    assert_eq!(
+        commands,
+        vec![
+            format!(
+                "{:?}",
+                DeviceCommand::SetDigitalOutput {
+                    pin: 5,
+                    value: true
+                }
+            ),
+            format!(
+                "{:?}",
+                DeviceCommand::SetPwmDuty {
+                    channel: 0,
+                    duty: 400
+                }
+            ),
+            format!(
+                "{:?}",
+                DeviceCommand::SetDigitalOutput {
+                    pin: 5,
+                    value: false
+                }
+            ),
+            format!(
+                "{:?}",
+                DeviceCommand::SetPwmDuty {
+                    channel: 0,
+                    duty: 0
+                }
+            ),
+        ]
+    );
+    drop(release);
+    assert!(shared_state.resources().claims().is_empty());
+}
+
+#[test]
+fn test_leased_outputs_reject_other_writes() {
+    let shared_state = lease_state();
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let (outputs, mut pwm) = OutputLease::acquire(
+        "pump",
+        &LeaseRequest::new().digital_output(3).pwm(1),
+        shared_state.clone(),
+        tx.clone(),
+    )
+    .unwrap()
+    .into_handles();
+
+    let resources = shared_state.resources();
+    assert!(matches!(
+        resources.check_writable(Resource::Pin(3), "set_digital_output"),
+        Err(ThreadError::ResourceConflict { .. })
+    ));
+    assert!(resources
+        .check_writable(Resource::PwmChannel(1), "set_pwm_duty")
+        .is_err());
+    assert!(resources
+        .check_writable(Resource::Pin(21), "set_servo_angle")
+        .is_err());
+    assert!(resources
+        .check_writable(Resource::Pin(4), "set_digital_output")
+        .is_ok());
+
+    // Plain claims do not block writes, but do block a lease
+    let _claim = resources.claim("logger", &[Resource::Pin(4)]).unwrap();
+    assert!(resources
+        .check_writable(Resource::Pin(4), "set_digital_output")
+        .is_ok());
+    assert!(OutputLease::acquire(
+        "fan",
+        &LeaseRequest::new().digital_output(4),
+        shared_state.clone(),
+        tx,
+    )
+    .is_err());
+
+    // The lease lasts until its last handle is dropped and the device
+    // thread has run the commands sent before the release
+    drop(outputs);
+    assert!(resources.is_claimed(Resource::Pin(3)));
+    pwm.clear();
+    assert!(resources.is_claimed(Resource::Pin(3)));
+    drop(rx.try_iter().collect::<Vec<_>>());
+    assert!(!resources.is_claimed(Resource::Pin(3)));
+    assert!(!resources.is_claimed(Resource::PwmChannel(1)));
+}
+
+#[test]
+fn test_invalid_lease_requests() {
+    let shared_state = lease_state();
+    let (tx, rx) = crossbeam_channel::unbounded();
+
+    for request in [
+        LeaseRequest::new(),
+        LeaseRequest::new().pwm(6),
+        LeaseRequest::new().digital_output(7).digital_output(7),
+        // Channel 0 is pin 22
+        LeaseRequest::new().digital_output(22).pwm(0),
+        LeaseRequest::new().pwm_with_default(0, PwmDuty::Ticks(5000)),
+    ] {
+        assert!(
+            OutputLease::acquire("test", &request, shared_state.clone(), tx.clone()).is_err(),
+            "{request:?} should be rejected"
+        );
+    }
+    assert!(shared_state.resources().claims().is_empty());
+    // Failed leases write nothing
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn test_raw_commands_respect_leases() {
+    let shared_state = lease_state();
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let device = DeviceHandle::new(1, shared_state.clone(), tx.clone());
+    let lease = OutputLease::acquire(
+        "heater",
+        &LeaseRequest::new().digital_output(5).pwm(1),
+        shared_state.clone(),
+        tx,
+    )
+    .unwrap();
+    let resources = shared_state.resources();
+    let channels = PwmChannelMap::standard();
+
+    let write = DeviceCommand::SetDigitalOutput {
+        pin: 5,
+        value: true,
+    };
+    assert!(matches!(
+        device.send_command(write.clone()),
+        Err(ThreadError::ResourceConflict { .. })
+    ));
+    // Channel 1 is pin 21, whichever way it is addressed
+    for command in [
+        DeviceCommand::SetPwmDuty {
+            channel: 1,
+            duty: 10,
+        },
+        DeviceCommand::SetServoAngle {
+            pin: 21,
+            angle: 90.0,
+        },
+        DeviceCommand::ConfigurePwm {
+            config: PwmConfig::new(PwmPeriod::Ticks(2000)),
+        },
+    ] {
+        assert!(
+            resources.check_command(&command, &channels).is_err(),
+            "{command:?} should be rejected"
+        );
+    }
+    assert!(resources
+        .check_command(
+            &DeviceCommand::SetDigitalOutput {
+                pin: 6,
+                value: true
+            },
+            &channels
+        )
+        .is_ok());
+
+    // The lease handles write by wrapping their commands with its claim
+    lease.digital_output(5).unwrap().set(true).unwrap();
+    let sent = rx.try_recv().unwrap();
+    assert!(resources.check_command(&sent, &channels).is_ok());
+    let DeviceCommand::Claimed { claim, .. } = sent else {
+        panic!("unclaimed command {sent:?}");
+    };
+    assert_eq!(
+        shared_state.resources().owner(Resource::Pin(5)).as_deref(),
+        Some("heater")
+    );
+
+    // Another claim under the same owner name cannot write the lease
+    let other = resources
+        .claim_exclusive("heater", &[Resource::Pin(9)])
+        .unwrap();
+    assert_ne!(other.id(), claim);
+    let forged = DeviceCommand::Claimed {
+        claim: other.id(),
+        command: Box::new(write.clone()),
+    };
+    assert!(matches!(
+        resources.check_command(&forged, &channels),
+        Err(ThreadError::ResourceConflict { .. })
+    ));
+    assert!(device.send_command(forged).is_err());
+    // Nor can a claimed command that went through serialization
+    let json = serde_json::to_string(&DeviceCommand::Claimed {
+        claim,
+        command: Box::new(write.clone()),
+    })
+    .unwrap();
+    let replayed: DeviceCommand = serde_json::from_str(&json).unwrap();
+    assert!(resources.check_command(&replayed, &channels).is_err());
+    // Only the lease's own handles reached the worker
+    assert!(rx.try_recv().is_err());
+}
@@ -0,0 +1,128 @@
+//! Tests for device state persistence
+
+use pokeys_lib::io::PinFunction;
+use pokeys_thread::{
+    PersistMode, PersistedState, PersistenceConfig, SharedDeviceState, StatePersister,
+};
+use std::time::Duration;
+
+mod common;
+
+fn io_state(serial: u32) -> SharedDeviceState {
+    let shared_state = common::shared_state_for(serial, common::PIN_COUNT);
+    shared_state.update(|state| {
+        state.pins[0].pin_function = PinFunction::DigitalOutput as u8;
+        state.pins[1].pin_function = PinFunction::DigitalOutput as u8;
+        state.pins[2].pin_function = PinFunction::DigitalInput as u8;
+    });
+    shared_state
+}
+
+#[test]
+fn test_persisted_state_from_device_state() {
+    let shared_state = io_state(1001);
+    shared_state.set_digital_output(2, true);
+    shared_state.set_pwm_duty_cycle(3, 2048);
+    shared_state.set_custom_value("recipe", "A");
+    shared_state.update(|state| {
+        state.servo_positions.insert(22, 90.0);
+        state.pwm.pwm_period = 25000;
+        state.pwm.enabled_channels = 0b1000;
+        // Inputs are not persisted
+        state.pins[2].digital_value_get = 1;
+    });
+
+    let persisted = PersistedState::from_state(&shared_state.snapshot());
+    assert_eq!(persisted.serial, 1001);
+    assert_eq!(
+        persisted
+            .digital_outputs
+            .clone()
+            .into_iter()
+            .collect::<Vec<_>>(),
+        vec![(1, false), (2, true)]
+    );
+    assert_eq!(persisted.pwm_period, 25000);
+    assert_eq!(persisted.pwm_enabled_channels, 0b1000);
+    assert_eq!(persisted.pwm_duties[&3], 2048);
+    assert_eq!(persisted.custom_values["recipe"], "A");
+    assert_eq!(persisted.servo_positions[&22], 90.0);
+
+    // Files saved without a PWM configuration load with PWM unconfigured
+    let mut json = serde_json::to_value(&persisted).unwrap();
+    let fields = json.as_object_mut().unwrap();
+    fields.remove("pwm_period");
+    fields.remove("pwm_enabled_channels");
+    let loaded: PersistedState = serde_json::from_value(json).unwrap();
+    assert_eq!(loaded.pwm_period, 0);
+    assert_eq!(loaded.pwm_enabled_channels, 0);
+}
+
+#[test]
+fn test_save_on_change_and_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let shared_state = io_state(1002);
+    let config = PersistenceConfig::new(dir.path());
+    let mut persister = StatePersister::new(config.clone(), 1002);
+    assert_eq!(persister.path(), &config.path_for(1002));
+    assert_eq!(persister.load().unwrap(), None);
+
+    let save = |persister: &mut StatePersister| {
+        persister
+            .maybe_save(&shared_state.snapshot(), shared_state.generation())
+            .unwrap()
+    };
+
+    assert!(save(&mut persister));
+    // Same generation is skipped without comparing
+    assert!(!save(&mut persister));
+    // A new generation with the same persisted values is not written
+    shared_state.update(|state| state.pins[2].analog_value = 100);
+    assert!(!save(&mut persister));
+
+    shared_state.set_digital_output(1, true);
+    assert!(save(&mut persister));
+
+    let loaded = persister.load().unwrap().unwrap();
+    assert!(loaded.digital_outputs[&1]);
+}
+
+#[test]
+fn test_interval_mode_limits_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    let shared_state = io_state(1003);
+    let config =
+        PersistenceConfig::new(dir.path()).mode(PersistMode::Interval(Duration::from_secs(60)));
+    let mut persister = StatePersister::new(config, 1003);
+
+    assert!(persister
+        .maybe_save(&shared_state.snapshot(), shared_state.generation())
+        .unwrap());
+
+    shared_state.set_custom_value("count", "1");
+    assert!(!persister
+        .maybe_save(&shared_state.snapshot(), shared_state.generation())
+        .unwrap());
+
+    // Shutdown saves regardless of the interval
+    assert!(persister.save_now(&shared_state.snapshot()).unwrap());
+    assert_eq!(
+        persister.load().unwrap().unwrap().custom_values["count"],
+        "1"
+    );
+
+    assert!(PersistenceConfig::new(dir.path())
+        .mode(PersistMode::Interval(Duration::ZERO))
+        .validate()
+        .is_err());
+}
+
+#[test]
+fn test_invalid_state_file_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = PersistenceConfig::new(dir.path());
+    std::fs::write(config.path_for(1004), "not json").unwrap();
+
+    let persister = StatePersister::new(config, 1004);
+    assert!(persister.load().is_err());
+}
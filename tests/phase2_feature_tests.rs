@@ -121,9 +121,13 @@ fn test_uspibridge_commands() {
         .with_device_count(4)
         .with_default_brightness(8);
 
-    let configure_cmd = DeviceCommand::ConfigureUSPIBridge { config };
+    let configure_cmd = DeviceCommand::ConfigureUSPIBridge {
+        address: 0x43,
+        config,
+    };
     match configure_cmd {
-        DeviceCommand::ConfigureUSPIBridge { config } => {
+        DeviceCommand::ConfigureUSPIBridge { address, config } => {
+            assert_eq!(address, 0x43);
             assert_eq!(config.device_count, 4);
             assert_eq!(config.default_brightness, 8);
         }
@@ -131,10 +135,12 @@ fn test_uspibridge_commands() {
     }
 
     let command_cmd = DeviceCommand::USPIBridgeCommand {
+        address: DEFAULT_USPIBRIDGE_ADDRESS,
         command: vec![0x11, 0x01, 0x02, 0x03],
     };
     match command_cmd {
-        DeviceCommand::USPIBridgeCommand { command } => {
+        DeviceCommand::USPIBridgeCommand { address, command } => {
+            assert_eq!(address, DEFAULT_USPIBRIDGE_ADDRESS);
             assert_eq!(command, vec![0x11, 0x01, 0x02, 0x03]);
         }
         _ => panic!("Expected USPIBridgeCommand command"),
@@ -313,9 +319,11 @@ mod integration_tests {
             },
             DeviceCommand::I2cScan,
             DeviceCommand::ConfigureUSPIBridge {
+                address: DEFAULT_USPIBRIDGE_ADDRESS,
                 config: USPIBridgeConfig::new(),
             },
             DeviceCommand::USPIBridgeCommand {
+                address: DEFAULT_USPIBRIDGE_ADDRESS,
                 command: vec![0x11, 0x01],
             },
         ];
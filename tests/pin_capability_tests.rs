@@ -0,0 +1,116 @@
+//! Tests for pin capability lookup in device models
+
+use pokeys_lib::models::{DeviceModel, PinModel};
+use pokeys_lib::PinCapability;
+use pokeys_thread::capabilities::{
+    encoder_pins, parse_operation, pin_supports, pins_with_capability, suggest_pins,
+    ALL_CAPABILITIES,
+};
+use pokeys_thread::PwmChannelMap;
+use std::collections::HashMap;
+
+fn test_model() -> DeviceModel {
+    let pins: &[(u8, &[&str])] = &[
+        (1, &["DigitalInput", "DigitalOutput", "Encoder_1A"]),
+        (2, &["DigitalInput", "DigitalOutput", "Encoder_1B"]),
+        (5, &["DigitalInput", "DigitalCounter"]),
+        (13, &["DigitalInput", "MatrixKeyboard_Row1"]),
+        (15, &["DigitalInput", "Encoder_8A"]),
+        (16, &["DigitalInput"]),
+        (17, &["DigitalOutput", "PwmOutput"]),
+        (18, &["DigitalOutput", "PwmOutput", "LcdE"]),
+        (20, &["DigitalOutput", "PwmOutput"]),
+        (26, &["DigitalInput", "AnalogInput"]),
+        (40, &["AnalogOutput"]),
+    ];
+    let mut model = DeviceModel {
+        name: "TestDevice".to_string(),
+        pins: HashMap::new(),
+    };
+    for &(pin, capabilities) in pins {
+        model.pins.insert(
+            pin,
+            PinModel {
+                capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+                active: true,
+            },
+        );
+    }
+    model
+}
+
+#[test]
+fn test_capabilities_from_model() {
+    let mut model = test_model();
+
+    assert!(pin_supports(&model, 1, PinCapability::DigitalOutput));
+    assert!(!pin_supports(&model, 5, PinCapability::DigitalOutput));
+    assert!(pin_supports(&model, 26, PinCapability::AnalogInput));
+    assert!(pin_supports(&model, 26, PinCapability::MfAnalogInput));
+    assert!(pin_supports(&model, 40, PinCapability::AnalogOutput));
+    assert!(pin_supports(&model, 5, PinCapability::DigitalCounter));
+    assert!(pin_supports(&model, 1, PinCapability::FastEncoder1A));
+    assert!(pin_supports(&model, 2, PinCapability::FastEncoder1B));
+    assert!(!pin_supports(&model, 1, PinCapability::FastEncoder2A));
+    // Fast encoder inputs are on fixed pins that the model marks as encoder inputs
+    assert!(pin_supports(&model, 15, PinCapability::FastEncoder3A));
+    assert!(!pin_supports(&model, 16, PinCapability::FastEncoder3B));
+    assert!(!pin_supports(&model, 13, PinCapability::FastEncoder3A));
+    assert!(pin_supports(&model, 13, PinCapability::KeyboardMapping));
+    assert!(pin_supports(&model, 18, PinCapability::LcdE));
+    assert!(!pin_supports(&model, 18, PinCapability::LcdRs));
+    assert!(!pin_supports(&model, 99, PinCapability::DigitalInput));
+
+    // Encoder pins come from the Encoder_<n>A/B names
+    assert_eq!(encoder_pins(&model, 0), Some((1, 2)));
+    assert_eq!(encoder_pins(&model, 7), None);
+
+    // Inactive pins support nothing
+    model.pins.get_mut(&18).unwrap().active = false;
+    assert!(!pin_supports(&model, 18, PinCapability::PwmOutput));
+    assert_eq!(
+        pins_with_capability(&model, PinCapability::PwmOutput),
+        vec![17, 20]
+    );
+    assert_eq!(PwmChannelMap::from_model(&model).pins(), vec![20, 17]);
+
+    // Every capability can be asked about
+    for capability in ALL_CAPABILITIES {
+        let _ = pins_with_capability(&model, capability);
+    }
+}
+
+#[test]
+fn test_alternative_pin_suggestions() {
+    let model = test_model();
+
+    // Closest pins first
+    assert_eq!(
+        suggest_pins(&model, 19, PinCapability::PwmOutput, 5),
+        vec![18, 20, 17]
+    );
+    assert_eq!(
+        suggest_pins(&model, 3, PinCapability::DigitalOutput, 2),
+        vec![2, 1]
+    );
+    assert!(suggest_pins(&model, 1, PinCapability::LcdD7, 5).is_empty());
+}
+
+#[test]
+fn test_parse_operation() {
+    assert_eq!(
+        parse_operation("digital_output").unwrap(),
+        PinCapability::DigitalOutput
+    );
+    assert_eq!(parse_operation("servo").unwrap(), PinCapability::PwmOutput);
+    assert_eq!(
+        parse_operation("counter").unwrap(),
+        PinCapability::DigitalCounter
+    );
+    assert_eq!(
+        parse_operation("fastencoder3i").unwrap(),
+        PinCapability::FastEncoder3I
+    );
+    assert_eq!(parse_operation("LcdD4").unwrap(), PinCapability::LcdD4);
+    assert!(parse_operation("teleport").is_err());
+}
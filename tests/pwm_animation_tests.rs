@@ -0,0 +1,191 @@
+//! Tests for worker-side PWM animations
+
+use pokeys_thread::{
+    PwmAnimations, PwmDuty, PwmWaveform, RampCurve, SharedDeviceState, StateChangeType,
+};
+use std::time::{Duration, Instant};
+
+const PERIOD: u32 = 1000;
+
+#[test]
+fn test_ramps() {
+    let mut animations = PwmAnimations::new();
+    let start = Instant::now();
+    animations
+        .start(
+            0,
+            &PwmWaveform::ramp(
+                PwmDuty::Ticks(0),
+                PwmDuty::Percent(100.0),
+                Duration::from_secs(1),
+            ),
+            PERIOD,
+            start,
+        )
+        .unwrap();
+    animations
+        .start(
+            2,
+            &PwmWaveform::exponential_ramp(
+                PwmDuty::Ticks(0),
+                PwmDuty::Ticks(1000),
+                Duration::from_secs(1),
+            ),
+            PERIOD,
+            start,
+        )
+        .unwrap();
+    assert_eq!(animations.channels(), vec![0, 2]);
+
+    let steps = animations.step(start + Duration::from_millis(500));
+    assert_eq!(steps[0].duty, 500);
+    // The exponential ramp is still low halfway through
+    assert_eq!(steps[1].duty, 30);
+    assert!(steps.iter().all(|step| !step.complete));
+
+    let steps = animations.step(start + Duration::from_secs(2));
+    assert_eq!(steps[0].duty, 1000);
+    assert_eq!(steps[1].duty, 1000);
+    assert!(steps.iter().all(|step| step.complete));
+    assert!(animations.is_empty());
+
+    assert_eq!(RampCurve::Exponential.apply(0.0), 0.0);
+    assert_eq!(RampCurve::Exponential.apply(1.0), 1.0);
+    // Duties must fit in the PWM period
+    assert!(animations
+        .start(
+            0,
+            &PwmWaveform::ramp(
+                PwmDuty::Ticks(0),
+                PwmDuty::Ticks(2000),
+                Duration::from_secs(1)
+            ),
+            PERIOD,
+            start,
+        )
+        .is_err());
+}
+
+#[test]
+fn test_sine_and_steps() {
+    let mut animations = PwmAnimations::new();
+    let start = Instant::now();
+    let sine = PwmWaveform::Sine {
+        min: PwmDuty::Ticks(200),
+        max: PwmDuty::Ticks(800),
+        period: Duration::from_secs(2),
+        cycles: Some(1),
+    };
+    animations.start(1, &sine, PERIOD, start).unwrap();
+    assert_eq!(animations.duty(1, start), Some(200));
+    assert_eq!(
+        animations.duty(1, start + Duration::from_secs(1)),
+        Some(800)
+    );
+    assert_eq!(
+        animations.duty(1, start + Duration::from_millis(500)),
+        Some(500)
+    );
+    let steps = animations.step(start + Duration::from_secs(2));
+    assert!(steps[0].complete);
+    assert_eq!(steps[0].duty, 200);
+
+    let sequence = PwmWaveform::Steps {
+        steps: vec![
+            (PwmDuty::Ticks(100), Duration::from_millis(100)),
+            (PwmDuty::Ticks(900), Duration::from_millis(300)),
+        ],
+        repeat: true,
+    };
+    animations.start(3, &sequence, PERIOD, start).unwrap();
+    assert_eq!(
+        animations.duty(3, start + Duration::from_millis(50)),
+        Some(100)
+    );
+    assert_eq!(
+        animations.duty(3, start + Duration::from_millis(150)),
+        Some(900)
+    );
+    // Repeated sequences wrap around
+    assert_eq!(
+        animations.duty(3, start + Duration::from_millis(450)),
+        Some(100)
+    );
+    assert!(!animations.step(start + Duration::from_secs(10))[0].complete);
+
+    assert!(PwmWaveform::steps(Vec::new()).validate(PERIOD).is_err());
+    assert!(PwmWaveform::breathe(PwmDuty::Percent(50.0), Duration::ZERO)
+        .validate(PERIOD)
+        .is_err());
+    // Percentages need a configured period
+    assert!(
+        PwmWaveform::breathe(PwmDuty::Percent(50.0), Duration::from_secs(1))
+            .validate(0)
+            .is_err()
+    );
+}
+
+#[test]
+fn test_cancel_and_completion_event() {
+    let mut animations = PwmAnimations::new();
+    let start = Instant::now();
+    animations
+        .start(
+            4,
+            &PwmWaveform::ramp(
+                PwmDuty::Ticks(1000),
+                PwmDuty::Ticks(0),
+                Duration::from_secs(1),
+            ),
+            PERIOD,
+            start,
+        )
+        .unwrap();
+    assert!(animations.is_running(4));
+    assert_eq!(
+        animations.cancel(4, start + Duration::from_millis(250)),
+        Some(750)
+    );
+    assert!(!animations.is_running(4));
+    assert_eq!(animations.cancel(4, start), None);
+
+    // The completion event records the final duty in the state
+    let shared_state = SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+    let rx = shared_state.setup_notifications();
+    let event = StateChangeType::PwmAnimationComplete {
+        channel: 4,
+        duty: 750,
+        cancelled: true,
+    };
+    shared_state.apply_change(event.clone());
+    assert_eq!(shared_state.get_pwm_duty_cycle(4), Some(750));
+    assert!(rx.try_iter().any(|change| change == event));
+}
+
+#[test]
+fn test_delay_resumes_where_paused() {
+    let mut animations = PwmAnimations::new();
+    let start = Instant::now();
+    animations
+        .start(
+            0,
+            &PwmWaveform::ramp(
+                PwmDuty::Ticks(0),
+                PwmDuty::Ticks(1000),
+                Duration::from_secs(1),
+            ),
+            PERIOD,
+            start,
+        )
+        .unwrap();
+
+    animations.delay(Duration::from_secs(2));
+    assert_eq!(
+        animations.duty(0, start + Duration::from_millis(2500)),
+        Some(500)
+    );
+    assert!(animations.step(start + Duration::from_secs(3))[0].complete);
+}
@@ -0,0 +1,95 @@
+//! Tests for PWM channel mapping and unit conversion
+
+use pokeys_lib::models::{DeviceModel, PinModel};
+use pokeys_thread::{PwmChannelMap, PwmConfig, PwmDuty, PwmPeriod, SharedDeviceState, ThreadError};
+use std::collections::HashMap;
+
+fn model_with_pins(pins: &[(u8, &str)]) -> DeviceModel {
+    let mut model = DeviceModel {
+        name: "TestDevice".to_string(),
+        pins: HashMap::new(),
+    };
+    for &(pin, capability) in pins {
+        model.pins.insert(
+            pin,
+            PinModel {
+                capabilities: vec!["DigitalOutput".to_string(), capability.to_string()],
+                active: true,
+            },
+        );
+    }
+    model
+}
+
+#[test]
+fn test_channel_map_from_model() {
+    let standard = PwmChannelMap::standard();
+    assert_eq!(standard.pins(), vec![22, 21, 20, 19, 18, 17]);
+    assert_eq!(standard.channel(17), Some(5));
+    assert_eq!(PwmChannelMap::for_model(None), standard);
+
+    // Only the hardware channels of PwmOutput pins exist, keeping their numbers
+    let model = model_with_pins(&[(20, "PwmOutput"), (22, "PwmOutput"), (5, "PwmOutput")]);
+    let map = PwmChannelMap::for_model(Some(&model));
+    assert_eq!(map.pins(), vec![22, 20]);
+    assert_eq!(map.channels(), vec![0, 2]);
+    assert_eq!(map.check_channel(2).unwrap(), 20);
+    assert!(matches!(
+        map.check_channel(1),
+        Err(ThreadError::ValidationError { .. })
+    ));
+    assert_eq!(map.check_pin(20).unwrap(), 2);
+    assert!(matches!(
+        map.check_pin(5),
+        Err(ThreadError::PinCapabilityError { pin: 5, .. })
+    ));
+
+    // The device state uses the map of its model
+    let shared_state = SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+    assert_eq!(shared_state.pwm_channel_map(), standard);
+    shared_state.set_model(Some(model));
+    assert_eq!(shared_state.pwm_channel_map(), map);
+}
+
+#[test]
+fn test_period_and_config_validation() {
+    assert_eq!(PwmPeriod::Frequency(50.0).ticks().unwrap(), 500_000);
+    assert_eq!(PwmPeriod::Frequency(25_000.0).ticks().unwrap(), 1000);
+    assert_eq!(PwmPeriod::Ticks(2500).ticks().unwrap(), 2500);
+    assert!(PwmPeriod::Ticks(0).ticks().is_err());
+    assert!(PwmPeriod::Frequency(0.0).ticks().is_err());
+    assert!(PwmPeriod::Frequency(f64::NAN).ticks().is_err());
+    assert!(PwmPeriod::Frequency(100e6).ticks().is_err());
+
+    let map = PwmChannelMap::standard();
+    let config = PwmConfig::frequency(1000.0).channel(0).channels([2, 2, 5]);
+    assert_eq!(config.enabled_channels, vec![0, 2, 5]);
+    assert_eq!(config.validate(&map).unwrap(), 25_000);
+    assert!(PwmConfig::frequency(1000.0)
+        .channel(6)
+        .validate(&map)
+        .is_err());
+}
+
+#[test]
+fn test_duty_conversion() {
+    let period = 500_000; // 50 Hz
+    assert_eq!(PwmDuty::Ticks(1234).ticks(period).unwrap(), 1234);
+    assert_eq!(PwmDuty::Percent(50.0).ticks(period).unwrap(), 250_000);
+    assert_eq!(PwmDuty::Percent(100.0).ticks(period).unwrap(), period);
+    assert_eq!(PwmDuty::Micros(1500.0).ticks(period).unwrap(), 37_500);
+
+    assert!(PwmDuty::Percent(150.0).ticks(period).is_err());
+    assert!(PwmDuty::Percent(-1.0).ticks(period).is_err());
+    assert!(PwmDuty::Micros(-5.0).ticks(period).is_err());
+    // 25 ms does not fit in a 20 ms period
+    assert!(PwmDuty::Micros(25_000.0).ticks(period).is_err());
+    assert!(PwmDuty::Ticks(period + 1).ticks(period).is_err());
+
+    // Percentages need a configured period; raw ticks do not
+    assert!(PwmDuty::Percent(50.0).ticks(0).is_err());
+    assert_eq!(PwmDuty::Ticks(4095).ticks(0).unwrap(), 4095);
+}
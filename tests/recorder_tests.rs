@@ -0,0 +1,116 @@
+//! Tests for the data recorder
+
+use pokeys_thread::{
+    DataRecorder, HistoryChannel, RecordFormat, RecordRow, RecorderConfig, SampleMode,
+    SharedDeviceState,
+};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+mod common;
+
+fn recorded_state(serial: u32) -> Arc<SharedDeviceState> {
+    Arc::new(common::shared_state_for(serial, common::PIN_COUNT))
+}
+
+fn read_files(directory: &Path) -> Vec<String> {
+    let mut paths: Vec<_> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| fs::read_to_string(path).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_csv_fixed_rate_recording() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = recorded_state(1234);
+    state.set_digital_output(5, true);
+
+    let config = RecorderConfig::new(dir.path())
+        .mode(SampleMode::FixedRate(Duration::from_millis(5)))
+        .channel(HistoryChannel::DigitalOutput(5))
+        .channel(HistoryChannel::Encoder(0));
+    let recorder = DataRecorder::start(config, vec![(7, state)]).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    recorder.stop().unwrap();
+
+    let files = read_files(dir.path());
+    assert_eq!(files.len(), 1);
+    let mut lines = files[0].lines();
+    assert_eq!(lines.next(), Some("timestamp,thread_id,serial,DO5,ENC0"));
+
+    let rows: Vec<&str> = lines.collect();
+    assert!(rows.len() > 1);
+    for row in rows {
+        let fields: Vec<&str> = row.split(',').collect();
+        assert_eq!(&fields[1..], &["7", "1234", "1", ""]);
+    }
+}
+
+#[test]
+fn test_json_lines_on_change_recording() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = recorded_state(42);
+
+    let config = RecorderConfig::new(dir.path())
+        .format(RecordFormat::JsonLines)
+        .mode(SampleMode::OnChange(Duration::from_millis(2)))
+        .channel(HistoryChannel::AnalogInput(1));
+    let recorder = DataRecorder::start(config, vec![(1, state.clone())]).unwrap();
+
+    thread::sleep(Duration::from_millis(30));
+    state.update(|state| state.pins[0].analog_value = 100);
+    thread::sleep(Duration::from_millis(30));
+    recorder.stop().unwrap();
+
+    let files = read_files(dir.path());
+    let rows: Vec<RecordRow> = files[0]
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    // One row for the initial value and one for the change
+    let values: Vec<i64> = rows.iter().map(|row| row.values["AI1"]).collect();
+    assert_eq!(values, vec![0, 100]);
+    assert!(rows
+        .iter()
+        .all(|row| row.serial == 42 && row.thread_id == 1));
+}
+
+#[test]
+fn test_rotation_keeps_max_files() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let config = RecorderConfig::new(dir.path())
+        .mode(SampleMode::FixedRate(Duration::from_millis(1)))
+        .channel(HistoryChannel::DigitalInput(1))
+        .rotation(64, 3);
+    let recorder =
+        DataRecorder::start(config, vec![(1, recorded_state(1)), (2, recorded_state(2))]).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    recorder.stop().unwrap();
+
+    let files = read_files(dir.path());
+    assert_eq!(files.len(), 3);
+    for contents in files {
+        assert!(contents.starts_with("timestamp,thread_id,serial,DI1\n"));
+    }
+}
+
+#[test]
+fn test_invalid_config_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = DataRecorder::start(
+        RecorderConfig::new(dir.path()),
+        vec![(1, recorded_state(1))],
+    );
+    assert!(result.is_err());
+}
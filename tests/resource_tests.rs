@@ -0,0 +1,233 @@
+//! Tests for device resource claims
+
+use pokeys_thread::{
+    DeviceCommand, DeviceHandle, PwmChannelMap, PwmConfig, Resource, ResourceClaim,
+    ResourceManager, SharedDeviceState, ThreadError,
+};
+use std::sync::Arc;
+
+#[test]
+fn test_conflicting_claims_are_rejected() {
+    let resources = ResourceManager::new();
+    let pwm = resources
+        .claim("lamp", &[Resource::PwmChannel(0), Resource::Pin(22)])
+        .unwrap();
+    assert_eq!(pwm.owner(), "lamp");
+    assert_eq!(resources.owner(Resource::Pin(22)), Some("lamp".to_string()));
+
+    // An encoder over the PWM pin conflicts and names the current owner
+    match resources.claim(
+        "spindle",
+        &[Resource::Encoder(0), Resource::Pin(21), Resource::Pin(22)],
+    ) {
+        Err(ThreadError::ResourceConflict {
+            message, resource, ..
+        }) => {
+            assert_eq!(resource, "pin 22");
+            assert!(message.contains("lamp"));
+        }
+        other => panic!("expected a resource conflict, got {other:?}"),
+    }
+
+    // A rejected claim takes nothing
+    assert!(!resources.is_claimed(Resource::Pin(21)));
+    assert!(!resources.is_claimed(Resource::Encoder(0)));
+}
+
+#[test]
+fn test_claims_are_released_on_drop() {
+    let resources = ResourceManager::new();
+    {
+        let _bus = resources.claim("sensor", &[Resource::I2cBus]).unwrap();
+        assert!(resources.claim("display", &[Resource::I2cBus]).is_err());
+    }
+    let bus = resources.claim("display", &[Resource::I2cBus]).unwrap();
+    assert_eq!(
+        resources.claims(),
+        vec![(Resource::I2cBus, "display".to_string())]
+    );
+    bus.release();
+    assert!(resources.claims().is_empty());
+}
+
+#[test]
+fn test_shared_state_resources() {
+    let shared_state = SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+    // Duplicates within one claim are merged
+    let claim = shared_state
+        .resources()
+        .claim(
+            "io",
+            &[Resource::Pin(17), Resource::Pin(17), Resource::SpiBus],
+        )
+        .unwrap();
+    assert_eq!(claim.resources(), &[Resource::Pin(17), Resource::SpiBus]);
+
+    // Clones of the manager share the claims
+    let resources = shared_state.resources().clone();
+    assert!(resources.claim("other", &[Resource::Pin(17)]).is_err());
+    drop(claim);
+    assert!(resources.claim("other", &[Resource::Pin(17)]).is_ok());
+    assert_eq!(Resource::PwmChannel(3).to_string(), "PWM channel 3");
+}
+
+#[test]
+fn test_only_owners_configure_claimed_resources() {
+    let resources = ResourceManager::new();
+    let channels = PwmChannelMap::standard();
+    let claim = resources
+        .claim("lamp", &[Resource::PwmChannel(0), Resource::Pin(22)])
+        .unwrap();
+    let pin_function = DeviceCommand::SetPinFunction {
+        pin: 22,
+        pin_function: pokeys_lib::PinFunction::DigitalOutput,
+    };
+    let spindle = resources.claim("spindle", &[Resource::Encoder(1)]).unwrap();
+    let claimed = |claim: &ResourceClaim, command: &DeviceCommand| DeviceCommand::Claimed {
+        claim: claim.id(),
+        command: Box::new(command.clone()),
+    };
+
+    match resources.check_command(&pin_function, &channels) {
+        Err(ThreadError::ResourceConflict {
+            resource,
+            conflicting_operation,
+            ..
+        }) => {
+            assert_eq!(resource, "pin 22");
+            assert_eq!(conflicting_operation, "set_pin_function");
+        }
+        other => panic!("expected a resource conflict, got {other:?}"),
+    }
+    assert!(resources
+        .check_command(&claimed(&claim, &pin_function), &channels)
+        .is_ok());
+    assert!(resources
+        .check_command(&claimed(&spindle, &pin_function), &channels)
+        .is_err());
+
+    // Encoders over claimed pins and PWM configurations of claimed channels
+    let encoder = DeviceCommand::ConfigureEncoder {
+        encoder_index: 0,
+        pin_a: 21,
+        pin_b: 22,
+        enabled: true,
+        sampling_4x: false,
+    };
+    assert!(resources.check_command(&encoder, &channels).is_err());
+    let pwm = DeviceCommand::ConfigurePwm {
+        config: PwmConfig::frequency(1000.0).channel(1),
+    };
+    assert!(resources.check_command(&pwm, &channels).is_err());
+    assert!(resources
+        .check_command(&claimed(&claim, &pwm), &channels)
+        .is_ok());
+    // Writes and unclaimed resources are not affected
+    let write = DeviceCommand::SetDigitalOutput {
+        pin: 22,
+        value: true,
+    };
+    assert!(resources.check_command(&write, &channels).is_ok());
+
+    assert!(resources.holds(&claim));
+    assert!(!ResourceManager::new().holds(&claim));
+    drop(claim);
+    assert!(resources.check_command(&pin_function, &channels).is_ok());
+}
+
+#[test]
+fn test_device_handle_checks_claims() {
+    let shared_state = Arc::new(SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    ));
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let device = DeviceHandle::new(1, shared_state.clone(), tx);
+    let _claim = shared_state
+        .resources()
+        .claim("spindle", &[Resource::Encoder(0)])
+        .unwrap();
+
+    assert!(matches!(
+        device.send_command(DeviceCommand::ConfigureEncoder {
+            encoder_index: 0,
+            pin_a: 1,
+            pin_b: 2,
+            enabled: true,
+            sampling_4x: false,
+        }),
+        Err(ThreadError::ResourceConflict { .. })
+    ));
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn test_exclusive_bus_claims_reject_other_transfers() {
+    let shared_state = Arc::new(SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    ));
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let device = DeviceHandle::new(1, shared_state.clone(), tx);
+    let resources = shared_state.resources();
+    let channels = PwmChannelMap::standard();
+    let write = DeviceCommand::I2cWrite {
+        address: 0x48,
+        data: vec![1],
+    };
+
+    // A plain claim on the bus leaves it usable by everyone
+    let claim = resources.claim("sensor", &[Resource::I2cBus]).unwrap();
+    assert!(resources.check_command(&write, &channels).is_ok());
+    drop(claim);
+
+    let claim = resources
+        .claim_exclusive("sensor", &[Resource::I2cBus])
+        .unwrap();
+    match device.i2c_bus().transfer(0x48, &[0x0F], 1) {
+        Err(ThreadError::ResourceConflict {
+            resource,
+            conflicting_operation,
+            ..
+        }) => {
+            assert_eq!(resource, "I2C bus");
+            assert_eq!(conflicting_operation, "i2c_transfer");
+        }
+        other => panic!("expected a resource conflict, got {other:?}"),
+    }
+    for command in [
+        write.clone(),
+        DeviceCommand::I2cScan,
+        DeviceCommand::USPIBridgeCommand {
+            address: 0x42,
+            command: vec![0x51, 0],
+        },
+    ] {
+        assert!(
+            resources.check_command(&command, &channels).is_err(),
+            "{command:?} should be rejected"
+        );
+    }
+    assert!(rx.try_recv().is_err());
+
+    // The holder of the claim uses the bus through a claimed handle
+    let owner = device.with_claim(&claim).unwrap();
+    owner.send_command(write).unwrap();
+    assert!(matches!(
+        rx.try_recv(),
+        Ok(DeviceCommand::Claimed { claim: id, .. }) if id == claim.id()
+    ));
+    assert!(DeviceHandle::new(
+        2,
+        Arc::new(SharedDeviceState::new(
+            pokeys_lib::DeviceInfo::default(),
+            pokeys_lib::DeviceData::default(),
+        )),
+        crossbeam_channel::unbounded().0
+    )
+    .with_claim(&claim)
+    .is_err());
+}
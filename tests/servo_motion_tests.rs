@@ -0,0 +1,91 @@
+//! Tests for servo motion profiles
+
+use pokeys_thread::{ServoMotions, ServoProfile};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_profile_timing() {
+    // Constant speed: 90 degrees at 45 deg/s
+    let profile = ServoProfile::new(45.0);
+    assert_eq!(profile.duration(90.0), Duration::from_secs(2));
+    assert_eq!(profile.position(90.0, Duration::from_secs(1)), 45.0);
+    assert_eq!(profile.position(90.0, Duration::from_secs(3)), 90.0);
+
+    // Trapezoid: 1 s ramps at 90 deg/s², 90 deg/s cruise
+    let profile = ServoProfile::new(90.0).max_acceleration(90.0);
+    assert_eq!(profile.duration(180.0), Duration::from_secs(3));
+    assert_eq!(profile.position(180.0, Duration::from_secs(1)), 45.0);
+    assert_eq!(profile.position(180.0, Duration::from_secs(2)), 135.0);
+    assert!((profile.position(180.0, Duration::from_millis(2500)) - 168.75).abs() < 1e-3);
+
+    // Triangle: too short to reach full speed
+    assert_eq!(profile.duration(22.5), Duration::from_secs(1));
+    assert!((profile.position(22.5, Duration::from_millis(500)) - 11.25).abs() < 1e-3);
+
+    assert!(ServoProfile::new(0.0).validate().is_err());
+    assert!(ServoProfile::new(10.0)
+        .max_acceleration(-1.0)
+        .validate()
+        .is_err());
+}
+
+#[test]
+fn test_synchronized_moves_arrive_together() {
+    let mut motions = ServoMotions::new();
+    let start = Instant::now();
+    motions.start(
+        &[(22, 0.0, 90.0), (21, 90.0, 45.0)],
+        ServoProfile::new(90.0),
+        start,
+    );
+    assert_eq!(motions.pins(), vec![21, 22]);
+
+    // Halfway through, both servos are halfway to their targets
+    let steps = motions.step(start + Duration::from_millis(500));
+    assert_eq!(steps.len(), 2);
+    assert!((steps[0].angle - 67.5).abs() < 1e-3);
+    assert!((steps[1].angle - 45.0).abs() < 1e-3);
+    assert!(steps.iter().all(|step| !step.complete));
+
+    let steps = motions.step(start + Duration::from_secs(1));
+    assert_eq!(steps[0].angle, 45.0);
+    assert_eq!(steps[1].angle, 90.0);
+    assert!(steps.iter().all(|step| step.complete));
+    assert!(motions.is_empty());
+}
+
+#[test]
+fn test_cancel_holds_reached_angle() {
+    let mut motions = ServoMotions::new();
+    let start = Instant::now();
+    motions.start(&[(20, 10.0, 110.0)], ServoProfile::new(100.0), start);
+    assert!(motions.is_moving(20));
+
+    let reached = motions
+        .cancel(20, start + Duration::from_millis(250))
+        .unwrap();
+    assert!((reached - 35.0).abs() < 1e-3);
+    assert!(!motions.is_moving(20));
+    assert_eq!(motions.cancel(20, start), None);
+    assert!(motions.step(start + Duration::from_secs(2)).is_empty());
+
+    // A move of zero distance completes on the first step
+    motions.start(&[(20, 35.0, 35.0)], ServoProfile::new(100.0), start);
+    let steps = motions.step(start);
+    assert!(steps[0].complete);
+    assert_eq!(steps[0].angle, 35.0);
+}
+
+#[test]
+fn test_delay_resumes_where_paused() {
+    let mut motions = ServoMotions::new();
+    let start = Instant::now();
+    motions.start(&[(22, 0.0, 90.0)], ServoProfile::new(90.0), start);
+
+    // A 2 s pause halfway through holds the servo at 45 degrees
+    motions.delay(Duration::from_secs(2));
+    let steps = motions.step(start + Duration::from_millis(2500));
+    assert!((steps[0].angle - 45.0).abs() < 1e-3);
+    assert!(!steps[0].complete);
+    assert!(motions.step(start + Duration::from_secs(3))[0].complete);
+}
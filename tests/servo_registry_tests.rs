@@ -0,0 +1,150 @@
+//! Tests for servo configuration storage and angle-to-duty conversion
+
+use pokeys_lib::models::{DeviceModel, PinModel};
+use pokeys_lib::ServoConfig;
+use pokeys_thread::servo::{angle_to_duty, speed_to_duty};
+use pokeys_thread::{
+    FloatValue, PwmChannelMap, ServoRegistry, SharedDeviceState, StateChangeType, ThreadError,
+};
+use std::collections::HashMap;
+
+#[test]
+fn test_calibrated_conversion() {
+    let mut servos = ServoRegistry::new();
+    let channels = PwmChannelMap::standard();
+    servos
+        .configure(
+            22,
+            ServoConfig::one_eighty(22, 25000, 50000),
+            &channels,
+            500000,
+        )
+        .unwrap();
+    servos
+        .configure(
+            21,
+            ServoConfig::three_sixty_position(0, 20000, 60000),
+            &channels,
+            0,
+        )
+        .unwrap();
+    servos
+        .configure(
+            20,
+            ServoConfig::three_sixty_speed(20, 37500, 50000, 25000),
+            &channels,
+            0,
+        )
+        .unwrap();
+    // The command pin overrides the pin in the configuration
+    assert_eq!(servos.get(21).unwrap().pin, 21);
+    assert_eq!(servos.pins(), vec![20, 21, 22]);
+
+    assert_eq!(servos.angle_duty(22, 0.0).unwrap(), 25000);
+    assert_eq!(servos.angle_duty(22, 90.0).unwrap(), 37500);
+    assert_eq!(servos.angle_duty(22, 180.0).unwrap(), 50000);
+    assert_eq!(servos.angle_duty(21, 270.0).unwrap(), 50000);
+    assert!(servos.angle_duty(22, 181.0).is_err());
+
+    assert_eq!(servos.speed_duty(20, 0.0).unwrap(), 37500);
+    assert_eq!(servos.speed_duty(20, 50.0).unwrap(), 43750);
+    assert_eq!(servos.speed_duty(20, -100.0).unwrap(), 25000);
+    assert_eq!(servos.stop_duty(20).unwrap(), 37500);
+
+    // Angle and speed only apply to the matching servo types
+    assert!(servos.speed_duty(22, 10.0).is_err());
+    assert!(servos.stop_duty(22).is_err());
+    assert!(angle_to_duty(servos.get(20).unwrap(), 10.0).is_err());
+    assert!(speed_to_duty(servos.get(20).unwrap(), 120.0).is_err());
+
+    // Unconfigured pins are rejected instead of guessing a duty value
+    assert!(matches!(
+        servos.angle_duty(19, 90.0),
+        Err(ThreadError::ValidationError { .. })
+    ));
+}
+
+#[test]
+fn test_configuration_validation() {
+    let mut servos = ServoRegistry::new();
+    let channels = PwmChannelMap::standard();
+
+    assert!(matches!(
+        servos.configure(5, ServoConfig::one_eighty(5, 1000, 2000), &channels, 0),
+        Err(ThreadError::PinCapabilityError { pin: 5, .. })
+    ));
+    assert!(servos
+        .configure(22, ServoConfig::one_eighty(22, 1000, 1000), &channels, 0)
+        .is_err());
+    assert!(servos
+        .configure(
+            22,
+            ServoConfig::three_sixty_speed(22, 1500, 1500, 1000),
+            &channels,
+            0
+        )
+        .is_err());
+    // Duty values must fit in the PWM period
+    assert!(servos
+        .configure(
+            22,
+            ServoConfig::one_eighty(22, 25000, 50000),
+            &channels,
+            40000
+        )
+        .is_err());
+    assert!(servos.pins().is_empty());
+
+    // Only pins in the channel map of the device can drive servos
+    let mut model = DeviceModel {
+        name: "TestDevice".to_string(),
+        pins: HashMap::new(),
+    };
+    model.pins.insert(
+        20,
+        PinModel {
+            capabilities: vec!["PwmOutput".to_string()],
+            active: true,
+        },
+    );
+    let channels = PwmChannelMap::from_model(&model);
+    assert!(matches!(
+        servos.configure(22, ServoConfig::one_eighty(22, 1000, 2000), &channels, 0),
+        Err(ThreadError::PinCapabilityError { pin: 22, .. })
+    ));
+    servos
+        .configure(20, ServoConfig::one_eighty(20, 1000, 2000), &channels, 0)
+        .unwrap();
+}
+
+#[test]
+fn test_servo_state_events() {
+    let shared_state = SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+    let before = shared_state.snapshot();
+    let rx = shared_state.setup_notifications();
+
+    shared_state.set_servo_position(22, 45.0);
+    shared_state.set_servo_speed(20, -30.0);
+    assert_eq!(shared_state.get_servo_position(22), Some(45.0));
+    assert_eq!(shared_state.get_servo_speed(20), Some(-30.0));
+
+    let expected = vec![
+        StateChangeType::ServoPosition {
+            pin: 22,
+            angle: FloatValue(45.0),
+        },
+        StateChangeType::ServoSpeed {
+            pin: 20,
+            speed: FloatValue(-30.0),
+        },
+    ];
+    let changes: Vec<StateChangeType> = rx
+        .try_iter()
+        .filter(|change| *change != StateChangeType::FullUpdate)
+        .collect();
+    assert_eq!(changes, expected);
+    assert_eq!(before.diff(&shared_state.snapshot()), expected);
+}
@@ -0,0 +1,149 @@
+//! Tests for the DeviceState diff API
+
+use pokeys_lib::io::PinData;
+use pokeys_thread::{DeviceState, SharedDeviceState, StateChangeType, ThreadStatus};
+
+fn device_state(pin_count: usize) -> DeviceState {
+    let mut state = DeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+    state.pins = vec![PinData::new(); pin_count];
+    state
+}
+
+#[test]
+fn test_diff_identical_states_is_empty() {
+    let state = device_state(55);
+    assert!(state.diff(&state.clone()).is_empty());
+}
+
+#[test]
+fn test_diff_covers_all_fields() {
+    let mut old = device_state(8);
+    old.custom_values.insert("removed".to_string(), "x".into());
+    old.custom_values.insert("kept".to_string(), "same".into());
+
+    let mut new = old.clone();
+    new.pins[1].digital_value_get = 1;
+    new.pins[3].pin_function = 4;
+    new.pins[4].digital_value_set = 1;
+    new.pwm.pwm_period = 25000;
+    new.pwm.enabled_channels = 0b1;
+    new.pwm.pwm_values[0] = 100;
+    new.status = ThreadStatus::Running;
+    new.error_message = Some("boom".to_string());
+    new.custom_values.remove("removed");
+    new.custom_values.insert("added".to_string(), "1".into());
+
+    assert_eq!(
+        old.diff(&new),
+        vec![
+            StateChangeType::DigitalInput {
+                pin: 2,
+                value: true
+            },
+            StateChangeType::PinFunction {
+                pin: 4,
+                function: 4
+            },
+            StateChangeType::DigitalOutput {
+                pin: 5,
+                value: true
+            },
+            StateChangeType::PwmConfiguration {
+                period: 25000,
+                enabled_channels: 0b1
+            },
+            StateChangeType::PwmDutyCycle {
+                channel: 0,
+                duty: 100
+            },
+            StateChangeType::ThreadStatus {
+                status: ThreadStatus::Running
+            },
+            StateChangeType::Error {
+                message: Some("boom".to_string())
+            },
+            StateChangeType::CustomValue {
+                key: "added".to_string(),
+                value: "1".to_string()
+            },
+            StateChangeType::CustomValueRemoved {
+                key: "removed".to_string()
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_diff_reports_added_and_removed_pins() {
+    let old = device_state(2);
+
+    let mut new = device_state(3);
+    new.pins[2].analog_value = 7;
+    new.encoders = vec![pokeys_lib::encoders::EncoderData::new(); 1];
+    new.encoders[0].encoder_value = 3;
+
+    let changes = old.diff(&new);
+    assert_eq!(
+        changes,
+        vec![
+            StateChangeType::PinCount { count: 3 },
+            StateChangeType::AnalogInput { pin: 3, value: 7 },
+            StateChangeType::EncoderCount { count: 1 },
+            StateChangeType::EncoderValue { index: 0, value: 3 },
+        ]
+    );
+
+    // Applying the diff turns the old state into the new one, both ways
+    let mut replayed = old.clone();
+    for change in &changes {
+        replayed.apply_change(change);
+    }
+    assert!(replayed.diff(&new).is_empty());
+
+    let mut shrunk = new.clone();
+    for change in &new.diff(&old) {
+        shrunk.apply_change(change);
+    }
+    assert_eq!(shrunk.pins.len(), 2);
+    assert!(shrunk.encoders.is_empty());
+    assert!(shrunk.diff(&old).is_empty());
+}
+
+#[test]
+fn test_diff_ignores_device_info() {
+    let old = device_state(1);
+    let mut new = old.clone();
+    new.device_info.pin_count = 55;
+    new.device_data.serial_number = 1234;
+    new.last_update = 42;
+
+    assert!(old.diff(&new).is_empty());
+}
+
+#[test]
+fn test_notifications_agree_with_diff() {
+    let shared_state = SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+    shared_state.update(|state| state.pins = vec![PinData::new(); 55]);
+
+    let before = shared_state.snapshot();
+
+    let mut pins = before.pins.clone();
+    pins[0].digital_value_get = 1;
+    pins[10].analog_value = 512;
+    pins[54].digital_value_set = 1;
+    let mut notified = Vec::new();
+    shared_state.update(|state| {
+        state.update_io_with(&pins, &before.encoders, &before.pwm, |change| {
+            notified.push(change)
+        });
+    });
+
+    assert_eq!(notified, shared_state.diff_since(&before));
+    assert_eq!(notified.len(), 3);
+}
@@ -0,0 +1,156 @@
+//! Tests for lock-free state snapshots
+
+use pokeys_lib::io::PinData;
+use pokeys_thread::{FloatValue, StateChangeType};
+use std::sync::Arc;
+use std::thread;
+
+mod common;
+
+#[test]
+fn test_snapshot_is_immutable() {
+    let shared_state = common::shared_state_for(0, 55);
+
+    let before = shared_state.snapshot();
+    shared_state.set_digital_output(1, true);
+    shared_state.set_digital_output(2, true);
+
+    // The held snapshot is not affected by later updates, even when
+    // the writer recycles older snapshots
+    assert_eq!(before.pins[0].digital_value_set, 0);
+    assert_eq!(before.pins[1].digital_value_set, 0);
+
+    let after = shared_state.snapshot();
+    assert_eq!(after.pins[0].digital_value_set, 1);
+    assert_eq!(after.pins[1].digital_value_set, 1);
+}
+
+#[test]
+fn test_snapshot_reads_are_consistent() {
+    let shared_state = Arc::new(common::shared_state_for(0, 55));
+
+    let writer_state = shared_state.clone();
+    let writer = thread::spawn(move || {
+        for i in 0..2000u32 {
+            writer_state.update(|state| {
+                for pin in state.pins.iter_mut() {
+                    pin.analog_value = i;
+                }
+            });
+        }
+    });
+
+    // Every snapshot must contain the same value on all pins
+    for _ in 0..2000 {
+        let snapshot = shared_state.snapshot();
+        let first = snapshot.pins[0].analog_value;
+        assert!(snapshot.pins.iter().all(|pin| pin.analog_value == first));
+    }
+
+    writer.join().unwrap();
+    assert_eq!(shared_state.get_analog_input(55), Some(1999));
+}
+
+#[test]
+fn test_incremental_update_reports_changes() {
+    let shared_state = common::shared_state_for(0, 4);
+    let observer = shared_state.setup_notifications();
+
+    let mut pins = vec![PinData::new(); 4];
+    pins[0].digital_value_get = 1;
+    pins[2].analog_value = 1234;
+    let mut encoders = vec![pokeys_lib::encoders::EncoderData::new(); 2];
+    encoders[1].encoder_value = -5;
+    let mut pwm = pokeys_lib::pwm::PwmData::new();
+    pwm.pwm_values[3] = 2048;
+
+    let mut changes = Vec::new();
+    shared_state.update(|state| {
+        // Start from two idle encoders so the change on encoder 1 is reported
+        state.encoders = vec![pokeys_lib::encoders::EncoderData::new(); 2];
+        state.update_io_with(&pins, &encoders, &pwm, |change| changes.push(change));
+    });
+
+    assert_eq!(
+        changes,
+        vec![
+            StateChangeType::DigitalInput {
+                pin: 1,
+                value: true
+            },
+            StateChangeType::AnalogInput {
+                pin: 3,
+                value: 1234
+            },
+            StateChangeType::EncoderValue {
+                index: 1,
+                value: -5
+            },
+            StateChangeType::PwmDutyCycle {
+                channel: 3,
+                duty: 2048
+            },
+        ]
+    );
+    assert_eq!(shared_state.get_encoder_value(1), Some(-5));
+
+    // update() notifies the changes between the snapshots, then a full update
+    let notified: Vec<StateChangeType> = observer.try_iter().collect();
+    assert_eq!(notified.last(), Some(&StateChangeType::FullUpdate));
+    assert!(changes.iter().all(|change| notified.contains(change)));
+    assert!(notified.contains(&StateChangeType::EncoderCount { count: 2 }));
+}
+
+#[test]
+fn test_notifications_follow_published_state() {
+    let shared_state = Arc::new(common::shared_state_for(0, 4));
+    let observer = shared_state.setup_notifications();
+
+    let reader_state = shared_state.clone();
+    let reader = thread::spawn(move || {
+        let mut seen = Vec::new();
+        while let Ok(change) = observer.recv() {
+            // Each notification is sent after its snapshot is published
+            match change {
+                StateChangeType::DigitalOutput { pin, value } => {
+                    assert_eq!(reader_state.get_digital_output(pin), Some(value));
+                }
+                StateChangeType::ServoPosition { pin, angle } => {
+                    assert_eq!(reader_state.get_servo_position(pin), Some(angle.get()));
+                }
+                StateChangeType::Error { ref message } => {
+                    assert_eq!(&reader_state.snapshot().error_message, message);
+                    seen.push(change);
+                    break;
+                }
+                _ => {}
+            }
+            seen.push(change);
+        }
+        seen
+    });
+
+    shared_state.set_digital_output(2, true);
+    shared_state.set_servo_position(17, 90.0);
+    shared_state.set_error(Some("failed".to_string()));
+
+    let seen = reader.join().unwrap();
+    assert_eq!(
+        seen,
+        vec![
+            StateChangeType::DigitalOutput {
+                pin: 2,
+                value: true
+            },
+            StateChangeType::FullUpdate,
+            StateChangeType::ServoPosition {
+                pin: 17,
+                angle: FloatValue(90.0)
+            },
+            StateChangeType::FullUpdate,
+            StateChangeType::Error {
+                message: Some("failed".to_string())
+            },
+        ]
+    );
+}
@@ -0,0 +1,79 @@
+//! Tests for the sync timing statistics
+
+use pokeys_thread::{SharedDeviceState, SyncGroup, SyncStatistics};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_sync_duration_summary() {
+    let stats = SyncStatistics::with_window(100);
+
+    for ms in 1..=100 {
+        stats.record_sync(Duration::from_millis(ms), ms != 50);
+    }
+
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.sync_count, 100);
+    assert_eq!(snapshot.sync_errors, 1);
+    assert_eq!(snapshot.sync_duration.samples, 100);
+    assert_eq!(snapshot.sync_duration.min, Duration::from_millis(1));
+    assert_eq!(snapshot.sync_duration.max, Duration::from_millis(100));
+    assert_eq!(snapshot.sync_duration.p99, Duration::from_millis(99));
+    assert_eq!(snapshot.sync_duration.avg, Duration::from_micros(50_500));
+}
+
+#[test]
+fn test_rolling_window_drops_old_samples() {
+    let stats = SyncStatistics::with_window(3);
+
+    for ms in [100, 1, 2, 3] {
+        stats.record_group_read(SyncGroup::Encoders, Duration::from_millis(ms));
+    }
+
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.encoder_read.samples, 3);
+    assert_eq!(snapshot.encoder_read.max, Duration::from_millis(3));
+    assert_eq!(snapshot.digital_input_read.samples, 0);
+}
+
+#[test]
+fn test_period_and_jitter() {
+    let stats = SyncStatistics::new();
+    let interval = Duration::from_millis(10);
+    let start = Instant::now();
+
+    stats.record_sync_start(start, interval);
+    stats.record_sync_start(start + Duration::from_millis(12), interval);
+    stats.record_sync_start(start + Duration::from_millis(20), interval);
+
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.period.samples, 2);
+    assert_eq!(snapshot.period.min, Duration::from_millis(8));
+    assert_eq!(snapshot.period.max, Duration::from_millis(12));
+    assert_eq!(snapshot.jitter.min, Duration::from_millis(2));
+    assert_eq!(snapshot.jitter.max, Duration::from_millis(2));
+}
+
+#[test]
+fn test_command_stats_and_reset() {
+    let shared_state = SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    );
+
+    shared_state
+        .sync_stats()
+        .record_command(Duration::from_micros(200), true);
+    shared_state
+        .sync_stats()
+        .record_command(Duration::from_micros(400), false);
+
+    let snapshot = shared_state.sync_stats().snapshot();
+    assert_eq!(snapshot.command_count, 2);
+    assert_eq!(snapshot.command_errors, 1);
+    assert_eq!(snapshot.command_latency.avg, Duration::from_micros(300));
+
+    shared_state.sync_stats().reset();
+    let snapshot = shared_state.sync_stats().snapshot();
+    assert_eq!(snapshot.command_count, 0);
+    assert_eq!(snapshot.command_latency.samples, 0);
+}
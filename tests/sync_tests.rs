@@ -2,7 +2,7 @@
 
 #[cfg(test)]
 mod tests {
-    use pokeys_thread::{DeviceSync, SharedDeviceState};
+    use pokeys_thread::{AdaptiveRefreshConfig, DeviceSync, SharedDeviceState};
     use std::sync::Arc;
     use std::thread;
     use std::time::Duration;
@@ -42,4 +42,57 @@ mod tests {
         // Check if it's time to sync
         assert!(device_sync.should_sync());
     }
+
+    #[test]
+    fn test_adaptive_refresh_backoff() {
+        let config = AdaptiveRefreshConfig::new(10, 1000)
+            .idle_threshold(500)
+            .backoff_factor(2.0);
+        assert!(config.validate().is_ok());
+
+        let fast = Duration::from_millis(10);
+
+        // Still within the idle threshold: stay at the fast interval
+        let next = config.next_interval(Duration::from_millis(80), Duration::from_millis(100));
+        assert_eq!(next, fast);
+
+        // Idle: back off by the configured factor
+        let next = config.next_interval(fast, Duration::from_millis(600));
+        assert_eq!(next, Duration::from_millis(20));
+
+        // Never exceed the slow interval
+        let next = config.next_interval(Duration::from_millis(800), Duration::from_secs(10));
+        assert_eq!(next, Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_adaptive_refresh_validation() {
+        assert!(AdaptiveRefreshConfig::new(0, 100).validate().is_err());
+        assert!(AdaptiveRefreshConfig::new(100, 10).validate().is_err());
+        assert!(AdaptiveRefreshConfig::default()
+            .backoff_factor(0.5)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_adaptive_refresh_activity_snaps_to_fast() {
+        let device_info = pokeys_lib::DeviceInfo::default();
+        let device_data = pokeys_lib::DeviceData::default();
+        let shared_state = Arc::new(SharedDeviceState::new(device_info, device_data));
+
+        let mut device_sync = DeviceSync::new(1, shared_state, 100)
+            .with_adaptive_refresh(AdaptiveRefreshConfig::new(10, 1000));
+        assert_eq!(device_sync.sync_interval(), Duration::from_millis(10));
+
+        device_sync.set_sync_interval(500);
+        device_sync.record_activity();
+        assert_eq!(device_sync.sync_interval(), Duration::from_millis(10));
+
+        // Without adaptive refresh, activity leaves the interval alone
+        device_sync.set_adaptive_refresh(None);
+        device_sync.set_sync_interval(500);
+        device_sync.record_activity();
+        assert_eq!(device_sync.sync_interval(), Duration::from_millis(500));
+    }
 }
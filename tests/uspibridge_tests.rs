@@ -0,0 +1,213 @@
+//! Tests for uSPIBridge commands and 7-segment displays
+
+use pokeys_lib::{SegmentMapping, SegmentMappingType, USPIBridgeCommand, USPIBridgeConfig};
+use pokeys_thread::uspibridge::configuration_commands;
+use pokeys_thread::{
+    BridgeCommand, DeviceCommand, DeviceHandle, SharedDeviceState, ThreadError, USPIBridge,
+    DEFAULT_USPIBRIDGE_ADDRESS,
+};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Answer uSPIBridge requests like a bridge, recording the commands sent
+fn fake_bridge() -> (DeviceHandle, JoinHandle<Vec<BridgeCommand>>) {
+    let shared_state = Arc::new(SharedDeviceState::new(
+        pokeys_lib::DeviceInfo::default(),
+        pokeys_lib::DeviceData::default(),
+    ));
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let worker = std::thread::spawn(move || {
+        let mut sent = Vec::new();
+        for command in rx {
+            let DeviceCommand::USPIBridgeRequest {
+                address,
+                commands,
+                response,
+            } = command
+            else {
+                continue;
+            };
+            assert_eq!(address, DEFAULT_USPIBRIDGE_ADDRESS);
+            let answer = commands
+                .last()
+                .map(|command| (0..command.response_length).collect())
+                .unwrap_or_default();
+            sent.extend(commands);
+            let _ = response.send(Ok(answer));
+        }
+        sent
+    });
+    (DeviceHandle::new(1, shared_state, tx), worker)
+}
+
+#[test]
+fn test_bridge_command_framing() {
+    let command = BridgeCommand::new(USPIBridgeCommand::DisplayText, 2, b"Hi".to_vec());
+    assert_eq!(command.response_length, 0);
+
+    let raw = BridgeCommand::from_raw(&[0x51, 0]).unwrap();
+    assert_eq!(raw.command, USPIBridgeCommand::SystemStatus);
+    assert_eq!(raw.response_length, 16);
+    assert_eq!(
+        BridgeCommand::from_raw(&[0x44, 3, 9]).unwrap(),
+        BridgeCommand::new(USPIBridgeCommand::VirtualBrightness, 3, vec![9])
+    );
+    assert!(BridgeCommand::from_raw(&[0x20]).is_err());
+    assert!(BridgeCommand::from_raw(&[0x99, 0]).is_err());
+    assert!(
+        BridgeCommand::new(USPIBridgeCommand::DisplayText, 0, vec![b'8'; 29])
+            .validate()
+            .is_err()
+    );
+
+    let config = USPIBridgeConfig::new()
+        .with_device_count(2)
+        .with_segment_mapping(1, SegmentMapping::custom([7, 6, 5, 4, 3, 2, 1, 0]))
+        .with_default_brightness(20);
+    assert_eq!(
+        configuration_commands(&config),
+        vec![
+            BridgeCommand::new(
+                USPIBridgeCommand::SetSegmentMappingType,
+                0,
+                vec![SegmentMappingType::Standard as u8]
+            ),
+            BridgeCommand::new(USPIBridgeCommand::SetBrightness, 0, vec![15]),
+            BridgeCommand::new(
+                USPIBridgeCommand::SetSegmentMapping,
+                1,
+                vec![7, 6, 5, 4, 3, 2, 1, 0]
+            ),
+            BridgeCommand::new(USPIBridgeCommand::SetBrightness, 1, vec![15]),
+        ]
+    );
+}
+
+#[test]
+fn test_bridge_requests() {
+    let (device, worker) = fake_bridge();
+    let bridge = USPIBridge::new(device.clone(), DEFAULT_USPIBRIDGE_ADDRESS).unwrap();
+
+    bridge
+        .configure(&USPIBridgeConfig::new().with_device_count(1))
+        .unwrap();
+    bridge.display_number(0, 1234).unwrap();
+    assert_eq!(bridge.status().unwrap(), (0..16).collect::<Vec<u8>>());
+    assert_eq!(bridge.segment_mapping(0).unwrap(), [0, 1, 2, 3, 4, 5, 6, 7]);
+    assert!(matches!(
+        bridge.set_brightness(0, 16),
+        Err(ThreadError::ValidationError { .. })
+    ));
+    assert!(USPIBridge::new(device.clone(), 0x80).is_err());
+
+    drop(bridge);
+    drop(device);
+    let sent = worker.join().unwrap();
+    assert_eq!(sent.len(), 5);
+    assert_eq!(
+        sent[2],
+        BridgeCommand::new(
+            USPIBridgeCommand::DisplayNumber,
+            0,
+            1234u32.to_le_bytes().to_vec()
+        )
+    );
+}
+
+#[test]
+fn test_seven_segment_display() {
+    let (device, worker) = fake_bridge();
+    let bridge = USPIBridge::new(device.clone(), DEFAULT_USPIBRIDGE_ADDRESS).unwrap();
+    assert!(bridge.display(0, &[]).is_err());
+
+    let display = bridge.display(1, &[0, 2]).unwrap();
+    assert_eq!(display.width(), 16);
+    // Decimal points share the digit before them
+    display.write_text("1.2.3.4.5.6.7.8.9.0.1.2.3.4.").unwrap();
+    assert!(display.write_text("12345678901234567").is_err());
+    display.write_number(-12.5, 2).unwrap();
+    display.write_integer(42).unwrap();
+    display.set_brightness(4).unwrap();
+    display.blink("ALArM", Duration::from_millis(500)).unwrap();
+    assert!(display.blink("ALArM", Duration::from_secs(70)).is_err());
+    display.stop().unwrap();
+    display
+        .set_segment_mapping(&SegmentMapping::new(SegmentMappingType::Reversed))
+        .unwrap();
+    display.remove().unwrap();
+
+    drop(bridge);
+    drop(device);
+    let sent = worker.join().unwrap();
+    let virtual_text = |text: &str| {
+        BridgeCommand::new(USPIBridgeCommand::VirtualText, 1, text.as_bytes().to_vec())
+    };
+    assert_eq!(
+        sent,
+        vec![
+            BridgeCommand::new(USPIBridgeCommand::CreateVirtualDevice, 1, vec![0, 2]),
+            virtual_text("1.2.3.4.5.6.7.8.9.0.1.2.3.4."),
+            virtual_text("           -12.50"),
+            virtual_text("              42"),
+            BridgeCommand::new(USPIBridgeCommand::VirtualBrightness, 1, vec![4]),
+            BridgeCommand::new(
+                USPIBridgeCommand::VirtualFlash,
+                1,
+                [b"ALArM".as_slice(), &500u16.to_le_bytes()].concat()
+            ),
+            BridgeCommand::new(USPIBridgeCommand::VirtualStop, 1, Vec::new()),
+            BridgeCommand::new(USPIBridgeCommand::SetSegmentMappingType, 0, vec![1]),
+            BridgeCommand::new(USPIBridgeCommand::SetSegmentMappingType, 2, vec![1]),
+            BridgeCommand::new(USPIBridgeCommand::DeleteVirtualDevice, 1, Vec::new()),
+        ]
+    );
+}
+
+#[test]
+fn test_long_text_is_split_over_modules() {
+    let (device, worker) = fake_bridge();
+    let bridge = USPIBridge::new(device.clone(), DEFAULT_USPIBRIDGE_ADDRESS).unwrap();
+    let display = bridge.display(0, &[0, 1, 2, 3]).unwrap();
+    assert_eq!(display.width(), 32);
+
+    // 30 bytes do not fit in one command
+    display
+        .write_text("1.2.3.4.5.6.7.8.9.0.ABCDEFGHIJ")
+        .unwrap();
+    assert!(display.write_text(&"8".repeat(33)).is_err());
+    // Blinking sends the text and its interval in one command
+    display
+        .blink(&"8".repeat(26), Duration::from_millis(500))
+        .unwrap();
+    assert!(matches!(
+        display.blink(&"8".repeat(27), Duration::from_millis(500)),
+        Err(ThreadError::ValidationError { .. })
+    ));
+
+    drop(display);
+    drop(bridge);
+    drop(device);
+    let sent = worker.join().unwrap();
+    let module_text = |module: u8, text: &str| {
+        BridgeCommand::new(
+            USPIBridgeCommand::DisplayText,
+            module,
+            text.as_bytes().to_vec(),
+        )
+    };
+    assert_eq!(
+        sent[1..],
+        [
+            module_text(0, "1.2.3.4.5.6.7.8."),
+            module_text(1, "9.0.ABCDEF"),
+            module_text(2, "GHIJ    "),
+            module_text(3, "        "),
+            BridgeCommand::new(
+                USPIBridgeCommand::VirtualFlash,
+                0,
+                ["8".repeat(26).as_bytes(), &500u16.to_le_bytes()].concat()
+            ),
+        ]
+    );
+}